@@ -0,0 +1,259 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use message::{CHUNK_SIZE, DEFAULT_ALPN, MAX_HEADER_LENGTH};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+/// QUIC congestion control algorithm of the connection to the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CongestionController {
+    /// Default of quinn.
+    #[default]
+    Cubic,
+
+    NewReno,
+
+    /// Keeps the throughput of links with a high bandwidth-delay product up.
+    Bbr,
+}
+
+impl CongestionController {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CongestionController::Cubic => "cubic",
+            CongestionController::NewReno => "new_reno",
+            CongestionController::Bbr => "bbr",
+        }
+    }
+}
+
+impl FromStr for CongestionController {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<CongestionController> {
+        match s {
+            "cubic" => Ok(CongestionController::Cubic),
+            "new_reno" => Ok(CongestionController::NewReno),
+            "bbr" => Ok(CongestionController::Bbr),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown congestion controller: {s}"),
+            )),
+        }
+    }
+}
+
+/// Parameters of the exponential backoff used when reconnecting to the server.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// Delay before the first retry.
+    pub base: Duration,
+
+    /// Upper bound of the delay between retries.
+    pub max: Duration,
+
+    /// Number of retries before giving up; retries forever when `None`.
+    pub max_retries: Option<u32>,
+}
+
+impl Backoff {
+    /// Returns the delay before the given (zero based) attempt: `base * 2^attempt`
+    /// capped by `max`, with a random jitter of up to half of the delay.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max);
+
+        let jitter = exp.mul_f64(rand::random::<f64>() / 2.0);
+
+        (exp - exp / 2 + jitter).min(self.max)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff {
+            base: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+            max_retries: None,
+        }
+    }
+}
+
+/// Where PEM encoded server certificates to trust are read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CertSource {
+    /// PEM file, or directory of them; see [`crate::tls::load_server_certs`].
+    File(PathBuf),
+
+    /// PEM text, e.g. taken from an environment variable.
+    Inline(String),
+
+    /// PEM text read from the standard input when the client is created.
+    Stdin,
+}
+
+/// Certificate chain and private key presented to servers requiring client certificates.
+#[derive(Debug)]
+pub struct ClientIdentity {
+    pub certs: Vec<CertificateDer<'static>>,
+    pub key: PrivateKeyDer<'static>,
+}
+
+impl Clone for ClientIdentity {
+    fn clone(&self) -> ClientIdentity {
+        ClientIdentity {
+            certs: self.certs.clone(),
+            key: self.key.clone_key(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// Address of the QUIC server.
+    pub server_addr: SocketAddr,
+
+    /// Name the server certificate is issued for.
+    pub server_name: String,
+
+    /// Local address the QUIC endpoint binds to.
+    pub bind_addr: SocketAddr,
+
+    /// Size of the send buffer (`SO_SNDBUF`) of the UDP socket of the endpoint; left to the OS
+    /// when `None`, and clamped by it to its maximum.
+    pub socket_send_buffer: Option<usize>,
+
+    /// Size of the receive buffer (`SO_RCVBUF`) of the UDP socket, likewise.
+    pub socket_recv_buffer: Option<usize>,
+
+    /// ALPN protocol identifier offered to the server; it has to match the one of the server.
+    pub alpn: Vec<u8>,
+
+    /// Address of the target the server will proxy data to.
+    pub proxy_addr: SocketAddr,
+
+    /// Tunnels the local connections redirected to the listener by iptables (`REDIRECT`) to
+    /// their original destination, read with `SO_ORIGINAL_DST`, instead of `proxy_addr`. Linux
+    /// only: other platforms always use `proxy_addr`.
+    pub original_dst: bool,
+
+    /// Certificates trusted when connecting to the server.
+    pub server_certs: Vec<CertificateDer<'static>>,
+
+    /// Sources of more certificates to trust; see [`crate::tls::load_cert_sources`].
+    pub server_cert_sources: Vec<CertSource>,
+
+    /// Client certificate presented during the handshake; none is sent when `None`.
+    pub identity: Option<ClientIdentity>,
+
+    /// Token presented to the server in the Initial message.
+    pub token: Option<Bytes>,
+
+    /// Presents `token` as a fresh token signed with it, see [`crate::token`], for a server
+    /// rejecting replayed tokens, rather than as is.
+    pub sign_token: bool,
+
+    /// Time spent retrying the first connection to the server before giving up, so that the
+    /// client can be started before the server.
+    pub connect_timeout: Duration,
+
+    /// Delay between two attempts of the first connection.
+    pub connect_retry_interval: Duration,
+
+    /// Interval between two `Ping` messages measuring the round-trip time.
+    pub ping_interval: Duration,
+
+    /// Time to wait for the `Pong`; the connection is considered unhealthy when it doesn't
+    /// arrive.
+    pub ping_timeout: Duration,
+
+    /// Number of pongs in a row that may not arrive in time before the connection is considered
+    /// dead and re-established.
+    pub max_missed_pongs: u32,
+
+    /// Time a tunnel closed locally waits for the server to acknowledge the `Close` before it is
+    /// removed anyway.
+    pub close_timeout: Duration,
+
+    /// Time [`Client::request`](crate::Client::request) waits for the whole response.
+    pub request_timeout: Duration,
+
+    /// Size of the reads from the local sockets, and so of the largest `Data` payload sent; it
+    /// must fit in the `max_frame_size` of the server, along with the header.
+    pub chunk_size: usize,
+
+    /// Largest read from the stream shared with the server. Frames are split across reads when
+    /// longer, so it defaults to the default `max_frame_size` of the server.
+    pub recv_chunk_size: usize,
+
+    /// Largest number of `Data` messages of a connection sent ahead of their `Ack`; the tunnels
+    /// wait for the server to acknowledge some of them before sending more. The server has to
+    /// acknowledge them, with its `auto_ack`, otherwise the tunnels stall once the window is full.
+    /// Unlimited when `None`.
+    pub max_in_flight: Option<usize>,
+
+    /// Stamps the `Data` messages with the time they are sent, for the server to measure their
+    /// transit time. Older servers can't decode timestamped messages.
+    pub timestamps: bool,
+
+    pub backoff: Backoff,
+
+    /// Congestion control algorithm of the connection.
+    pub congestion_controller: CongestionController,
+
+    /// Accepts any server certificate, ignoring `server_certs`. Development only: never enable
+    /// it against a server reachable by others.
+    #[cfg(feature = "insecure")]
+    pub insecure: bool,
+
+    /// Connects over TCP+TLS, to the TCP port of `server_addr`, when the QUIC connection fails or
+    /// isn't established within this time, e.g. because the network blocks UDP. Disabled when
+    /// `None`.
+    #[cfg(feature = "tcp-fallback")]
+    pub tcp_fallback: Option<Duration>,
+}
+
+impl ClientConfig {
+    pub fn new(server_addr: SocketAddr, proxy_addr: SocketAddr) -> ClientConfig {
+        ClientConfig {
+            server_addr,
+            server_name: "localhost".to_string(),
+            bind_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+            socket_send_buffer: None,
+            socket_recv_buffer: None,
+            alpn: DEFAULT_ALPN.to_vec(),
+            proxy_addr,
+            original_dst: false,
+            server_certs: Vec::new(),
+            server_cert_sources: Vec::new(),
+            identity: None,
+            token: None,
+            sign_token: false,
+            connect_timeout: Duration::from_secs(30),
+            connect_retry_interval: Duration::from_secs(1),
+            ping_interval: Duration::from_secs(10),
+            ping_timeout: Duration::from_secs(5),
+            max_missed_pongs: 3,
+            close_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(30),
+            chunk_size: CHUNK_SIZE,
+            recv_chunk_size: 64 * 1024 + MAX_HEADER_LENGTH,
+            max_in_flight: None,
+            timestamps: false,
+            backoff: Backoff::default(),
+            congestion_controller: CongestionController::default(),
+            #[cfg(feature = "insecure")]
+            insecure: false,
+            #[cfg(feature = "tcp-fallback")]
+            tcp_fallback: None,
+        }
+    }
+}
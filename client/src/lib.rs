@@ -0,0 +1,582 @@
+// Client side of the tunnel.
+//
+// The client opens a QUIC connection to the server and keeps it alive. When the connection is lost,
+// it reconnects using exponential backoff.
+//
+// Every local TCP connection accepted by `Client::listen_local` gets its own tunnel, identified by
+// a fresh `connection_id`: the `Initial` message describing the target the server has to proxy to,
+// followed by the `Data` messages relayed in both directions. All the tunnels of a QUIC connection
+// are multiplexed over a single bidirectional stream and demultiplexed by their `connection_id`.
+// With the `tcp-fallback` feature, the stream is carried over TCP+TLS when QUIC can't connect.
+// `Client::listen_socks5` runs a local SOCKS5 proxy instead, tunneling each connection to the
+// target it requests.
+use std::{
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use message::{CloseReason, InitializationMessage, ProxyTarget};
+use quinn::{Endpoint, EndpointConfig, TokioRuntime};
+use session::{Link, Session};
+use socket2::{Domain, Protocol, Socket, Type};
+use spdlog::prelude::{error, info, warn};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, watch},
+    time::{Instant, sleep, timeout},
+};
+use uuid::Uuid;
+
+pub mod config;
+pub mod original_dst;
+mod session;
+pub mod socks5;
+#[cfg(feature = "tcp-fallback")]
+pub mod tcp;
+pub mod tls;
+pub mod token;
+pub mod transport;
+mod tunnel;
+
+pub use config::{Backoff, CertSource, ClientConfig, ClientIdentity, CongestionController};
+pub use session::{Health, Rtt};
+
+/// Lifecycle events emitted by the [`Client`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientEvent {
+    /// Connection to the server is established.
+    Connected,
+
+    /// Connection to the server is lost.
+    Disconnected,
+
+    /// Connection is re-established after the given number of attempts.
+    Reconnected { attempts: u32 },
+
+    /// Reconnection failed permanently after the given number of attempts.
+    ReconnectFailed { attempts: u32 },
+
+    /// Server closed the tunnel `connection_id` for another reason than
+    /// [`CloseReason::Normal`], e.g. [`CloseReason::BackendRefused`] when nothing listens on the
+    /// target.
+    TunnelRejected {
+        connection_id: Uuid,
+        reason: CloseReason,
+    },
+
+    /// Server acknowledged the message `message_id` of the tunnel `connection_id`.
+    Acked {
+        connection_id: Uuid,
+        message_id: Uuid,
+    },
+}
+
+/// Builds the QUIC client config: the TLS config of [`client_tls_config`] along with the transport
+/// parameters.
+fn quic_client_config(
+    config: &ClientConfig,
+    tls_config: rustls::ClientConfig,
+) -> io::Result<quinn::ClientConfig> {
+    let mut client_config = tls::quic_config(tls_config)?;
+    client_config.transport_config(Arc::new(tls::transport_config(
+        config.congestion_controller,
+    )));
+
+    Ok(client_config)
+}
+
+/// Builds the TLS client config offering the ALPN protocol of the config.
+fn client_tls_config(config: &ClientConfig) -> io::Result<rustls::ClientConfig> {
+    let mut tls_config = verifying_tls_config(config)?;
+    tls_config.alpn_protocols = vec![config.alpn.clone()];
+
+    Ok(tls_config)
+}
+
+/// TLS client config verifying the server certificate, unless in insecure mode.
+fn verifying_tls_config(config: &ClientConfig) -> io::Result<rustls::ClientConfig> {
+    #[cfg(feature = "insecure")]
+    if config.insecure {
+        warn!(
+            "[client] INSECURE MODE: the server certificate is NOT verified, anyone can impersonate the server. Never use it outside of local development"
+        );
+        return tls::tls_config_insecure(config.identity.as_ref());
+    }
+
+    let mut server_certs = config.server_certs.clone();
+    if !config.server_cert_sources.is_empty() {
+        server_certs.extend(tls::load_cert_sources(&config.server_cert_sources)?);
+    }
+
+    tls::tls_config(&server_certs, config.identity.as_ref())
+}
+
+/// Binds the UDP socket of the endpoint to the `bind_addr` of the config, dual-stack like the
+/// sockets of [`Endpoint::client`], with the buffer sizes of the config, if any; the OS may clamp
+/// them.
+fn bind_socket(config: &ClientConfig) -> io::Result<std::net::UdpSocket> {
+    let addr = config.bind_addr;
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if addr.is_ipv6() {
+        if let Err(e) = socket.set_only_v6(false) {
+            warn!("[client] failed to make the socket dual-stack: {e}");
+        }
+    }
+    if let Some(size) = config.socket_send_buffer {
+        socket.set_send_buffer_size(size)?;
+        info!(
+            "[client] UDP send buffer: requested={size} effective={}",
+            socket.send_buffer_size()?
+        );
+    }
+    if let Some(size) = config.socket_recv_buffer {
+        socket.set_recv_buffer_size(size)?;
+        info!(
+            "[client] UDP receive buffer: requested={size} effective={}",
+            socket.recv_buffer_size()?
+        );
+    }
+    socket.bind(&addr.into())?;
+
+    Ok(socket.into())
+}
+
+/// Longest time a single attempt of the initial connection may take.
+const CONNECT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest time a SOCKS5 client may take to send its request.
+const SOCKS5_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Capacity of the [`ClientEvent`] channel; slow subscribers miss the oldest events.
+const EVENTS_CAPACITY: usize = 64;
+
+pub struct Client {
+    config: ClientConfig,
+    endpoint: Endpoint,
+    events: broadcast::Sender<ClientEvent>,
+
+    /// TLS config of the TCP connections.
+    #[cfg(feature = "tcp-fallback")]
+    tls_config: Arc<rustls::ClientConfig>,
+
+    /// Session of the current connection; `None` while (re)connecting.
+    session: watch::Sender<Option<Arc<Session>>>,
+}
+
+impl Client {
+    pub fn new(config: ClientConfig) -> io::Result<Client> {
+        let tls_config = client_tls_config(&config)?;
+        let socket = bind_socket(&config)?;
+        let mut endpoint = Endpoint::new(
+            EndpointConfig::default(),
+            None,
+            socket,
+            Arc::new(TokioRuntime),
+        )?;
+        endpoint.set_default_client_config(quic_client_config(&config, tls_config.clone())?);
+
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+
+        Ok(Client {
+            config,
+            endpoint,
+            events,
+            #[cfg(feature = "tcp-fallback")]
+            tls_config: Arc::new(tls_config),
+            session: watch::Sender::new(None),
+        })
+    }
+
+    /// Round-trip times measured on the current connection; empty while (re)connecting.
+    pub fn rtt(&self) -> Rtt {
+        self.session
+            .borrow()
+            .as_ref()
+            .map(|session| session.rtt())
+            .unwrap_or_default()
+    }
+
+    /// Health of the current connection; [`Health::Dead`] while (re)connecting.
+    pub fn health(&self) -> Health {
+        self.session
+            .borrow()
+            .as_ref()
+            .map_or(Health::Dead, |session| session.health())
+    }
+
+    /// Whether the client is connected and the server answers the pings in time.
+    pub fn is_healthy(&self) -> bool {
+        self.health() == Health::Healthy
+    }
+
+    /// Number of tunnels open on the current connection.
+    pub fn open_tunnels(&self) -> usize {
+        self.session
+            .borrow()
+            .as_ref()
+            .map_or(0, |session| session.open_tunnels())
+    }
+
+    /// Subscribes to the [`ClientEvent`]s emitted from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.events.subscribe()
+    }
+
+    /// Connects to the server and keeps the tunnel alive, reconnecting when the connection is lost.
+    /// Returns an error once reconnection permanently fails.
+    pub async fn run(&self) -> io::Result<()> {
+        let mut session = self.connect_initial().await?;
+        self.emit(ClientEvent::Connected);
+
+        loop {
+            self.session.send_replace(Some(session.clone()));
+
+            let reason = session.link().closed().await;
+            warn!("[client] connection lost: {reason}");
+            self.session.send_replace(None);
+            self.emit(ClientEvent::Disconnected);
+
+            session = self.reconnect().await?;
+        }
+    }
+
+    /// Accepts local TCP connections on `bind_addr` and tunnels each of them to the server.
+    /// Has to run alongside [`Client::run`], which provides the QUIC connection.
+    pub async fn listen_local(&self, bind_addr: SocketAddr) -> io::Result<()> {
+        let listener = TcpListener::bind(bind_addr).await?;
+        info!("[client] listening on {}", listener.local_addr()?);
+
+        loop {
+            let (socket, peer_addr) = listener.accept().await?;
+            info!("[client] accepted local connection: addr={peer_addr}");
+
+            let session = self.current_session().await;
+            let proxy_addr = self.target(&socket);
+            let init = self.initialization_message(&session, proxy_addr)?;
+            let chunk_size = self.config.chunk_size;
+            let timestamps = self.config.timestamps;
+            let close_timeout = self.config.close_timeout;
+            let events = self.events.clone();
+
+            tokio::spawn(async move {
+                let tunnel = tunnel::run(
+                    session,
+                    init,
+                    socket,
+                    chunk_size,
+                    timestamps,
+                    close_timeout,
+                    events,
+                );
+                if let Err(e) = tunnel.await {
+                    warn!("[client] tunnel failed: {e:?}");
+                }
+            });
+        }
+    }
+
+    /// Runs a local SOCKS5 proxy on `bind_addr`, see [`socks5`]: each `CONNECT` gets a tunnel of
+    /// its own to the target it requests, rather than to the configured one. Has to run alongside
+    /// [`Client::run`], like [`Client::listen_local`].
+    pub async fn listen_socks5(&self, bind_addr: SocketAddr) -> io::Result<()> {
+        let listener = TcpListener::bind(bind_addr).await?;
+        info!(
+            "[client] SOCKS5 proxy listening on {}",
+            listener.local_addr()?
+        );
+
+        loop {
+            let (mut socket, peer_addr) = listener.accept().await?;
+            info!("[client] accepted SOCKS5 connection: addr={peer_addr}");
+
+            let session = self.current_session().await;
+            let local_addr = self.local_addr(&session)?;
+            let token = self.presented_token();
+            let chunk_size = self.config.chunk_size;
+            let timestamps = self.config.timestamps;
+            let close_timeout = self.config.close_timeout;
+            let events = self.events.clone();
+
+            tokio::spawn(async move {
+                let target =
+                    match timeout(SOCKS5_HANDSHAKE_TIMEOUT, socks5::accept(&mut socket)).await {
+                        Ok(Ok(target)) => target,
+                        Ok(Err(e)) => {
+                            warn!("[client] invalid SOCKS5 request: addr={peer_addr} err={e}");
+                            return;
+                        }
+                        Err(_) => {
+                            warn!("[client] SOCKS5 request not received in time: addr={peer_addr}");
+                            return;
+                        }
+                    };
+
+                let init = match target_initialization_message(local_addr, &target, token) {
+                    Ok(init) => init,
+                    Err(e) => {
+                        warn!("[client] can't tunnel to {target:?}: {e}");
+                        let _ = socks5::reply(&mut socket, socks5::REPLY_GENERAL_FAILURE).await;
+                        return;
+                    }
+                };
+                if let Err(e) = socks5::reply(&mut socket, socks5::REPLY_SUCCEEDED).await {
+                    warn!("[client] failed replying to the SOCKS5 request: {e}");
+                    return;
+                }
+
+                info!("[client] tunneling SOCKS5 connection: addr={peer_addr} target={target:?}");
+                let tunnel = tunnel::run(
+                    session,
+                    init,
+                    socket,
+                    chunk_size,
+                    timestamps,
+                    close_timeout,
+                    events,
+                );
+                if let Err(e) = tunnel.await {
+                    warn!("[client] tunnel failed: {e:?}");
+                }
+            });
+        }
+    }
+
+    /// Sends `payload` to the target of `init` over a tunnel of its own and returns the response,
+    /// read until the target closes the connection. Waits for the QUIC connection like
+    /// [`Client::listen_local`] does, then fails if the response isn't complete within
+    /// `request_timeout`.
+    pub async fn request(&self, init: InitializationMessage, payload: Bytes) -> io::Result<Bytes> {
+        let session = self.current_session().await;
+
+        tunnel::request(
+            session,
+            init,
+            payload,
+            self.config.chunk_size,
+            self.config.request_timeout,
+            self.config.close_timeout,
+            self.events.clone(),
+        )
+        .await
+    }
+
+    /// Tunnels the TCP connections of the local processes to `local_port` of the loopback
+    /// interface, like [`Client::listen_local`].
+    pub async fn forward_local(&self, local_port: u16) -> io::Result<()> {
+        self.listen_local(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), local_port))
+            .await
+    }
+
+    /// Waits until the QUIC connection is established.
+    async fn current_session(&self) -> Arc<Session> {
+        let mut rx = self.session.subscribe();
+        let session = rx
+            .wait_for(Option::is_some)
+            .await
+            .expect("Sender is owned by the client")
+            .clone();
+
+        session.expect("Checked by wait_for")
+    }
+
+    /// Opens the first connection, retrying every `connect_retry_interval` until `connect_timeout`
+    /// elapses, so that the client can be started before the server.
+    async fn connect_initial(&self) -> io::Result<Arc<Session>> {
+        let deadline = Instant::now() + self.config.connect_timeout;
+        let interval = self.config.connect_retry_interval;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let error = match timeout(remaining.min(CONNECT_ATTEMPT_TIMEOUT), self.connect()).await
+            {
+                Ok(Ok(session)) => return Ok(session),
+                // The server address or name is invalid, retrying won't help.
+                Ok(Err(e)) if e.kind() == ErrorKind::InvalidInput => return Err(e),
+                Ok(Err(e)) => e,
+                Err(_) => io::Error::new(ErrorKind::TimedOut, "Connection attempt timed out"),
+            };
+
+            if Instant::now() + interval >= deadline {
+                error!("[client] failed to connect after {attempt} attempts: {error}");
+                return Err(io::Error::new(
+                    ErrorKind::TimedOut,
+                    format!(
+                        "Failed to connect within {:?}: {error}",
+                        self.config.connect_timeout
+                    ),
+                ));
+            }
+
+            warn!(
+                "[client] connection attempt {attempt} failed: {error}, retrying in {interval:?}"
+            );
+            sleep(interval).await;
+        }
+    }
+
+    /// Retries the connection following the configured [`Backoff`].
+    async fn reconnect(&self) -> io::Result<Arc<Session>> {
+        let backoff = &self.config.backoff;
+        let mut attempt = 0;
+
+        loop {
+            if backoff.max_retries.is_some_and(|max| attempt >= max) {
+                error!("[client] giving up after {attempt} reconnection attempts");
+                self.emit(ClientEvent::ReconnectFailed { attempts: attempt });
+                return Err(io::Error::new(
+                    ErrorKind::NotConnected,
+                    "Reconnection attempts exhausted",
+                ));
+            }
+
+            let delay = backoff.delay(attempt);
+            attempt += 1;
+            info!("[client] reconnecting in {delay:?} (attempt {attempt})");
+            sleep(delay).await;
+
+            match self.connect().await {
+                Ok(session) => {
+                    info!("[client] reconnected after {attempt} attempts");
+                    self.emit(ClientEvent::Reconnected { attempts: attempt });
+                    return Ok(session);
+                }
+                Err(e) => warn!("[client] reconnection failed: {e:?}"),
+            }
+        }
+    }
+
+    /// Opens the connection and the stream shared by its tunnels, over TCP if the QUIC connection
+    /// can't be established and the fallback is enabled.
+    async fn connect(&self) -> io::Result<Arc<Session>> {
+        #[cfg(feature = "tcp-fallback")]
+        if let Some(fallback) = self.config.tcp_fallback {
+            match timeout(fallback, self.connect_quic()).await {
+                Ok(Ok(session)) => return Ok(session),
+                Ok(Err(e)) if e.kind() == ErrorKind::InvalidInput => return Err(e),
+                Ok(Err(e)) => warn!("[client] QUIC connection failed: {e}, falling back to TCP"),
+                Err(_) => warn!(
+                    "[client] QUIC connection not established within {fallback:?}, falling back to TCP"
+                ),
+            }
+            return self.connect_tcp().await;
+        }
+
+        self.connect_quic().await
+    }
+
+    async fn connect_quic(&self) -> io::Result<Arc<Session>> {
+        let connection = self
+            .endpoint
+            .connect(self.config.server_addr, &self.config.server_name)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?
+            .await
+            .map_err(|e| io::Error::new(ErrorKind::ConnectionRefused, e))?;
+
+        info!("[client] connected: addr={}", connection.remote_address());
+
+        Session::open(connection, &self.config).await
+    }
+
+    #[cfg(feature = "tcp-fallback")]
+    async fn connect_tcp(&self) -> io::Result<Arc<Session>> {
+        let stream = tcp::connect(
+            self.config.server_addr,
+            &self.config.server_name,
+            self.tls_config.clone(),
+        )
+        .await?;
+
+        info!(
+            "[client] connected over TCP: addr={}",
+            self.config.server_addr
+        );
+
+        Ok(Session::open_tcp(stream, &self.config))
+    }
+
+    /// Target of a local connection: the configured one, or its original destination when it was
+    /// redirected to the listener and `original_dst` is set.
+    fn target(&self, socket: &TcpStream) -> SocketAddr {
+        if !self.config.original_dst {
+            return self.config.proxy_addr;
+        }
+
+        match original_dst::original_dst(socket) {
+            Ok(Some(original)) => original,
+            Ok(None) => self.config.proxy_addr,
+            Err(e) => {
+                warn!("[client] failed to read the original destination: {e}");
+                self.config.proxy_addr
+            }
+        }
+    }
+
+    fn initialization_message(
+        &self,
+        session: &Session,
+        proxy_addr: SocketAddr,
+    ) -> io::Result<InitializationMessage> {
+        let init = InitializationMessage::new(self.local_addr(session)?, proxy_addr)?;
+
+        match self.presented_token() {
+            Some(token) => init.with_token(token),
+            None => Ok(init),
+        }
+    }
+
+    /// Address the server sees the client connect from, described in the `Initial` messages.
+    fn local_addr(&self, session: &Session) -> io::Result<SocketAddr> {
+        let local_addr = match session.link() {
+            Link::Quic(connection) => SocketAddr::new(
+                connection
+                    .local_ip()
+                    .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+                self.endpoint.local_addr()?.port(),
+            ),
+            #[cfg(feature = "tcp-fallback")]
+            Link::Tcp(link) => link.local_addr(),
+        };
+
+        Ok(local_addr)
+    }
+
+    /// Token presented in the next `Initial` message: a freshly signed one when `sign_token` is
+    /// set.
+    fn presented_token(&self) -> Option<Bytes> {
+        match &self.config.token {
+            Some(secret) if self.config.sign_token => Some(token::sign(secret)),
+            Some(token) => Some(token.clone()),
+            None => None,
+        }
+    }
+
+    fn emit(&self, event: ClientEvent) {
+        // Sending only fails when there are no subscribers.
+        let _ = self.events.send(event);
+    }
+}
+
+/// `Initial` of a tunnel to `target`, presenting `token`.
+fn target_initialization_message(
+    local_addr: SocketAddr,
+    target: &ProxyTarget,
+    token: Option<Bytes>,
+) -> io::Result<InitializationMessage> {
+    let init = match target {
+        ProxyTarget::Addr(addr) => InitializationMessage::new(local_addr, *addr)?,
+        ProxyTarget::Host(host, port) => {
+            InitializationMessage::with_hostname(local_addr, host, *port)?
+        }
+    };
+
+    match token {
+        Some(token) => init.with_token(token),
+        None => Ok(init),
+    }
+}
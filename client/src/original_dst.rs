@@ -0,0 +1,42 @@
+// Original destination of the local connections redirected to the listener by iptables, for
+// transparent proxying.
+use std::{io, net::SocketAddr};
+
+use tokio::net::TcpStream;
+
+/// Destination the peer of `socket` connected to before iptables redirected the connection to
+/// the local listener, read with `SO_ORIGINAL_DST`; `None` when it wasn't redirected.
+#[cfg(target_os = "linux")]
+pub fn original_dst(socket: &TcpStream) -> io::Result<Option<SocketAddr>> {
+    use std::io::ErrorKind;
+
+    use socket2::SockRef;
+
+    let local_addr = socket.local_addr()?;
+    let sock = SockRef::from(socket);
+    let original = match local_addr {
+        SocketAddr::V4(_) => sock.original_dst_v4(),
+        SocketAddr::V6(_) => sock.original_dst_v6(),
+    };
+
+    // Connections without a NAT entry have no original destination.
+    let original = match original {
+        Ok(original) => original,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let original = original.as_socket().ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            "Original destination is not an IP address",
+        )
+    })?;
+
+    Ok((original != local_addr).then_some(original))
+}
+
+/// Connections are never redirected on other platforms than Linux.
+#[cfg(not(target_os = "linux"))]
+pub fn original_dst(_socket: &TcpStream) -> io::Result<Option<SocketAddr>> {
+    Ok(None)
+}
@@ -0,0 +1,413 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    sync::{
+        Arc, Mutex as StdMutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use message::{Message, MessageType};
+use quinn::{Connection, SendStream, VarInt};
+use spdlog::prelude::{debug, error, info, warn};
+use tokio::{
+    sync::{Mutex, Notify, Semaphore, mpsc},
+    time::{sleep, timeout},
+};
+use uuid::Uuid;
+
+#[cfg(feature = "tcp-fallback")]
+use crate::tcp::{TcpLink, TcpSink, TcpSource};
+use crate::{
+    config::ClientConfig,
+    transport::{self, AsyncFrameSink, AsyncFrameSource, MessageReader},
+};
+
+/// Number of messages buffered per tunnel before the stream reader waits for the tunnel to catch up.
+const TUNNEL_CHANNEL_CAPACITY: usize = 64;
+
+/// Round-trip times measured by the pings of a session.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rtt {
+    /// Last measured round-trip time.
+    pub latest: Option<Duration>,
+
+    /// Exponentially weighted moving average of the round-trip times, giving 1/8 of the weight
+    /// to the last one.
+    pub average: Option<Duration>,
+}
+
+impl Rtt {
+    fn record(&mut self, sample: Duration) {
+        self.latest = Some(sample);
+        self.average = Some(match self.average {
+            Some(average) => average.mul_f64(7.0 / 8.0) + sample / 8,
+            None => sample,
+        });
+    }
+}
+
+/// Health of a session, according to the pongs of its pings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    /// Last ping was answered in time.
+    Healthy,
+
+    /// Last pings weren't answered in time, though fewer than the missed pongs tolerated.
+    Suspect,
+
+    /// Too many pongs were missed, or the connection is lost; the connection is re-established.
+    Dead,
+}
+
+/// Connection to the server a session runs over.
+pub enum Link {
+    Quic(Connection),
+    #[cfg(feature = "tcp-fallback")]
+    Tcp(TcpLink),
+}
+
+impl Link {
+    /// Waits until the connection is lost, returning why.
+    pub async fn closed(&self) -> io::Error {
+        match self {
+            Link::Quic(connection) => connection.closed().await.into(),
+            #[cfg(feature = "tcp-fallback")]
+            Link::Tcp(link) => {
+                link.closed().await;
+                io::Error::new(ErrorKind::ConnectionAborted, "TCP connection closed")
+            }
+        }
+    }
+
+    fn close(&self, reason: &[u8]) {
+        match self {
+            Link::Quic(connection) => connection.close(VarInt::from_u32(0), reason),
+            #[cfg(feature = "tcp-fallback")]
+            Link::Tcp(link) => link.close(),
+        }
+    }
+}
+
+/// Sending half of the stream of a [`Link`].
+enum Sink {
+    Quic(SendStream),
+    #[cfg(feature = "tcp-fallback")]
+    Tcp(TcpSink),
+}
+
+impl AsyncFrameSink for Sink {
+    async fn send_chunk(&mut self, chunk: Bytes) -> io::Result<()> {
+        match self {
+            Sink::Quic(send) => send.send_chunk(chunk).await,
+            #[cfg(feature = "tcp-fallback")]
+            Sink::Tcp(send) => send.send_chunk(chunk).await,
+        }
+    }
+}
+
+/// `Data` messages of a session sent but not acknowledged yet.
+struct Window {
+    /// One permit per message that may still be sent before an `Ack` arrives.
+    credits: Semaphore,
+
+    /// IDs of the messages waiting for their `Ack`, along with the tunnel they belong to.
+    in_flight: StdMutex<HashMap<Uuid, Uuid>>,
+}
+
+impl Window {
+    fn new(size: usize) -> Window {
+        Window {
+            credits: Semaphore::new(size),
+            in_flight: StdMutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits for room for the message `message_id` of the tunnel `connection_id`; fails once the
+    /// session is closed.
+    async fn acquire(&self, message_id: Uuid, connection_id: Uuid) -> io::Result<()> {
+        let credit = self
+            .credits
+            .acquire()
+            .await
+            .map_err(|_| io::Error::new(ErrorKind::ConnectionAborted, "Session was closed"))?;
+        credit.forget();
+        self.in_flight
+            .lock()
+            .unwrap()
+            .insert(message_id, connection_id);
+        Ok(())
+    }
+
+    /// Makes room for another message if `message_id` was in flight.
+    fn release(&self, message_id: Uuid) {
+        if self.in_flight.lock().unwrap().remove(&message_id).is_some() {
+            self.credits.add_permits(1);
+        }
+    }
+
+    /// Makes room for the messages of a tunnel that is gone: the server doesn't acknowledge the
+    /// ones it didn't deliver.
+    fn release_tunnel(&self, connection_id: Uuid) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let before = in_flight.len();
+        in_flight.retain(|_, tunnel| *tunnel != connection_id);
+        self.credits.add_permits(before - in_flight.len());
+    }
+}
+
+/// A single bidirectional stream shared by all the tunnels of a connection.
+/// Messages are routed to the tunnels by their `connection_id`.
+pub struct Session {
+    link: Link,
+    send: Mutex<Sink>,
+    tunnels: StdMutex<HashMap<Uuid, mpsc::Sender<Message>>>,
+
+    /// Reference the timestamps carried by the pings are relative to.
+    epoch: Instant,
+    rtt: StdMutex<Rtt>,
+
+    /// ID of the ping waiting for its pong.
+    pending_ping: StdMutex<Option<Uuid>>,
+    pong: Notify,
+
+    health: StdMutex<Health>,
+
+    /// Number of consecutive pings whose pong didn't arrive in time.
+    missed_pongs: AtomicU32,
+
+    /// Limits the `Data` messages in flight; unlimited when `None`.
+    window: Option<Window>,
+}
+
+impl Session {
+    /// Opens the shared stream, starts routing the incoming messages, read `recv_chunk_size`
+    /// bytes at most at a time, and pinging the server every `ping_interval`. The session is
+    /// [`Health::Suspect`] while pongs don't arrive within `ping_timeout`, and the connection is
+    /// closed after `max_missed_pongs` of them. At most `max_in_flight` `Data` messages are sent
+    /// ahead of their `Ack`.
+    pub async fn open(connection: Connection, config: &ClientConfig) -> io::Result<Arc<Session>> {
+        let (send, recv) = connection.open_bi().await?;
+
+        Ok(Session::start(
+            Link::Quic(connection),
+            Sink::Quic(send),
+            recv,
+            config,
+        ))
+    }
+
+    /// Runs the session over the stream of a TCP connection, like [`Session::open`].
+    #[cfg(feature = "tcp-fallback")]
+    pub fn open_tcp(
+        (link, send, recv): (TcpLink, TcpSink, TcpSource),
+        config: &ClientConfig,
+    ) -> Arc<Session> {
+        Session::start(Link::Tcp(link), Sink::Tcp(send), recv, config)
+    }
+
+    fn start(
+        link: Link,
+        send: Sink,
+        recv: impl AsyncFrameSource + 'static,
+        config: &ClientConfig,
+    ) -> Arc<Session> {
+        let session = Arc::new(Session {
+            link,
+            send: Mutex::new(send),
+            tunnels: StdMutex::new(HashMap::new()),
+            epoch: Instant::now(),
+            rtt: StdMutex::new(Rtt::default()),
+            pending_ping: StdMutex::new(None),
+            pong: Notify::new(),
+            health: StdMutex::new(Health::Healthy),
+            missed_pongs: AtomicU32::new(0),
+            window: config.max_in_flight.map(Window::new),
+        });
+
+        tokio::spawn(demux(recv, session.clone(), config.recv_chunk_size));
+        tokio::spawn(ping(
+            session.clone(),
+            config.ping_interval,
+            config.ping_timeout,
+            config.max_missed_pongs,
+        ));
+
+        session
+    }
+
+    pub fn link(&self) -> &Link {
+        &self.link
+    }
+
+    pub fn rtt(&self) -> Rtt {
+        *self.rtt.lock().unwrap()
+    }
+
+    pub fn health(&self) -> Health {
+        *self.health.lock().unwrap()
+    }
+
+    fn set_health(&self, health: Health) {
+        let mut current = self.health.lock().unwrap();
+        if *current != health {
+            debug!("[client] health: {:?} -> {health:?}", *current);
+            *current = health;
+        }
+    }
+
+    /// Records the round-trip time of the pending ping the pong answers.
+    fn handle_pong(&self, msg: &Message) {
+        let mut pending = self.pending_ping.lock().unwrap();
+        if pending.is_none() || *pending != msg.in_reply_to {
+            debug!("[client] unexpected pong: {:?}", msg);
+            return;
+        }
+        *pending = None;
+
+        let Some((sent, _)) = msg.ping_deadline() else {
+            warn!("[client] invalid pong payload: {:?}", msg.payload);
+            return;
+        };
+        let rtt = self
+            .epoch
+            .elapsed()
+            .saturating_sub(Duration::from_micros(sent));
+
+        debug!("[client] rtt: {rtt:?}");
+        self.rtt.lock().unwrap().record(rtt);
+        self.missed_pongs.store(0, Ordering::Relaxed);
+        self.set_health(Health::Healthy);
+        self.pong.notify_one();
+    }
+
+    /// Registers a tunnel; messages addressed to `connection_id` are delivered to the returned receiver.
+    pub fn register(&self, connection_id: Uuid) -> mpsc::Receiver<Message> {
+        let (tx, rx) = mpsc::channel(TUNNEL_CHANNEL_CAPACITY);
+        self.tunnels.lock().unwrap().insert(connection_id, tx);
+        rx
+    }
+
+    /// Unregisters a tunnel, giving back the room its unacknowledged messages took in the window.
+    pub fn unregister(&self, connection_id: Uuid) {
+        self.tunnels.lock().unwrap().remove(&connection_id);
+        if let Some(window) = &self.window {
+            window.release_tunnel(connection_id);
+        }
+    }
+
+    pub fn open_tunnels(&self) -> usize {
+        self.tunnels.lock().unwrap().len()
+    }
+
+    /// Sends the message, first waiting for room in the window if it is a `Data` one.
+    pub async fn send(&self, msg: &Message) -> io::Result<()> {
+        let window = match (&msg.message_type, &self.window) {
+            (MessageType::Data, Some(window)) => Some(window),
+            _ => None,
+        };
+        if let Some(window) = window {
+            window.acquire(msg.message_id, msg.connection_id).await?;
+        }
+
+        let mut send = self.send.lock().await;
+        let sent = transport::send_message(&mut *send, msg).await;
+        // A message that wasn't sent won't be acknowledged.
+        if let (Err(_), Some(window)) = (&sent, window) {
+            window.release(msg.message_id);
+        }
+        sent
+    }
+}
+
+/// Reads the shared stream and forwards each message to the tunnel it is addressed to.
+/// Dropping the tunnel senders when the stream ends closes all of the tunnels.
+async fn demux(recv: impl AsyncFrameSource, session: Arc<Session>, recv_chunk_size: usize) {
+    let mut reader = MessageReader::new(recv, recv_chunk_size);
+
+    loop {
+        let msg = match reader.next_message().await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => break,
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                error!("[client] failed to decode message: {e:?}");
+                break;
+            }
+            Err(e) => {
+                info!("[client] error reading: {e:?}");
+                break;
+            }
+        };
+
+        if let MessageType::Pong = msg.message_type {
+            session.handle_pong(&msg);
+            continue;
+        }
+        if let (MessageType::Ack, Some(window), Some(message_id)) =
+            (&msg.message_type, &session.window, msg.in_reply_to)
+        {
+            window.release(message_id);
+        }
+
+        let tunnel = session
+            .tunnels
+            .lock()
+            .unwrap()
+            .get(&msg.connection_id)
+            .cloned();
+
+        match tunnel {
+            Some(tx) => {
+                // The tunnel is gone when its receiver is dropped.
+                let _ = tx.send(msg).await;
+            }
+            None => debug!("[client] message for unknown tunnel: {:?}", msg),
+        }
+    }
+
+    session.tunnels.lock().unwrap().clear();
+    // Wakes up the tunnels waiting for room.
+    if let Some(window) = &session.window {
+        window.credits.close();
+    }
+}
+
+/// Sends a [`Message::ping_with_deadline`] every `interval`, and closes the connection once
+/// `max_missed` pongs in a row don't arrive within `deadline`. The first ping is sent right away,
+/// completing the handshake of the server before any tunnel is opened.
+async fn ping(session: Arc<Session>, interval: Duration, deadline: Duration, max_missed: u32) {
+    loop {
+        let sent = session.epoch.elapsed().as_micros() as u64;
+        let ping = Message::ping_with_deadline(sent, deadline);
+        *session.pending_ping.lock().unwrap() = Some(ping.message_id);
+
+        if let Err(e) = session.send(&ping).await {
+            debug!("[client] failed to send ping: {e:?}");
+            session.set_health(Health::Dead);
+            return;
+        }
+
+        if timeout(deadline, session.pong.notified()).await.is_err() {
+            let missed = session.missed_pongs.fetch_add(1, Ordering::Relaxed) + 1;
+            if missed >= max_missed {
+                warn!("[client] {missed} pongs missed, reconnecting");
+                session.set_health(Health::Dead);
+                session.link.close(b"ping timeout");
+                return;
+            }
+
+            warn!("[client] no pong received within {deadline:?} ({missed}/{max_missed})");
+            session.set_health(Health::Suspect);
+        }
+
+        tokio::select! {
+            _ = sleep(interval) => {}
+            _ = session.link.closed() => {
+                session.set_health(Health::Dead);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+// Local SOCKS5 proxy (RFC 1928), tunneling each `CONNECT` to the target it requests.
+//
+// Only the "no authentication" method and the `CONNECT` command are supported, to IPv4 addresses
+// and domain names; the server resolves the latter. The success reply is sent as soon as the
+// request is parsed, before the server connected to the target: a target the server can't reach
+// shows as a connection closed right away.
+use std::{
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+};
+
+use message::ProxyTarget;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub const VERSION: u8 = 0x05;
+
+/// Method of the clients not authenticating, the only one supported.
+pub const NO_AUTHENTICATION: u8 = 0x00;
+
+/// Method replied when none of the ones offered is supported.
+pub const NO_ACCEPTABLE_METHODS: u8 = 0xFF;
+
+pub const CMD_CONNECT: u8 = 0x01;
+
+pub const ATYP_IPV4: u8 = 0x01;
+pub const ATYP_DOMAIN: u8 = 0x03;
+pub const ATYP_IPV6: u8 = 0x04;
+
+pub const REPLY_SUCCEEDED: u8 = 0x00;
+pub const REPLY_GENERAL_FAILURE: u8 = 0x01;
+pub const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+pub const REPLY_ADDRESS_TYPE_NOT_SUPPORTED: u8 = 0x08;
+
+/// Negotiates the method with the SOCKS client and reads its `CONNECT` request; returns the
+/// target requested. Requests that can't be served are replied to with their error before
+/// failing.
+pub async fn accept<S>(socket: &mut S) -> io::Result<ProxyTarget>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let [version, methods] = read_array(socket).await?;
+    check_version(version)?;
+    let mut offered = vec![0; methods as usize];
+    socket.read_exact(&mut offered).await?;
+
+    if !offered.contains(&NO_AUTHENTICATION) {
+        socket.write_all(&[VERSION, NO_ACCEPTABLE_METHODS]).await?;
+        return Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "SOCKS client requires authentication",
+        ));
+    }
+    socket.write_all(&[VERSION, NO_AUTHENTICATION]).await?;
+
+    let [version, command, _reserved, address_type] = read_array(socket).await?;
+    check_version(version)?;
+
+    let target = match address_type {
+        ATYP_IPV4 => {
+            let [a, b, c, d, port @ ..] = read_array::<_, 6>(socket).await?;
+            let ip = IpAddr::V4(Ipv4Addr::new(a, b, c, d));
+            ProxyTarget::Addr(SocketAddr::new(ip, u16::from_be_bytes(port)))
+        }
+        ATYP_DOMAIN => {
+            let [len] = read_array(socket).await?;
+            let mut domain = vec![0; len as usize];
+            socket.read_exact(&mut domain).await?;
+            let port = u16::from_be_bytes(read_array(socket).await?);
+
+            match String::from_utf8(domain) {
+                Ok(domain) if !domain.is_empty() => ProxyTarget::Host(domain, port),
+                _ => {
+                    reply(socket, REPLY_GENERAL_FAILURE).await?;
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "Invalid SOCKS domain name",
+                    ));
+                }
+            }
+        }
+        ATYP_IPV6 => {
+            reply(socket, REPLY_ADDRESS_TYPE_NOT_SUPPORTED).await?;
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "IPv6 SOCKS targets are not supported",
+            ));
+        }
+        _ => {
+            reply(socket, REPLY_ADDRESS_TYPE_NOT_SUPPORTED).await?;
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                format!("Unsupported SOCKS address type: {address_type:#04x}"),
+            ));
+        }
+    };
+
+    if command != CMD_CONNECT {
+        reply(socket, REPLY_COMMAND_NOT_SUPPORTED).await?;
+        return Err(io::Error::new(
+            ErrorKind::Unsupported,
+            format!("Unsupported SOCKS command: {command:#04x}"),
+        ));
+    }
+
+    Ok(target)
+}
+
+/// Replies to the request with `status`; the bound address isn't known, so it's left
+/// unspecified.
+pub async fn reply<S>(socket: &mut S, status: u8) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    socket
+        .write_all(&[VERSION, status, 0x00, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+        .await
+}
+
+fn check_version(version: u8) -> io::Result<()> {
+    if version != VERSION {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Unsupported SOCKS version: {version}"),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn read_array<S, const N: usize>(socket: &mut S) -> io::Result<[u8; N]>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buf = [0; N];
+    socket.read_exact(&mut buf).await?;
+    Ok(buf)
+}
@@ -0,0 +1,128 @@
+// TCP+TLS transport, for the networks blocking the UDP traffic of QUIC.
+//
+// The connection carries a single stream of the same frames as the QUIC stream; frames are
+// delimited by the length of their header.
+use std::{
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use bytes::{Bytes, BytesMut};
+use rustls::pki_types::ServerName;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
+    net::TcpStream,
+    sync::watch,
+};
+use tokio_rustls::{TlsConnector, client::TlsStream};
+
+use crate::transport::{AsyncFrameSink, AsyncFrameSource};
+
+/// Connects to the server and splits the connection into the halves of its stream.
+pub async fn connect(
+    server_addr: SocketAddr,
+    server_name: &str,
+    tls_config: Arc<rustls::ClientConfig>,
+) -> io::Result<(TcpLink, TcpSink, TcpSource)> {
+    let server_name = ServerName::try_from(server_name.to_string())
+        .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+
+    let socket = TcpStream::connect(server_addr).await?;
+    socket.set_nodelay(true)?;
+    let local_addr = socket.local_addr()?;
+    let stream = TlsConnector::from(tls_config)
+        .connect(server_name, socket)
+        .await?;
+
+    let (closed, _) = watch::channel(false);
+    let link = TcpLink {
+        local_addr,
+        closed: Arc::new(closed),
+    };
+    let (read, write) = tokio::io::split(stream);
+
+    let sink = TcpSink {
+        write,
+        closed: link.closed.subscribe(),
+    };
+    let source = TcpSource {
+        read,
+        closed: link.closed.clone(),
+    };
+    Ok((link, sink, source))
+}
+
+/// TCP connection to the server; it is closed once either side ends the stream.
+#[derive(Clone)]
+pub struct TcpLink {
+    local_addr: SocketAddr,
+    closed: Arc<watch::Sender<bool>>,
+}
+
+impl TcpLink {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Aborts both halves of the stream.
+    pub fn close(&self) {
+        self.closed.send_replace(true);
+    }
+
+    /// Waits until the connection is closed.
+    pub async fn closed(&self) {
+        let mut closed = self.closed.subscribe();
+        let _ = closed.wait_for(|closed| *closed).await;
+    }
+}
+
+fn aborted() -> io::Error {
+    io::Error::new(ErrorKind::ConnectionAborted, "Connection was closed")
+}
+
+pub struct TcpSource {
+    read: ReadHalf<TlsStream<TcpStream>>,
+
+    /// Set when the server ends the stream too.
+    closed: Arc<watch::Sender<bool>>,
+}
+
+impl TcpSource {
+    async fn read(&mut self, max_length: usize) -> io::Result<Option<Bytes>> {
+        let mut chunk = BytesMut::zeroed(max_length);
+        let mut closed = self.closed.subscribe();
+        let read = tokio::select! {
+            read = self.read.read(&mut chunk) => read?,
+            _ = closed.wait_for(|closed| *closed) => return Err(aborted()),
+        };
+
+        chunk.truncate(read);
+        Ok((read > 0).then(|| chunk.freeze()))
+    }
+}
+
+impl AsyncFrameSource for TcpSource {
+    async fn recv_chunk(&mut self, max_length: usize) -> io::Result<Option<Bytes>> {
+        let chunk = self.read(max_length).await;
+        if !matches!(chunk, Ok(Some(_))) {
+            self.closed.send_replace(true);
+        }
+        chunk
+    }
+}
+
+pub struct TcpSink {
+    write: WriteHalf<TlsStream<TcpStream>>,
+    closed: watch::Receiver<bool>,
+}
+
+impl AsyncFrameSink for TcpSink {
+    async fn send_chunk(&mut self, chunk: Bytes) -> io::Result<()> {
+        if *self.closed.borrow() {
+            return Err(aborted());
+        }
+
+        self.write.write_all(&chunk).await
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    env, fs,
+    io::{self, ErrorKind, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use message::DEFAULT_ALPN;
+use quinn::{
+    ClientConfig, TransportConfig,
+    congestion::{BbrConfig, CubicConfig, NewRenoConfig},
+    crypto::rustls::QuicClientConfig,
+};
+use rustls::{
+    ConfigBuilder, WantsVerifier,
+    client::WantsClientCert,
+    crypto::ring,
+    pki_types::{CertificateDer, pem::PemObject},
+    version::TLS13,
+};
+use spdlog::prelude::warn;
+
+use crate::config::{CertSource, ClientIdentity, CongestionController};
+
+/// Interval of the QUIC keep-alive packets; keeps the long-lasting connection from idling out.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Name of the environment variable holding the path of the server certificate.
+pub const CERT_PATH_ENV: &str = "REVERPROX_CERT_PATH";
+
+/// Location the server writes its certificate to by default, in the per-user data directory of
+/// the platform: `$XDG_DATA_HOME` or `~/.local/share` on Linux, `~/Library/Application Support`
+/// on macOS and `%APPDATA%` on Windows. Falls back to the working directory.
+pub fn default_server_cert_path() -> PathBuf {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+
+    let data_dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| home().map(|home| home.join(".local").join("share")))
+    };
+
+    match data_dir {
+        Some(dir) => dir.join("reverprox").join("server_cert.pem"),
+        None => PathBuf::from("server_cert.pem"),
+    }
+}
+
+/// Extensions of the files loaded from a certificate directory.
+const CERT_EXTENSIONS: [&str; 2] = ["pem", "crt"];
+
+/// Loads the trusted server certificates from the given PEM files. A directory contributes every
+/// `.pem` and `.crt` file it contains.
+///
+/// Files that can't be read or parsed are skipped with a warning; fails only when no certificate
+/// could be loaded at all.
+pub fn load_server_certs<P: AsRef<Path>>(paths: &[P]) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut certs = Vec::new();
+
+    for path in paths {
+        load_path(path.as_ref(), &mut certs)?;
+    }
+
+    require_certs(certs)
+}
+
+/// Loads the trusted server certificates from the given sources; files are loaded like
+/// [`load_server_certs`] does.
+///
+/// Sources that can't be parsed are skipped with a warning; fails only when no certificate could
+/// be loaded at all, or the standard input can't be read.
+pub fn load_cert_sources(sources: &[CertSource]) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut certs = Vec::new();
+
+    for source in sources {
+        match source {
+            CertSource::File(path) => load_path(path, &mut certs)?,
+            CertSource::Inline(pem) => load_pem(pem.as_bytes(), "inline PEM", &mut certs),
+            CertSource::Stdin => {
+                let mut pem = Vec::new();
+                io::stdin().read_to_end(&mut pem)?;
+                load_pem(&pem, "standard input", &mut certs);
+            }
+        }
+    }
+
+    require_certs(certs)
+}
+
+fn require_certs(certs: Vec<CertificateDer<'static>>) -> io::Result<Vec<CertificateDer<'static>>> {
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "No trusted server certificate could be loaded",
+        ));
+    }
+
+    Ok(certs)
+}
+
+/// Appends the certificates of a PEM file, or of the certificate files of a directory.
+fn load_path(path: &Path, certs: &mut Vec<CertificateDer<'static>>) -> io::Result<()> {
+    if !path.is_dir() {
+        load_pem_file(path, certs);
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| CERT_EXTENSIONS.contains(&ext))
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    for entry in entries {
+        load_pem_file(&entry, certs);
+    }
+
+    Ok(())
+}
+
+/// Appends the certificates of a single PEM file, logging why the file was skipped on failure.
+fn load_pem_file(path: &Path, certs: &mut Vec<CertificateDer<'static>>) {
+    let loaded =
+        CertificateDer::pem_file_iter(path).and_then(|iter| iter.collect::<Result<Vec<_>, _>>());
+
+    match loaded {
+        Ok(loaded) if loaded.is_empty() => {
+            warn!("[client] No certificate found in {}", path.display());
+        }
+        Ok(loaded) => certs.extend(loaded),
+        Err(e) => {
+            warn!("[client] Skipping certificate file {}: {e}", path.display());
+        }
+    }
+}
+
+/// Appends the certificates of PEM text, logging why it was skipped on failure; `origin`
+/// describes where the text comes from.
+fn load_pem(pem: &[u8], origin: &str, certs: &mut Vec<CertificateDer<'static>>) {
+    let loaded = CertificateDer::pem_slice_iter(pem).collect::<Result<Vec<_>, _>>();
+
+    match loaded {
+        Ok(loaded) if loaded.is_empty() => warn!("[client] No certificate found in {origin}"),
+        Ok(loaded) => certs.extend(loaded),
+        Err(e) => warn!("[client] Skipping certificates of {origin}: {e}"),
+    }
+}
+
+/// Builds the QUIC client config trusting only the given server certificates and presenting the
+/// identity, if any, to servers requiring client certificates.
+pub fn configure_client(
+    server_certs: &[CertificateDer<'static>],
+    identity: Option<&ClientIdentity>,
+) -> io::Result<ClientConfig> {
+    quic_config(tls_config(server_certs, identity)?)
+}
+
+/// Builds a QUIC client config accepting any server certificate. Only meant for local
+/// development: the connection is open to man-in-the-middle attacks.
+#[cfg(feature = "insecure")]
+pub fn configure_client_insecure(identity: Option<&ClientIdentity>) -> io::Result<ClientConfig> {
+    quic_config(tls_config_insecure(identity)?)
+}
+
+/// Builds the TLS config of [`configure_client`], offering the [`DEFAULT_ALPN`] protocol.
+pub fn tls_config(
+    server_certs: &[CertificateDer<'static>],
+    identity: Option<&ClientIdentity>,
+) -> io::Result<rustls::ClientConfig> {
+    let mut certs = rustls::RootCertStore::empty();
+    for cert in server_certs {
+        certs
+            .add(cert.clone())
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    }
+
+    with_identity(tls_builder()?.with_root_certificates(certs), identity)
+}
+
+/// Builds the TLS config of [`configure_client_insecure`].
+#[cfg(feature = "insecure")]
+pub fn tls_config_insecure(identity: Option<&ClientIdentity>) -> io::Result<rustls::ClientConfig> {
+    let verifier = Arc::new(insecure::AcceptAnyServerCert(ring::default_provider()));
+
+    with_identity(
+        tls_builder()?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier),
+        identity,
+    )
+}
+
+/// TLS 1.3 only, `ring` backed, rustls client config builder.
+fn tls_builder() -> io::Result<ConfigBuilder<rustls::ClientConfig, WantsVerifier>> {
+    rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_protocol_versions(&[&TLS13])
+        .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))
+}
+
+/// Finishes the TLS config with the client identity and the [`DEFAULT_ALPN`] protocol.
+fn with_identity(
+    tls_config: ConfigBuilder<rustls::ClientConfig, WantsClientCert>,
+    identity: Option<&ClientIdentity>,
+) -> io::Result<rustls::ClientConfig> {
+    let mut tls_config = match identity {
+        Some(identity) => tls_config
+            .with_client_auth_cert(identity.certs.clone(), identity.key.clone_key())
+            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?,
+        None => tls_config.with_no_client_auth(),
+    };
+    tls_config.alpn_protocols = vec![DEFAULT_ALPN.to_vec()];
+
+    Ok(tls_config)
+}
+
+/// Wraps the TLS config into the QUIC client config.
+pub fn quic_config(tls_config: rustls::ClientConfig) -> io::Result<ClientConfig> {
+    let crypto = QuicClientConfig::try_from(tls_config)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+    let mut client_config = ClientConfig::new(Arc::new(crypto));
+    client_config.transport_config(Arc::new(transport_config(CongestionController::default())));
+
+    Ok(client_config)
+}
+
+/// Transport parameters of the connection to the server.
+pub fn transport_config(congestion_controller: CongestionController) -> TransportConfig {
+    let mut transport_config = TransportConfig::default();
+    transport_config.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+
+    match congestion_controller {
+        CongestionController::Cubic => {
+            transport_config.congestion_controller_factory(Arc::new(CubicConfig::default()))
+        }
+        CongestionController::NewReno => {
+            transport_config.congestion_controller_factory(Arc::new(NewRenoConfig::default()))
+        }
+        CongestionController::Bbr => {
+            transport_config.congestion_controller_factory(Arc::new(BbrConfig::default()))
+        }
+    };
+
+    transport_config
+}
+
+#[cfg(feature = "insecure")]
+mod insecure {
+    use rustls::{
+        DigitallySignedStruct, Error, SignatureScheme,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature},
+        pki_types::{CertificateDer, ServerName, UnixTime},
+    };
+
+    /// Accepts every server certificate; the handshake signatures are still checked so the
+    /// server has to own the key of the certificate it presents.
+    #[derive(Debug)]
+    pub struct AcceptAnyServerCert(pub CryptoProvider);
+
+    impl ServerCertVerifier for AcceptAnyServerCert {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, Error> {
+            verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, Error> {
+            verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+}
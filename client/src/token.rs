@@ -0,0 +1,28 @@
+// Fresh tokens, for the servers rejecting the replays of a captured `Initial`.
+//
+// A fresh token is the time it was signed at, in microseconds since the UNIX epoch (8 bytes), a
+// random nonce (16 bytes), then the HMAC-SHA256 of both keyed by the secret token (32 bytes). The
+// server accepts it only within a window of its own time, and only once.
+use bytes::{BufMut, Bytes, BytesMut};
+use ring::hmac;
+
+/// Length of the nonce of a fresh token.
+pub const NONCE_LENGTH: usize = 16;
+
+/// Signs a fresh token with `secret`, at the current time and with a random nonce.
+pub fn sign(secret: &[u8]) -> Bytes {
+    sign_at(secret, message::now_micros(), rand::random())
+}
+
+/// Signs a fresh token with `secret`, at `timestamp` and with `nonce`.
+pub fn sign_at(secret: &[u8], timestamp: u64, nonce: [u8; NONCE_LENGTH]) -> Bytes {
+    let mut token = BytesMut::with_capacity(8 + NONCE_LENGTH + 32);
+    token.put_u64(timestamp);
+    token.put_slice(&nonce);
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let tag = hmac::sign(&key, &token);
+    token.put_slice(tag.as_ref());
+
+    token.freeze()
+}
@@ -0,0 +1,81 @@
+use std::{
+    future::Future,
+    io::{self, ErrorKind},
+};
+
+use bytes::Bytes;
+use message::{Decoder, Message};
+use quinn::{RecvStream, SendStream};
+
+/// Receiving half of a stream carrying frames; frames may be split across chunks.
+pub trait AsyncFrameSource: Send {
+    /// Reads the next chunk, of at most `max_length` bytes; `None` once the peer finished the
+    /// stream.
+    fn recv_chunk(
+        &mut self,
+        max_length: usize,
+    ) -> impl Future<Output = io::Result<Option<Bytes>>> + Send;
+}
+
+/// Sending half of a stream carrying frames.
+pub trait AsyncFrameSink: Send {
+    fn send_chunk(&mut self, chunk: Bytes) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+impl AsyncFrameSource for RecvStream {
+    async fn recv_chunk(&mut self, max_length: usize) -> io::Result<Option<Bytes>> {
+        let chunk = self.read_chunk(max_length, true).await?;
+        Ok(chunk.map(|chunk| chunk.bytes))
+    }
+}
+
+impl AsyncFrameSink for SendStream {
+    async fn send_chunk(&mut self, chunk: Bytes) -> io::Result<()> {
+        self.write_chunk(chunk).await.map_err(io::Error::from)
+    }
+}
+
+/// Decodes the messages read from a frame source.
+pub struct MessageReader<R> {
+    source: R,
+    decoder: Decoder,
+
+    /// Largest chunk read from the source at once.
+    recv_chunk_size: usize,
+}
+
+impl<R: AsyncFrameSource> MessageReader<R> {
+    pub fn new(source: R, recv_chunk_size: usize) -> MessageReader<R> {
+        MessageReader {
+            source,
+            decoder: Decoder::new(),
+            recv_chunk_size,
+        }
+    }
+
+    /// Returns the next message; `None` once the stream is finished. Fails if a frame is
+    /// invalid or cut short by the end of the stream.
+    pub async fn next_message(&mut self) -> io::Result<Option<Message>> {
+        loop {
+            if let Some(msg) = self.decoder.next_message()? {
+                return Ok(Some(msg));
+            }
+
+            match self.source.recv_chunk(self.recv_chunk_size).await? {
+                Some(chunk) => self.decoder.extend(&chunk),
+                None if self.decoder.buffered() > 0 => {
+                    return Err(io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "Stream finished within a frame",
+                    ));
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Encodes the message and sends it as a single chunk.
+pub async fn send_message(sink: &mut impl AsyncFrameSink, msg: &Message) -> io::Result<()> {
+    sink.send_chunk(msg.encode()).await
+}
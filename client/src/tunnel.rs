@@ -0,0 +1,310 @@
+use std::{
+    io::{self, ErrorKind},
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use message::{CloseReason, InitializationMessage, Message, MessageType, msg_utils};
+use spdlog::prelude::{debug, info, warn};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::{broadcast, mpsc},
+    time::timeout,
+};
+use uuid::Uuid;
+
+use crate::{ClientEvent, session::Session};
+
+/// Opens a new tunnel over the shared session and relays the local socket through it
+/// until the server closes it. Each side finishing to send is propagated as a
+/// [`MessageType::ShutdownWrite`], keeping the other direction open. When the local socket
+/// fails, the client closes the tunnel itself and waits for the server to acknowledge the
+/// `Close`, or `close_timeout` to elapse. The local socket is read `chunk_size` bytes at a time.
+/// The `Ack`s of the server are emitted as [`ClientEvent::Acked`].
+pub async fn run(
+    session: Arc<Session>,
+    init: InitializationMessage,
+    socket: TcpStream,
+    chunk_size: usize,
+    timestamps: bool,
+    close_timeout: Duration,
+    events: broadcast::Sender<ClientEvent>,
+) -> io::Result<()> {
+    let connection_id = msg_utils::generate_uuid();
+    let mut rx = session.register(connection_id);
+
+    let init_msg = Message::initial(connection_id, &init);
+    if let Err(e) = session.send(&init_msg).await {
+        session.unregister(connection_id);
+        return Err(e);
+    }
+
+    info!("[client] tunnel opened: connection_id={connection_id}");
+
+    let (reader, writer) = socket.into_split();
+
+    let result = {
+        let server = relay_server(&mut rx, writer, &session, connection_id, &events);
+        tokio::pin!(server);
+
+        tokio::select! {
+            result = relay_local(reader, &session, connection_id, chunk_size, timestamps) => match result {
+                // The server closes the tunnel once the backend finished sending as well.
+                Ok(()) => server.await,
+                Err(e) => Err(e),
+            },
+            result = &mut server => result,
+        }
+    };
+
+    if result.is_err() {
+        close(&session, &mut rx, connection_id, close_timeout).await;
+    }
+
+    session.unregister(connection_id);
+    info!("[client] tunnel closed: connection_id={connection_id}");
+
+    result
+}
+
+/// Opens a new tunnel over the shared session for a single exchange: sends `payload`, in
+/// `chunk_size` pieces, then finishes sending and collects the payloads of the `Data` messages
+/// correlated to the request until the server closes the tunnel. Fails with
+/// [`ErrorKind::TimedOut`] if the response isn't complete within `request_timeout`; the tunnel is
+/// then closed like a failed one of [`run`].
+pub async fn request(
+    session: Arc<Session>,
+    init: InitializationMessage,
+    payload: Bytes,
+    chunk_size: usize,
+    request_timeout: Duration,
+    close_timeout: Duration,
+    events: broadcast::Sender<ClientEvent>,
+) -> io::Result<Bytes> {
+    let connection_id = msg_utils::generate_uuid();
+    let mut rx = session.register(connection_id);
+
+    let exchange = exchange(
+        &session,
+        &mut rx,
+        connection_id,
+        &init,
+        payload,
+        chunk_size,
+        &events,
+    );
+    let result = match timeout(request_timeout, exchange).await {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(
+            ErrorKind::TimedOut,
+            format!("No response within {request_timeout:?}"),
+        )),
+    };
+
+    if result.is_err() {
+        close(&session, &mut rx, connection_id, close_timeout).await;
+    }
+    session.unregister(connection_id);
+
+    result
+}
+
+/// Sends the request of [`request`] and reassembles its response.
+async fn exchange(
+    session: &Session,
+    rx: &mut mpsc::Receiver<Message>,
+    connection_id: Uuid,
+    init: &InitializationMessage,
+    payload: Bytes,
+    chunk_size: usize,
+    events: &broadcast::Sender<ClientEvent>,
+) -> io::Result<Bytes> {
+    session.send(&Message::initial(connection_id, init)).await?;
+    debug!("[client] request tunnel opened: connection_id={connection_id}");
+
+    // Responses are correlated to the last chunk the server forwarded to the backend.
+    let mut request_ids = Vec::new();
+    let mut offset = 0;
+    while offset < payload.len() {
+        let end = payload.len().min(offset + chunk_size);
+        let data = Message::data(connection_id, payload.slice(offset..end))
+            .with_sequence(request_ids.len() as u64);
+        request_ids.push(data.message_id);
+        session.send(&data).await?;
+        offset = end;
+    }
+    let shutdown = Message::new(MessageType::ShutdownWrite, connection_id, Bytes::new());
+    session.send(&shutdown).await?;
+
+    let mut response = BytesMut::new();
+    while let Some(msg) = rx.recv().await {
+        match msg.message_type {
+            MessageType::Data
+                if msg
+                    .in_reply_to
+                    .is_some_and(|reply_to| request_ids.contains(&reply_to)) =>
+            {
+                response.extend_from_slice(&msg.payload);
+            }
+            MessageType::Close => {
+                session
+                    .send(&Message::ack(connection_id, msg.message_id))
+                    .await?;
+
+                return match CloseReason::decode(&msg.payload)? {
+                    CloseReason::Normal => Ok(response.freeze()),
+                    reason => {
+                        let _ = events.send(ClientEvent::TunnelRejected {
+                            connection_id,
+                            reason,
+                        });
+                        Err(io::Error::new(
+                            ErrorKind::ConnectionRefused,
+                            format!("Tunnel rejected: {reason}"),
+                        ))
+                    }
+                };
+            }
+            _ => debug!("[client] received: {:?}", msg),
+        }
+    }
+
+    Err(io::Error::new(
+        ErrorKind::ConnectionAborted,
+        "Connection lost before the response",
+    ))
+}
+
+/// Forwards the bytes read from the local socket to the server as [`MessageType::Data`] messages,
+/// followed by a [`MessageType::ShutdownWrite`] once the local socket finished sending. With
+/// `timestamps`, the messages carry the time they are sent.
+async fn relay_local(
+    mut reader: OwnedReadHalf,
+    session: &Session,
+    connection_id: Uuid,
+    chunk_size: usize,
+    timestamps: bool,
+) -> io::Result<()> {
+    let mut buf = vec![0; chunk_size];
+    let mut sequence = 0;
+
+    loop {
+        let n = reader.read(&mut buf).await?;
+
+        if n == 0 {
+            let shutdown = Message::new(MessageType::ShutdownWrite, connection_id, Bytes::new());
+            return session.send(&shutdown).await;
+        }
+
+        let mut data =
+            Message::data(connection_id, Bytes::copy_from_slice(&buf[..n])).with_sequence(sequence);
+        sequence += 1;
+        if timestamps {
+            data = data.timestamped();
+        }
+
+        session.send(&data).await?;
+    }
+}
+
+/// Writes the payloads of the [`MessageType::Data`] messages sent by the server to the local socket,
+/// shutting its write half down on [`MessageType::ShutdownWrite`].
+/// Acknowledges the [`MessageType::Close`] of the server.
+async fn relay_server(
+    rx: &mut mpsc::Receiver<Message>,
+    mut writer: OwnedWriteHalf,
+    session: &Session,
+    connection_id: Uuid,
+    events: &broadcast::Sender<ClientEvent>,
+) -> io::Result<()> {
+    let mut shut_down = false;
+
+    while let Some(msg) = rx.recv().await {
+        match msg.message_type {
+            MessageType::Data if !shut_down => writer.write_all(&msg.payload).await?,
+            MessageType::ShutdownWrite if !shut_down => {
+                writer.shutdown().await?;
+                shut_down = true;
+            }
+            MessageType::Ack => {
+                if let Some(message_id) = msg.in_reply_to {
+                    // Sending only fails when there are no subscribers.
+                    let _ = events.send(ClientEvent::Acked {
+                        connection_id,
+                        message_id,
+                    });
+                }
+            }
+            MessageType::Close => {
+                match CloseReason::decode(&msg.payload) {
+                    Ok(CloseReason::Normal) => {}
+                    Ok(reason) => {
+                        warn!(
+                            "[client] tunnel rejected: connection_id={connection_id} reason={reason}"
+                        );
+                        let _ = events.send(ClientEvent::TunnelRejected {
+                            connection_id,
+                            reason,
+                        });
+                    }
+                    Err(e) => {
+                        warn!("[client] tunnel rejected: connection_id={connection_id} err={e}")
+                    }
+                }
+
+                let ack = Message::ack(connection_id, msg.message_id);
+                session.send(&ack).await?;
+                break;
+            }
+            _ => debug!("[client] received: {:?}", msg),
+        }
+    }
+
+    if shut_down {
+        return Ok(());
+    }
+    writer.shutdown().await
+}
+
+/// Closes the tunnel from the client side and waits for the server to acknowledge it.
+async fn close(
+    session: &Session,
+    rx: &mut mpsc::Receiver<Message>,
+    connection_id: Uuid,
+    deadline: Duration,
+) {
+    let close = Message::close(connection_id, CloseReason::Normal);
+
+    match session.send(&close).await {
+        Ok(()) => await_close_ack(rx, connection_id, close.message_id, deadline).await,
+        Err(e) => debug!("[client] failed to send close: connection_id={connection_id} err={e:?}"),
+    }
+}
+
+/// Waits for the server to acknowledge the `Close` of the tunnel, either with an `Ack` of it or
+/// with its own `Close`; gives up after `deadline`.
+async fn await_close_ack(
+    rx: &mut mpsc::Receiver<Message>,
+    connection_id: Uuid,
+    close_id: Uuid,
+    deadline: Duration,
+) {
+    let acked = timeout(deadline, async {
+        while let Some(msg) = rx.recv().await {
+            match msg.message_type {
+                MessageType::Ack if msg.in_reply_to == Some(close_id) => return,
+                MessageType::Close => return,
+                _ => debug!("[client] ignoring message of a closing tunnel: {:?}", msg),
+            }
+        }
+    });
+
+    if acked.await.is_err() {
+        warn!("[client] close not acknowledged within {deadline:?}: connection_id={connection_id}");
+    }
+}
@@ -0,0 +1,147 @@
+use std::{fs, path::PathBuf, sync::Arc, time::Duration};
+
+use client::{CertSource, Client, ClientConfig, ClientEvent, tls};
+use rustls::pki_types::CertificateDer;
+use tokio::time::timeout;
+
+mod common;
+
+/// Creates an empty directory unique to the test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("reverprox-{name}-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Returns whether the client reaches the server trusting only the given certificates.
+async fn connects(server: &common::TestServer, certs: Vec<CertificateDer<'static>>) -> bool {
+    let mut config = ClientConfig::new(server.addr, "127.0.0.1:3000".parse().unwrap());
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.server_certs = certs;
+
+    let client = Arc::new(Client::new(config).unwrap());
+    let mut events = client.subscribe();
+
+    let runner = client.clone();
+    let task = tokio::spawn(async move { runner.run().await });
+
+    let connected = timeout(Duration::from_secs(2), async {
+        loop {
+            if events.recv().await.unwrap() == ClientEvent::Connected {
+                return;
+            }
+        }
+    })
+    .await
+    .is_ok();
+
+    task.abort();
+    connected
+}
+
+#[tokio::test]
+async fn trusts_every_certificate_of_a_directory() {
+    let first = common::spawn_server(true);
+    let second = common::spawn_server(true);
+
+    let dir = temp_dir("certs");
+    fs::write(dir.join("first.pem"), &first.cert_pem).unwrap();
+    fs::write(dir.join("second.crt"), &second.cert_pem).unwrap();
+    fs::write(
+        dir.join("broken.pem"),
+        "-----BEGIN CERTIFICATE-----\n!!!\n-----END CERTIFICATE-----\n",
+    )
+    .unwrap();
+    fs::write(dir.join("notes.txt"), "not a certificate").unwrap();
+
+    let certs = tls::load_server_certs(&[&dir]).unwrap();
+    assert_eq!(certs.len(), 2);
+
+    assert!(connects(&first, certs.clone()).await);
+    assert!(connects(&second, certs).await);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn loads_a_list_of_files_skipping_the_unreadable_ones() {
+    let first = common::spawn_server(true);
+    let second = common::spawn_server(true);
+    let untrusted = common::spawn_server(true);
+
+    let dir = temp_dir("files");
+    fs::write(dir.join("first.pem"), &first.cert_pem).unwrap();
+    fs::write(dir.join("second.pem"), &second.cert_pem).unwrap();
+
+    let certs = tls::load_server_certs(&[
+        dir.join("first.pem"),
+        dir.join("missing.pem"),
+        dir.join("second.pem"),
+    ])
+    .unwrap();
+    assert_eq!(certs.len(), 2);
+
+    assert!(connects(&first, certs.clone()).await);
+    assert!(connects(&second, certs.clone()).await);
+    assert!(!connects(&untrusted, certs).await);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn fails_when_no_certificate_is_loaded() {
+    let dir = temp_dir("empty");
+
+    let err = tls::load_server_certs(&[&dir]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn trusts_inline_and_file_sources() {
+    let inline = common::spawn_server(true);
+    let file = common::spawn_server(true);
+
+    let dir = temp_dir("sources");
+    fs::write(dir.join("file.pem"), &file.cert_pem).unwrap();
+
+    let certs = tls::load_cert_sources(&[
+        CertSource::Inline(inline.cert_pem.clone()),
+        CertSource::File(dir.join("file.pem")),
+    ])
+    .unwrap();
+    assert_eq!(certs, vec![inline.cert.clone(), file.cert.clone()]);
+
+    assert!(connects(&inline, certs.clone()).await);
+    assert!(connects(&file, certs).await);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn invalid_inline_pem_is_skipped() {
+    let err =
+        tls::load_cert_sources(&[CertSource::Inline("not a certificate".to_string())]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn client_config_accepts_inline_certificates() {
+    let server = common::spawn_server(true);
+
+    let mut config = ClientConfig::new(server.addr, "127.0.0.1:3000".parse().unwrap());
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.server_cert_sources = vec![CertSource::Inline(server.cert_pem.clone())];
+    let client = Arc::new(Client::new(config).unwrap());
+    let mut events = client.subscribe();
+
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    let event = timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("client did not connect")
+        .unwrap();
+    assert_eq!(event, ClientEvent::Connected);
+}
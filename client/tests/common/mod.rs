@@ -0,0 +1,164 @@
+#![allow(dead_code)]
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+use message::{DEFAULT_ALPN, Message, MessageStream, MessageType};
+use quinn::{Endpoint, ServerConfig, crypto::rustls::QuicServerConfig};
+use rustls::{
+    crypto::ring,
+    pki_types::{CertificateDer, PrivatePkcs8KeyDer},
+    version::TLS13,
+};
+use tokio::time::sleep;
+
+/// Server presenting a fresh self-signed certificate for `localhost`.
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub cert: CertificateDer<'static>,
+
+    /// PEM encoding of `cert`.
+    pub cert_pem: String,
+}
+
+/// Generates a self-signed certificate for `localhost`.
+pub fn generate_cert() -> rcgen::CertifiedKey {
+    rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap()
+}
+
+/// Starts a server answering the pings with pongs when `pong` is set, ignoring them otherwise.
+pub fn spawn_server(pong: bool) -> TestServer {
+    spawn_server_at("127.0.0.1:0".parse().unwrap(), generate_cert(), pong)
+}
+
+/// Starts a server like [`spawn_server`], bound to `addr` and presenting `cert`.
+pub fn spawn_server_at(addr: SocketAddr, cert: rcgen::CertifiedKey, pong: bool) -> TestServer {
+    spawn(addr, cert, pong.then_some(usize::MAX), usize::MAX)
+}
+
+/// Starts a server answering the first `pongs` pings of each connection, then ignoring them.
+pub fn spawn_unresponsive_server(pongs: usize) -> TestServer {
+    spawn(
+        "127.0.0.1:0".parse().unwrap(),
+        generate_cert(),
+        Some(usize::MAX),
+        pongs,
+    )
+}
+
+/// Starts a server answering the pings with pongs written `fragment` bytes at a time, so that the
+/// client reads them in pieces.
+pub fn spawn_fragmenting_server(fragment: usize) -> TestServer {
+    spawn(
+        "127.0.0.1:0".parse().unwrap(),
+        generate_cert(),
+        Some(fragment),
+        usize::MAX,
+    )
+}
+
+/// Starts a server answering the pings, which finishes its side of the stream as soon as it
+/// receives an `Initial`.
+pub fn spawn_finishing_server() -> TestServer {
+    let (endpoint, server) = bind("127.0.0.1:0".parse().unwrap(), generate_cert());
+
+    tokio::spawn(async move {
+        while let Some(incoming) = endpoint.accept().await {
+            tokio::spawn(async move {
+                let connection = incoming.await.unwrap();
+                let Ok((mut send, recv)) = connection.accept_bi().await else {
+                    return;
+                };
+
+                let mut messages = MessageStream::new(recv);
+                while let Some(Ok(msg)) = messages.next().await {
+                    match msg.message_type {
+                        MessageType::Ping => {
+                            let pong =
+                                Message::new(MessageType::Pong, msg.connection_id, msg.payload)
+                                    .with_reply_to(msg.message_id);
+                            let _ = send.write_all(&pong.encode()).await;
+                        }
+                        MessageType::Initial => {
+                            let _ = send.finish();
+                        }
+                        _ => {}
+                    }
+                }
+
+                // Keeps the connection open until the client closes it.
+                connection.closed().await;
+            });
+        }
+    });
+
+    server
+}
+
+/// Binds a server endpoint to `addr`, presenting `cert`.
+pub fn bind(addr: SocketAddr, cert: rcgen::CertifiedKey) -> (Endpoint, TestServer) {
+    let cert_pem = cert.cert.pem();
+    let cert_der = CertificateDer::from(cert.cert);
+    let key = PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+
+    let mut tls_config =
+        rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_protocol_versions(&[&TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert_der.clone()], key.into())
+            .unwrap();
+    tls_config.alpn_protocols = vec![DEFAULT_ALPN.to_vec()];
+    let config =
+        ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls_config).unwrap()));
+    let endpoint = Endpoint::server(config, addr).unwrap();
+    let server = TestServer {
+        addr: endpoint.local_addr().unwrap(),
+        cert: cert_der,
+        cert_pem,
+    };
+
+    (endpoint, server)
+}
+
+/// Starts a server writing its `pongs` first pongs of each connection `fragment` bytes at a
+/// time; pings are ignored when `None`.
+fn spawn(
+    addr: SocketAddr,
+    cert: rcgen::CertifiedKey,
+    fragment: Option<usize>,
+    pongs: usize,
+) -> TestServer {
+    let (endpoint, server) = bind(addr, cert);
+
+    tokio::spawn(async move {
+        while let Some(incoming) = endpoint.accept().await {
+            tokio::spawn(async move {
+                let connection = incoming.await.unwrap();
+                let Ok((mut send, recv)) = connection.accept_bi().await else {
+                    return;
+                };
+
+                let mut messages = MessageStream::new(recv);
+                let mut answered = 0;
+                while let Some(Ok(msg)) = messages.next().await {
+                    let Some(fragment) = fragment else { continue };
+                    if !matches!(msg.message_type, MessageType::Ping) || answered == pongs {
+                        continue;
+                    }
+                    answered += 1;
+
+                    let reply = Message::new(MessageType::Pong, msg.connection_id, msg.payload)
+                        .with_reply_to(msg.message_id);
+                    for piece in reply.encode().chunks(fragment) {
+                        send.write_all(piece).await.unwrap();
+                        // Gives each piece a chance to be read on its own.
+                        sleep(Duration::from_millis(5)).await;
+                    }
+                }
+            });
+        }
+    });
+
+    server
+}
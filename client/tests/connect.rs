@@ -0,0 +1,67 @@
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use client::{Client, ClientConfig, ClientEvent};
+use rustls::pki_types::CertificateDer;
+use tokio::time::{sleep, timeout};
+
+mod common;
+
+/// Returns a loopback address no server listens on yet.
+fn free_addr() -> SocketAddr {
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+fn client_config(server_addr: SocketAddr) -> ClientConfig {
+    let mut config = ClientConfig::new(server_addr, "127.0.0.1:3000".parse().unwrap());
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.connect_retry_interval = Duration::from_millis(100);
+    config
+}
+
+#[tokio::test]
+async fn client_started_before_the_server_connects_once_it_is_up() {
+    let addr = free_addr();
+    let cert = common::generate_cert();
+
+    let mut config = client_config(addr);
+    config.connect_timeout = Duration::from_secs(10);
+    config.server_certs = vec![CertificateDer::from(cert.cert.der().to_vec())];
+    let client = Arc::new(Client::new(config).unwrap());
+    let mut events = client.subscribe();
+
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    sleep(Duration::from_millis(500)).await;
+    common::spawn_server_at(addr, cert, true);
+
+    let event = timeout(Duration::from_secs(10), events.recv())
+        .await
+        .expect("client did not connect")
+        .unwrap();
+    assert_eq!(event, ClientEvent::Connected);
+}
+
+#[tokio::test]
+async fn client_gives_up_after_the_connect_timeout() {
+    let mut config = client_config(free_addr());
+    config.connect_timeout = Duration::from_millis(500);
+    let client = Client::new(config).unwrap();
+
+    let started = Instant::now();
+    let err = timeout(Duration::from_secs(5), client.run())
+        .await
+        .expect("client kept retrying after the deadline")
+        .unwrap_err();
+
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(started.elapsed() >= Duration::from_millis(400));
+}
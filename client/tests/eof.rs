@@ -0,0 +1,56 @@
+use std::{sync::Arc, time::Duration};
+
+use client::{Client, ClientConfig};
+use tokio::{
+    io::AsyncReadExt,
+    net::{TcpListener, TcpStream},
+    time::{sleep, timeout},
+};
+
+mod common;
+
+#[tokio::test]
+async fn tunnels_are_closed_once_the_server_finishes_the_stream() {
+    let server = common::spawn_finishing_server();
+    let mut config = ClientConfig::new(server.addr, "127.0.0.1:3000".parse().unwrap());
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.server_certs = vec![server.cert];
+    let client = Arc::new(Client::new(config).unwrap());
+
+    let local_addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+    let listener = client.clone();
+    tokio::spawn(async move { listener.listen_local(local_addr).await });
+
+    let mut socket = timeout(Duration::from_secs(5), async {
+        loop {
+            match TcpStream::connect(local_addr).await {
+                Ok(socket) => return socket,
+                Err(_) => sleep(Duration::from_millis(10)).await,
+            }
+        }
+    })
+    .await
+    .expect("client is not listening");
+
+    // The end of the stream closes the tunnel rather than being read over and over.
+    let mut buf = [0; 16];
+    let n = timeout(Duration::from_secs(5), socket.read(&mut buf))
+        .await
+        .expect("tunnel was not closed")
+        .unwrap();
+    assert_eq!(n, 0);
+
+    timeout(Duration::from_secs(5), async {
+        while client.open_tunnels() > 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("tunnel was not unregistered");
+}
@@ -0,0 +1,71 @@
+use std::{sync::Arc, time::Duration};
+
+use client::{Backoff, Client, ClientConfig, ClientEvent, Health};
+use tokio::{
+    sync::watch,
+    time::{sleep, timeout},
+};
+
+mod common;
+
+#[tokio::test]
+async fn dead_connection_is_re_established() {
+    let server = common::spawn_unresponsive_server(2);
+
+    let mut config = ClientConfig::new(server.addr, "127.0.0.1:3000".parse().unwrap());
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.server_certs = vec![server.cert];
+    config.ping_interval = Duration::from_millis(50);
+    config.ping_timeout = Duration::from_millis(200);
+    config.max_missed_pongs = 3;
+    config.backoff = Backoff {
+        base: Duration::from_millis(100),
+        ..Backoff::default()
+    };
+    let client = Arc::new(Client::new(config).unwrap());
+    let mut events = client.subscribe();
+
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    // Records each change of the health of the client.
+    let (stop, mut stopped) = watch::channel(false);
+    let sampler = tokio::spawn({
+        let client = client.clone();
+        async move {
+            let mut states = vec![client.health()];
+            while !*stopped.borrow() {
+                let health = client.health();
+                if states.last() != Some(&health) {
+                    states.push(health);
+                }
+                tokio::select! {
+                    _ = sleep(Duration::from_millis(5)) => {}
+                    _ = stopped.changed() => {}
+                }
+            }
+            states
+        }
+    });
+
+    let reconnected = timeout(Duration::from_secs(10), async {
+        loop {
+            match events.recv().await.unwrap() {
+                ClientEvent::Reconnected { attempts } => return attempts,
+                _ => continue,
+            }
+        }
+    })
+    .await
+    .expect("dead connection was not re-established");
+    assert_eq!(reconnected, 1);
+
+    stop.send_replace(true);
+    let states = sampler.await.unwrap();
+    assert!(
+        states
+            .windows(3)
+            .any(|states| states == [Health::Healthy, Health::Suspect, Health::Dead]),
+        "unexpected health changes: {states:?}"
+    );
+}
@@ -0,0 +1,46 @@
+use std::{sync::Arc, time::Duration};
+
+use client::{Client, ClientConfig, ClientEvent};
+use tokio::time::timeout;
+
+mod common;
+
+/// Returns whether the client, trusting no certificate at all, reaches the server.
+async fn connects(server: &common::TestServer, insecure: bool) -> bool {
+    let mut config = ClientConfig::new(server.addr, "127.0.0.1:3000".parse().unwrap());
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.insecure = insecure;
+
+    let client = Arc::new(Client::new(config).unwrap());
+    let mut events = client.subscribe();
+
+    let runner = client.clone();
+    let task = tokio::spawn(async move { runner.run().await });
+
+    let connected = timeout(Duration::from_secs(2), async {
+        loop {
+            if events.recv().await.unwrap() == ClientEvent::Connected {
+                return;
+            }
+        }
+    })
+    .await
+    .is_ok();
+
+    task.abort();
+    connected
+}
+
+#[tokio::test]
+async fn insecure_mode_accepts_an_untrusted_certificate() {
+    let server = common::spawn_server(true);
+
+    assert!(connects(&server, true).await);
+}
+
+#[tokio::test]
+async fn untrusted_certificate_is_rejected_by_default() {
+    let server = common::spawn_server(true);
+
+    assert!(!connects(&server, false).await);
+}
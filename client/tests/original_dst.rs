@@ -0,0 +1,32 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use client::original_dst::original_dst;
+use tokio::net::{TcpListener, TcpStream};
+
+/// Connects to a fresh listener of `ip`, returning both ends of the connection.
+async fn connected(ip: SocketAddr) -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind(ip).await.unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (accepted, _) = listener.accept().await.unwrap();
+    (client, accepted)
+}
+
+#[tokio::test]
+async fn direct_connection_has_no_original_destination() {
+    let (_client, accepted) = connected(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).await;
+    assert_eq!(original_dst(&accepted).unwrap(), None);
+}
+
+#[tokio::test]
+async fn direct_ipv6_connection_has_no_original_destination() {
+    let Ok(listener) = TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).await else {
+        // IPv6 is disabled on the host.
+        return;
+    };
+    drop(listener);
+
+    let (_client, accepted) = connected(SocketAddr::from((Ipv6Addr::LOCALHOST, 0))).await;
+    assert_eq!(original_dst(&accepted).unwrap(), None);
+}
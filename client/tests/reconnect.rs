@@ -0,0 +1,77 @@
+use std::{sync::Arc, time::Duration};
+
+use client::{Backoff, Client, ClientConfig, ClientEvent};
+use quinn::Connection;
+use tokio::{
+    sync::{broadcast, mpsc},
+    time::timeout,
+};
+
+mod common;
+
+/// Next event emitted by the client.
+async fn next_event(events: &mut broadcast::Receiver<ClientEvent>) -> ClientEvent {
+    timeout(Duration::from_secs(10), events.recv())
+        .await
+        .expect("no event emitted")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn client_reconnects_until_it_runs_out_of_retries() {
+    let (endpoint, server) = common::bind("127.0.0.1:0".parse().unwrap(), common::generate_cert());
+    let (connections_tx, mut connections) = mpsc::unbounded_channel::<Connection>();
+    tokio::spawn({
+        let endpoint = endpoint.clone();
+        async move {
+            while let Some(incoming) = endpoint.accept().await {
+                let Ok(connection) = incoming.await else {
+                    continue;
+                };
+                let _ = connections_tx.send(connection);
+            }
+        }
+    });
+
+    let mut config = ClientConfig::new(server.addr, "127.0.0.1:3000".parse().unwrap());
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.server_certs = vec![server.cert.clone()];
+    config.backoff = Backoff {
+        base: Duration::from_millis(10),
+        max: Duration::from_millis(50),
+        max_retries: Some(2),
+    };
+    let client = Arc::new(Client::new(config).unwrap());
+    let mut events = client.subscribe();
+    let running = tokio::spawn({
+        let client = client.clone();
+        async move { client.run().await }
+    });
+
+    assert_eq!(next_event(&mut events).await, ClientEvent::Connected);
+    let connection = connections.recv().await.unwrap();
+
+    // The server drops the connection, but keeps accepting new ones.
+    connection.close(0u32.into(), b"restart");
+    assert_eq!(next_event(&mut events).await, ClientEvent::Disconnected);
+    assert_eq!(
+        next_event(&mut events).await,
+        ClientEvent::Reconnected { attempts: 1 }
+    );
+    connections.recv().await.unwrap();
+
+    // The server goes away for good, refusing every new connection.
+    endpoint.close(0u32.into(), b"shutdown");
+    assert_eq!(next_event(&mut events).await, ClientEvent::Disconnected);
+    assert_eq!(
+        next_event(&mut events).await,
+        ClientEvent::ReconnectFailed { attempts: 2 }
+    );
+
+    let result = timeout(Duration::from_secs(5), running)
+        .await
+        .expect("client kept running once out of retries")
+        .unwrap();
+    assert!(result.is_err());
+    assert!(events.try_recv().is_err(), "ReconnectFailed emitted twice");
+}
@@ -0,0 +1,82 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use client::{Client, ClientConfig, ClientEvent};
+use rustls::pki_types::CertificateDer;
+use tokio::time::{sleep, timeout};
+
+mod common;
+
+fn client(server_addr: SocketAddr, server_cert: CertificateDer<'static>) -> Arc<Client> {
+    let mut config = ClientConfig::new(server_addr, "127.0.0.1:3000".parse().unwrap());
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.server_certs = vec![server_cert];
+    config.ping_interval = Duration::from_millis(50);
+    config.ping_timeout = Duration::from_millis(500);
+
+    Arc::new(Client::new(config).unwrap())
+}
+
+#[tokio::test]
+async fn measures_round_trip_time() {
+    let server = common::spawn_server(true);
+    let client = client(server.addr, server.cert);
+
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    let rtt = timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(latest) = client.rtt().latest {
+                return latest;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("no rtt measured");
+
+    assert!(rtt < Duration::from_millis(500));
+    assert!(client.rtt().average.is_some());
+    assert!(client.is_healthy());
+}
+
+#[tokio::test]
+async fn reconnects_when_pongs_stop_arriving() {
+    let server = common::spawn_server(false);
+    let client = client(server.addr, server.cert);
+    let mut events = client.subscribe();
+
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    let event = timeout(Duration::from_secs(5), async {
+        loop {
+            match events.recv().await.unwrap() {
+                ClientEvent::Connected => continue,
+                event => return event,
+            }
+        }
+    })
+    .await
+    .expect("connection was not considered unhealthy");
+
+    assert_eq!(event, ClientEvent::Disconnected);
+}
+
+#[tokio::test]
+async fn pongs_read_in_pieces_are_decoded_once_complete() {
+    let server = common::spawn_fragmenting_server(10);
+    let client = client(server.addr, server.cert);
+
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    timeout(Duration::from_secs(5), async {
+        while client.rtt().latest.is_none() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("no pong decoded");
+    assert!(client.is_healthy());
+}
@@ -0,0 +1,14 @@
+use client::{Client, ClientConfig};
+
+#[tokio::test]
+async fn endpoint_builds_with_custom_buffers() {
+    let mut config = ClientConfig::new(
+        "127.0.0.1:9003".parse().unwrap(),
+        "127.0.0.1:3000".parse().unwrap(),
+    );
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.socket_send_buffer = Some(4 * 1024 * 1024);
+    config.socket_recv_buffer = Some(4 * 1024 * 1024);
+
+    assert!(Client::new(config).is_ok());
+}
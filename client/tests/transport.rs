@@ -0,0 +1,108 @@
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind},
+};
+
+use bytes::Bytes;
+use client::transport::{self, AsyncFrameSink, AsyncFrameSource, MessageReader};
+use message::{Message, MessageType};
+use uuid::Uuid;
+
+/// Source handing out the chunks it was given, then finishing the stream.
+struct MockSource {
+    chunks: VecDeque<Bytes>,
+}
+
+impl MockSource {
+    /// Splits `bytes` into chunks of `chunk_size` bytes.
+    fn new(bytes: &[u8], chunk_size: usize) -> MockSource {
+        MockSource {
+            chunks: bytes
+                .chunks(chunk_size)
+                .map(Bytes::copy_from_slice)
+                .collect(),
+        }
+    }
+}
+
+impl AsyncFrameSource for MockSource {
+    async fn recv_chunk(&mut self, max_length: usize) -> io::Result<Option<Bytes>> {
+        let Some(mut chunk) = self.chunks.pop_front() else {
+            return Ok(None);
+        };
+        if chunk.len() > max_length {
+            self.chunks.push_front(chunk.split_off(max_length));
+        }
+        Ok(Some(chunk))
+    }
+}
+
+/// Sink keeping the chunks sent.
+#[derive(Default)]
+struct MockSink {
+    chunks: Vec<Bytes>,
+}
+
+impl AsyncFrameSink for MockSink {
+    async fn send_chunk(&mut self, chunk: Bytes) -> io::Result<()> {
+        self.chunks.push(chunk);
+        Ok(())
+    }
+}
+
+fn messages() -> Vec<Message> {
+    (0..10_u8)
+        .map(|i| {
+            Message::new(
+                MessageType::Data,
+                Uuid::new_v4(),
+                Bytes::from(vec![i; 100 * i as usize]),
+            )
+            .with_sequence(i as u64)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn messages_split_across_chunks_are_read_in_order() {
+    let messages = messages();
+    let mut sink = MockSink::default();
+    for msg in &messages {
+        transport::send_message(&mut sink, msg).await.unwrap();
+    }
+    // One chunk per message.
+    assert_eq!(sink.chunks.len(), messages.len());
+    let stream = sink.chunks.concat();
+
+    for (chunk_size, recv_chunk_size) in [(7, usize::MAX), (usize::MAX, 7), (1000, 333)] {
+        let source = MockSource::new(&stream, chunk_size);
+        let mut reader = MessageReader::new(source, recv_chunk_size);
+
+        for msg in &messages {
+            let read = reader.next_message().await.unwrap().unwrap();
+            assert_eq!(read.message_id, msg.message_id);
+            assert_eq!(read.sequence, msg.sequence);
+            assert_eq!(read.payload, msg.payload);
+        }
+        assert!(reader.next_message().await.unwrap().is_none());
+    }
+}
+
+#[tokio::test]
+async fn stream_finished_within_a_frame_fails() {
+    let frame = messages()[3].encode();
+    let source = MockSource::new(&frame[..frame.len() - 1], 64);
+    let mut reader = MessageReader::new(source, usize::MAX);
+
+    let err = reader.next_message().await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
+
+#[tokio::test]
+async fn invalid_frame_fails() {
+    let source = MockSource::new(&[0x42; 64], 64);
+    let mut reader = MessageReader::new(source, usize::MAX);
+
+    let err = reader.next_message().await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
@@ -0,0 +1,151 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use client::{Client, ClientConfig};
+use futures_util::{SinkExt, StreamExt};
+use message::{CloseReason, Message, MessageSink, MessageStream};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time::{sleep, timeout},
+};
+
+mod common;
+
+/// Starts a server reporting the `Data` messages it receives, and acknowledging them only when
+/// told to; returns it with the receiver of the messages and the sender of the acknowledgments.
+fn spawn_acking_server() -> (
+    common::TestServer,
+    mpsc::UnboundedReceiver<Message>,
+    mpsc::UnboundedSender<Message>,
+) {
+    let (endpoint, server) = common::bind("127.0.0.1:0".parse().unwrap(), common::generate_cert());
+    let (data_tx, data_rx) = mpsc::unbounded_channel();
+    let (ack_tx, mut ack_rx) = mpsc::unbounded_channel::<Message>();
+
+    tokio::spawn(async move {
+        let connection = endpoint.accept().await.unwrap().await.unwrap();
+        let (send, recv) = connection.accept_bi().await.unwrap();
+
+        tokio::spawn(async move {
+            let mut sink = MessageSink::new(send);
+            while let Some(ack) = ack_rx.recv().await {
+                sink.send(ack).await.unwrap();
+            }
+        });
+
+        let mut messages = MessageStream::new(recv);
+        while let Some(Ok(msg)) = messages.next().await {
+            if msg.message_type.is_data() {
+                let _ = data_tx.send(msg);
+            }
+        }
+        connection.closed().await;
+    });
+
+    (server, data_rx, ack_tx)
+}
+
+/// Starts a client sending at most `max_in_flight` messages ahead of their `Ack` to the server;
+/// returns its local address.
+async fn start_client(server: common::TestServer, max_in_flight: usize) -> SocketAddr {
+    let mut config = ClientConfig::new(server.addr, "127.0.0.1:3000".parse().unwrap());
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.server_certs = vec![server.cert];
+    config.max_in_flight = Some(max_in_flight);
+    let client = Arc::new(Client::new(config).unwrap());
+
+    let local_addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+    let listener = client.clone();
+    tokio::spawn(async move { listener.listen_local(local_addr).await });
+    local_addr
+}
+
+/// Connects to the local listener of the client, waiting for it to listen.
+async fn connect_local(local_addr: SocketAddr) -> TcpStream {
+    timeout(Duration::from_secs(5), async {
+        loop {
+            match TcpStream::connect(local_addr).await {
+                Ok(socket) => return socket,
+                Err(_) => sleep(Duration::from_millis(10)).await,
+            }
+        }
+    })
+    .await
+    .expect("client is not listening")
+}
+
+/// Writes each payload and waits for the server to receive it before the next one, so that they
+/// are sent as messages of their own; returns the messages.
+async fn send_each(
+    socket: &mut TcpStream,
+    data_rx: &mut mpsc::UnboundedReceiver<Message>,
+    payloads: &[&[u8]],
+) -> Vec<Message> {
+    let mut sent = Vec::new();
+    for payload in payloads {
+        socket.write_all(payload).await.unwrap();
+        let msg = timeout(Duration::from_secs(5), data_rx.recv())
+            .await
+            .expect("Data within the window was not sent")
+            .unwrap();
+        assert_eq!(&msg.payload[..], *payload);
+        sent.push(msg);
+    }
+    sent
+}
+
+#[tokio::test]
+async fn sender_pauses_once_the_window_is_full_until_acks_arrive() {
+    let (server, mut data_rx, ack_tx) = spawn_acking_server();
+    let local_addr = start_client(server, 2).await;
+    let mut socket = connect_local(local_addr).await;
+
+    let sent = send_each(&mut socket, &mut data_rx, &[b"first", b"second"]).await;
+
+    socket.write_all(b"third").await.unwrap();
+    assert!(
+        timeout(Duration::from_millis(300), data_rx.recv())
+            .await
+            .is_err(),
+        "Data beyond the window was sent before any Ack"
+    );
+
+    let first = &sent[0];
+    ack_tx
+        .send(Message::ack(first.connection_id, first.message_id))
+        .unwrap();
+    let third = timeout(Duration::from_secs(5), data_rx.recv())
+        .await
+        .expect("Ack did not make room in the window")
+        .unwrap();
+    assert_eq!(&third.payload[..], b"third");
+}
+
+#[tokio::test]
+async fn closed_tunnel_gives_back_its_unacknowledged_room() {
+    let (server, mut data_rx, ack_tx) = spawn_acking_server();
+    let local_addr = start_client(server, 2).await;
+
+    // The first tunnel fills the window, then the server closes it without acknowledging them.
+    let mut first = connect_local(local_addr).await;
+    let sent = send_each(&mut first, &mut data_rx, &[b"first", b"second"]).await;
+    ack_tx
+        .send(Message::close(sent[0].connection_id, CloseReason::Normal))
+        .unwrap();
+
+    let mut second = connect_local(local_addr).await;
+    second.write_all(b"third").await.unwrap();
+    let third = timeout(Duration::from_secs(5), data_rx.recv())
+        .await
+        .expect("window still full of the messages of the closed tunnel")
+        .unwrap();
+    assert_eq!(&third.payload[..], b"third");
+    assert_ne!(third.connection_id, sent[0].connection_id);
+}
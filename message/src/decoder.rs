@@ -0,0 +1,77 @@
+use bytes::BytesMut;
+use uuid::Uuid;
+
+use crate::{ErrorKind, MAGIC_BYTE, Message, Result, error};
+
+/// Accumulates bytes read from a stream and splits them into complete [`Message`]s.
+///
+/// Stream reads don't respect message boundaries: a single read may contain a part of a header,
+/// or the end of one message followed by the beginning of the next one.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buffer: BytesMut,
+
+    /// Longest frame accepted, header included; longer ones are rejected.
+    max_frame_size: Option<usize>,
+
+    /// Connection ID of the frame currently being decoded.
+    connection_id: Option<Uuid>,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Creates a decoder rejecting the frames longer than `max_frame_size` bytes, header
+    /// included, as soon as their header is read.
+    pub fn with_max_frame_size(max_frame_size: usize) -> Decoder {
+        Decoder {
+            max_frame_size: Some(max_frame_size),
+            ..Decoder::default()
+        }
+    }
+
+    /// Appends the bytes read from the stream.
+    pub fn extend(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Number of buffered bytes not yet returned as a [`Message`].
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Connection ID of the last frame whose header was read, i.e. of the frame that failed to
+    /// decode after [`Decoder::next_message`] returned an error.
+    pub fn connection_id(&self) -> Option<Uuid> {
+        self.connection_id
+    }
+
+    /// Returns the next complete message, or `None` if more bytes are required. A frame not
+    /// starting with the magic byte is rejected without waiting for the rest of it.
+    pub fn next_message(&mut self) -> Result<Option<Message>> {
+        if self.buffer.first().is_some_and(|&byte| byte != MAGIC_BYTE) {
+            self.connection_id = None;
+            return Err(error(ErrorKind::InvalidData, "Invalid magic byte"));
+        }
+
+        let Some(frame_length) = Message::frame_length(&self.buffer) else {
+            return Ok(None);
+        };
+        self.connection_id = Uuid::from_slice(&self.buffer[4..20]).ok();
+
+        if self.max_frame_size.is_some_and(|max| frame_length > max) {
+            return Err(error(
+                ErrorKind::InvalidData,
+                "Frame exceeds the maximum size",
+            ));
+        }
+        if self.buffer.len() < frame_length {
+            return Ok(None);
+        }
+
+        let frame = self.buffer.split_to(frame_length).freeze();
+        Message::decode(&frame).map(Some)
+    }
+}
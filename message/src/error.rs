@@ -0,0 +1,80 @@
+use core::fmt;
+
+/// Category of a [`MessageError`]; converted to the matching `std::io::ErrorKind` with `std`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Frame or payload is shorter than announced.
+    UnexpectedEof,
+
+    /// Bytes don't form a valid message.
+    InvalidData,
+
+    /// Value can't be represented in a message.
+    InvalidInput,
+
+    /// Value is valid but not supported by the protocol.
+    Unsupported,
+}
+
+/// Error returned when a message can't be encoded or decoded; usable without `std`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageError {
+    kind: ErrorKind,
+    message: &'static str,
+}
+
+impl MessageError {
+    pub const fn new(kind: ErrorKind, message: &'static str) -> MessageError {
+        MessageError { kind, message }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message)
+    }
+}
+
+impl core::error::Error for MessageError {}
+
+#[cfg(feature = "std")]
+impl From<ErrorKind> for std::io::ErrorKind {
+    fn from(kind: ErrorKind) -> std::io::ErrorKind {
+        match kind {
+            ErrorKind::UnexpectedEof => std::io::ErrorKind::UnexpectedEof,
+            ErrorKind::InvalidData => std::io::ErrorKind::InvalidData,
+            ErrorKind::InvalidInput => std::io::ErrorKind::InvalidInput,
+            ErrorKind::Unsupported => std::io::ErrorKind::Unsupported,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<MessageError> for std::io::Error {
+    fn from(err: MessageError) -> std::io::Error {
+        std::io::Error::new(err.kind.into(), err)
+    }
+}
+
+/// Error returned by the crate: `std::io::Error` with `std`, [`MessageError`] otherwise.
+#[cfg(feature = "std")]
+pub type Error = std::io::Error;
+
+/// Error returned by the crate: `std::io::Error` with `std`, [`MessageError`] otherwise.
+#[cfg(not(feature = "std"))]
+pub type Error = MessageError;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Builds the error returned by the crate.
+pub(crate) fn error(kind: ErrorKind, message: &'static str) -> Error {
+    #[cfg(feature = "std")]
+    return MessageError::new(kind, message).into();
+
+    #[cfg(not(feature = "std"))]
+    return MessageError::new(kind, message);
+}
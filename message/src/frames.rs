@@ -0,0 +1,58 @@
+use bytes::Bytes;
+
+use crate::{ErrorKind, MAGIC_BYTE, Message, Result, error};
+
+/// Iterator over the complete frames of a buffer, see [`Message::decode_all`].
+///
+/// It ends at a trailing partial frame, whose bytes are left in [`Frames::remaining`], or after
+/// yielding the error of an invalid frame.
+#[derive(Debug, Clone)]
+pub struct Frames {
+    buf: Bytes,
+    failed: bool,
+}
+
+impl Frames {
+    pub(crate) fn new(buf: &Bytes) -> Frames {
+        Frames {
+            buf: buf.clone(),
+            failed: false,
+        }
+    }
+
+    /// Number of bytes not decoded yet: those of the trailing partial frame once the iterator
+    /// ended, unless a frame failed to decode.
+    pub fn remaining(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Bytes not decoded yet, to be completed by the next read.
+    pub fn rest(&self) -> &Bytes {
+        &self.buf
+    }
+}
+
+impl Iterator for Frames {
+    type Item = Result<Message>;
+
+    fn next(&mut self) -> Option<Result<Message>> {
+        if self.failed {
+            return None;
+        }
+
+        if self.buf.first().is_some_and(|&byte| byte != MAGIC_BYTE) {
+            self.failed = true;
+            return Some(Err(error(ErrorKind::InvalidData, "Invalid magic byte")));
+        }
+
+        let frame_length = Message::frame_length(&self.buf)?;
+        if self.buf.len() < frame_length {
+            return None;
+        }
+
+        let frame = self.buf.split_to(frame_length);
+        let msg = Message::decode(&frame);
+        self.failed = msg.is_err();
+        Some(msg)
+    }
+}
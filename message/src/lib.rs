@@ -6,20 +6,26 @@
 // - Message Start Bit (magic)
 // - Protocol Version
 // - Message Type (Initial)
+// - Flags
 // - Connection ID
 // - Message ID
 // - Connection details:
 //   - Client IP and Port
 //   - Target host and port the client is proxying to
+//   - Optional authentication token (length-prefixed)
+//   - Optional target hostname the server resolves (length-prefixed)
 // - Payload length and data
 //
 // After the connection is initialized, all messages from that client will include:
 // - Message Start Bit (magic)
 // - Protocol Version
 // - Message Type (Data, Close, Ping, etc.)
+// - Flags (which optional fields follow the header)
 // - Connection ID
 // - Message ID
 // - Payload length
+// - Optional fields (e.g. ID of the message this one replies to, or the time it was sent)
+// - Optional extensions: type-length-value fields, added without breaking the format
 // - Payload
 //
 // Since the conenction is always bidirectional, server will have the same structure of the
@@ -33,25 +39,82 @@
 // defined by the `length`.
 //
 // This protocol works both ways — from client to server and from server to client.
+//
+// Encoding and decoding only need `core` and `alloc`; the `std` feature (on by default) adds the
+// `SocketAddr` based constructors and reports errors as `std::io::Error` instead of `MessageError`.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::{fmt, net::Ipv4Addr, time::Duration};
+#[cfg(feature = "std")]
 use std::{
-    io::{self, ErrorKind},
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    net::{IpAddr, SocketAddr},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 use uuid::Uuid;
 
 #[path = "utils.rs"]
 pub mod msg_utils;
 
+mod decoder;
+mod error;
+mod frames;
+#[cfg(feature = "stream")]
+mod sink;
+#[cfg(feature = "stream")]
+mod stream;
+
+pub use decoder::Decoder;
+pub use error::{Error, ErrorKind, MessageError, Result};
+pub use frames::Frames;
+#[cfg(feature = "stream")]
+pub use sink::MessageSink;
+#[cfg(feature = "stream")]
+pub use stream::MessageStream;
+
+use error::error;
+
 /// The maximum size of a single chunk of data in bytes.
 pub const CHUNK_SIZE: usize = 512;
 
 /// Message Start Bit used to identify the beginning of a message frame.
 pub const MAGIC_BYTE: u8 = 0xAA;
 
+/// ALPN protocol identifier negotiated by the client and the server by default.
+pub const DEFAULT_ALPN: &[u8] = b"reverprox/1";
+
 /// Lenght of the fields magic-lenght
-pub const HEADER_LENGTH: usize = 39;
+pub const HEADER_LENGTH: usize = 40;
+
+/// Length of the header followed by all of its optional fields; a frame without extensions is at
+/// most this much longer than its payload.
+pub const MAX_HEADER_LENGTH: usize = HEADER_LENGTH + 16 + 8 + 8;
+
+/// Flag bit set when the header is followed by the [`Message::in_reply_to`] ID (16 bytes).
+pub const FLAG_IN_REPLY_TO: u8 = 0b0000_0001;
+
+/// Flag bit set when the header is followed by the [`Message::sequence`] number (8 bytes).
+pub const FLAG_SEQUENCE: u8 = 0b0000_0010;
+
+/// Flag bit set when the header is followed by the [`Message::timestamp`] (8 bytes).
+pub const FLAG_TIMESTAMP: u8 = 0b0000_0100;
+
+/// Flag bit set when the header is followed by [`Message::extensions`]: their total length
+/// (2 bytes), then each of them as its kind (1 byte), length (2 bytes) and value.
+pub const FLAG_EXTENSIONS: u8 = 0b0000_1000;
+
+/// Kind of the extension holding a time in microseconds since the UNIX epoch (8 bytes).
+pub const EXTENSION_TIMESTAMP: u8 = 0x1;
+
+/// Kind of the extension holding a checksum of the payload (4 bytes).
+pub const EXTENSION_CHECKSUM: u8 = 0x2;
+
+/// Kind of the extension holding a token, of any length.
+pub const EXTENSION_TOKEN: u8 = 0x3;
 
 /// Represents the type of the message transferred between server and client.
 /// It is used to determine how to decode the payload and how to route the logic.
@@ -69,18 +132,205 @@ pub enum MessageType {
 
     /// Used to check if the connection is alive.
     Ping = 0x4,
+
+    /// Used to acknowledge the message whose ID is carried in [`Message::in_reply_to`].
+    Ack = 0x5,
+
+    /// Used to answer a [`MessageType::Ping`]; carries the payload of the ping back.
+    Pong = 0x6,
+
+    /// Used to signal that the sender won't send more data on the tunnel, like a TCP FIN; data
+    /// keeps flowing in the other direction until the tunnel is closed.
+    ShutdownWrite = 0x7,
+}
+
+impl MessageType {
+    /// Whether the message carries proxied bytes in its payload.
+    pub fn is_data(&self) -> bool {
+        matches!(self, MessageType::Data)
+    }
+
+    /// Whether the message manages a tunnel or the connection instead of carrying proxied bytes.
+    pub fn is_control(&self) -> bool {
+        !self.is_data()
+    }
+
+    /// Whether the message belongs to the tunnel identified by its `connection_id`; `Ping` and
+    /// `Pong` concern the whole connection and carry no tunnel.
+    pub fn requires_connection(&self) -> bool {
+        !matches!(self, MessageType::Ping | MessageType::Pong)
+    }
+
+    /// Whether the message may be processed from QUIC 0-RTT data, which can be replayed: it
+    /// neither reaches a backend nor opens a connection to one, unlike `Initial`, `Data`,
+    /// `ShutdownWrite` and `Close`.
+    pub fn is_replay_safe(&self) -> bool {
+        matches!(
+            self,
+            MessageType::Ping | MessageType::Pong | MessageType::Ack
+        )
+    }
+
+    /// Stable lowercase name, e.g. for log fields and metric labels.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageType::Initial => "initial",
+            MessageType::Data => "data",
+            MessageType::Close => "close",
+            MessageType::Ping => "ping",
+            MessageType::Ack => "ack",
+            MessageType::Pong => "pong",
+            MessageType::ShutdownWrite => "shutdown_write",
+        }
+    }
+}
+
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Represents the reason carried in the payload of a [`MessageType::Close`] message.
+/// Encoded as a single byte.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// Connection is closed without any error.
+    Normal = 0x0,
+
+    /// Client failed to present a valid authentication token.
+    AuthFailed = 0x1,
+
+    /// Requested proxy target is not allowed by the server policy.
+    TargetNotAllowed = 0x2,
+
+    /// Server could not resolve or connect to the requested proxy target, e.g. timed out.
+    BackendUnreachable = 0x3,
+
+    /// Peer sent a message that is invalid in the current state of the connection.
+    ProtocolError = 0x4,
+
+    /// Tunnel carried no data for longer than the idle timeout of the server.
+    Timeout = 0x5,
+
+    /// Requested proxy target actively refused the connection: nothing listens on its port, so
+    /// retrying right away is pointless.
+    BackendRefused = 0x6,
+
+    /// Server has no room for more connections; the client should back off before retrying.
+    ServerFull = 0x7,
+}
+
+impl CloseReason {
+    pub fn encode(&self) -> Bytes {
+        Bytes::copy_from_slice(&[*self as u8])
+    }
+
+    pub fn decode(msg: &Bytes) -> Result<CloseReason> {
+        if msg.is_empty() {
+            return Err(error(ErrorKind::UnexpectedEof, "Close reason is missing"));
+        }
+
+        match msg[0] {
+            0x0 => Ok(CloseReason::Normal),
+            0x1 => Ok(CloseReason::AuthFailed),
+            0x2 => Ok(CloseReason::TargetNotAllowed),
+            0x3 => Ok(CloseReason::BackendUnreachable),
+            0x4 => Ok(CloseReason::ProtocolError),
+            0x5 => Ok(CloseReason::Timeout),
+            0x6 => Ok(CloseReason::BackendRefused),
+            0x7 => Ok(CloseReason::ServerFull),
+            _ => Err(error(ErrorKind::InvalidData, "Unknown close reason")),
+        }
+    }
+
+    /// Stable lowercase name, e.g. for log fields and metric labels.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CloseReason::Normal => "normal",
+            CloseReason::AuthFailed => "auth_failed",
+            CloseReason::TargetNotAllowed => "target_not_allowed",
+            CloseReason::BackendUnreachable => "backend_unreachable",
+            CloseReason::ProtocolError => "protocol_error",
+            CloseReason::Timeout => "timeout",
+            CloseReason::BackendRefused => "backend_refused",
+            CloseReason::ServerFull => "server_full",
+        }
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// Represents the version of the QUIC protocol used in the system.
+///
+/// The client advertises the latest version it supports in the [`InitializationMessage`]; the
+/// server picks the latest version both support and sends it back in the payload of the
+/// [`MessageType::Ack`] of the `Initial`. The messages of the tunnel are encoded with it.
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ProtocolVersion {
     /// Current and only supported version.
     V1 = 0x1,
 }
 
+impl ProtocolVersion {
+    /// Latest version supported by this implementation.
+    pub const LATEST: ProtocolVersion = ProtocolVersion::V1;
+
+    /// Returns the version with the given wire value, if supported.
+    pub fn from_u8(value: u8) -> Option<ProtocolVersion> {
+        match value {
+            0x1 => Some(ProtocolVersion::V1),
+            _ => None,
+        }
+    }
+
+    /// Picks the latest supported version not above the one `advertised` by the peer; `None` if
+    /// the peer supports none of ours.
+    pub fn negotiate(advertised: u8) -> Option<ProtocolVersion> {
+        ProtocolVersion::from_u8(advertised.min(ProtocolVersion::LATEST as u8))
+    }
+
+    pub fn encode(&self) -> Bytes {
+        Bytes::copy_from_slice(&[*self as u8])
+    }
+
+    pub fn decode(msg: &Bytes) -> Result<ProtocolVersion> {
+        if msg.is_empty() {
+            return Err(error(
+                ErrorKind::UnexpectedEof,
+                "Protocol version is missing",
+            ));
+        }
+
+        ProtocolVersion::from_u8(msg[0])
+            .ok_or_else(|| error(ErrorKind::InvalidData, "Unknown protocol version"))
+    }
+
+    /// Stable lowercase name, e.g. for log fields and metric labels.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProtocolVersion::V1 => "v1",
+        }
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// The core protocol unit that is transmitted through the QUIC stream.
 /// It contains all metadata and the payload needed to process a client-server exchange.
+///
+/// Cloning is cheap: the fields besides the payload are `Copy`, and the payload [`Bytes`] is
+/// reference counted, so the clone shares it, like a decoded message shares its frame.
 #[derive(Debug, Clone)]
 pub struct Message {
     /// Message Start Bit; fixed length = 1 byte; used to identify the beginning of a message.
@@ -92,15 +342,35 @@ pub struct Message {
     /// Type of the message; defines how the payload should be interpreted. len = 1 byte
     pub message_type: MessageType,
 
+    /// Bit set describing the optional fields following the header; fixed length = 1 byte.
+    pub flags: u8,
+
     /// ID that identifies the connection; fixed length = 16 bytes - UUIDv4; same for all messages on a virtual tunnel.
     pub connection_id: Uuid,
 
-    /// ID that identifies the message; fixed length = 16 bytes, UUIDv4;
+    /// ID that identifies the message; fixed length = 16 bytes, UUIDv4 (time-ordered UUIDv7 with the
+    /// `uuid-v7` feature);
     pub message_id: Uuid,
 
     /// Payload length in bytes; fixed length = 4 bytes; used to determine how many bytes to read after header.
     pub length: u32,
 
+    /// ID of the message this one replies to; optional, 16 bytes, present when [`FLAG_IN_REPLY_TO`] is set.
+    pub in_reply_to: Option<Uuid>,
+
+    /// Position of the message in its tunnel, starting at 0; optional, 8 bytes, present when
+    /// [`FLAG_SEQUENCE`] is set. Follows `in_reply_to` when both are present.
+    pub sequence: Option<u64>,
+
+    /// Time the message was sent, in microseconds since the UNIX epoch on the clock of the
+    /// sender; optional, 8 bytes, present when [`FLAG_TIMESTAMP`] is set. Follows `sequence`.
+    pub timestamp: Option<u64>,
+
+    /// Extensions by kind, in the order they are encoded; optional, present when
+    /// [`FLAG_EXTENSIONS`] is set. Follow `timestamp`. The kinds unknown to the receiver are kept
+    /// as is.
+    pub extensions: Vec<(u8, Bytes)>,
+
     /// Actual Payload; variable length = N; interpretation depends on `message_type`.
     pub payload: Bytes,
 }
@@ -111,117 +381,477 @@ impl Message {
             magic: MAGIC_BYTE,
             version: ProtocolVersion::V1,
             message_type: msg_type,
+            flags: 0,
             connection_id,
-            message_id: msg_utils::generate_uuid(),
+            message_id: msg_utils::generate_message_id(),
             length: payload.len() as u32,
+            in_reply_to: None,
+            sequence: None,
+            timestamp: None,
+            extensions: Vec::new(),
             payload,
         }
     }
 
+    /// Builds a [`MessageType::Data`] message relaying the payload over the tunnel.
+    pub fn data(connection_id: Uuid, payload: Bytes) -> Message {
+        Message::new(MessageType::Data, connection_id, payload)
+    }
+
+    /// Builds the [`MessageType::Initial`] opening the tunnel described by `init`.
+    pub fn initial(connection_id: Uuid, init: &InitializationMessage) -> Message {
+        Message::new(MessageType::Initial, connection_id, init.encode())
+    }
+
+    /// Builds the [`MessageType::Close`] of the tunnel for the given reason.
+    pub fn close(connection_id: Uuid, reason: CloseReason) -> Message {
+        Message::new(MessageType::Close, connection_id, reason.encode())
+    }
+
+    /// Builds the [`MessageType::Ack`] of the message `acked_id` of the tunnel.
+    pub fn ack(connection_id: Uuid, acked_id: Uuid) -> Message {
+        Message::new(MessageType::Ack, connection_id, Bytes::new()).with_reply_to(acked_id)
+    }
+
+    /// Builds a [`MessageType::Ping`] sent at `sent` microseconds, on the clock of the sender,
+    /// whose pong is expected within `deadline`. The pong carries both back.
+    pub fn ping_with_deadline(sent: u64, deadline: Duration) -> Message {
+        let deadline = u64::try_from(deadline.as_micros()).unwrap_or(u64::MAX);
+        let mut payload = BytesMut::with_capacity(16);
+        payload.put_u64(sent);
+        payload.put_u64(deadline);
+
+        Message::new(MessageType::Ping, Uuid::nil(), payload.freeze())
+    }
+
+    /// Time a [`Message::ping_with_deadline`], or the pong answering it, was sent at and its
+    /// deadline; `None` if the payload isn't of such a ping.
+    pub fn ping_deadline(&self) -> Option<(u64, Duration)> {
+        let payload = <[u8; 16]>::try_from(&self.payload[..]).ok()?;
+        let (sent, deadline) = payload.split_at(8);
+
+        Some((
+            u64::from_be_bytes(sent.try_into().ok()?),
+            Duration::from_micros(u64::from_be_bytes(deadline.try_into().ok()?)),
+        ))
+    }
+
+    /// Marks the message as a reply to the message with the given ID.
+    pub fn with_reply_to(mut self, message_id: Uuid) -> Message {
+        self.in_reply_to = Some(message_id);
+        self.flags |= FLAG_IN_REPLY_TO;
+        self
+    }
+
+    /// Sets the position of the message in its tunnel.
+    pub fn with_sequence(mut self, sequence: u64) -> Message {
+        self.sequence = Some(sequence);
+        self.flags |= FLAG_SEQUENCE;
+        self
+    }
+
+    /// Sets the time the message is sent at, in microseconds since the UNIX epoch.
+    pub fn with_timestamp(mut self, timestamp: u64) -> Message {
+        self.timestamp = Some(timestamp);
+        self.flags |= FLAG_TIMESTAMP;
+        self
+    }
+
+    /// Stamps the message with the current time.
+    #[cfg(feature = "std")]
+    pub fn timestamped(self) -> Message {
+        self.with_timestamp(now_micros())
+    }
+
+    /// Time the message took to arrive by `now` (in microseconds since the UNIX epoch), if it is
+    /// timestamped. The clocks of the two sides may be skewed: a timestamp ahead of `now` gives
+    /// zero rather than a negative time.
+    pub fn transit_time(&self, now: u64) -> Option<Duration> {
+        self.timestamp
+            .map(|timestamp| Duration::from_micros(now.saturating_sub(timestamp)))
+    }
+
+    /// Sets the value of the extension `kind`, replacing the previous one.
+    pub fn set_extension(&mut self, kind: u8, value: Bytes) {
+        match self.extensions.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, current)) => *current = value,
+            None => self.extensions.push((kind, value)),
+        }
+        self.flags |= FLAG_EXTENSIONS;
+    }
+
+    /// Value of the extension `kind`, if the message has one.
+    pub fn get_extension(&self, kind: u8) -> Option<&Bytes> {
+        self.extensions
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, value)| value)
+    }
+
+    /// Encodes the message with the version negotiated for its tunnel.
+    pub fn with_version(mut self, version: ProtocolVersion) -> Message {
+        self.version = version;
+        self
+    }
+
+    /// Length of the optional fields announced by the `flags`.
+    fn optional_length(flags: u8) -> usize {
+        let mut length = 0;
+
+        if flags & FLAG_IN_REPLY_TO != 0 {
+            length += 16;
+        }
+        if flags & FLAG_SEQUENCE != 0 {
+            length += 8;
+        }
+        if flags & FLAG_TIMESTAMP != 0 {
+            length += 8;
+        }
+
+        length
+    }
+
+    /// Length of the encoded extensions, their total length included.
+    fn extensions_length(&self) -> usize {
+        if self.extensions.is_empty() {
+            return 0;
+        }
+
+        2 + self
+            .extensions
+            .iter()
+            .map(|(_, value)| 3 + value.len())
+            .sum::<usize>()
+    }
+
+    /// Returns the length of the whole frame starting at the beginning of `buf`,
+    /// or `None` if the header, along with the length of its extensions, isn't complete yet.
+    pub fn frame_length(buf: &[u8]) -> Option<usize> {
+        if buf.len() < HEADER_LENGTH {
+            return None;
+        }
+
+        let length = u32::from_be_bytes(buf[36..40].try_into().unwrap()) as usize;
+        let mut header_length = HEADER_LENGTH + Message::optional_length(buf[3]);
+        if buf[3] & FLAG_EXTENSIONS != 0 {
+            let extensions = buf.get(header_length..header_length + 2)?;
+            header_length += 2 + u16::from_be_bytes([extensions[0], extensions[1]]) as usize;
+        }
+
+        Some(header_length + length)
+    }
+
+    /// Flags written on the wire: the optional field bits follow the fields actually set.
+    fn wire_flags(&self) -> u8 {
+        let mut flags =
+            self.flags & !(FLAG_IN_REPLY_TO | FLAG_SEQUENCE | FLAG_TIMESTAMP | FLAG_EXTENSIONS);
+        if self.in_reply_to.is_some() {
+            flags |= FLAG_IN_REPLY_TO;
+        }
+        if self.sequence.is_some() {
+            flags |= FLAG_SEQUENCE;
+        }
+        if self.timestamp.is_some() {
+            flags |= FLAG_TIMESTAMP;
+        }
+        if !self.extensions.is_empty() {
+            flags |= FLAG_EXTENSIONS;
+        }
+
+        flags
+    }
+
+    /// Length of the encoded frame in bytes.
+    pub fn encoded_len(&self) -> usize {
+        HEADER_LENGTH
+            + Message::optional_length(self.wire_flags())
+            + self.extensions_length()
+            + self.payload.len()
+    }
+
     pub fn encode(&self) -> Bytes {
-        let mut buffer = Vec::with_capacity(HEADER_LENGTH + self.payload.len());
+        let mut buffer = BytesMut::with_capacity(self.encoded_len());
+        self.encode_into(&mut buffer);
 
-        buffer.push(self.magic);
-        buffer.push(self.version as u8);
-        buffer.push(self.message_type as u8);
-        buffer.extend_from_slice(self.connection_id.as_bytes());
-        buffer.extend_from_slice(self.message_id.as_bytes());
-        buffer.extend_from_slice(&self.length.to_be_bytes());
-        buffer.extend_from_slice(&self.payload);
+        buffer.freeze()
+    }
 
-        Bytes::from(buffer)
+    /// Appends the encoded frame to `buf`, letting several frames share a single buffer.
+    pub fn encode_into(&self, buf: &mut BytesMut) {
+        buf.reserve(self.encoded_len());
+
+        buf.put_u8(self.magic);
+        buf.put_u8(self.version as u8);
+        buf.put_u8(self.message_type as u8);
+        buf.put_u8(self.wire_flags());
+        buf.put_slice(self.connection_id.as_bytes());
+        buf.put_slice(self.message_id.as_bytes());
+        buf.put_u32(self.length);
+        if let Some(in_reply_to) = &self.in_reply_to {
+            buf.put_slice(in_reply_to.as_bytes());
+        }
+        if let Some(sequence) = self.sequence {
+            buf.put_u64(sequence);
+        }
+        if let Some(timestamp) = self.timestamp {
+            buf.put_u64(timestamp);
+        }
+        if !self.extensions.is_empty() {
+            buf.put_u16((self.extensions_length() - 2) as u16);
+            for (kind, value) in &self.extensions {
+                buf.put_u8(*kind);
+                buf.put_u16(value.len() as u16);
+                buf.put_slice(value);
+            }
+        }
+        buf.put_slice(&self.payload);
     }
 
-    pub fn decode(msg: &Bytes) -> io::Result<Message> {
+    /// Decodes each complete frame of a buffer holding several; the payloads are slices of `buf`.
+    /// A trailing partial frame is left undecoded, see [`Frames::remaining`].
+    pub fn decode_all(buf: &Bytes) -> Frames {
+        Frames::new(buf)
+    }
+
+    /// Decodes a complete frame; the payload is a slice of `msg`, not a copy.
+    pub fn decode(msg: &Bytes) -> Result<Message> {
         if msg.len() < HEADER_LENGTH {
-            return Err(io::Error::new(
-                ErrorKind::UnexpectedEof,
-                "Headers are incomplete",
-            ));
+            return Err(error(ErrorKind::UnexpectedEof, "Headers are incomplete"));
         }
 
         let magic = msg[0];
-        let version = match msg[1] {
-            0x1 => ProtocolVersion::V1,
-            _ => {
-                return Err(io::Error::new(
-                    ErrorKind::InvalidData,
-                    "Unknown message type",
-                ));
-            }
-        };
+        let version = ProtocolVersion::from_u8(msg[1])
+            .ok_or_else(|| error(ErrorKind::InvalidData, "Unknown protocol version"))?;
         let message_type = match msg[2] {
             0x1 => MessageType::Initial,
             0x2 => MessageType::Data,
             0x3 => MessageType::Close,
             0x4 => MessageType::Ping,
+            0x5 => MessageType::Ack,
+            0x6 => MessageType::Pong,
+            0x7 => MessageType::ShutdownWrite,
             _ => {
-                return Err(io::Error::new(
-                    ErrorKind::InvalidData,
-                    "Unknown message type",
-                ));
+                return Err(error(ErrorKind::InvalidData, "Unknown message type"));
             }
         };
-        let connection_id = match Uuid::from_slice(&msg[3..19]) {
-            Ok(uuid) => uuid,
-            Err(err) => {
-                return Err(io::Error::new(ErrorKind::InvalidData, err));
-            }
+        let flags = msg[3];
+
+        let connection_id = Uuid::from_slice(&msg[4..20])
+            .map_err(|_| error(ErrorKind::InvalidData, "Invalid connection ID"))?;
+
+        let message_id = Uuid::from_slice(&msg[20..36])
+            .map_err(|_| error(ErrorKind::InvalidData, "Invalid message ID"))?;
+        let length = u32::from_be_bytes(msg[36..40].try_into().unwrap());
+
+        let mut payload_start = HEADER_LENGTH + Message::optional_length(flags);
+        let extensions_start = payload_start;
+        if flags & FLAG_EXTENSIONS != 0 {
+            let Some(extensions) = msg.get(payload_start..payload_start + 2) else {
+                return Err(error(ErrorKind::UnexpectedEof, "Extensions incomplete"));
+            };
+            payload_start += 2 + u16::from_be_bytes([extensions[0], extensions[1]]) as usize;
+        }
+
+        if msg.len() < payload_start + length as usize {
+            return Err(error(ErrorKind::UnexpectedEof, "Payload incomplete"));
+        }
+
+        let mut offset = HEADER_LENGTH;
+
+        let in_reply_to = if flags & FLAG_IN_REPLY_TO != 0 {
+            offset += 16;
+            Some(Uuid::from_slice(&msg[offset - 16..offset]).unwrap())
+        } else {
+            None
         };
 
-        let message_id = match Uuid::from_slice(&msg[19..35]) {
-            Ok(uuid) => uuid,
-            Err(err) => {
-                return Err(io::Error::new(ErrorKind::InvalidData, err));
-            }
+        let sequence = if flags & FLAG_SEQUENCE != 0 {
+            offset += 8;
+            Some(u64::from_be_bytes(
+                msg[offset - 8..offset].try_into().unwrap(),
+            ))
+        } else {
+            None
         };
-        let length = u32::from_be_bytes(msg[35..39].try_into().unwrap());
 
-        if msg.len() < HEADER_LENGTH + length as usize {
-            return Err(io::Error::new(
-                ErrorKind::UnexpectedEof,
-                "Payload incomplete",
-            ));
-        }
+        let timestamp = if flags & FLAG_TIMESTAMP != 0 {
+            offset += 8;
+            Some(u64::from_be_bytes(
+                msg[offset - 8..offset].try_into().unwrap(),
+            ))
+        } else {
+            None
+        };
+
+        let extensions = if flags & FLAG_EXTENSIONS != 0 {
+            decode_extensions(&msg.slice(extensions_start + 2..payload_start))?
+        } else {
+            Vec::new()
+        };
 
-        let payload = Bytes::from(msg[HEADER_LENGTH..HEADER_LENGTH + length as usize].to_vec());
+        // Shares the frame buffer: relaying the payload doesn't copy it.
+        let payload = msg.slice(payload_start..payload_start + length as usize);
 
-        Ok(Message {
+        let msg = Message {
             magic,
             version,
             message_type,
+            flags,
             message_id,
             connection_id,
             length,
+            in_reply_to,
+            sequence,
+            timestamp,
+            extensions,
             payload,
-        })
+        };
+        msg.validate()?;
+
+        Ok(msg)
+    }
+
+    /// Checks the message is well formed: the magic byte is [`MAGIC_BYTE`], `length` matches the
+    /// payload and the messages of a tunnel have a connection ID. Connection-wide messages
+    /// (`Ping`, `Pong`, and the `Close` or `Ack` of the whole connection) may use the nil ID.
+    pub fn validate(&self) -> Result<()> {
+        if self.magic != MAGIC_BYTE {
+            return Err(error(ErrorKind::InvalidData, "Invalid magic byte"));
+        }
+
+        if self.length as usize != self.payload.len() {
+            return Err(error(
+                ErrorKind::InvalidInput,
+                "Length doesn't match the payload",
+            ));
+        }
+
+        let tunnel_only = matches!(
+            self.message_type,
+            MessageType::Initial | MessageType::Data | MessageType::ShutdownWrite
+        );
+        if tunnel_only && self.connection_id.is_nil() {
+            return Err(error(ErrorKind::InvalidData, "Nil connection ID"));
+        }
+
+        // Their total length doesn't count its own 2 bytes.
+        if self.extensions_length().saturating_sub(2) > u16::MAX as usize {
+            return Err(error(ErrorKind::InvalidData, "Extensions are too long"));
+        }
+
+        Ok(())
     }
 }
 
+/// Decodes the extensions of a frame, checking the length of the known kinds; the values are
+/// slices of `buf`.
+fn decode_extensions(buf: &Bytes) -> Result<Vec<(u8, Bytes)>> {
+    let mut extensions = Vec::new();
+    let mut offset = 0;
+
+    while offset < buf.len() {
+        let Some(header) = buf.get(offset..offset + 3) else {
+            return Err(error(ErrorKind::InvalidData, "Truncated extension"));
+        };
+        let kind = header[0];
+        let length = u16::from_be_bytes([header[1], header[2]]) as usize;
+        offset += 3;
+
+        if buf.len() < offset + length {
+            return Err(error(ErrorKind::InvalidData, "Truncated extension"));
+        }
+        let expected = match kind {
+            EXTENSION_TIMESTAMP => Some(8),
+            EXTENSION_CHECKSUM => Some(4),
+            _ => None,
+        };
+        if expected.is_some_and(|expected| expected != length) {
+            return Err(error(ErrorKind::InvalidData, "Invalid extension length"));
+        }
+
+        extensions.push((kind, buf.slice(offset..offset + length)));
+        offset += length;
+    }
+
+    Ok(extensions)
+}
+
+/// Current time in microseconds since the UNIX epoch, as carried by [`Message::timestamp`].
+#[cfg(feature = "std")]
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_micros() as u64)
+}
+
+/// Length of the fixed part of the [`InitializationMessage`] (ports and addresses).
+pub const INITIALIZATION_LENGTH: usize = 12;
+
+/// Destination the server should open the backend connection to.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProxyTarget {
+    /// Already resolved socket address.
+    Addr(SocketAddr),
+
+    /// Hostname and port that have to be resolved by the server.
+    Host(String, u16),
+}
+
 /// A payload structure that appears only in the [`MessageType::Initial`] message.
 /// It contains metadata required to associate a client with a target server to proxy.
-#[derive(Debug, Clone, Copy)]
+///
+/// Wire layout, in the order of the fields: `client_port | proxy_port | client_ip | proxy_host`,
+/// optionally followed by `token_len (2 bytes) | token | hostname_len (1 byte) | hostname`.
+/// Its `Debug` output leaves the token out, since it's a secret.
+#[derive(Clone)]
 pub struct InitializationMessage {
-    /// IPv4 address of the client
-    pub client_ip: Ipv4Addr,
-
     /// Port on which the client runs the QUIC connection
     pub client_port: u16,
 
     /// Local port on the client machine the server will proxy data to
     pub proxy_port: u16,
 
-    /// Local host on the client machine the server will proxy data to
+    /// IPv4 address of the client
+    pub client_ip: Ipv4Addr,
+
+    /// Local host on the client machine the server will proxy data to;
+    /// unspecified (`0.0.0.0`) when `proxy_hostname` is set.
     pub proxy_host: Ipv4Addr,
+
+    /// Shared-secret token used by the server to authenticate the client;
+    /// encoded after the fixed fields as a 2 bytes length followed by the token bytes.
+    /// Zero length on the wire means no token.
+    pub token: Option<Bytes>,
+
+    /// Hostname the server resolves instead of using `proxy_host`;
+    /// encoded after the token as a 1 byte length followed by the name.
+    pub proxy_hostname: Option<String>,
+
+    /// Latest [`ProtocolVersion`] supported by the client, as a raw value since it may be unknown
+    /// to the server; encoded after the hostname, only when above [`ProtocolVersion::V1`].
+    pub max_version: u8,
+}
+
+impl fmt::Debug for InitializationMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InitializationMessage")
+            .field("client_port", &self.client_port)
+            .field("proxy_port", &self.proxy_port)
+            .field("client_ip", &self.client_ip)
+            .field("proxy_host", &self.proxy_host)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .field("proxy_hostname", &self.proxy_hostname)
+            .field("max_version", &self.max_version)
+            .finish()
+    }
 }
 
 impl InitializationMessage {
-    pub fn new(addr: SocketAddr, proxy_addr: SocketAddr) -> io::Result<InitializationMessage> {
+    #[cfg(feature = "std")]
+    pub fn new(addr: SocketAddr, proxy_addr: SocketAddr) -> Result<InitializationMessage> {
         if !addr.is_ipv4() || !proxy_addr.is_ipv4() {
-            return Err(io::Error::new(
-                ErrorKind::Unsupported,
-                "IPv6 is not supported",
-            ));
+            return Err(error(ErrorKind::Unsupported, "IPv6 is not supported"));
         }
 
         let ipv4 = match addr.ip() {
@@ -235,27 +865,130 @@ impl InitializationMessage {
         };
 
         Ok(InitializationMessage {
-            client_ip: ipv4,
             client_port: addr.port(),
             proxy_port: proxy_addr.port(),
+            client_ip: ipv4,
             proxy_host: proxy_ipv4,
+            token: None,
+            proxy_hostname: None,
+            max_version: ProtocolVersion::LATEST as u8,
         })
     }
 
+    /// Creates a message asking the server to resolve `hostname` and proxy to it.
+    #[cfg(feature = "std")]
+    pub fn with_hostname(
+        addr: SocketAddr,
+        hostname: &str,
+        port: u16,
+    ) -> Result<InitializationMessage> {
+        if hostname.is_empty() || hostname.len() > u8::MAX as usize {
+            return Err(error(
+                ErrorKind::InvalidInput,
+                "Hostname length must be between 1 and 255 bytes",
+            ));
+        }
+
+        let mut init = InitializationMessage::new(
+            addr,
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port),
+        )?;
+        init.proxy_hostname = Some(String::from(hostname));
+
+        Ok(init)
+    }
+
+    /// Attaches the authentication token that will be sent to the server.
+    pub fn with_token(mut self, token: Bytes) -> Result<InitializationMessage> {
+        if token.len() > u16::MAX as usize {
+            return Err(error(ErrorKind::InvalidInput, "Token is too long"));
+        }
+
+        self.token = Some(token);
+        Ok(self)
+    }
+
+    /// Advertises `max_version` as the latest protocol version supported by the client.
+    pub fn with_max_version(mut self, max_version: u8) -> InitializationMessage {
+        self.max_version = max_version;
+        self
+    }
+
+    /// Returns the destination the server should proxy to.
+    #[cfg(feature = "std")]
+    pub fn target(&self) -> ProxyTarget {
+        match &self.proxy_hostname {
+            Some(hostname) => ProxyTarget::Host(hostname.clone(), self.proxy_port),
+            None => ProxyTarget::Addr(SocketAddr::new(
+                IpAddr::V4(self.proxy_host),
+                self.proxy_port,
+            )),
+        }
+    }
+
     pub fn encode(&self) -> Bytes {
-        let mut buffer = Vec::with_capacity(12);
+        let token = self.token.as_deref().unwrap_or_default();
+        let hostname = self.proxy_hostname.as_deref().unwrap_or_default();
+        let has_version = self.max_version != ProtocolVersion::V1 as u8;
+        let has_extra = !token.is_empty() || !hostname.is_empty() || has_version;
+
+        let mut buffer =
+            Vec::with_capacity(INITIALIZATION_LENGTH + 4 + token.len() + hostname.len());
 
         buffer.extend_from_slice(&self.client_port.to_be_bytes());
         buffer.extend_from_slice(&self.proxy_port.to_be_bytes());
         buffer.extend_from_slice(&self.client_ip.octets());
         buffer.extend_from_slice(&self.proxy_host.octets());
 
+        if has_extra {
+            buffer.extend_from_slice(&(token.len() as u16).to_be_bytes());
+            buffer.extend_from_slice(token);
+        }
+
+        if !hostname.is_empty() || has_version {
+            buffer.push(hostname.len() as u8);
+            buffer.extend_from_slice(hostname.as_bytes());
+        }
+
+        if has_version {
+            buffer.push(self.max_version);
+        }
+
         Bytes::from(buffer)
     }
 
-    pub fn decode(msg: &Bytes) -> io::Result<InitializationMessage> {
-        if msg.len() < 12 {
-            return Err(io::Error::new(
+    /// Decodes the message and checks that it describes a target a tunnel can be opened to:
+    /// a non-zero `proxy_port` and, unless a `proxy_hostname` is given, a unicast `proxy_host`.
+    pub fn decode(msg: &Bytes) -> Result<InitializationMessage> {
+        let init = InitializationMessage::decode_unchecked(msg)?;
+
+        if init.proxy_port == 0 {
+            return Err(error(ErrorKind::InvalidData, "Proxy port must not be 0"));
+        }
+
+        if init.proxy_hostname.is_none() {
+            if init.proxy_host.is_unspecified() {
+                return Err(error(
+                    ErrorKind::InvalidData,
+                    "Proxy host must not be unspecified",
+                ));
+            }
+
+            if init.proxy_host.is_multicast() || init.proxy_host.is_broadcast() {
+                return Err(error(
+                    ErrorKind::InvalidData,
+                    "Proxy host must be a unicast address",
+                ));
+            }
+        }
+
+        Ok(init)
+    }
+
+    /// Decodes the message without validating the ports and addresses.
+    pub fn decode_unchecked(msg: &Bytes) -> Result<InitializationMessage> {
+        if msg.len() < INITIALIZATION_LENGTH {
+            return Err(error(
                 ErrorKind::UnexpectedEof,
                 "Initial message is incorrect",
             ));
@@ -267,11 +1000,63 @@ impl InitializationMessage {
         let client_ip = Ipv4Addr::from_bits(u32::from_be_bytes(msg[4..8].try_into().unwrap()));
         let proxy_host = Ipv4Addr::from_bits(u32::from_be_bytes(msg[8..12].try_into().unwrap()));
 
+        let mut offset = INITIALIZATION_LENGTH;
+
+        let token = if msg.len() > offset {
+            if msg.len() < offset + 2 {
+                return Err(error(
+                    ErrorKind::UnexpectedEof,
+                    "Token length is incomplete",
+                ));
+            }
+
+            let token_len =
+                u16::from_be_bytes(msg[offset..offset + 2].try_into().unwrap()) as usize;
+            offset += 2;
+
+            if msg.len() < offset + token_len {
+                return Err(error(ErrorKind::UnexpectedEof, "Token is incomplete"));
+            }
+
+            let token = msg.slice(offset..offset + token_len);
+            offset += token_len;
+
+            (!token.is_empty()).then_some(token)
+        } else {
+            None
+        };
+
+        let proxy_hostname = if msg.len() > offset {
+            let hostname_len = msg[offset] as usize;
+            offset += 1;
+
+            if msg.len() < offset + hostname_len {
+                return Err(error(ErrorKind::UnexpectedEof, "Hostname is incomplete"));
+            }
+
+            let hostname = core::str::from_utf8(&msg[offset..offset + hostname_len])
+                .map_err(|_| error(ErrorKind::InvalidData, "Hostname is not valid UTF-8"))?;
+            offset += hostname_len;
+
+            (!hostname.is_empty()).then(|| String::from(hostname))
+        } else {
+            None
+        };
+
+        // Clients predating the negotiation only support the first version.
+        let max_version = msg
+            .get(offset)
+            .copied()
+            .unwrap_or(ProtocolVersion::V1 as u8);
+
         Ok(InitializationMessage {
-            client_ip,
             client_port,
             proxy_port,
+            client_ip,
             proxy_host,
+            token,
+            proxy_hostname,
+            max_version,
         })
     }
 }
@@ -0,0 +1,93 @@
+use std::{
+    io::{self, ErrorKind},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use bytes::{Buf, BytesMut};
+use futures_sink::Sink;
+use tokio::io::AsyncWrite;
+
+use crate::Message;
+
+/// [`Sink`] encoding the messages into a byte stream, e.g. a quinn `SendStream`.
+///
+/// Each message is written before the next one is accepted, unless coalescing: the frames are
+/// then buffered until `coalesce_max_bytes` of them are, or the sink is flushed, and written
+/// together. Closing the sink flushes it, then shuts the stream down.
+pub struct MessageSink<W> {
+    writer: W,
+
+    /// Encoded frames not written yet.
+    buffer: BytesMut,
+    coalesce_max_bytes: usize,
+}
+
+impl<W: AsyncWrite + Unpin> MessageSink<W> {
+    pub fn new(writer: W) -> MessageSink<W> {
+        MessageSink::with_coalescing(writer, 0)
+    }
+
+    /// Buffers the frames until `coalesce_max_bytes` of them are, see [`MessageSink`].
+    pub fn with_coalescing(writer: W, coalesce_max_bytes: usize) -> MessageSink<W> {
+        MessageSink {
+            writer,
+            buffer: BytesMut::new(),
+            coalesce_max_bytes,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Writes the buffered frames.
+    fn poll_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.buffer.is_empty() {
+            let written = ready!(Pin::new(&mut self.writer).poll_write(cx, &self.buffer))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::Error::new(
+                    ErrorKind::WriteZero,
+                    "Stream accepts no more frames",
+                )));
+            }
+            self.buffer.advance(written);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Sink<Message> for MessageSink<W> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.buffer.is_empty() && this.buffer.len() >= this.coalesce_max_bytes {
+            ready!(this.poll_write_buffer(cx))?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: Message) -> io::Result<()> {
+        msg.validate()?;
+        msg.encode_into(&mut self.get_mut().buffer);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.writer).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.get_mut().writer).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    io::{self, ErrorKind},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use alloc::{boxed::Box, vec};
+use futures_core::Stream;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::{Decoder, Message};
+
+/// Size of the reads from the underlying reader.
+const READ_SIZE: usize = 64 * 1024;
+
+/// [`Stream`] of the messages read from a byte stream, e.g. a quinn `RecvStream`; frames may be
+/// split across reads, they are buffered by a [`Decoder`] until complete.
+///
+/// It ends with the reader, and after yielding the error of an invalid frame, of a frame cut
+/// short by the end of the reader, or of a failed read.
+pub struct MessageStream<R> {
+    reader: R,
+    decoder: Decoder,
+    buf: Box<[u8]>,
+
+    /// Set once nothing is left to yield.
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> MessageStream<R> {
+    pub fn new(reader: R) -> MessageStream<R> {
+        MessageStream::with_decoder(reader, Decoder::new())
+    }
+
+    /// Decodes the messages with `decoder`, e.g. one rejecting the frames too long.
+    pub fn with_decoder(reader: R, decoder: Decoder) -> MessageStream<R> {
+        MessageStream {
+            reader,
+            decoder,
+            buf: vec![0; READ_SIZE].into_boxed_slice(),
+            done: false,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for MessageStream<R> {
+    type Item = io::Result<Message>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            match this.decoder.next_message() {
+                Ok(Some(msg)) => return Poll::Ready(Some(Ok(msg))),
+                Ok(None) => {}
+                Err(e) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+
+            let mut buf = ReadBuf::new(&mut this.buf);
+            if let Err(e) = ready!(Pin::new(&mut this.reader).poll_read(cx, &mut buf)) {
+                this.done = true;
+                return Poll::Ready(Some(Err(e)));
+            }
+
+            if buf.filled().is_empty() {
+                this.done = true;
+                if this.decoder.buffered() > 0 {
+                    return Poll::Ready(Some(Err(io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "Stream finished within a frame",
+                    ))));
+                }
+                return Poll::Ready(None);
+            }
+            this.decoder.extend(buf.filled());
+        }
+    }
+}
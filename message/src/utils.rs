@@ -3,3 +3,18 @@ use uuid::Uuid;
 pub fn generate_uuid() -> Uuid {
     Uuid::new_v4()
 }
+
+/// Generates a time-ordered UUIDv7; the IDs generated by a process sort in creation order.
+#[cfg(feature = "std")]
+pub fn generate_uuid_v7() -> Uuid {
+    Uuid::now_v7()
+}
+
+/// Generates the ID of a new message: UUIDv7 with the `uuid-v7` feature, UUIDv4 otherwise.
+pub fn generate_message_id() -> Uuid {
+    #[cfg(feature = "uuid-v7")]
+    return generate_uuid_v7();
+
+    #[cfg(not(feature = "uuid-v7"))]
+    return generate_uuid();
+}
@@ -0,0 +1,25 @@
+use message::MessageType;
+
+#[test]
+fn every_message_type_is_classified() {
+    // (type, is_data, requires_connection)
+    let classes = [
+        (MessageType::Initial, false, true),
+        (MessageType::Data, true, true),
+        (MessageType::Close, false, true),
+        (MessageType::Ping, false, false),
+        (MessageType::Ack, false, true),
+        (MessageType::Pong, false, false),
+        (MessageType::ShutdownWrite, false, true),
+    ];
+
+    for (message_type, is_data, requires_connection) in classes {
+        assert_eq!(message_type.is_data(), is_data, "{message_type}");
+        assert_eq!(message_type.is_control(), !is_data, "{message_type}");
+        assert_eq!(
+            message_type.requires_connection(),
+            requires_connection,
+            "{message_type}"
+        );
+    }
+}
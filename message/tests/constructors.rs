@@ -0,0 +1,48 @@
+use bytes::Bytes;
+use message::{CloseReason, InitializationMessage, Message, MessageType};
+use uuid::Uuid;
+
+#[test]
+fn data_carries_the_payload_as_is() {
+    let connection_id = Uuid::new_v4();
+    let msg = Message::data(connection_id, Bytes::from_static(b"hello"));
+
+    assert!(matches!(msg.message_type, MessageType::Data));
+    assert_eq!(msg.connection_id, connection_id);
+    assert_eq!(msg.payload, Bytes::from_static(b"hello"));
+    assert_eq!(msg.length, 5);
+}
+
+#[test]
+fn initial_encodes_the_initialization_message() {
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new(
+        "127.0.0.1:4000".parse().unwrap(),
+        "127.0.0.1:3000".parse().unwrap(),
+    )
+    .unwrap()
+    .with_token(Bytes::from_static(b"secret"))
+    .unwrap();
+    let msg = Message::initial(connection_id, &init);
+
+    assert!(matches!(msg.message_type, MessageType::Initial));
+    assert_eq!(msg.connection_id, connection_id);
+    assert_eq!(msg.payload, init.encode());
+
+    let decoded = InitializationMessage::decode(&msg.payload).unwrap();
+    assert_eq!(decoded.proxy_port, 3000);
+    assert_eq!(decoded.token.as_deref(), Some(&b"secret"[..]));
+}
+
+#[test]
+fn close_encodes_the_reason() {
+    let connection_id = Uuid::new_v4();
+    let msg = Message::close(connection_id, CloseReason::BackendRefused);
+
+    assert!(matches!(msg.message_type, MessageType::Close));
+    assert_eq!(msg.connection_id, connection_id);
+    assert_eq!(
+        CloseReason::decode(&msg.payload).unwrap(),
+        CloseReason::BackendRefused
+    );
+}
@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use message::{Decoder, MAX_HEADER_LENGTH, Message, MessageType};
+use uuid::Uuid;
+
+#[test]
+fn frames_encoded_into_a_shared_buffer_decode_in_order() {
+    let connection_id = Uuid::new_v4();
+    let first = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from_static(b"first"),
+    )
+    .with_sequence(0);
+    let second = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from_static(b"second"),
+    )
+    .with_reply_to(first.message_id);
+
+    let mut buf = BytesMut::new();
+    first.encode_into(&mut buf);
+    second.encode_into(&mut buf);
+
+    assert_eq!(buf.len(), first.encoded_len() + second.encoded_len());
+    assert_eq!(&buf[..first.encoded_len()], &first.encode()[..]);
+    assert_eq!(&buf[first.encoded_len()..], &second.encode()[..]);
+
+    let mut decoder = Decoder::new();
+    decoder.extend(&buf);
+
+    let decoded = decoder.next_message().unwrap().unwrap();
+    assert_eq!(decoded.message_id, first.message_id);
+    assert_eq!(decoded.sequence, Some(0));
+
+    let decoded = decoder.next_message().unwrap().unwrap();
+    assert_eq!(decoded.message_id, second.message_id);
+    assert_eq!(decoded.in_reply_to, Some(first.message_id));
+    assert_eq!(decoded.payload, Bytes::from_static(b"second"));
+
+    assert!(decoder.next_message().unwrap().is_none());
+}
+
+#[test]
+fn decoded_payload_shares_the_frame() {
+    let msg = Message::new(
+        MessageType::Data,
+        Uuid::new_v4(),
+        Bytes::from_static(b"relayed as is"),
+    );
+    let frame = msg.encode();
+
+    let decoded = Message::decode(&frame).unwrap();
+    assert_eq!(decoded.payload, msg.payload);
+
+    let frame_range = frame.as_ptr_range();
+    assert!(frame_range.contains(&decoded.payload.as_ptr()));
+}
+
+#[test]
+fn cloned_message_shares_the_payload() {
+    let msg = Message::new(
+        MessageType::Data,
+        Uuid::new_v4(),
+        Bytes::from(vec![0x5A; 1024 * 1024]),
+    );
+    let frame = msg.encode();
+    let decoded = Message::decode(&frame).unwrap();
+
+    for original in [msg, decoded] {
+        let clone = original.clone();
+        assert_eq!(clone.payload.as_ptr(), original.payload.as_ptr());
+        assert_eq!(clone.payload.len(), original.payload.len());
+    }
+}
+
+#[test]
+fn ack_replies_to_the_acknowledged_message() {
+    let connection_id = Uuid::new_v4();
+    let acked_id = Uuid::new_v4();
+
+    let ack = Message::decode(&Message::ack(connection_id, acked_id).encode()).unwrap();
+    assert!(matches!(ack.message_type, MessageType::Ack));
+    assert_eq!(ack.connection_id, connection_id);
+    assert_eq!(ack.in_reply_to, Some(acked_id));
+    assert!(ack.payload.is_empty());
+}
+
+#[test]
+fn ping_carries_its_sent_time_and_deadline() {
+    let ping = Message::ping_with_deadline(42, Duration::from_millis(1500));
+    let ping = Message::decode(&ping.encode()).unwrap();
+    assert!(matches!(ping.message_type, MessageType::Ping));
+    assert!(ping.connection_id.is_nil());
+    assert_eq!(
+        ping.ping_deadline(),
+        Some((42, Duration::from_millis(1500)))
+    );
+
+    let other = Message::new(MessageType::Ping, Uuid::nil(), Bytes::from_static(b"ping"));
+    assert_eq!(other.ping_deadline(), None);
+}
+
+#[test]
+fn encoded_len_matches_the_encoded_frame() {
+    let connection_id = Uuid::new_v4();
+    let data = || {
+        Message::new(
+            MessageType::Data,
+            connection_id,
+            Bytes::from(vec![0x42; 1000]),
+        )
+    };
+    let messages = [
+        Message::new(MessageType::Ping, Uuid::nil(), Bytes::new()),
+        data(),
+        data().with_sequence(7),
+        data().with_reply_to(Uuid::new_v4()),
+        data().with_reply_to(Uuid::new_v4()).with_sequence(8),
+        Message::ack(connection_id, Uuid::new_v4()),
+    ];
+
+    for msg in messages {
+        assert_eq!(msg.encoded_len(), msg.encode().len(), "{msg:?}");
+    }
+}
+
+#[test]
+fn timestamp_follows_the_other_optional_fields() {
+    let msg = Message::new(
+        MessageType::Data,
+        Uuid::new_v4(),
+        Bytes::from_static(b"timed"),
+    )
+    .with_reply_to(Uuid::new_v4())
+    .with_sequence(3)
+    .with_timestamp(1_000_000);
+    assert_eq!(msg.encoded_len(), MAX_HEADER_LENGTH + msg.payload.len());
+
+    let decoded = Message::decode(&msg.encode()).unwrap();
+    assert_eq!(decoded.in_reply_to, msg.in_reply_to);
+    assert_eq!(decoded.sequence, Some(3));
+    assert_eq!(decoded.timestamp, Some(1_000_000));
+    assert_eq!(decoded.payload, Bytes::from_static(b"timed"));
+}
+
+#[test]
+fn transit_time_is_clamped_to_zero_on_clock_skew() {
+    let msg = Message::new(MessageType::Data, Uuid::new_v4(), Bytes::new());
+    assert_eq!(msg.transit_time(1_000_000), None);
+
+    let msg = msg.with_timestamp(1_000_000);
+    assert_eq!(
+        msg.transit_time(1_002_500),
+        Some(Duration::from_micros(2_500))
+    );
+    // Sent "after" it was received: the clock of the sender is ahead.
+    assert_eq!(msg.transit_time(999_000), Some(Duration::ZERO));
+}
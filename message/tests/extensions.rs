@@ -0,0 +1,70 @@
+use bytes::Bytes;
+use message::{Decoder, EXTENSION_CHECKSUM, EXTENSION_TOKEN, FLAG_EXTENSIONS, Message};
+use uuid::Uuid;
+
+const UNKNOWN: u8 = 0x7f;
+
+fn with_extensions() -> Message {
+    let mut msg = Message::data(Uuid::new_v4(), Bytes::from_static(b"hello")).with_sequence(3);
+    msg.set_extension(EXTENSION_TOKEN, Bytes::from_static(b"secret"));
+    msg.set_extension(UNKNOWN, Bytes::from_static(b"future"));
+    msg
+}
+
+#[test]
+fn extensions_round_trip_unknown_kinds_included() {
+    let msg = with_extensions();
+    let encoded = msg.encode();
+    assert_ne!(encoded[3] & FLAG_EXTENSIONS, 0);
+    assert_eq!(Message::frame_length(&encoded), Some(encoded.len()));
+
+    let decoded = Message::decode(&encoded).unwrap();
+    assert_eq!(decoded.extensions, msg.extensions);
+    assert_eq!(
+        decoded.get_extension(EXTENSION_TOKEN),
+        Some(&Bytes::from_static(b"secret"))
+    );
+    assert_eq!(
+        decoded.get_extension(UNKNOWN),
+        Some(&Bytes::from_static(b"future"))
+    );
+    assert_eq!(decoded.get_extension(EXTENSION_CHECKSUM), None);
+    assert_eq!(decoded.sequence, Some(3));
+    assert_eq!(decoded.payload, Bytes::from_static(b"hello"));
+    assert_eq!(decoded.encode(), encoded);
+}
+
+#[test]
+fn decoder_waits_for_the_length_of_the_extensions() {
+    let encoded = with_extensions().encode();
+    let mut decoder = Decoder::new();
+
+    for byte in encoded.iter() {
+        assert!(decoder.next_message().unwrap().is_none());
+        decoder.extend(&[*byte]);
+    }
+
+    let decoded = decoder.next_message().unwrap().unwrap();
+    assert_eq!(decoded.extensions.len(), 2);
+    assert_eq!(decoded.payload, Bytes::from_static(b"hello"));
+}
+
+#[test]
+fn set_extension_replaces_the_previous_value() {
+    let mut msg = with_extensions();
+    msg.set_extension(EXTENSION_TOKEN, Bytes::from_static(b"other"));
+
+    assert_eq!(msg.extensions.len(), 2);
+    assert_eq!(
+        msg.get_extension(EXTENSION_TOKEN),
+        Some(&Bytes::from_static(b"other"))
+    );
+}
+
+#[test]
+fn known_extension_of_the_wrong_length_is_rejected() {
+    let mut msg = Message::data(Uuid::new_v4(), Bytes::from_static(b"hello"));
+    msg.set_extension(EXTENSION_CHECKSUM, Bytes::from_static(b"too long"));
+
+    assert!(Message::decode(&msg.encode()).is_err());
+}
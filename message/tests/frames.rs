@@ -0,0 +1,59 @@
+use bytes::{Bytes, BytesMut};
+use message::{Message, MessageType};
+use uuid::Uuid;
+
+fn data(payload: &'static [u8]) -> Message {
+    Message::new(
+        MessageType::Data,
+        Uuid::new_v4(),
+        Bytes::from_static(payload),
+    )
+}
+
+#[test]
+fn complete_frames_are_decoded_and_the_partial_one_is_left() {
+    let (first, second, third) = (data(b"first"), data(b"second"), data(b"third"));
+
+    let mut buf = BytesMut::new();
+    first.encode_into(&mut buf);
+    second.encode_into(&mut buf);
+    let partial = &third.encode()[..third.encoded_len() - 2];
+    buf.extend_from_slice(partial);
+    let buf = buf.freeze();
+
+    let mut frames = Message::decode_all(&buf);
+    let decoded = frames.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(decoded.len(), 2);
+    assert_eq!(decoded[0].message_id, first.message_id);
+    assert_eq!(&decoded[0].payload[..], b"first");
+    assert_eq!(decoded[1].message_id, second.message_id);
+    assert_eq!(&decoded[1].payload[..], b"second");
+
+    assert_eq!(frames.remaining(), partial.len());
+    assert_eq!(&frames.rest()[..], partial);
+    assert!(frames.next().is_none());
+}
+
+#[test]
+fn partial_header_is_left_undecoded() {
+    let buf = data(b"payload").encode().slice(..10);
+
+    let mut frames = Message::decode_all(&buf);
+    assert!(frames.next().is_none());
+    assert_eq!(frames.remaining(), 10);
+}
+
+#[test]
+fn iteration_stops_at_an_invalid_frame() {
+    let mut buf = BytesMut::new();
+    data(b"valid").encode_into(&mut buf);
+    buf.extend_from_slice(&[0x00; 64]);
+    data(b"unreached").encode_into(&mut buf);
+    let buf = buf.freeze();
+
+    let mut frames = Message::decode_all(&buf);
+    assert!(frames.next().unwrap().is_ok());
+    let e = frames.next().unwrap().unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    assert!(frames.next().is_none());
+}
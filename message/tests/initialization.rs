@@ -0,0 +1,158 @@
+use std::{
+    io::ErrorKind,
+    net::{Ipv4Addr, SocketAddr},
+};
+
+use bytes::Bytes;
+use message::{INITIALIZATION_LENGTH, InitializationMessage, ProtocolVersion};
+use uuid::Uuid;
+
+fn encoded(proxy_addr: &str) -> bytes::Bytes {
+    InitializationMessage::new(
+        "127.0.0.1:4000".parse().unwrap(),
+        proxy_addr.parse().unwrap(),
+    )
+    .unwrap()
+    .encode()
+}
+
+#[test]
+fn accepts_unicast_target() {
+    let init = InitializationMessage::decode(&encoded("127.0.0.1:3000")).unwrap();
+    assert_eq!(init.proxy_port, 3000);
+}
+
+#[test]
+fn rejects_zero_proxy_port() {
+    let err = InitializationMessage::decode(&encoded("127.0.0.1:0")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn rejects_unspecified_proxy_host() {
+    let err = InitializationMessage::decode(&encoded("0.0.0.0:3000")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn rejects_multicast_proxy_host() {
+    let err = InitializationMessage::decode(&encoded("224.0.0.1:3000")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn accepts_unspecified_proxy_host_with_hostname() {
+    let init =
+        InitializationMessage::with_hostname("127.0.0.1:4000".parse().unwrap(), "localhost", 3000)
+            .unwrap();
+
+    let decoded = InitializationMessage::decode(&init.encode()).unwrap();
+    assert_eq!(decoded.proxy_hostname.as_deref(), Some("localhost"));
+}
+
+#[test]
+fn unchecked_decode_skips_validation() {
+    let init = InitializationMessage::decode_unchecked(&encoded("0.0.0.0:0")).unwrap();
+    assert_eq!(init.proxy_port, 0);
+    assert!(init.proxy_host.is_unspecified());
+}
+
+/// Returns a unicast address and a non-zero port built from random bytes.
+fn random_addr() -> SocketAddr {
+    let bytes = *Uuid::new_v4().as_bytes();
+    let ip = Ipv4Addr::new(bytes[0].clamp(1, 223), bytes[1], bytes[2], bytes[3].max(1));
+    let port = u16::from_be_bytes([bytes[4], bytes[5]]).max(1);
+    SocketAddr::from((ip, port))
+}
+
+#[test]
+fn round_trips_random_addresses() {
+    for _ in 0..1000 {
+        let (addr, proxy_addr) = (random_addr(), random_addr());
+        let init = InitializationMessage::new(addr, proxy_addr).unwrap();
+
+        let decoded = InitializationMessage::decode(&init.encode()).unwrap();
+        assert_eq!(decoded.client_port, init.client_port);
+        assert_eq!(decoded.proxy_port, init.proxy_port);
+        assert_eq!(decoded.client_ip, init.client_ip);
+        assert_eq!(decoded.proxy_host, init.proxy_host);
+        assert_eq!(decoded.token, init.token);
+        assert_eq!(decoded.proxy_hostname, init.proxy_hostname);
+
+        assert_eq!(
+            SocketAddr::from((decoded.client_ip, decoded.client_port)),
+            addr
+        );
+        assert_eq!(
+            SocketAddr::from((decoded.proxy_host, decoded.proxy_port)),
+            proxy_addr
+        );
+    }
+}
+
+#[test]
+fn fields_are_encoded_in_wire_order() {
+    let init = InitializationMessage::new(
+        "10.1.2.3:4660".parse().unwrap(),
+        "192.168.4.5:22136".parse().unwrap(),
+    )
+    .unwrap();
+
+    let encoded = init.encode();
+    assert_eq!(encoded.len(), INITIALIZATION_LENGTH);
+    assert_eq!(
+        &encoded[..],
+        &[
+            0x12, 0x34, // client_port
+            0x56, 0x78, // proxy_port
+            10, 1, 2, 3, // client_ip
+            192, 168, 4, 5, // proxy_host
+        ]
+    );
+}
+
+#[test]
+fn rejects_truncated_fixed_fields() {
+    let encoded = encoded("127.0.0.1:3000");
+    let truncated = Bytes::copy_from_slice(&encoded[..INITIALIZATION_LENGTH - 1]);
+
+    let err = InitializationMessage::decode(&truncated).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn max_version_round_trips() {
+    let init = InitializationMessage::new(
+        "127.0.0.1:4000".parse().unwrap(),
+        "127.0.0.1:3000".parse().unwrap(),
+    )
+    .unwrap()
+    .with_max_version(9);
+
+    let decoded = InitializationMessage::decode(&init.encode()).unwrap();
+    assert_eq!(decoded.max_version, 9);
+    assert_eq!(decoded.token, None);
+    assert_eq!(decoded.proxy_hostname, None);
+}
+
+#[test]
+fn missing_max_version_is_the_first_version() {
+    let init = InitializationMessage::decode(&encoded("127.0.0.1:3000")).unwrap();
+    assert_eq!(init.max_version, ProtocolVersion::V1 as u8);
+}
+
+#[test]
+fn debug_output_redacts_the_token() {
+    let init = InitializationMessage::new(
+        "127.0.0.1:4000".parse().unwrap(),
+        "127.0.0.1:3000".parse().unwrap(),
+    )
+    .unwrap()
+    .with_token(Bytes::from_static(b"shared-secret"))
+    .unwrap();
+
+    let debug = format!("{init:?}");
+    assert!(!debug.contains("shared-secret"), "{debug}");
+    assert!(debug.contains("<redacted>"), "{debug}");
+    assert!(debug.contains("proxy_port: 3000"), "{debug}");
+}
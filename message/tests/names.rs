@@ -0,0 +1,44 @@
+use message::{CloseReason, MessageType, ProtocolVersion};
+
+#[test]
+fn message_types_have_stable_names() {
+    let names = [
+        (MessageType::Initial, "initial"),
+        (MessageType::Data, "data"),
+        (MessageType::Close, "close"),
+        (MessageType::Ping, "ping"),
+        (MessageType::Ack, "ack"),
+        (MessageType::Pong, "pong"),
+        (MessageType::ShutdownWrite, "shutdown_write"),
+    ];
+
+    for (message_type, name) in names {
+        assert_eq!(message_type.as_str(), name);
+        assert_eq!(message_type.to_string(), name);
+    }
+}
+
+#[test]
+fn close_reasons_have_stable_names() {
+    let names = [
+        (CloseReason::Normal, "normal"),
+        (CloseReason::AuthFailed, "auth_failed"),
+        (CloseReason::TargetNotAllowed, "target_not_allowed"),
+        (CloseReason::BackendUnreachable, "backend_unreachable"),
+        (CloseReason::ProtocolError, "protocol_error"),
+        (CloseReason::Timeout, "timeout"),
+        (CloseReason::BackendRefused, "backend_refused"),
+        (CloseReason::ServerFull, "server_full"),
+    ];
+
+    for (reason, name) in names {
+        assert_eq!(reason.as_str(), name);
+        assert_eq!(reason.to_string(), name);
+    }
+}
+
+#[test]
+fn protocol_versions_have_stable_names() {
+    assert_eq!(ProtocolVersion::V1.as_str(), "v1");
+    assert_eq!(ProtocolVersion::V1.to_string(), "v1");
+}
@@ -0,0 +1,24 @@
+use std::{env, path::Path, process::Command};
+
+/// Builds the crate without its default features, i.e. as `#![no_std]` with `alloc` only.
+#[test]
+fn builds_without_std() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+
+    let output = Command::new(cargo)
+        .arg("check")
+        .arg("--no-default-features")
+        .arg("--manifest-path")
+        .arg(manifest_dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(manifest_dir.join("../target/no_std"))
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "no_std build failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
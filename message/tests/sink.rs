@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use message::{Message, MessageSink, MessageStream, MessageType};
+use tokio::time::timeout;
+use uuid::Uuid;
+
+fn messages(connection_id: Uuid) -> Vec<Message> {
+    (0..5u64)
+        .map(|i| {
+            let payload = Bytes::from(vec![i as u8; 1000 * i as usize + 1]);
+            Message::data(connection_id, payload).with_sequence(i)
+        })
+        .collect()
+}
+
+async fn assert_read_back(reader: tokio::io::DuplexStream, sent: &[Message]) {
+    let mut stream = MessageStream::new(reader);
+    for sent in sent {
+        let received = stream.next().await.unwrap().unwrap();
+        assert_eq!(received.message_id, sent.message_id);
+        assert_eq!(received.sequence, sent.sequence);
+        assert_eq!(received.payload, sent.payload);
+    }
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn messages_sent_through_the_sink_are_read_back() {
+    let sent = messages(Uuid::new_v4());
+    let (writer, reader) = tokio::io::duplex(256);
+
+    let mut sink = MessageSink::new(writer);
+    let to_send = sent.clone();
+    tokio::spawn(async move {
+        for msg in to_send {
+            sink.send(msg).await.unwrap();
+        }
+        sink.close().await.unwrap();
+    });
+
+    assert_read_back(reader, &sent).await;
+}
+
+#[tokio::test]
+async fn coalesced_messages_are_written_once_flushed() {
+    let sent = messages(Uuid::new_v4());
+    let (writer, reader) = tokio::io::duplex(64 * 1024);
+    let mut stream = MessageStream::new(reader);
+
+    let mut sink = MessageSink::with_coalescing(writer, 64 * 1024);
+    for msg in sent.clone() {
+        sink.feed(msg).await.unwrap();
+    }
+    // Nothing is written below the coalescing threshold until the sink is flushed.
+    assert!(
+        timeout(Duration::from_millis(50), stream.next())
+            .await
+            .is_err()
+    );
+
+    sink.close().await.unwrap();
+    for sent in &sent {
+        let received = stream.next().await.unwrap().unwrap();
+        assert_eq!(received.message_id, sent.message_id);
+    }
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn invalid_message_is_refused() {
+    let (writer, _reader) = tokio::io::duplex(1024);
+    let mut sink = MessageSink::new(writer);
+
+    let invalid = Message::new(MessageType::Data, Uuid::nil(), Bytes::new());
+    assert!(sink.send(invalid).await.is_err());
+}
@@ -0,0 +1,58 @@
+use std::io::ErrorKind;
+
+use bytes::{Bytes, BytesMut};
+use futures_util::StreamExt;
+use message::{Message, MessageStream, MessageType};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn frames_split_across_reads_are_decoded_in_order() {
+    let connection_id = Uuid::new_v4();
+    let messages = (0..5u64)
+        .map(|i| {
+            let payload = Bytes::from(vec![i as u8; 100 * i as usize + 1]);
+            Message::data(connection_id, payload).with_sequence(i)
+        })
+        .collect::<Vec<_>>();
+    let mut encoded = BytesMut::new();
+    for msg in &messages {
+        msg.encode_into(&mut encoded);
+    }
+
+    // A pipe smaller than a frame, written in pieces straddling the frame boundaries.
+    let (mut writer, reader) = tokio::io::duplex(64);
+    tokio::spawn(async move {
+        for piece in encoded.chunks(37) {
+            writer.write_all(piece).await.unwrap();
+        }
+    });
+
+    let mut stream = MessageStream::new(reader);
+    let mut received = Vec::new();
+    while let Some(msg) = stream.next().await {
+        received.push(msg.unwrap());
+    }
+
+    assert_eq!(received.len(), messages.len());
+    for (received, sent) in received.iter().zip(&messages) {
+        assert!(matches!(received.message_type, MessageType::Data));
+        assert_eq!(received.message_id, sent.message_id);
+        assert_eq!(received.sequence, sent.sequence);
+        assert_eq!(received.payload, sent.payload);
+    }
+}
+
+#[tokio::test]
+async fn stream_finished_within_a_frame_fails() {
+    let frame = Message::data(Uuid::new_v4(), Bytes::from_static(b"hello")).encode();
+
+    let (mut writer, reader) = tokio::io::duplex(1024);
+    writer.write_all(&frame[..frame.len() - 1]).await.unwrap();
+    drop(writer);
+
+    let mut stream = MessageStream::new(reader);
+    let err = stream.next().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    assert!(stream.next().await.is_none());
+}
@@ -0,0 +1,9 @@
+use message::msg_utils::generate_uuid_v7;
+
+#[test]
+fn v7_ids_sort_in_creation_order() {
+    let ids: Vec<_> = (0..1000).map(|_| generate_uuid_v7()).collect();
+
+    assert!(ids.iter().all(|id| id.get_version_num() == 7));
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+}
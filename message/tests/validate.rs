@@ -0,0 +1,82 @@
+use bytes::Bytes;
+use message::{Decoder, MAGIC_BYTE, Message, MessageType};
+use uuid::Uuid;
+
+fn data() -> Message {
+    Message::new(
+        MessageType::Data,
+        Uuid::new_v4(),
+        Bytes::from_static(b"payload"),
+    )
+}
+
+#[test]
+fn well_formed_messages_are_valid() {
+    assert!(data().validate().is_ok());
+    assert!(
+        Message::new(MessageType::Ping, Uuid::nil(), Bytes::new())
+            .validate()
+            .is_ok()
+    );
+}
+
+#[test]
+fn tunnel_message_with_a_nil_connection_id_is_invalid() {
+    let msg = Message::new(
+        MessageType::Data,
+        Uuid::nil(),
+        Bytes::from_static(b"payload"),
+    );
+    assert_eq!(
+        msg.validate().unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+
+    // Rejected on decode as well.
+    let err = Message::decode(&msg.encode()).unwrap_err();
+    assert_eq!(err.to_string(), "Nil connection ID");
+}
+
+#[test]
+fn length_not_matching_the_payload_is_invalid() {
+    let mut msg = data();
+    msg.length += 1;
+
+    let err = msg.validate().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(err.to_string(), "Length doesn't match the payload");
+}
+
+#[test]
+fn wrong_magic_byte_is_invalid() {
+    let mut frame = data().encode().to_vec();
+    frame[0] = !MAGIC_BYTE;
+
+    let err = Message::decode(&Bytes::from(frame)).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid magic byte");
+}
+
+#[test]
+fn decoder_rejects_a_wrong_magic_byte_without_the_rest_of_the_frame() {
+    let mut decoder = Decoder::new();
+    decoder.extend(&[!MAGIC_BYTE]);
+
+    let err = decoder.next_message().unwrap_err();
+    assert_eq!(err.to_string(), "Invalid magic byte");
+    assert_eq!(decoder.connection_id(), None);
+}
+
+#[test]
+fn decoder_rejects_frames_longer_than_the_maximum_size() {
+    let frame = data().encode();
+
+    let mut decoder = Decoder::with_max_frame_size(frame.len());
+    decoder.extend(&frame);
+    assert!(decoder.next_message().unwrap().is_some());
+
+    // Rejected from the header alone.
+    let mut decoder = Decoder::with_max_frame_size(frame.len() - 1);
+    decoder.extend(&frame[..message::HEADER_LENGTH]);
+    let err = decoder.next_message().unwrap_err();
+    assert_eq!(err.to_string(), "Frame exceeds the maximum size");
+}
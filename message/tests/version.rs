@@ -0,0 +1,11 @@
+use message::ProtocolVersion;
+
+#[test]
+fn negotiates_the_latest_common_version() {
+    assert_eq!(ProtocolVersion::negotiate(1), Some(ProtocolVersion::V1));
+    assert_eq!(
+        ProtocolVersion::negotiate(u8::MAX),
+        Some(ProtocolVersion::LATEST)
+    );
+    assert_eq!(ProtocolVersion::negotiate(0), None);
+}
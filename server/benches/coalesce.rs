@@ -0,0 +1,94 @@
+// Compares the coalesced and the per-frame writes of the server send path: a burst of pings is
+// sent in a single write and the time until every pong is received is measured.
+//
+// Run with `cargo bench -p server --bench coalesce`.
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use message::{Decoder, Message, MessageType};
+use quinn::{Connection, Endpoint};
+use server::{config::Config, events::Events, registry::Registry, server::make_server_endpoint};
+use uuid::Uuid;
+
+/// Pings sent per burst.
+const FRAMES: usize = 10_000;
+
+/// Bursts measured per mode; the first one warms the connection up and is discarded.
+const ROUNDS: usize = 6;
+
+async fn connect(flush_window: Duration) -> Connection {
+    let mut config = Config::new();
+    config.host = "127.0.0.1:0".parse().unwrap();
+    config.flush_window = flush_window;
+
+    let (endpoint, cert) = make_server_endpoint(&config).unwrap();
+    let addr = endpoint.local_addr().unwrap();
+    tokio::spawn(server::serve(
+        endpoint,
+        Arc::new(config),
+        Arc::new(Registry::new()),
+        Events::new(),
+    ));
+
+    let mut client = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    client.set_default_client_config(client::tls::configure_client(&[cert], None).unwrap());
+    client.connect(addr, "localhost").unwrap().await.unwrap()
+}
+
+/// Returns the time taken to get the pongs of a burst of pings back.
+async fn burst(connection: &Connection) -> Duration {
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let burst = (0..FRAMES)
+        .flat_map(|_| Message::new(MessageType::Ping, connection_id, Bytes::new()).encode())
+        .collect::<Vec<_>>();
+
+    let started = Instant::now();
+    send.write_all(&burst).await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let mut pongs = 0;
+    while pongs < FRAMES {
+        let chunk = recv.read_chunk(usize::MAX, true).await.unwrap().unwrap();
+        decoder.extend(&chunk.bytes);
+        while decoder.next_message().unwrap().is_some() {
+            pongs += 1;
+        }
+    }
+    let elapsed = started.elapsed();
+
+    let _ = send.finish();
+    elapsed
+}
+
+async fn bench(name: &str, flush_window: Duration) {
+    let connection = connect(flush_window).await;
+
+    let mut rounds = Vec::with_capacity(ROUNDS);
+    for _ in 0..ROUNDS {
+        rounds.push(burst(&connection).await);
+    }
+    rounds.remove(0);
+    rounds.sort();
+
+    let median = rounds[rounds.len() / 2];
+    println!(
+        "{name:<10} median {median:>10.2?}  {:>10.0} frames/s",
+        FRAMES as f64 / median.as_secs_f64()
+    );
+
+    connection.close(0u32.into(), b"done");
+}
+
+fn main() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    runtime.block_on(async {
+        bench("per-frame", Duration::ZERO).await;
+        bench("coalesced", Duration::from_millis(1)).await;
+    });
+}
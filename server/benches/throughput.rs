@@ -0,0 +1,143 @@
+// Measures the end-to-end throughput of the proxy: a client, the server and an echo backend run on
+// loopback, and a large transfer is written to the local listener of the client and read back.
+// The transfer is repeated for several chunk sizes, used by both the client and the server.
+//
+// Run with `cargo bench -p server --bench throughput`.
+//
+// Rough baseline on a single core VM, 32 MiB echoed (so 64 MiB through the tunnel):
+//
+//   chunk 512      ~115 MB/s
+//   chunk 2048     ~180 MB/s
+//   chunk 8192     ~165 MB/s
+//   chunk 32768    ~190 MB/s
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use server::{config::Config, events::Events, registry::Registry, server::make_server_endpoint};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::sleep,
+};
+
+/// Bytes written to the tunnel per transfer, and read back.
+const TRANSFER: usize = 32 * 1024 * 1024;
+
+/// Chunk sizes compared.
+const CHUNK_SIZES: [usize; 4] = [512, 2048, 8192, 32768];
+
+/// Transfers measured per chunk size; the first one warms the connection up and is discarded.
+const ROUNDS: usize = 4;
+
+async fn spawn_echo_backend() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = socket.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                let _ = writer.shutdown().await;
+            });
+        }
+    });
+
+    addr
+}
+
+/// Starts the server and a client tunneling to the backend; returns the local address of the
+/// client.
+async fn start(chunk_size: usize, backend_addr: SocketAddr) -> SocketAddr {
+    let mut config = Config::new();
+    config.host = "127.0.0.1:0".parse().unwrap();
+    config.chunk_size = chunk_size;
+
+    let (endpoint, cert) = make_server_endpoint(&config).unwrap();
+    let server_addr = endpoint.local_addr().unwrap();
+    tokio::spawn(server::serve(
+        endpoint,
+        Arc::new(config),
+        Arc::new(Registry::new()),
+        Events::new(),
+    ));
+
+    let mut config = client::ClientConfig::new(server_addr, backend_addr);
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.server_certs = vec![cert];
+    config.chunk_size = chunk_size;
+    let client = Arc::new(client::Client::new(config).unwrap());
+
+    let local_addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+    tokio::spawn(async move { client.listen_local(local_addr).await });
+
+    local_addr
+}
+
+async fn connect_local(local_addr: SocketAddr) -> TcpStream {
+    loop {
+        match TcpStream::connect(local_addr).await {
+            Ok(socket) => return socket,
+            Err(_) => sleep(Duration::from_millis(10)).await,
+        }
+    }
+}
+
+/// Returns the time taken to echo the transfer through a new tunnel.
+async fn transfer(local_addr: SocketAddr) -> Duration {
+    let socket = connect_local(local_addr).await;
+    let (mut reader, mut writer) = socket.into_split();
+    let payload = vec![0xAB; TRANSFER];
+
+    let started = Instant::now();
+    let write = tokio::spawn(async move {
+        writer.write_all(&payload).await.unwrap();
+        writer.shutdown().await.unwrap();
+    });
+
+    let mut received = Vec::with_capacity(TRANSFER);
+    reader.read_to_end(&mut received).await.unwrap();
+    let elapsed = started.elapsed();
+
+    write.await.unwrap();
+    assert_eq!(received.len(), TRANSFER);
+    elapsed
+}
+
+async fn bench(chunk_size: usize, backend_addr: SocketAddr) {
+    let local_addr = start(chunk_size, backend_addr).await;
+
+    let mut rounds = Vec::with_capacity(ROUNDS);
+    for _ in 0..ROUNDS {
+        rounds.push(transfer(local_addr).await);
+    }
+    rounds.remove(0);
+    rounds.sort();
+
+    let median = rounds[rounds.len() / 2];
+    println!(
+        "chunk {chunk_size:<8} median {median:>10.2?}  {:>8.1} MB/s",
+        (2 * TRANSFER) as f64 / median.as_secs_f64() / 1_000_000.0
+    );
+}
+
+fn main() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    runtime.block_on(async {
+        let backend_addr = spawn_echo_backend().await;
+        for chunk_size in CHUNK_SIZES {
+            bench(chunk_size, backend_addr).await;
+        }
+    });
+}
@@ -1,147 +1,40 @@
 use bytes::Bytes;
-use message::{InitializationMessage, Message, MessageType, msg_utils};
-use spdlog::{error, info, warn};
+use client::{CertSource, Client, ClientConfig, tls};
+use spdlog::info;
 use std::{
+    env,
     error::Error,
-    fs,
     net::{IpAddr, Ipv4Addr, SocketAddr},
-    sync::Arc,
-    time::Duration,
+    path::PathBuf,
 };
-use tokio::{
-    sync::{Mutex, TryLockError},
-    time::sleep,
-};
-
-use quinn::{ClientConfig, Endpoint, SendStream};
-use rustls::pki_types::CertificateDer;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
     let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 9003);
-    let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
-
-    // Read the PEM file
-    let pem_data =
-        fs::read_to_string("examples/server_cert.pem").expect("Failed to read certificate file");
-
-    let pem_file = pem::parse(pem_data).expect("Failed to parse PEM");
-
-    let server_cert = CertificateDer::from(pem_file.contents());
-
-    info!(
-        "Loaded server certificate with {} bytes",
-        server_cert.as_ref().len()
-    );
-
-    let client = make_client_endpoint(client_addr, &[&server_cert])?;
-
-    run_client(&client, server_addr).await;
-
-    client.wait_idle().await;
-
-    Ok(())
-}
-
-async fn run_client(endpoint: &Endpoint, server_addr: SocketAddr) {
-    let connect = endpoint.connect(server_addr, "localhost").unwrap();
-    let connection = connect.await.unwrap();
-    let connection_id = msg_utils::generate_uuid();
-
-    let initialization_payload = InitializationMessage::new(
-        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 20000),
-        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 3000),
-    )
-    .unwrap_or_else(|e| panic!("{e:?}"));
-
-    let init_msg = Message::new(
-        MessageType::Initial,
-        connection_id,
-        initialization_payload.encode(),
-    );
-
-    let (send, mut recv) = connection.open_bi().await.unwrap();
-    info!("[client] connected: addr={}", connection.remote_address());
-
-    let send = Arc::new(Mutex::new(send));
-
-    tokio::spawn(async move {
-        loop {
-            match recv.read_chunk(500, true).await {
-                Ok(Some(chunk)) => {
-                    info!(
-                        "[client] received: {:?}",
-                        String::from_utf8_lossy(&chunk.bytes)
-                    );
-                }
-
-                Ok(None) => {
-                    continue;
-                }
-
-                Err(e) => {
-                    info!("[server] error reading: {e:?}");
-                    break;
-                }
-            }
-        }
-    });
-
-    send_msg(&send, &init_msg).await;
-
-    tokio::spawn({
-        let send = send.clone();
-        async move {
-            loop {
-                let ping = Message::new(
-                    MessageType::Ping,
-                    connection_id,
-                    Bytes::from_static(b"ping"),
-                );
-                info!("Sending ping..");
-                send_msg(&send, &ping).await;
-
-                sleep(Duration::from_secs(10)).await;
-            }
-        }
-    });
-
-    info!("Here before 2nd init");
-    send_msg(&send, &init_msg).await;
-}
-
-async fn send_msg(send: &Arc<Mutex<SendStream>>, msg: &Message) {
-    let mut send = send.lock().await;
-    if let Err(e) = send.write_chunk(msg.encode()).await {
-        error!("Failed to send message: {:?}", e);
-    }
-}
-
-fn make_client_endpoint(
-    bind_addr: SocketAddr,
-    server_certs: &[&[u8]],
-) -> Result<Endpoint, Box<dyn Error + Send + Sync + 'static>> {
-    let client_cfg = configure_client(server_certs)?;
-    let mut endpoint = Endpoint::client(bind_addr)?;
-    endpoint.set_default_client_config(client_cfg);
-    Ok(endpoint)
-}
-
-fn configure_client(
-    server_certs: &[&[u8]],
-) -> Result<ClientConfig, Box<dyn Error + Send + Sync + 'static>> {
-    let mut certs = rustls::RootCertStore::empty();
-    for cert in server_certs {
-        certs.add(CertificateDer::from(*cert))?;
+    let proxy_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 3000);
+    let local_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8000);
+
+    // Trust the certificate written by the server; a directory of certificates works as well
+    let cert_path = env::var_os(tls::CERT_PATH_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(tls::default_server_cert_path);
+    info!("Trusting the server certificate at {}", cert_path.display());
+
+    let mut config = ClientConfig::new(server_addr, proxy_addr);
+    config.server_cert_sources.push(CertSource::File(cert_path));
+    config.token = env::var("REVERPROX_AUTH_TOKEN").ok().map(Bytes::from);
+
+    let client = Client::new(config)?;
+
+    if env::args().nth(1).as_deref() == Some("socks5") {
+        // `local_addr` is a SOCKS5 proxy: each connection is proxied through the server to the
+        // target it requests
+        info!("Running a SOCKS5 proxy on {local_addr}");
+        tokio::try_join!(client.run(), client.listen_socks5(local_addr))?;
+    } else {
+        // Connections to `local_addr` are proxied through the server to `proxy_addr`
+        tokio::try_join!(client.run(), client.listen_local(local_addr))?;
     }
 
-    Ok(ClientConfig::with_root_certificates(Arc::new(certs))?)
+    Ok(())
 }
-
-// async fn send_tcp_request() -> Result<(), Box<dyn Error>> {
-//     let mut stream = TcpStream::connect("127.0.0.1:8080").await?;
-//
-//     let raw_http = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
-//     stream.write_all(raw_http).await?;
-//     Ok(())
-// }
@@ -0,0 +1,61 @@
+use std::{fmt, sync::Arc};
+
+use message::MessageType;
+use spdlog::{Logger, prelude::info};
+use uuid::Uuid;
+
+/// Name of the logger the access log lines are emitted to.
+pub const ACCESS_LOGGER_NAME: &str = "access";
+
+/// Side a forwarded message travels to.
+#[derive(Debug, Clone, Copy)]
+pub enum Direction {
+    ClientToBackend,
+    BackendToClient,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::ClientToBackend => f.write_str("client->backend"),
+            Direction::BackendToClient => f.write_str("backend->client"),
+        }
+    }
+}
+
+/// Emits a line per forwarded message to the [`ACCESS_LOGGER_NAME`] logger, forked from the
+/// default logger; does nothing when disabled.
+#[derive(Clone)]
+pub struct AccessLog {
+    logger: Option<Arc<Logger>>,
+}
+
+impl AccessLog {
+    pub fn new(enabled: bool) -> AccessLog {
+        let logger = enabled.then(|| {
+            spdlog::default_logger()
+                .fork_with_name(Some(ACCESS_LOGGER_NAME))
+                .expect("Logger name is valid")
+        });
+
+        AccessLog { logger }
+    }
+
+    pub fn record(
+        &self,
+        direction: Direction,
+        connection_id: Uuid,
+        message_id: Uuid,
+        message_type: MessageType,
+        bytes: usize,
+    ) {
+        let Some(logger) = &self.logger else {
+            return;
+        };
+
+        info!(
+            logger: logger,
+            "connection_id={connection_id} message_id={message_id} direction={direction} bytes={bytes} type={message_type}"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
+    str::FromStr,
+};
+
+/// Determines how the [`TargetRule`]s of a [`TargetPolicy`] are applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyMode {
+    /// Only targets matching one of the rules are allowed.
+    Allow,
+
+    /// Every target is allowed except the ones matching one of the rules.
+    Deny,
+}
+
+/// Network in CIDR notation, e.g. `10.0.0.0/8` or `::1/128`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl Cidr {
+    pub fn new(addr: IpAddr, prefix: u8) -> io::Result<Cidr> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        if prefix > max {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "CIDR prefix is out of range",
+            ));
+        }
+
+        Ok(Cidr { addr, prefix })
+    }
+
+    /// Whether the network contains `ip`. IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`) are
+    /// matched both as they are and as the IPv4 address they carry, on either side, so that a
+    /// rule can't be bypassed by writing its address the other way.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (net, prefix) = self.canonical();
+        contains(self.addr, self.prefix, ip) || contains(net, prefix, ip.to_canonical())
+    }
+
+    /// The network with an IPv4-mapped address as the IPv4 network it covers.
+    fn canonical(&self) -> (IpAddr, u8) {
+        match self.addr {
+            IpAddr::V6(addr) if self.prefix >= 96 => match addr.to_ipv4_mapped() {
+                Some(addr) => (IpAddr::V4(addr), self.prefix - 96),
+                None => (self.addr, self.prefix),
+            },
+            addr => (addr, self.prefix),
+        }
+    }
+}
+
+fn contains(net: IpAddr, prefix: u8, ip: IpAddr) -> bool {
+    match (net, ip) {
+        (IpAddr::V4(net), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            net.to_bits() & mask == ip.to_bits() & mask
+        }
+        (IpAddr::V6(net), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            net.to_bits() & mask == ip.to_bits() & mask
+        }
+        _ => false,
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Cidr> {
+        let invalid = || io::Error::new(ErrorKind::InvalidInput, format!("Invalid CIDR: {s}"));
+
+        match s.split_once('/') {
+            Some((addr, prefix)) => Cidr::new(
+                addr.parse().map_err(|_| invalid())?,
+                prefix.parse().map_err(|_| invalid())?,
+            ),
+            None => {
+                let addr: IpAddr = s.parse().map_err(|_| invalid())?;
+                let prefix = if addr.is_ipv4() { 32 } else { 128 };
+                Cidr::new(addr, prefix)
+            }
+        }
+    }
+}
+
+/// A single entry of the [`TargetPolicy`]: a network and a range of ports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetRule {
+    pub network: Cidr,
+    pub ports: RangeInclusive<u16>,
+}
+
+impl TargetRule {
+    pub fn matches(&self, target: SocketAddr) -> bool {
+        self.network.contains(target.ip()) && self.ports.contains(&target.port())
+    }
+}
+
+impl FromStr for TargetRule {
+    type Err = io::Error;
+
+    /// Parses rules in the `<cidr>[:<port>[-<port>]]` form, e.g. `10.0.0.0/8:80-443`.
+    /// IPv6 networks have to be wrapped in brackets when ports are specified: `[::1/128]:8080`.
+    fn from_str(s: &str) -> io::Result<TargetRule> {
+        let invalid = || io::Error::new(ErrorKind::InvalidInput, format!("Invalid rule: {s}"));
+
+        let (network, ports) = if let Some(rest) = s.strip_prefix('[') {
+            let (network, rest) = rest.split_once(']').ok_or_else(invalid)?;
+            (network, rest.strip_prefix(':'))
+        } else {
+            match s.rsplit_once(':') {
+                Some((network, ports)) if !network.contains(':') => (network, Some(ports)),
+                _ => (s, None),
+            }
+        };
+
+        let ports = match ports {
+            None => 0..=u16::MAX,
+            Some(ports) => match ports.split_once('-') {
+                Some((start, end)) => {
+                    let start = start.parse().map_err(|_| invalid())?;
+                    let end = end.parse().map_err(|_| invalid())?;
+                    if start > end {
+                        return Err(invalid());
+                    }
+                    start..=end
+                }
+                None => {
+                    let port = ports.parse().map_err(|_| invalid())?;
+                    port..=port
+                }
+            },
+        };
+
+        Ok(TargetRule {
+            network: network.parse()?,
+            ports,
+        })
+    }
+}
+
+/// Restricts the destinations clients are allowed to proxy to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetPolicy {
+    pub mode: PolicyMode,
+    pub rules: Vec<TargetRule>,
+}
+
+impl TargetPolicy {
+    pub fn is_allowed(&self, target: SocketAddr) -> bool {
+        let matched = self.rules.iter().any(|rule| rule.matches(target));
+
+        match self.mode {
+            PolicyMode::Allow => matched,
+            PolicyMode::Deny => !matched,
+        }
+    }
+}
+
+impl Default for TargetPolicy {
+    /// Deny mode without rules, i.e. every target is allowed.
+    fn default() -> TargetPolicy {
+        TargetPolicy {
+            mode: PolicyMode::Deny,
+            rules: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use tokio::sync::watch;
+
+/// Largest ratio of the bytes sent to a client not validated yet to the bytes received from it,
+/// the limit QUIC applies to unvalidated addresses as well (RFC 9000, section 8).
+pub const AMPLIFICATION_FACTOR: u64 = 3;
+
+/// Bounds what the server sends over a stream until its client is validated: it completed the
+/// QUIC handshake, proving it receives from its address, and presented an authenticated
+/// `Initial`. Until then, at most [`AMPLIFICATION_FACTOR`] times the bytes received are sent, so
+/// that the responses to a request with a spoofed source address can't flood its victim.
+#[derive(Debug)]
+pub struct AmplificationLimit {
+    state: watch::Sender<State>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    received: u64,
+    sent: u64,
+    validated: bool,
+}
+
+impl State {
+    fn fits(&self, len: u64) -> bool {
+        self.validated
+            || self.sent.saturating_add(len) <= self.received.saturating_mul(AMPLIFICATION_FACTOR)
+    }
+}
+
+impl AmplificationLimit {
+    /// Limit of a client not validated yet.
+    pub fn new() -> AmplificationLimit {
+        AmplificationLimit {
+            state: watch::Sender::new(State::default()),
+        }
+    }
+
+    /// Counts the bytes received from the client, allowing more to be sent.
+    pub fn record_received(&self, len: usize) {
+        self.state.send_if_modified(|state| {
+            state.received = state.received.saturating_add(len as u64);
+            !state.validated
+        });
+    }
+
+    /// Lifts the limit once the client is validated.
+    pub fn validate(&self) {
+        self.state
+            .send_if_modified(|state| !std::mem::replace(&mut state.validated, true));
+    }
+
+    pub fn is_validated(&self) -> bool {
+        self.state.borrow().validated
+    }
+
+    /// Counts `len` bytes as sent if they fit within the limit; `false` if they don't and must not
+    /// be sent.
+    pub fn try_send(&self, len: usize) -> bool {
+        let len = len as u64;
+        let mut fits = false;
+        self.state.send_if_modified(|state| {
+            fits = state.fits(len);
+            if fits && !state.validated {
+                state.sent += len;
+            }
+            false
+        });
+        fits
+    }
+
+    /// Waits until `len` bytes fit within the limit, once more bytes are received or the client
+    /// is validated, then counts them as sent.
+    pub async fn send(&self, len: usize) {
+        let mut state = self.state.subscribe();
+        loop {
+            state.borrow_and_update();
+            if self.try_send(len) {
+                return;
+            }
+            // The sender is owned by the limit itself.
+            let _ = state.changed().await;
+        }
+    }
+}
+
+impl Default for AmplificationLimit {
+    fn default() -> AmplificationLimit {
+        AmplificationLimit::new()
+    }
+}
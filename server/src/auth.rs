@@ -0,0 +1,122 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use message::InitializationMessage;
+use ring::hmac;
+
+/// Length of the time a fresh token was signed at: microseconds since the UNIX epoch.
+pub const TIMESTAMP_LENGTH: usize = 8;
+
+/// Length of the nonce of a fresh token, unique for each of them.
+pub const NONCE_LENGTH: usize = 16;
+
+/// Length of a fresh token: its time and nonce, then their HMAC-SHA256 keyed by the secret token.
+pub const FRESH_TOKEN_LENGTH: usize = TIMESTAMP_LENGTH + NONCE_LENGTH + 32;
+
+/// Checks the token presented in the [`InitializationMessage`] against the expected one.
+/// Every client is accepted when no token is configured.
+pub fn authenticate(expected: Option<&[u8]>, init: &InitializationMessage) -> bool {
+    let Some(expected) = expected else {
+        return true;
+    };
+
+    match &init.token {
+        Some(token) => constant_time_eq(expected, token),
+        None => false,
+    }
+}
+
+/// Checks the fresh token presented in the [`InitializationMessage`] is signed with the expected
+/// secret, within `window` of now, and wasn't presented before; see [`verify_fresh`].
+/// Every client is accepted when no token is configured.
+pub fn authenticate_fresh(
+    expected: Option<&[u8]>,
+    init: &InitializationMessage,
+    window: Duration,
+    nonces: &NonceCache,
+) -> bool {
+    let Some(expected) = expected else {
+        return true;
+    };
+
+    match &init.token {
+        Some(token) => verify_fresh(expected, token, message::now_micros(), window, nonces),
+        None => false,
+    }
+}
+
+/// Checks `token` is signed with `secret` at most `window` away from `now` (in microseconds since
+/// the UNIX epoch), and remembers its nonce for as long as it is fresh to reject its replays.
+pub fn verify_fresh(
+    secret: &[u8],
+    token: &[u8],
+    now: u64,
+    window: Duration,
+    nonces: &NonceCache,
+) -> bool {
+    if token.len() != FRESH_TOKEN_LENGTH {
+        return false;
+    }
+
+    let (signed, tag) = token.split_at(TIMESTAMP_LENGTH + NONCE_LENGTH);
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    if hmac::verify(&key, signed, tag).is_err() {
+        return false;
+    }
+
+    let (timestamp, nonce) = signed.split_at(TIMESTAMP_LENGTH);
+    let timestamp = u64::from_be_bytes(timestamp.try_into().unwrap());
+    let window = u64::try_from(window.as_micros()).unwrap_or(u64::MAX);
+    // Clocks of the client and the server may drift apart either way.
+    if now.abs_diff(timestamp) > window {
+        return false;
+    }
+
+    nonces.insert(
+        nonce.try_into().unwrap(),
+        timestamp.saturating_add(window),
+        now,
+    )
+}
+
+/// Nonces of the fresh tokens accepted recently, each kept until its token expires.
+#[derive(Debug, Default)]
+pub struct NonceCache {
+    /// Time each nonce expires at, in microseconds since the UNIX epoch.
+    expiries: Mutex<HashMap<[u8; NONCE_LENGTH], u64>>,
+}
+
+impl NonceCache {
+    pub fn new() -> NonceCache {
+        NonceCache::default()
+    }
+
+    /// Records the nonce until `expiry`, forgetting the ones expired at `now`; returns `false` if
+    /// it was already recorded.
+    pub fn insert(&self, nonce: [u8; NONCE_LENGTH], expiry: u64, now: u64) -> bool {
+        let mut expiries = self.expiries.lock().unwrap();
+        expiries.retain(|_, expires_at| *expires_at >= now);
+
+        if expiries.contains_key(&nonce) {
+            return false;
+        }
+        expiries.insert(nonce, expiry);
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.expiries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Compares two byte slices without short-circuiting on the first mismatch.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,180 @@
+use std::{
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    str::FromStr,
+    time::Duration,
+};
+
+use message::ProxyTarget;
+use socket2::SockRef;
+use spdlog::prelude::debug;
+use tokio::{
+    net::{TcpSocket, TcpStream, lookup_host},
+    time::timeout,
+};
+use uuid::Uuid;
+
+use crate::dscp;
+
+/// Determines which tunnels are routed to the [`Backends`] instead of the target of the client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Routing {
+    /// Every tunnel, whatever target the client asked for.
+    #[default]
+    Override,
+
+    /// Only the tunnels whose target is an unspecified address (`0.0.0.0` or `::`), i.e. left to
+    /// the server.
+    Unspecified,
+}
+
+impl FromStr for Routing {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Routing> {
+        match s {
+            "override" => Ok(Routing::Override),
+            "unspecified" => Ok(Routing::Unspecified),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown backend routing: {s}"),
+            )),
+        }
+    }
+}
+
+/// Instances of a backend the tunnels are spread across. A tunnel sticks to the instance its
+/// `connection_id` hashes to; removing an instance only moves the tunnels that hashed to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Backends {
+    /// Disabled when empty.
+    pub instances: Vec<SocketAddr>,
+    pub routing: Routing,
+}
+
+impl Backends {
+    pub fn new(instances: Vec<SocketAddr>, routing: Routing) -> Backends {
+        Backends { instances, routing }
+    }
+
+    /// Picks the instance of the tunnel, by rendezvous hashing: the instance with the highest
+    /// hash of the `connection_id` along with its address wins.
+    pub fn select(&self, connection_id: Uuid) -> Option<SocketAddr> {
+        self.instances
+            .iter()
+            .copied()
+            .max_by_key(|instance| weight(connection_id, *instance))
+    }
+
+    /// Target of the tunnel: its instance, when the routing applies to the requested target.
+    pub fn route(&self, connection_id: Uuid, requested: ProxyTarget) -> ProxyTarget {
+        let applies = match self.routing {
+            Routing::Override => true,
+            Routing::Unspecified => {
+                matches!(requested, ProxyTarget::Addr(addr) if addr.ip().is_unspecified())
+            }
+        };
+
+        match self.select(connection_id).filter(|_| applies) {
+            Some(instance) => ProxyTarget::Addr(instance),
+            None => requested,
+        }
+    }
+}
+
+/// Hash of the tunnel and the instance; FNV-1a followed by the finalizer of SplitMix64, so that
+/// it stays the same across builds and restarts.
+fn weight(connection_id: Uuid, instance: SocketAddr) -> u64 {
+    let ip = match instance {
+        SocketAddr::V4(addr) => addr.ip().to_ipv6_mapped().octets(),
+        SocketAddr::V6(addr) => addr.ip().octets(),
+    };
+    let port = instance.port().to_be_bytes();
+    let bytes = connection_id.as_bytes().iter().chain(&ip).chain(&port);
+
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for byte in bytes {
+        hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Resolves the [`ProxyTarget`] to the list of socket addresses, in the order returned by the resolver.
+pub async fn resolve(
+    target: &ProxyTarget,
+    resolve_timeout: Duration,
+) -> io::Result<Vec<SocketAddr>> {
+    let (host, port) = match target {
+        ProxyTarget::Addr(addr) => return Ok(vec![*addr]),
+        ProxyTarget::Host(host, port) => (host.as_str(), *port),
+    };
+
+    let addrs = match timeout(resolve_timeout, lookup_host((host, port))).await {
+        Ok(addrs) => addrs?.collect::<Vec<_>>(),
+        Err(_) => {
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                format!("Resolving {host} timed out"),
+            ));
+        }
+    };
+
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("No addresses found for {host}"),
+        ));
+    }
+
+    Ok(addrs)
+}
+
+/// Connects to the first reachable address, trying them in order and giving up on each after
+/// `connect_timeout`. The packets of the connection are marked with the `dscp` codepoint, if any.
+pub async fn connect(
+    addrs: &[SocketAddr],
+    connect_timeout: Duration,
+    dscp: Option<u8>,
+) -> io::Result<TcpStream> {
+    let mut last_err: Option<io::Error> = None;
+
+    for addr in addrs {
+        let err = match timeout(connect_timeout, connect_addr(*addr, dscp)).await {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => e,
+            Err(_) => io::Error::new(
+                ErrorKind::TimedOut,
+                format!("Connecting to {addr} timed out"),
+            ),
+        };
+
+        debug!("[backend] failed to connect to {addr}: {err:?}");
+        // A refusal is only reported when every address refused; another failure prevails.
+        let refused = |e: &io::Error| e.kind() == ErrorKind::ConnectionRefused;
+        if !last_err
+            .as_ref()
+            .is_some_and(|last| refused(&err) && !refused(last))
+        {
+            last_err = Some(err);
+        }
+    }
+
+    Err(last_err
+        .unwrap_or_else(|| io::Error::new(ErrorKind::InvalidInput, "No addresses to connect to")))
+}
+
+async fn connect_addr(addr: SocketAddr, dscp: Option<u8>) -> io::Result<TcpStream> {
+    let Some(dscp) = dscp else {
+        return TcpStream::connect(addr).await;
+    };
+
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    dscp::mark(SockRef::from(&socket), addr.is_ipv6(), dscp)?;
+    socket.connect(addr).await
+}
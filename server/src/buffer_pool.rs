@@ -0,0 +1,74 @@
+use std::sync::Mutex;
+
+use bytes::{Bytes, BytesMut};
+
+/// Buffers the backend data is read into, kept for reuse once their `Data` frame is written to
+/// the stream instead of being freed, sparing the allocator a buffer per frame under a sustained
+/// transfer.
+///
+/// Pooling is disabled when `max_buffers` is 0: the payloads are then copied out of a buffer
+/// reused by each tunnel instead.
+pub struct BufferPool {
+    /// Maximum number of free buffers kept.
+    max_buffers: usize,
+
+    /// Capacity of the buffers, the size of the reads from the backends.
+    buffer_size: usize,
+
+    free: Mutex<Vec<BytesMut>>,
+}
+
+impl BufferPool {
+    pub fn new(max_buffers: usize, buffer_size: usize) -> BufferPool {
+        BufferPool {
+            max_buffers,
+            buffer_size,
+            free: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_buffers > 0
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Number of free buffers waiting to be checked out.
+    pub fn free_buffers(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+
+    /// Takes a free buffer, empty with room for `buffer_size` bytes; a new one is allocated when
+    /// none is free.
+    pub fn checkout(&self) -> BytesMut {
+        self.free
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| BytesMut::with_capacity(self.buffer_size))
+    }
+
+    /// Keeps the buffer of a payload that was written for reuse. It is dropped instead when the
+    /// payload is still referenced elsewhere, when the buffer is too small, or when the pool is
+    /// full.
+    pub fn checkin(&self, payload: Bytes) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let Ok(mut buf) = payload.try_into_mut() else {
+            return;
+        };
+        buf.clear();
+        if buf.capacity() < self.buffer_size {
+            return;
+        }
+
+        let mut free = self.free.lock().unwrap();
+        if free.len() < self.max_buffers {
+            free.push(buf);
+        }
+    }
+}
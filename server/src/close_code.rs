@@ -0,0 +1,39 @@
+// Application error codes the server closes the client connections with.
+//
+// Unlike the `CloseReason` of a `Close` message, which concerns a single tunnel, a code tells why
+// the whole connection was closed; it is visible to the peer and in packet captures even when the
+// `Close` message couldn't be delivered. The codes shared with `CloseReason` keep its values.
+use quinn::VarInt;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseCode {
+    /// Client failed to present a valid authentication token.
+    AuthFailed = 0x1,
+
+    /// Client opened no stream, or sent no `Initial`, within the handshake timeout; the value of
+    /// [`message::CloseReason::Timeout`].
+    HandshakeTimeout = 0x5,
+
+    /// Server has no room for more connections; the client should back off before retrying.
+    ServerFull = 0x7,
+
+    /// Client sent a frame that can't be decoded: invalid magic byte, unknown type, longer than
+    /// the `max_frame_size`...
+    InvalidFrame = 0x10,
+
+    /// Client finished its stream within a frame.
+    TruncatedFrame = 0x11,
+
+    /// Client sent an `Initial` whose payload can't be decoded.
+    InvalidInitial = 0x12,
+
+    /// Client didn't read what the server sent within the `client_write_timeout`.
+    WriteTimeout = 0x13,
+}
+
+impl From<CloseCode> for VarInt {
+    fn from(code: CloseCode) -> VarInt {
+        VarInt::from_u32(code as u32)
+    }
+}
@@ -1,24 +1,822 @@
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fs, io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use message::{CHUNK_SIZE, DEFAULT_ALPN, MAX_HEADER_LENGTH};
+use spdlog::Level;
+
+use crate::{
+    allowlist::{PolicyMode, TargetPolicy, TargetRule},
+    backend::Backends,
+    dscp::MAX_DSCP,
+    server::{Identity, load_certs},
+};
+
+/// Name of the environment variable holding the address the server binds to, e.g. `[::]:9003`.
+pub const BIND_ENV: &str = "REVERPROX_BIND";
+
+/// Name of the environment variable holding the shared-secret token clients must present.
+pub const AUTH_TOKEN_ENV: &str = "REVERPROX_AUTH_TOKEN";
+
+/// Name of the environment variable holding the path of the PEM file with the CA certificates
+/// client certificates have to be signed by.
+pub const CLIENT_CA_ENV: &str = "REVERPROX_CLIENT_CA";
+
+/// Name of the environment variable holding the path the server certificate is written to.
+pub const CERT_PATH_ENV: &str = "REVERPROX_CERT_PATH";
+
+/// Name of the environment variable holding the path the private key of the server certificate
+/// is kept in.
+pub const KEY_PATH_ENV: &str = "REVERPROX_KEY_PATH";
+
+/// Default `max_frame_size`, fitting 64 KiB of payload.
+const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024 + MAX_HEADER_LENGTH;
+
+/// File name of the server certificate in the default location.
+const CERT_FILE_NAME: &str = "server_cert.pem";
+
+/// Name of the environment variable holding the path of the log file; logs only go to the
+/// console when unset.
+pub const LOG_FILE_ENV: &str = "REVERPROX_LOG_FILE";
+
+/// Name of the environment variable selecting the target policy mode (`allow` or `deny`).
+pub const TARGET_MODE_ENV: &str = "REVERPROX_TARGET_MODE";
+
+/// Name of the environment variable holding comma separated [`TargetRule`]s.
+pub const TARGET_RULES_ENV: &str = "REVERPROX_TARGET_RULES";
+
+/// Name of the environment variable holding the DSCP codepoint of the traffic.
+pub const DSCP_ENV: &str = "REVERPROX_DSCP";
+
+/// Name of the environment variable holding the ALPN protocol identifier.
+pub const ALPN_ENV: &str = "REVERPROX_ALPN";
+
+/// Name of the environment variable holding the comma separated addresses of the
+/// [`Backends`] instances.
+pub const BACKENDS_ENV: &str = "REVERPROX_BACKENDS";
+
+/// Name of the environment variable selecting the [`crate::backend::Routing`] of the backends
+/// (`override` or `unspecified`).
+pub const BACKEND_ROUTING_ENV: &str = "REVERPROX_BACKEND_ROUTING";
+
+/// Name of the environment variable enabling the PROXY protocol header sent to the backends
+/// (`true` or `false`).
+pub const PROXY_PROTOCOL_ENV: &str = "REVERPROX_PROXY_PROTOCOL";
+
+/// Name of the environment variable enabling QUIC 0-RTT (`true` or `false`).
+pub const ZERO_RTT_ENV: &str = "REVERPROX_ZERO_RTT";
+
+/// Name of the environment variable enabling the address validation by QUIC Retry (`true` or
+/// `false`).
+pub const ADDRESS_VALIDATION_ENV: &str = "REVERPROX_ADDRESS_VALIDATION";
+
+/// Name of the environment variable selecting the [`CongestionController`] (`cubic`, `new_reno`
+/// or `bbr`).
+pub const CONGESTION_ENV: &str = "REVERPROX_CONGESTION";
+
+/// Name of the environment variable enabling the TCP fallback (`true` or `false`).
+#[cfg(feature = "tcp-fallback")]
+pub const TCP_FALLBACK_ENV: &str = "REVERPROX_TCP_FALLBACK";
+
+/// Name of the environment variable holding the path of a config file: `NAME=VALUE` lines
+/// setting the variables of this module, which the environment overrides. It is read again when
+/// the config is reloaded, see [`crate::reload`].
+pub const CONFIG_FILE_ENV: &str = "REVERPROX_CONFIG_FILE";
+
+/// Name of the environment variable holding the least severe level logged, e.g. `debug`.
+pub const LOG_LEVEL_ENV: &str = "REVERPROX_LOG_LEVEL";
+
+/// Name of the environment variable holding the `rate_limit_bytes` of [`Config`].
+pub const RATE_LIMIT_BYTES_ENV: &str = "REVERPROX_RATE_LIMIT_BYTES";
+
+/// Name of the environment variable holding the `rate_limit_connections` of [`Config`].
+pub const RATE_LIMIT_CONNECTIONS_ENV: &str = "REVERPROX_RATE_LIMIT_CONNECTIONS";
+
+/// Name of the environment variable holding the `handshake_timeout` of [`Config`], in seconds.
+pub const HANDSHAKE_TIMEOUT_ENV: &str = "REVERPROX_HANDSHAKE_TIMEOUT";
+
+/// Name of the environment variable holding the `idle_timeout` of [`Config`], in seconds.
+pub const IDLE_TIMEOUT_ENV: &str = "REVERPROX_IDLE_TIMEOUT";
+
+/// Name of the environment variable holding the `max_connection_lifetime` of [`Config`], in
+/// seconds.
+pub const MAX_CONNECTION_LIFETIME_ENV: &str = "REVERPROX_MAX_CONNECTION_LIFETIME";
+
+/// Name of the environment variable holding the `auth_token_window` of [`Config`], in seconds.
+pub const AUTH_TOKEN_WINDOW_ENV: &str = "REVERPROX_AUTH_TOKEN_WINDOW";
+
+/// Name of the environment variable holding the `socket_send_buffer` of [`Config`], in bytes.
+pub const SOCKET_SEND_BUFFER_ENV: &str = "REVERPROX_SOCKET_SEND_BUFFER";
+
+/// Name of the environment variable holding the `socket_recv_buffer` of [`Config`], in bytes.
+pub const SOCKET_RECV_BUFFER_ENV: &str = "REVERPROX_SOCKET_RECV_BUFFER";
+
+/// Name of the environment variable holding the address of the HTTP health endpoint.
+pub const HEALTH_ADDR_ENV: &str = "REVERPROX_HEALTH_ADDR";
+
+/// Name of the environment variable holding the address of the canary backend the health
+/// endpoint checks.
+pub const HEALTH_CANARY_ENV: &str = "REVERPROX_HEALTH_CANARY";
+
+/// Name of the environment variable holding the `health_canary_ttl` of [`Config`], in seconds.
+pub const HEALTH_CANARY_TTL_ENV: &str = "REVERPROX_HEALTH_CANARY_TTL";
+
+/// QUIC congestion control algorithm of the connections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CongestionController {
+    /// Default of quinn.
+    #[default]
+    Cubic,
+
+    NewReno,
+
+    /// Keeps the throughput of links with a high bandwidth-delay product up.
+    Bbr,
+}
+
+impl CongestionController {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CongestionController::Cubic => "cubic",
+            CongestionController::NewReno => "new_reno",
+            CongestionController::Bbr => "bbr",
+        }
+    }
+}
+
+impl FromStr for CongestionController {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<CongestionController> {
+        match s {
+            "cubic" => Ok(CongestionController::Cubic),
+            "new_reno" => Ok(CongestionController::NewReno),
+            "bbr" => Ok(CongestionController::Bbr),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown congestion controller: {s}"),
+            )),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    _ipv4: IpAddr,
-    _port: u16,
-
+    /// Address the server binds to; an IPv6 address accepts IPv4 clients as well unless
+    /// `ipv6_only` is set.
     pub host: SocketAddr,
+
+    /// Restricts an IPv6 socket to IPv6 traffic instead of binding it dual-stack.
+    pub ipv6_only: bool,
+
+    /// Size of the send buffer (`SO_SNDBUF`) of the UDP socket; the defaults of the OS cap the
+    /// throughput of fast links. Left to the OS when `None`, and clamped by it to its maximum.
+    pub socket_send_buffer: Option<usize>,
+
+    /// Size of the receive buffer (`SO_RCVBUF`) of the UDP socket, likewise.
+    pub socket_recv_buffer: Option<usize>,
+
+    /// Congestion control algorithm of the client connections.
+    pub congestion_controller: CongestionController,
+
+    /// Number of bidirectional streams a client may have open at once; the tunnels of a client
+    /// are multiplexed over each of its streams.
+    pub max_concurrent_bidi_streams: u32,
+
+    /// DSCP codepoint (0 to 63) the packets of the QUIC socket and of the backend connections are
+    /// marked with, for QoS on managed networks; left to the OS when `None`.
+    pub dscp: Option<u8>,
+
+    /// Accepts unreliable QUIC datagrams from the clients, e.g. for the pings.
+    pub datagrams: bool,
+
+    /// ALPN protocol identifier the clients have to negotiate, so that the server can share its
+    /// port with other QUIC services; the handshake of the clients offering others fails.
+    pub alpn: Vec<u8>,
+
+    /// Also accepts the clients over TCP+TLS, on the TCP port of `host`, for the networks
+    /// blocking UDP.
+    #[cfg(feature = "tcp-fallback")]
+    pub tcp_fallback: bool,
+
+    /// PEM file the generated server certificate is written to, for the clients to trust it.
+    pub cert_path: PathBuf,
+
+    /// PEM file the private key of the server certificate is kept in. When set, the certificate
+    /// is generated once and reused across restarts; see [`crate::server::Identity`].
+    pub key_path: Option<PathBuf>,
+
+    /// Token expected in the [`message::InitializationMessage`]; authentication is disabled when `None`.
+    pub auth_token: Option<Bytes>,
+
+    /// Requires the clients to present `auth_token` signed along with the time and a nonce
+    /// instead of as is, accepting it only within this window of the time of the server and
+    /// once, so that a captured `Initial` can't be replayed; see [`crate::auth::verify_fresh`].
+    pub auth_token_window: Option<Duration>,
+
+    /// PEM file with the CA certificates used to verify client certificates; clients are not
+    /// required to present a certificate when `None`.
+    pub client_ca: Option<PathBuf>,
+
+    /// Accepts QUIC 0-RTT data from the clients resuming a session, saving them a round trip
+    /// when reconnecting. Early data can be replayed by an attacker, so only the messages that
+    /// reach no backend, the pings and acknowledgements, are handled before the handshake
+    /// completes; the `Initial` opening a tunnel and its `Data` wait for it. Incompatible with
+    /// `client_ca`: the client certificate is only verified once the handshake completed.
+    pub zero_rtt: bool,
+
+    /// Sends a QUIC Retry to the clients connecting from an address not validated yet, for them
+    /// to prove they receive from it before the server answers their handshake. Costs them a
+    /// round trip, but the server can't be used to flood a spoofed address.
+    pub address_validation: bool,
+
+    /// Destinations clients are allowed to proxy to.
+    pub target_policy: TargetPolicy,
+
+    /// Instances the tunnels are spread across instead of connecting to the target of the client;
+    /// their addresses are still checked against `target_policy`.
+    pub backends: Backends,
+
+    /// Starts every backend connection with a PROXY protocol v2 header carrying the address the
+    /// client reported in its `Initial`, so that the backend can log it; see
+    /// [`crate::proxy_protocol`]. The backend has to expect the header.
+    pub proxy_protocol: bool,
+
+    /// Maximum time spent resolving a hostname target.
+    pub resolve_timeout: Duration,
+
+    /// Maximum time spent connecting to each address of the target; the tunnel is closed with
+    /// [`message::CloseReason::BackendUnreachable`] once none could be reached, or with
+    /// [`message::CloseReason::BackendRefused`] if they all refused the connection.
+    pub connect_timeout: Duration,
+
+    /// Time a client has to open a stream and send a valid first message, an `Initial` or a
+    /// `Ping`, before its connection is closed with
+    /// [`CloseCode::HandshakeTimeout`](crate::close_code::CloseCode::HandshakeTimeout).
+    pub handshake_timeout: Duration,
+
+    /// Number of payloads buffered per tunnel before reading from the client is paused.
+    pub backend_channel_capacity: usize,
+
+    /// Number of recent `Data` message IDs remembered per tunnel to drop retransmissions.
+    pub dedup_window: usize,
+
+    /// Maximum number of out of order `Data` messages held per tunnel before it is closed with
+    /// [`message::CloseReason::ProtocolError`].
+    pub reorder_window: usize,
+
+    /// Maximum number of payload bytes of the out of order `Data` messages held per tunnel
+    /// before it is closed likewise.
+    pub reorder_max_bytes: usize,
+
+    /// Tunnels carrying no data in either direction for longer than this are closed.
+    pub idle_timeout: Duration,
+
+    /// Interval between two checks for idle tunnels, and for the ones past their maximum lifetime.
+    pub idle_sweep_interval: Duration,
+
+    /// Tunnels opened longer than this ago are closed with [`message::CloseReason::Normal`],
+    /// however active they are, for the clients to open fresh ones; `None` keeps them open.
+    pub max_connection_lifetime: Option<Duration>,
+
+    /// Maximum time spent relaying the remaining backend data of a tunnel the server closes.
+    pub drain_timeout: Duration,
+
+    /// Time a tunnel closed by the server waits for the client to acknowledge the `Close` before
+    /// it is removed anyway.
+    pub close_timeout: Duration,
+
+    /// Maximum time a write to a backend may take; a tunnel whose backend doesn't drain its
+    /// socket is closed with [`message::CloseReason::Timeout`].
+    pub backend_write_timeout: Duration,
+
+    /// Writes to a backend taking longer than this are logged as a warning, the backend being
+    /// slow to drain its socket.
+    pub slow_backend_write: Duration,
+
+    /// Maximum time a write to the stream of a client may take; a client not reading its stream
+    /// is closed with [`CloseCode::WriteTimeout`](crate::close_code::CloseCode::WriteTimeout).
+    pub client_write_timeout: Duration,
+
+    /// Longest frame, header included, the server sends or accepts from a client; a client
+    /// sending a longer one is closed.
+    pub max_frame_size: usize,
+
+    /// Size of the reads from a backend, and so of the largest `Data` payload sent to the
+    /// clients.
+    pub chunk_size: usize,
+
+    /// Largest read from a client stream. Frames are split across reads when longer, so it
+    /// defaults to `max_frame_size`.
+    pub recv_chunk_size: usize,
+
+    /// Maximum number of idle backend connections kept for reuse; 0 disables the pool.
+    pub backend_pool_max_idle: usize,
+
+    /// Maximum number of idle backend connections kept per target.
+    pub backend_pool_max_per_host: usize,
+
+    /// Number of `chunk_size` buffers the backend data is read into kept for reuse once sent,
+    /// see [`crate::buffer_pool::BufferPool`]; 0 disables the pool.
+    pub buffer_pool_size: usize,
+
+    /// Acknowledges every `Data` message accepted for its backend with a
+    /// [`message::MessageType::Ack`]; retransmissions are acknowledged either way.
+    pub auto_ack: bool,
+
+    /// Logs a line per forwarded message to the `access` logger.
+    pub access_log: bool,
+
+    /// File the logs are written to, besides the console; see [`crate::logging::init`].
+    pub log_file: Option<PathBuf>,
+
+    /// Least severe level logged.
+    pub log_level: Level,
+
+    /// Size in bytes above which the log file is rotated.
+    pub log_max_size: u64,
+
+    /// Number of log files kept, the current one included; 0 keeps them all.
+    pub log_max_files: usize,
+
+    /// Bytes per second each client IP address may send; reading from it is paused beyond.
+    /// 0 disables the limit.
+    pub rate_limit_bytes: u64,
+
+    /// Connections per second each client IP address may open; the ones beyond are refused.
+    /// 0 disables the limit.
+    pub rate_limit_connections: u32,
+
+    /// Maximum number of connections served at once; 0 disables the limit.
+    pub max_connections: usize,
+
+    /// Number of connections beyond `max_connections` held until one is closed, smoothing bursts
+    /// out; the ones beyond are closed with
+    /// [`CloseCode::ServerFull`](crate::close_code::CloseCode::ServerFull).
+    pub connection_queue: usize,
+
+    /// Time frames sent in a burst are held to be coalesced into a single write; a frame sent
+    /// while the stream is idle is written right away. Zero writes every frame on its own.
+    pub flush_window: Duration,
+
+    /// Size of a coalesced write above which it is flushed without waiting for the window.
+    pub coalesce_max_bytes: usize,
+
+    /// Address the HTTP health endpoint, see [`crate::http`], is served on; not served when
+    /// `None`.
+    pub health_addr: Option<SocketAddr>,
+
+    /// Backend the health endpoint has to reach, within `connect_timeout`, for the server to
+    /// report healthy; only the QUIC endpoint is checked when `None`.
+    pub health_canary: Option<SocketAddr>,
+
+    /// Time the result of a canary check is reused for, sparing the backend a connection per
+    /// health check.
+    pub health_canary_ttl: Duration,
 }
 
 impl Config {
     pub fn new() -> Config {
-        let ipv4 = IpAddr::V4(Ipv4Addr::LOCALHOST);
-        let port = 9003;
+        Config {
+            host: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 9003),
+            ipv6_only: false,
+            socket_send_buffer: None,
+            socket_recv_buffer: None,
+            congestion_controller: CongestionController::default(),
+            max_concurrent_bidi_streams: 100,
+            dscp: None,
+            datagrams: false,
+            alpn: DEFAULT_ALPN.to_vec(),
+            #[cfg(feature = "tcp-fallback")]
+            tcp_fallback: false,
+            cert_path: default_cert_path(),
+            key_path: None,
+            auth_token: None,
+            auth_token_window: None,
+            client_ca: None,
+            zero_rtt: false,
+            address_validation: false,
+            target_policy: TargetPolicy::default(),
+            backends: Backends::default(),
+            proxy_protocol: false,
+            resolve_timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(5),
+            handshake_timeout: Duration::from_secs(10),
+            backend_channel_capacity: 32,
+            dedup_window: 256,
+            reorder_window: 64,
+            reorder_max_bytes: 1024 * 1024,
+            idle_timeout: Duration::from_secs(300),
+            idle_sweep_interval: Duration::from_secs(30),
+            max_connection_lifetime: None,
+            drain_timeout: Duration::from_secs(5),
+            close_timeout: Duration::from_secs(5),
+            backend_write_timeout: Duration::from_secs(30),
+            slow_backend_write: Duration::from_secs(1),
+            client_write_timeout: Duration::from_secs(30),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            chunk_size: CHUNK_SIZE,
+            recv_chunk_size: DEFAULT_MAX_FRAME_SIZE,
+            backend_pool_max_idle: 0,
+            backend_pool_max_per_host: 4,
+            buffer_pool_size: 0,
+            auto_ack: false,
+            access_log: false,
+            log_file: None,
+            log_max_size: 10 * 1024 * 1024,
+            log_max_files: 5,
+            log_level: Level::Info,
+            rate_limit_bytes: 0,
+            rate_limit_connections: 0,
+            max_connections: 0,
+            connection_queue: 16,
+            flush_window: Duration::from_millis(1),
+            coalesce_max_bytes: 16 * 1024,
+            health_addr: None,
+            health_canary: None,
+            health_canary_ttl: Duration::from_secs(5),
+        }
+    }
 
-        let host = SocketAddr::new(ipv4, port);
+    /// Builds the default config and overrides it with the values of the config file named by
+    /// [`CONFIG_FILE_ENV`], if any, then with the ones of the environment.
+    pub fn from_env() -> io::Result<Config> {
+        let mut config = Config::new();
+        let vars = Vars::load()?;
 
-        Config {
-            _ipv4: ipv4,
-            _port: port,
-            host,
+        if let Some(host) = vars.var(BIND_ENV) {
+            config.host = host.parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid bind address: {host}"),
+                )
+            })?;
+        }
+
+        if let Some(cert_path) = vars.var_os(CERT_PATH_ENV) {
+            config.cert_path = PathBuf::from(cert_path);
+        }
+
+        if let Some(congestion_controller) = vars.var(CONGESTION_ENV) {
+            config.congestion_controller = congestion_controller.parse()?;
+        }
+
+        #[cfg(feature = "tcp-fallback")]
+        if let Some(tcp_fallback) = vars.var(TCP_FALLBACK_ENV) {
+            config.tcp_fallback = tcp_fallback.parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid TCP fallback flag: {tcp_fallback}"),
+                )
+            })?;
+        }
+
+        if let Some(proxy_protocol) = vars.var(PROXY_PROTOCOL_ENV) {
+            config.proxy_protocol = proxy_protocol.parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid PROXY protocol flag: {proxy_protocol}"),
+                )
+            })?;
+        }
+
+        if let Some(zero_rtt) = vars.var(ZERO_RTT_ENV) {
+            config.zero_rtt = zero_rtt.parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid 0-RTT flag: {zero_rtt}"),
+                )
+            })?;
+        }
+
+        if let Some(address_validation) = vars.var(ADDRESS_VALIDATION_ENV) {
+            config.address_validation = address_validation.parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid address validation flag: {address_validation}"),
+                )
+            })?;
+        }
+
+        if let Some(dscp) = vars.var(DSCP_ENV) {
+            config.dscp = Some(dscp.parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid DSCP: {dscp}"))
+            })?);
+        }
+
+        if let Some(alpn) = vars.var(ALPN_ENV) {
+            config.alpn = alpn.into_bytes();
+        }
+
+        config.key_path = vars.var_os(KEY_PATH_ENV).map(PathBuf::from);
+        config.auth_token = vars.var(AUTH_TOKEN_ENV).map(Bytes::from);
+        config.client_ca = vars.var_os(CLIENT_CA_ENV).map(PathBuf::from);
+        config.log_file = vars.var_os(LOG_FILE_ENV).map(PathBuf::from);
+
+        if let Some(mode) = vars.var(TARGET_MODE_ENV) {
+            config.target_policy.mode = match mode.as_str() {
+                "allow" => PolicyMode::Allow,
+                "deny" => PolicyMode::Deny,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown target mode: {mode}"),
+                    ));
+                }
+            };
+        }
+
+        if let Some(rules) = vars.var(TARGET_RULES_ENV) {
+            config.target_policy.rules = rules
+                .split(',')
+                .map(str::trim)
+                .filter(|rule| !rule.is_empty())
+                .map(str::parse::<TargetRule>)
+                .collect::<io::Result<_>>()?;
+        }
+
+        if let Some(instances) = vars.var(BACKENDS_ENV) {
+            config.backends.instances = instances
+                .split(',')
+                .map(str::trim)
+                .filter(|instance| !instance.is_empty())
+                .map(|instance| {
+                    instance.parse().map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Invalid backend address: {instance}"),
+                        )
+                    })
+                })
+                .collect::<io::Result<_>>()?;
+        }
+
+        if let Some(routing) = vars.var(BACKEND_ROUTING_ENV) {
+            config.backends.routing = routing.parse()?;
+        }
+
+        if let Some(level) = vars.var(LOG_LEVEL_ENV) {
+            config.log_level = level.parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid log level: {level}"),
+                )
+            })?;
+        }
+
+        if let Some(rate) = vars.parse(RATE_LIMIT_BYTES_ENV, "byte rate limit")? {
+            config.rate_limit_bytes = rate;
+        }
+        if let Some(rate) = vars.parse(RATE_LIMIT_CONNECTIONS_ENV, "connection rate limit")? {
+            config.rate_limit_connections = rate;
+        }
+        if let Some(secs) = vars.parse(HANDSHAKE_TIMEOUT_ENV, "handshake timeout")? {
+            config.handshake_timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = vars.parse(IDLE_TIMEOUT_ENV, "idle timeout")? {
+            config.idle_timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = vars.parse(MAX_CONNECTION_LIFETIME_ENV, "max connection lifetime")? {
+            config.max_connection_lifetime = Some(Duration::from_secs(secs));
+        }
+        if let Some(secs) = vars.parse(AUTH_TOKEN_WINDOW_ENV, "auth token window")? {
+            config.auth_token_window = Some(Duration::from_secs(secs));
+        }
+        if let Some(size) = vars.parse(SOCKET_SEND_BUFFER_ENV, "socket send buffer size")? {
+            config.socket_send_buffer = Some(size);
+        }
+        if let Some(size) = vars.parse(SOCKET_RECV_BUFFER_ENV, "socket receive buffer size")? {
+            config.socket_recv_buffer = Some(size);
+        }
+        if let Some(addr) = vars.parse(HEALTH_ADDR_ENV, "health address")? {
+            config.health_addr = Some(addr);
+        }
+        if let Some(addr) = vars.parse(HEALTH_CANARY_ENV, "health canary address")? {
+            config.health_canary = Some(addr);
+        }
+        if let Some(secs) = vars.parse(HEALTH_CANARY_TTL_ENV, "health canary TTL")? {
+            config.health_canary_ttl = Duration::from_secs(secs);
+        }
+
+        Ok(config)
+    }
+}
+
+/// Variables the config is built from: the ones of the environment, then the ones of the config
+/// file.
+struct Vars {
+    file: HashMap<String, String>,
+}
+
+impl Vars {
+    fn load() -> io::Result<Vars> {
+        let Some(path) = env::var_os(CONFIG_FILE_ENV) else {
+            return Ok(Vars {
+                file: HashMap::new(),
+            });
+        };
+
+        let contents = fs::read_to_string(&path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "Failed to read config file {}: {e}",
+                    Path::new(&path).display()
+                ),
+            )
+        })?;
+
+        let mut file = HashMap::new();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((name, value)) = line.split_once('=') else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid config file line: {line}"),
+                ));
+            };
+            file.insert(name.trim().to_string(), value.trim().to_string());
+        }
+
+        Ok(Vars { file })
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        env::var(name).ok().or_else(|| self.file.get(name).cloned())
+    }
+
+    fn var_os(&self, name: &str) -> Option<OsString> {
+        env::var_os(name).or_else(|| self.file.get(name).map(OsString::from))
+    }
+
+    /// Parses the variable, naming it `what` in the error.
+    fn parse<T: FromStr>(&self, name: &str, what: &str) -> io::Result<Option<T>> {
+        self.var(name)
+            .map(|value| {
+                value.parse().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid {what}: {value}"),
+                    )
+                })
+            })
+            .transpose()
+    }
+}
+
+impl Config {
+    /// Checks the config can be served without binding anything: the client CA file loads, the
+    /// cert path can be written to, the saved identity, if any, loads and the sizes and intervals
+    /// are usable.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+
+        if let Some(client_ca) = &self.client_ca {
+            load_certs(client_ca).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Invalid client CA {}: {e}", client_ca.display()),
+                )
+            })?;
+
+            if self.zero_rtt {
+                return invalid("zero_rtt can't be enabled along with client_ca".to_string());
+            }
+        }
+
+        if self.cert_path.is_dir() {
+            return invalid(format!(
+                "Cert path {} is a directory",
+                self.cert_path.display()
+            ));
+        }
+
+        if let Some(key_path) = self.key_path.as_ref().filter(|path| path.is_dir()) {
+            return invalid(format!("Key path {} is a directory", key_path.display()));
+        }
+
+        // Generated on start when either is missing, otherwise loaded.
+        if let Some(key_path) = self.key_path.as_ref() {
+            if self.cert_path.exists() && key_path.exists() {
+                Identity::load(&self.cert_path, key_path).map_err(|e| {
+                    io::Error::new(e.kind(), format!("Invalid server identity: {e}"))
+                })?;
+            }
+        }
+
+        if let Some(dscp) = self.dscp.filter(|dscp| *dscp > MAX_DSCP) {
+            return invalid(format!(
+                "dscp {dscp} is out of range, it must be at most {MAX_DSCP}"
+            ));
+        }
+
+        // A pooled connection was opened for another client, its header is already sent.
+        if self.proxy_protocol && self.backend_pool_max_idle > 0 {
+            return invalid("proxy_protocol can't be used with the backend pool".to_string());
         }
+
+        if self.socket_send_buffer == Some(0) || self.socket_recv_buffer == Some(0) {
+            return invalid("socket buffer sizes must not be zero".to_string());
+        }
+
+        if self.alpn.is_empty() || self.alpn.len() > u8::MAX as usize {
+            return invalid("alpn must be between 1 and 255 bytes".to_string());
+        }
+
+        let durations = [
+            ("handshake_timeout", self.handshake_timeout),
+            ("resolve_timeout", self.resolve_timeout),
+            ("connect_timeout", self.connect_timeout),
+            ("idle_timeout", self.idle_timeout),
+            ("idle_sweep_interval", self.idle_sweep_interval),
+            ("backend_write_timeout", self.backend_write_timeout),
+            ("slow_backend_write", self.slow_backend_write),
+            ("client_write_timeout", self.client_write_timeout),
+        ];
+        for (name, duration) in durations {
+            if duration.is_zero() {
+                return invalid(format!("{name} must not be zero"));
+            }
+        }
+        if self
+            .auth_token_window
+            .is_some_and(|window| window.is_zero())
+        {
+            return invalid("auth_token_window must not be zero".to_string());
+        }
+        if self
+            .max_connection_lifetime
+            .is_some_and(|lifetime| lifetime.is_zero())
+        {
+            return invalid("max_connection_lifetime must not be zero".to_string());
+        }
+
+        let sizes = [
+            ("backend_channel_capacity", self.backend_channel_capacity),
+            ("max_frame_size", self.max_frame_size),
+            ("chunk_size", self.chunk_size),
+            ("recv_chunk_size", self.recv_chunk_size),
+        ];
+        for (name, size) in sizes {
+            if size == 0 {
+                return invalid(format!("{name} must not be zero"));
+            }
+        }
+
+        if self.max_concurrent_bidi_streams == 0 {
+            return invalid("max_concurrent_bidi_streams must not be zero".to_string());
+        }
+
+        // The chunks read from the backends are sent with every optional header field.
+        if self.chunk_size + MAX_HEADER_LENGTH > self.max_frame_size {
+            return invalid(format!(
+                "chunk_size ({}) doesn't fit in max_frame_size ({})",
+                self.chunk_size, self.max_frame_size
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Default location of the server certificate, in the per-user data directory of the platform:
+/// `$XDG_DATA_HOME` or `~/.local/share` on Linux, `~/Library/Application Support` on macOS and
+/// `%APPDATA%` on Windows. Falls back to the working directory.
+pub fn default_cert_path() -> PathBuf {
+    match data_dir() {
+        Some(dir) => dir.join("reverprox").join(CERT_FILE_NAME),
+        None => PathBuf::from(CERT_FILE_NAME),
+    }
+}
+
+fn data_dir() -> Option<PathBuf> {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| home().map(|home| home.join(".local").join("share")))
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
     }
 }
@@ -0,0 +1,1372 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::{self, Future},
+    io,
+    net::SocketAddr,
+    ops::ControlFlow,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::{BufMut, Bytes};
+use message::{
+    CloseReason, Decoder, InitializationMessage, Message, MessageType, ProtocolVersion, ProxyTarget,
+};
+use spdlog::prelude::{debug, error, info, warn};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::{mpsc, oneshot, watch},
+    task::JoinSet,
+    time::{MissedTickBehavior, interval, sleep, timeout},
+};
+use uuid::Uuid;
+
+use crate::{
+    access_log::{AccessLog, Direction},
+    amplification::AmplificationLimit,
+    auth::{self, NonceCache},
+    backend,
+    buffer_pool::BufferPool,
+    close_code::CloseCode,
+    config::Config,
+    dedup::RecentIds,
+    events::{Events, ServerEvent},
+    log_context::LogContext,
+    metrics::{ActiveBackend, BackendMetrics, Metrics},
+    outbound::Outbound,
+    pool::BackendPool,
+    proxy_protocol,
+    rate_limit::RateLimiter,
+    registry::{CloseRequest, ConnectionGuard, ConnectionState, DataRoute, Registry, Tunnel},
+    reorder::ReorderBuffer,
+    transport::{AsyncFrameSink, AsyncFrameSource, PeerConnection},
+};
+
+/// State shared by the streams of all the connections.
+#[derive(Clone)]
+pub struct Shared {
+    pub config: Arc<Config>,
+    pub registry: Arc<Registry>,
+    pub pool: Arc<BackendPool>,
+    pub buffers: Arc<BufferPool>,
+    pub access_log: AccessLog,
+    pub events: Events,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub nonces: Arc<NonceCache>,
+}
+
+/// Handles the messages of a single bidirectional stream.
+/// A stream carries multiple tunnels, demultiplexed by the message `connection_id`.
+struct StreamHandler<C> {
+    connection: C,
+    context: LogContext,
+    send: Outbound,
+    config: Arc<Config>,
+    registry: Arc<Registry>,
+    pool: Arc<BackendPool>,
+    buffers: Arc<BufferPool>,
+    access_log: AccessLog,
+    events: Events,
+    nonces: Arc<NonceCache>,
+
+    /// Identifies the stream as the owner of the tunnels it opens in the registry.
+    stream_id: Uuid,
+
+    /// Tunnels opened over this stream; closed when the stream ends.
+    tunnels: HashSet<Uuid>,
+
+    /// Close the tunnels opened over this stream if the handler ends without closing them.
+    guards: HashMap<Uuid, ConnectionGuard>,
+
+    /// Notified by the backend readers once the backend closed the connection.
+    closed_tx: mpsc::UnboundedSender<Uuid>,
+
+    /// Notified by the backend writers once a write to the backend timed out.
+    stalled_tx: mpsc::UnboundedSender<Uuid>,
+
+    /// Tunnels being drained before their `Close` is sent; yield the `connection_id` and the ID
+    /// of the `Close` once sent.
+    drains: JoinSet<Option<(Uuid, Uuid)>>,
+
+    /// IDs of the `Close` messages waiting for the client to acknowledge them, by `connection_id`.
+    closing: HashMap<Uuid, Uuid>,
+
+    /// Yield the `connection_id` of a `Close` once its acknowledgment timed out.
+    close_timers: JoinSet<Uuid>,
+
+    /// Connect to the backends of the `Initial`s received; yield the outcome, so that the other
+    /// tunnels of the stream keep being served meanwhile.
+    opening: JoinSet<Opening>,
+
+    /// Messages received for the tunnels whose backend is being connected to, handled in order
+    /// once it is.
+    pending: HashMap<Uuid, Vec<Message>>,
+
+    /// Whether the client sent a valid `Initial` or a `Ping`, within the handshake timeout.
+    handshaken: bool,
+
+    /// Messages received as 0-RTT data that aren't replay safe, held until the QUIC handshake
+    /// completes; `None` once it completed, or when the connection doesn't accept 0-RTT data.
+    early: Option<Vec<Message>>,
+
+    /// Bounds what is sent to the client until its handshake completed and it presented an
+    /// authenticated `Initial`.
+    amplification: Arc<AmplificationLimit>,
+
+    /// Whether the client presented an authenticated `Initial`.
+    authenticated: bool,
+}
+
+/// Outcome of connecting to the backend of an `Initial`.
+struct Opening {
+    msg: Message,
+    payload: InitializationMessage,
+    version: ProtocolVersion,
+    opened: Result<(TcpStream, ProxyTarget, Arc<BackendMetrics>), CloseReason>,
+}
+
+/// Reads messages from a single bidirectional stream and routes them to the backends.
+pub async fn handle_stream(
+    connection: impl PeerConnection,
+    send: impl AsyncFrameSink,
+    recv: impl AsyncFrameSource,
+    shared: Shared,
+) {
+    serve_stream(connection, send, recv, shared, None::<future::Ready<bool>>).await;
+}
+
+/// Handles a stream of a connection accepted before its handshake completed, like
+/// [`handle_stream`]: the messages that aren't replay safe, see
+/// [`MessageType::is_replay_safe`], wait for `handshake` to resolve. It resolves to `false` if the
+/// handshake failed.
+pub async fn handle_early_stream(
+    connection: impl PeerConnection,
+    send: impl AsyncFrameSink,
+    recv: impl AsyncFrameSource,
+    shared: Shared,
+    handshake: impl Future<Output = bool> + Send,
+) {
+    serve_stream(connection, send, recv, shared, Some(handshake)).await;
+}
+
+async fn serve_stream(
+    connection: impl PeerConnection,
+    send: impl AsyncFrameSink,
+    mut recv: impl AsyncFrameSource,
+    shared: Shared,
+    handshake: Option<impl Future<Output = bool> + Send>,
+) {
+    let Shared {
+        config,
+        registry,
+        pool,
+        buffers,
+        access_log,
+        events,
+        rate_limiter,
+        nonces,
+    } = shared;
+    let client_ip = connection.remote_address().ip();
+    let (closed_tx, mut closed_rx) = mpsc::unbounded_channel::<Uuid>();
+    let (stalled_tx, mut stalled_rx) = mpsc::unbounded_channel::<Uuid>();
+
+    let amplification = Arc::new(AmplificationLimit::new());
+    let send = Outbound::spawn(
+        send,
+        config.flush_window,
+        config.coalesce_max_bytes,
+        config.max_frame_size,
+        buffers.clone(),
+        config.client_write_timeout,
+    )
+    .with_amplification_limit(amplification.clone());
+
+    // Watched apart: the backpressure of a stalled client can hold the handler up.
+    let stalled = send.stalled();
+    let peer = connection.clone();
+    tokio::spawn(async move {
+        if stalled.await {
+            warn!("[server] client isn't reading its stream, closing the connection");
+            peer.close(CloseCode::WriteTimeout, b"write timed out");
+        }
+    });
+
+    let mut handler = StreamHandler {
+        context: LogContext::new(connection.remote_address()),
+        connection,
+        send,
+        config,
+        registry,
+        pool,
+        buffers,
+        access_log,
+        events,
+        nonces,
+        stream_id: Uuid::new_v4(),
+        tunnels: HashSet::new(),
+        guards: HashMap::new(),
+        closed_tx,
+        stalled_tx,
+        drains: JoinSet::new(),
+        closing: HashMap::new(),
+        close_timers: JoinSet::new(),
+        opening: JoinSet::new(),
+        pending: HashMap::new(),
+        handshaken: false,
+        early: handshake.is_some().then(Vec::new),
+        amplification,
+        authenticated: false,
+    };
+    let mut decoder = Decoder::with_max_frame_size(handler.config.max_frame_size);
+
+    let mut sweep = interval(handler.config.idle_sweep_interval);
+    sweep.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let initial_timeout = sleep(handler.config.handshake_timeout);
+    tokio::pin!(initial_timeout);
+
+    let handshake_done = async {
+        match handshake {
+            Some(handshake) => handshake.await,
+            None => future::pending().await,
+        }
+    };
+    tokio::pin!(handshake_done);
+
+    // Whether the client finished the stream, in which case the tunnels are drained.
+    let mut finished = false;
+
+    'read: loop {
+        let read = tokio::select! {
+            read = recv.recv_chunk(handler.config.recv_chunk_size) => read,
+            Some(connection_id) = closed_rx.recv() => {
+                if handler.backend_finished(connection_id).await.is_break() {
+                    break;
+                }
+                continue;
+            }
+            Some(connection_id) = stalled_rx.recv() => {
+                warn!("[server] backend isn't draining its socket: connection_id={connection_id}");
+                if handler.close(connection_id, CloseReason::Timeout).await.is_break() {
+                    break;
+                }
+                continue;
+            }
+            Some(Ok(drained)) = handler.drains.join_next() => {
+                if let Some((connection_id, close_id)) = drained {
+                    handler.await_ack(connection_id, close_id);
+                }
+                continue;
+            }
+            Some(Ok(connection_id)) = handler.close_timers.join_next() => {
+                handler.expire_close(connection_id);
+                continue;
+            }
+            Some(Ok(opening)) = handler.opening.join_next() => {
+                if handler.opened(opening).await.is_break() {
+                    break;
+                }
+                continue;
+            }
+            _ = sweep.tick() => {
+                handler.close_idle();
+                handler.close_expired();
+                continue;
+            }
+            completed = &mut handshake_done, if handler.early.is_some() => {
+                let held = handler.early.take().unwrap_or_default();
+                if !completed {
+                    warn!("[server] handshake failed, dropping the early data");
+                    break;
+                }
+                handler.validate_if_ready();
+                debug!("[server] handshake completed, handling the early data held: messages={}", held.len());
+                for msg in held {
+                    if handler.handle(msg).await.is_break() {
+                        break 'read;
+                    }
+                }
+                continue;
+            }
+            _ = &mut initial_timeout, if !handler.handshaken => {
+                warn!(
+                    "[server] no Initial received within {:?}, closing the connection",
+                    handler.config.handshake_timeout
+                );
+                reject(
+                    &handler.connection,
+                    &handler.send,
+                    Uuid::nil(),
+                    CloseReason::Timeout,
+                    CloseCode::HandshakeTimeout,
+                )
+                .await;
+                break;
+            }
+        };
+
+        match read {
+            Ok(Some(chunk)) => {
+                // Pausing the reads makes QUIC flow control slow the client down.
+                handler.amplification.record_received(chunk.len());
+                let delay = rate_limiter.throttle(client_ip, chunk.len());
+                if !delay.is_zero() {
+                    sleep(delay).await;
+                }
+                decoder.extend(&chunk);
+
+                loop {
+                    let msg = match decoder.next_message() {
+                        Ok(Some(msg)) => msg,
+                        Ok(None) => break,
+                        Err(e) => {
+                            error!("[server] failed to decode message: {e:?}");
+                            let connection_id = decoder.connection_id().unwrap_or(Uuid::nil());
+                            warn!(
+                                "[server] closing connection sending an invalid message: connection_id={connection_id}"
+                            );
+                            if !connection_id.is_nil() {
+                                handler.finish(connection_id, CloseReason::ProtocolError);
+                            }
+                            reject(
+                                &handler.connection,
+                                &handler.send,
+                                connection_id,
+                                CloseReason::ProtocolError,
+                                CloseCode::InvalidFrame,
+                            )
+                            .await;
+                            break 'read;
+                        }
+                    };
+                    debug!("[server] received: {:?}", msg);
+
+                    // Replayed early data must not reach the backends.
+                    if let Some(held) = handler.early.as_mut() {
+                        if !msg.message_type.is_replay_safe() {
+                            held.push(msg);
+                            continue;
+                        }
+                    }
+
+                    if handler.handle(msg).await.is_break() {
+                        break 'read;
+                    }
+                    // Reading is paused while a tunnel being opened holds too many messages.
+                    if handler.await_opened().await.is_break() {
+                        break 'read;
+                    }
+                }
+            }
+            Ok(None) if decoder.buffered() > 0 => {
+                error!(
+                    "[server] stream finished within a frame: buffered={}",
+                    decoder.buffered()
+                );
+                reject(
+                    &handler.connection,
+                    &handler.send,
+                    Uuid::nil(),
+                    CloseReason::ProtocolError,
+                    CloseCode::TruncatedFrame,
+                )
+                .await;
+                break;
+            }
+            Ok(None) => {
+                finished = true;
+                break;
+            }
+            Err(e) => {
+                info!("[server] error reading: {e:?}");
+                break;
+            }
+        }
+    }
+
+    // The data sent along with the `Initial`s is drained as well.
+    if finished {
+        while let Some(Ok(opening)) = handler.opening.join_next().await {
+            if handler.opened(opening).await.is_break() {
+                finished = false;
+                break;
+            }
+        }
+    }
+    for connection_id in std::mem::take(&mut handler.tunnels) {
+        if finished {
+            handler.drain(connection_id, CloseReason::Normal);
+        } else {
+            handler.events.emit(ServerEvent::Error {
+                id: connection_id,
+                err: "client stream was lost".to_string(),
+            });
+            handler.finish(connection_id, CloseReason::Normal);
+        }
+    }
+    // Acknowledgments can't be received anymore.
+    while let Some(drained) = handler.drains.join_next().await {
+        if let Ok(Some((connection_id, _))) = drained {
+            let _ = handler
+                .registry
+                .transition(&connection_id, ConnectionState::Closed);
+        }
+    }
+    for (connection_id, _) in std::mem::take(&mut handler.closing) {
+        let _ = handler
+            .registry
+            .transition(&connection_id, ConnectionState::Closed);
+    }
+    handler.close_timers.abort_all();
+    handler.send.finish(None).await;
+}
+
+impl<C: PeerConnection> StreamHandler<C> {
+    /// Dispatches the message within the log context of its tunnel; `Break` stops reading the
+    /// stream.
+    async fn handle(&mut self, msg: Message) -> ControlFlow<()> {
+        let context = if msg.message_type.requires_connection() {
+            self.context.with_connection(msg.connection_id)
+        } else {
+            self.context
+        };
+        context.scope(self.dispatch(msg)).await
+    }
+
+    async fn dispatch(&mut self, msg: Message) -> ControlFlow<()> {
+        if !msg.message_type.requires_connection() {
+            return self.handle_connection_control(msg).await;
+        }
+
+        // The tunnels of the other streams are out of reach, their messages come from another
+        // client; an `Initial` is still rejected for reusing their ID.
+        let state = match self
+            .registry
+            .owned_state(&msg.connection_id, &self.stream_id)
+        {
+            Ok(state) => state,
+            Err(state) if matches!(msg.message_type, MessageType::Initial) => {
+                return self.handle_invalid(msg, Some(state)).await;
+            }
+            Err(_) => {
+                warn!(
+                    "[server] message for a tunnel of another stream, ignoring: connection_id={} type={}",
+                    msg.connection_id, msg.message_type
+                );
+                return ControlFlow::Continue(());
+            }
+        };
+
+        if let Some(pending) = self.pending.get_mut(&msg.connection_id) {
+            pending.push(msg);
+            return ControlFlow::Continue(());
+        }
+
+        // Data is the bulk of the traffic: only valid on an active tunnel.
+        if msg.message_type.is_data() {
+            return match state {
+                Some(ConnectionState::Active) => self.handle_data(msg).await,
+                Some(ConnectionState::Closing | ConnectionState::Closed) => {
+                    ignore_closed(&msg);
+                    ControlFlow::Continue(())
+                }
+                state => self.handle_invalid(msg, state).await,
+            };
+        }
+
+        match (msg.message_type, state) {
+            (MessageType::Ack, _) => {
+                let close_id = self.closing.get(&msg.connection_id);
+                if close_id.is_some() && close_id == msg.in_reply_to.as_ref() {
+                    self.complete_close(msg.connection_id);
+                }
+                ControlFlow::Continue(())
+            }
+            (MessageType::Close, _) => self.handle_close(msg).await,
+            (_, Some(ConnectionState::Closing | ConnectionState::Closed)) => {
+                ignore_closed(&msg);
+                ControlFlow::Continue(())
+            }
+            (MessageType::Initial, None) => self.handle_initial(msg).await,
+            (MessageType::ShutdownWrite, Some(ConnectionState::Active)) => {
+                self.handle_shutdown_write(msg.connection_id).await
+            }
+            (_, state) => self.handle_invalid(msg, state).await,
+        }
+    }
+
+    /// Handles the messages concerning the whole connection rather than a tunnel.
+    async fn handle_connection_control(&mut self, msg: Message) -> ControlFlow<()> {
+        match msg.message_type {
+            MessageType::Ping => {
+                self.handshaken = true;
+                let pong = Message::new(MessageType::Pong, msg.connection_id, msg.payload)
+                    .with_reply_to(msg.message_id);
+                self.send_message(pong).await
+            }
+            _ => ControlFlow::Continue(()),
+        }
+    }
+
+    /// Closes the tunnel of a message that is invalid in its state.
+    async fn handle_invalid(
+        &mut self,
+        msg: Message,
+        state: Option<ConnectionState>,
+    ) -> ControlFlow<()> {
+        warn!(
+            "[server] invalid message for tunnel state: connection_id={} type={} state={:?}",
+            msg.connection_id, msg.message_type, state
+        );
+        self.send_close(msg.connection_id, CloseReason::ProtocolError)
+            .await
+    }
+
+    async fn handle_initial(&mut self, msg: Message) -> ControlFlow<()> {
+        let payload = match InitializationMessage::decode(&msg.payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!(
+                    "[server] invalid Initial payload, closing the connection: connection_id={} err={e}",
+                    msg.connection_id
+                );
+                reject(
+                    &self.connection,
+                    &self.send,
+                    msg.connection_id,
+                    CloseReason::ProtocolError,
+                    CloseCode::InvalidInitial,
+                )
+                .await;
+                return ControlFlow::Break(());
+            }
+        };
+        self.handshaken = true;
+
+        info!(
+            "[server] Initial: connection_id={} target={:?} max_version={}",
+            msg.connection_id,
+            payload.target(),
+            payload.max_version
+        );
+
+        let Some(version) = ProtocolVersion::negotiate(payload.max_version) else {
+            warn!(
+                "[server] no common protocol version: connection_id={} max_version={}",
+                msg.connection_id, payload.max_version
+            );
+            return self
+                .send_close(msg.connection_id, CloseReason::ProtocolError)
+                .await;
+        };
+
+        if self
+            .registry
+            .insert(msg.connection_id, version, self.stream_id)
+            .is_err()
+        {
+            return self
+                .send_close(msg.connection_id, CloseReason::ProtocolError)
+                .await;
+        }
+        self.tunnels.insert(msg.connection_id);
+        self.guards.insert(
+            msg.connection_id,
+            ConnectionGuard::new(self.registry.clone(), msg.connection_id),
+        );
+
+        self.pending.insert(msg.connection_id, Vec::new());
+        let config = self.config.clone();
+        let pool = self.pool.clone();
+        let nonces = self.nonces.clone();
+        let registry = self.registry.clone();
+        let context = self.context.with_connection(msg.connection_id);
+        self.opening.spawn(context.scope(async move {
+            let opened =
+                open_backend(&config, &pool, &nonces, registry.metrics(), &msg, &payload).await;
+            Opening {
+                msg,
+                payload,
+                version,
+                opened,
+            }
+        }));
+
+        ControlFlow::Continue(())
+    }
+
+    /// Waits for the backends being connected to while a tunnel holds more messages than its
+    /// backend channel does.
+    async fn await_opened(&mut self) -> ControlFlow<()> {
+        let capacity = self.config.backend_channel_capacity;
+        while self
+            .pending
+            .values()
+            .any(|pending| pending.len() > capacity)
+        {
+            let Some(Ok(opening)) = self.opening.join_next().await else {
+                break;
+            };
+            self.opened(opening).await?;
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    /// Activates the tunnel once its backend is connected, or closes it if that failed, then
+    /// handles the messages received for it meanwhile.
+    async fn opened(&mut self, opening: Opening) -> ControlFlow<()> {
+        let connection_id = opening.msg.connection_id;
+        let pending = self.pending.remove(&connection_id).unwrap_or_default();
+
+        let context = self.context.with_connection(connection_id);
+        context.scope(self.activate(opening)).await?;
+        for msg in pending {
+            self.handle(msg).await?;
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    async fn activate(&mut self, opening: Opening) -> ControlFlow<()> {
+        let Opening {
+            msg,
+            payload,
+            version,
+            opened,
+        } = opening;
+        let (stream, target, backend) = match opened {
+            Ok(opened) => {
+                self.authenticated = true;
+                self.validate_if_ready();
+                opened
+            }
+            Err(CloseReason::AuthFailed) => {
+                self.fail(msg.connection_id, CloseReason::AuthFailed);
+                self.finish(msg.connection_id, CloseReason::AuthFailed);
+                reject(
+                    &self.connection,
+                    &self.send,
+                    msg.connection_id,
+                    CloseReason::AuthFailed,
+                    CloseCode::AuthFailed,
+                )
+                .await;
+                return ControlFlow::Break(());
+            }
+            Err(reason) => {
+                self.fail(msg.connection_id, reason);
+                return self.close(msg.connection_id, reason).await;
+            }
+        };
+
+        // The first reply tells the client the version the tunnel uses.
+        let ack = Message::new(MessageType::Ack, msg.connection_id, version.encode())
+            .with_reply_to(msg.message_id);
+        if self.send_message(ack).await.is_break() {
+            return ControlFlow::Break(());
+        }
+
+        let (reader, writer) = stream.into_split();
+        let (data_tx, data_rx) = mpsc::channel(self.config.backend_channel_capacity);
+        let context = self.context.with_connection(msg.connection_id);
+        let backend_writer = BackendWriter {
+            writer,
+            connection_id: msg.connection_id,
+            events: self.events.clone(),
+            write_timeout: self.config.backend_write_timeout,
+            slow_write: self.config.slow_backend_write,
+            stalled_tx: self.stalled_tx.clone(),
+            target,
+            backend: backend.activate(),
+        };
+        let writer_task = tokio::spawn(context.scope(backend_writer.relay(data_rx)));
+
+        let (last_request, last_request_rx) = watch::channel(None);
+        let (stop_reader, stop_reader_rx) = oneshot::channel();
+        let backend_reader = BackendReader {
+            reader,
+            send: self.send.clone(),
+            connection_id: msg.connection_id,
+            last_request: last_request_rx,
+            registry: self.registry.clone(),
+            access_log: self.access_log.clone(),
+            events: self.events.clone(),
+            buffers: self.buffers.clone(),
+            chunk_size: self.config.chunk_size,
+            version,
+            closed_tx: self.closed_tx.clone(),
+            backend,
+        };
+        let reader_task = tokio::spawn(context.scope(backend_reader.relay(stop_reader_rx)));
+
+        let tunnel = Tunnel {
+            data_tx: Some(data_tx),
+            reader_task,
+            writer_task,
+            stop_reader,
+            target: payload.target(),
+            last_request,
+            seen: RecentIds::new(self.config.dedup_window),
+            reorder: ReorderBuffer::new(self.config.reorder_window, self.config.reorder_max_bytes),
+        };
+
+        match self.registry.activate(&msg.connection_id, tunnel) {
+            Ok(()) => self.events.emit(ServerEvent::ConnectionOpened {
+                id: msg.connection_id,
+            }),
+            Err(state) => warn!(
+                "[server] tunnel closed while connecting: connection_id={} state={:?}",
+                msg.connection_id, state
+            ),
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    /// Tears the tunnel closed by the client down and acknowledges the `Close`. A `Close` crossing
+    /// the one sent by the server acknowledges it as well; a repeated one tears nothing down.
+    async fn handle_close(&mut self, msg: Message) -> ControlFlow<()> {
+        let connection_id = msg.connection_id;
+        let reason = CloseReason::decode(&msg.payload);
+
+        match self.registry.close_by_client(&connection_id) {
+            CloseRequest::Teardown(tunnel) => {
+                info!(
+                    "[server] tunnel closed by client: connection_id={connection_id} reason={reason:?}"
+                );
+                let reusable = matches!(reason, Ok(CloseReason::Normal)) && self.pool.is_enabled();
+                self.tunnels.remove(&connection_id);
+                self.events.emit(ServerEvent::ConnectionClosed {
+                    id: connection_id,
+                    reason: reason.unwrap_or(CloseReason::ProtocolError),
+                });
+
+                match tunnel {
+                    Some(tunnel) if reusable => self.release(connection_id, *tunnel),
+                    tunnel => {
+                        if let Some(tunnel) = tunnel {
+                            tunnel.close();
+                        }
+                        let _ = self
+                            .registry
+                            .transition(&connection_id, ConnectionState::Closed);
+                    }
+                }
+            }
+            CloseRequest::Crossed => self.complete_close(connection_id),
+            CloseRequest::Closed(None) => {
+                debug!("[server] close for unknown tunnel: connection_id={connection_id}");
+                return ControlFlow::Continue(());
+            }
+            CloseRequest::Closed(Some(state)) => {
+                debug!(
+                    "[server] tunnel already closed, nothing to tear down: connection_id={connection_id} state={state:?}"
+                );
+            }
+        }
+
+        let ack = Message::ack(connection_id, msg.message_id);
+        self.send_message(ack).await
+    }
+
+    async fn handle_data(&mut self, msg: Message) -> ControlFlow<()> {
+        let connection_id = msg.connection_id;
+        let message_id = msg.message_id;
+        let length = msg.payload.len();
+
+        if let Some(transit) = msg.transit_time(message::now_micros()) {
+            self.registry.metrics().record_transit(transit);
+        }
+
+        let (data_tx, payloads) = match self.registry.route_data(msg) {
+            DataRoute::Forward(data_tx, payloads) => (data_tx, payloads),
+            DataRoute::Duplicate => {
+                debug!(
+                    "[server] duplicate message: connection_id={connection_id} message_id={message_id}"
+                );
+                let ack = Message::ack(connection_id, message_id);
+                return self.send_message(ack).await;
+            }
+            DataRoute::WindowExceeded => {
+                warn!("[server] reorder window exceeded: connection_id={connection_id}");
+                return self.close(connection_id, CloseReason::ProtocolError).await;
+            }
+            DataRoute::Gone => return ControlFlow::Continue(()),
+        };
+
+        self.access_log.record(
+            Direction::ClientToBackend,
+            connection_id,
+            message_id,
+            MessageType::Data,
+            length,
+        );
+
+        for payload in payloads {
+            if data_tx.send(payload).await.is_err() {
+                warn!("[server] backend writer is gone: connection_id={connection_id}");
+                return ControlFlow::Continue(());
+            }
+        }
+
+        if self.config.auto_ack {
+            return self
+                .send_message(Message::ack(connection_id, message_id))
+                .await;
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    /// Shuts the backend write half down once the client finished sending; the tunnel is closed
+    /// if the backend finished as well.
+    async fn handle_shutdown_write(&mut self, connection_id: Uuid) -> ControlFlow<()> {
+        debug!("[server] client finished sending: connection_id={connection_id}");
+
+        match self.registry.shutdown_write(&connection_id) {
+            Some(directions) if directions.is_closed() => {
+                self.close(connection_id, CloseReason::Normal).await
+            }
+            _ => ControlFlow::Continue(()),
+        }
+    }
+
+    /// Called once the backend finished sending: the tunnel is closed if the client finished as
+    /// well, otherwise the client is told with a [`MessageType::ShutdownWrite`].
+    async fn backend_finished(&mut self, connection_id: Uuid) -> ControlFlow<()> {
+        match self.registry.backend_finished(&connection_id) {
+            Some(directions) if directions.is_closed() => {
+                return self.close(connection_id, CloseReason::Normal).await;
+            }
+            Some(_) => {}
+            None => return ControlFlow::Continue(()),
+        }
+
+        debug!("[server] backend finished sending: connection_id={connection_id}");
+        let shutdown = Message::new(MessageType::ShutdownWrite, connection_id, Bytes::new());
+        self.send_message(shutdown).await
+    }
+
+    /// Closes the tunnels of this stream that stayed idle beyond the configured timeout, and
+    /// forgets the guards of the closed ones.
+    fn close_idle(&mut self) {
+        self.guards.retain(|_, guard| !guard.is_released());
+
+        let idle: Vec<Uuid> = self
+            .tunnels
+            .iter()
+            // Connecting to their backend is bounded by timeouts of its own.
+            .filter(|id| {
+                !self.pending.contains_key(id)
+                    && self.registry.is_idle(id, self.config.idle_timeout)
+            })
+            .copied()
+            .collect();
+
+        for connection_id in idle {
+            info!("[server] closing idle tunnel: connection_id={connection_id}");
+            self.drain(connection_id, CloseReason::Timeout);
+        }
+    }
+
+    /// Closes the tunnels of this stream opened longer than the configured maximum lifetime ago,
+    /// however active they are, for their clients to open fresh ones.
+    fn close_expired(&mut self) {
+        let Some(max_lifetime) = self.config.max_connection_lifetime else {
+            return;
+        };
+
+        let expired: Vec<Uuid> = self
+            .tunnels
+            .iter()
+            // Connecting to their backend is bounded by timeouts of its own.
+            .filter(|id| {
+                !self.pending.contains_key(id) && self.registry.is_expired(id, max_lifetime)
+            })
+            .copied()
+            .collect();
+
+        for connection_id in expired {
+            info!(
+                "[server] closing tunnel past its maximum lifetime: connection_id={connection_id}"
+            );
+            self.drain(connection_id, CloseReason::Normal);
+        }
+    }
+
+    /// Lifts the amplification limit once the client completed the handshake and authenticated.
+    fn validate_if_ready(&self) {
+        if self.authenticated && self.early.is_none() {
+            self.amplification.validate();
+        }
+    }
+
+    /// Reports the tunnel failed to open for the given reason.
+    fn fail(&self, connection_id: Uuid, reason: CloseReason) {
+        self.events.emit(ServerEvent::Error {
+            id: connection_id,
+            err: format!("failed to open the backend: {reason}"),
+        });
+    }
+
+    /// Tears the tunnel down and marks it closed for the given reason, without sending a `Close`;
+    /// `false` if it was already closing.
+    fn finish(&mut self, connection_id: Uuid, reason: CloseReason) -> bool {
+        self.tunnels.remove(&connection_id);
+
+        match self
+            .registry
+            .transition(&connection_id, ConnectionState::Closing)
+        {
+            Ok(()) => {
+                self.events.emit(ServerEvent::ConnectionClosed {
+                    id: connection_id,
+                    reason,
+                });
+                let _ = self
+                    .registry
+                    .transition(&connection_id, ConnectionState::Closed);
+                true
+            }
+            Err(state) => {
+                debug!(
+                    "[server] tunnel already closed: connection_id={connection_id} state={state:?}"
+                );
+                false
+            }
+        }
+    }
+
+    /// Tears the tunnel down and sends its `Close`; the tunnel is kept closing until the client
+    /// acknowledges it or the close timeout elapses.
+    async fn close(&mut self, connection_id: Uuid, reason: CloseReason) -> ControlFlow<()> {
+        self.tunnels.remove(&connection_id);
+
+        if let Err(state) = self
+            .registry
+            .transition(&connection_id, ConnectionState::Closing)
+        {
+            debug!("[server] tunnel already closed: connection_id={connection_id} state={state:?}");
+            return ControlFlow::Continue(());
+        }
+        self.events.emit(ServerEvent::ConnectionClosed {
+            id: connection_id,
+            reason,
+        });
+
+        let close = Message::close(connection_id, reason);
+        let close_id = close.message_id;
+        let flow = self.send_message(close).await;
+        self.await_ack(connection_id, close_id);
+
+        flow
+    }
+
+    /// Waits for the client to acknowledge the `Close` with the given ID.
+    fn await_ack(&mut self, connection_id: Uuid, close_id: Uuid) {
+        self.closing.insert(connection_id, close_id);
+
+        let deadline = self.config.close_timeout;
+        self.close_timers.spawn(async move {
+            sleep(deadline).await;
+            connection_id
+        });
+    }
+
+    /// Removes the tunnel whose `Close` the client acknowledged.
+    fn complete_close(&mut self, connection_id: Uuid) {
+        if self.closing.remove(&connection_id).is_some() {
+            let _ = self
+                .registry
+                .transition(&connection_id, ConnectionState::Closed);
+        }
+    }
+
+    /// Removes the tunnel whose `Close` wasn't acknowledged in time.
+    fn expire_close(&mut self, connection_id: Uuid) {
+        if self.closing.remove(&connection_id).is_some() {
+            warn!("[server] close not acknowledged in time: connection_id={connection_id}");
+            let _ = self
+                .registry
+                .transition(&connection_id, ConnectionState::Closed);
+        }
+    }
+
+    /// Returns the backend connection of the tunnel closed by the client to the pool.
+    fn release(&mut self, connection_id: Uuid, tunnel: Tunnel) {
+        let pool = self.pool.clone();
+        let registry = self.registry.clone();
+        let context = self.context.with_connection(connection_id);
+
+        self.drains.spawn(context.scope(async move {
+            tunnel.release(&pool).await;
+
+            let _ = registry.transition(&connection_id, ConnectionState::Closed);
+            None
+        }));
+    }
+
+    /// Closes the tunnel in the background: stops routing its messages, relays the data the
+    /// backend still sends until the configured deadline, then sends the `Close`, whose
+    /// acknowledgment is awaited once the drain is reaped.
+    fn drain(&mut self, connection_id: Uuid, reason: CloseReason) {
+        self.tunnels.remove(&connection_id);
+
+        let tunnel = match self.registry.drain(&connection_id) {
+            Ok(tunnel) => tunnel,
+            Err(state) => {
+                debug!(
+                    "[server] tunnel already closed: connection_id={connection_id} state={state:?}"
+                );
+                return;
+            }
+        };
+        self.events.emit(ServerEvent::ConnectionClosed {
+            id: connection_id,
+            reason,
+        });
+
+        let send = self.send.clone();
+        let registry = self.registry.clone();
+        let deadline = self.config.drain_timeout;
+        let context = self.context.with_connection(connection_id);
+
+        self.drains.spawn(context.scope(async move {
+            if let Some(tunnel) = tunnel {
+                tunnel.drain(deadline).await;
+            }
+
+            let close = Message::close(connection_id, reason)
+                .with_version(registry.version(&connection_id));
+            let close_id = close.message_id;
+            if let Err(e) = send.send(close).await {
+                error!("[server] failed sending close: connection_id={connection_id} err={e:?}");
+                let _ = registry.transition(&connection_id, ConnectionState::Closed);
+                return None;
+            }
+
+            Some((connection_id, close_id))
+        }));
+    }
+
+    /// Sends a [`MessageType::Close`] for a single tunnel; `Break` if the stream is broken.
+    async fn send_close(&self, connection_id: Uuid, reason: CloseReason) -> ControlFlow<()> {
+        let close = Message::close(connection_id, reason);
+        self.send_message(close).await
+    }
+
+    /// Writes the message to the stream, encoded with the version of its tunnel; `Break` if the
+    /// stream is broken.
+    async fn send_message(&self, msg: Message) -> ControlFlow<()> {
+        let message_type = msg.message_type;
+        let version = self.registry.version(&msg.connection_id);
+        let msg = msg.with_version(version);
+
+        match self.send.send(msg).await {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(e) => {
+                error!("Failed to send {:?} message: {:?}", message_type, e);
+                ControlFlow::Break(())
+            }
+        }
+    }
+}
+
+/// Client side of a tunnel relaying to the backend.
+struct BackendWriter {
+    writer: OwnedWriteHalf,
+    connection_id: Uuid,
+    events: Events,
+
+    /// Maximum time a write may take.
+    write_timeout: Duration,
+
+    /// Writes taking longer are logged as a warning.
+    slow_write: Duration,
+
+    /// Notified when a write takes longer than `write_timeout`.
+    stalled_tx: mpsc::UnboundedSender<Uuid>,
+
+    /// Backend written to, and its metrics counting the tunnel as active.
+    target: ProxyTarget,
+    backend: ActiveBackend,
+}
+
+impl BackendWriter {
+    /// Writes the payloads received from the client to the backend until the channel is closed,
+    /// then hands the write half back; `None` if writing failed.
+    async fn relay(mut self, mut data_rx: mpsc::Receiver<Bytes>) -> Option<OwnedWriteHalf> {
+        let connection_id = self.connection_id;
+
+        while let Some(payload) = data_rx.recv().await {
+            let started = Instant::now();
+            let err = match timeout(self.write_timeout, self.writer.write_all(&payload)).await {
+                Ok(Ok(())) => {
+                    let elapsed = started.elapsed();
+                    self.backend.metrics().record_write(elapsed, payload.len());
+                    if elapsed > self.slow_write {
+                        warn!(
+                            "[server] slow backend: connection_id={connection_id} target={:?} write of {} bytes took {elapsed:?}",
+                            self.target,
+                            payload.len()
+                        );
+                    }
+                    continue;
+                }
+                Ok(Err(e)) => e.to_string(),
+                Err(_) => {
+                    let _ = self.stalled_tx.send(connection_id);
+                    format!("timed out after {:?}", self.write_timeout)
+                }
+            };
+
+            error!("[server] failed writing to backend: connection_id={connection_id} err={err}");
+            self.events.emit(ServerEvent::Error {
+                id: connection_id,
+                err: format!("failed writing to backend: {err}"),
+            });
+            return None;
+        }
+
+        Some(self.writer)
+    }
+}
+
+/// Backend side of a tunnel relaying to the client.
+struct BackendReader {
+    reader: OwnedReadHalf,
+    send: Outbound,
+    connection_id: Uuid,
+
+    /// ID of the last request forwarded to the backend.
+    last_request: watch::Receiver<Option<Uuid>>,
+    registry: Arc<Registry>,
+    access_log: AccessLog,
+    events: Events,
+
+    /// Buffers the reads from the backend go into, when enabled.
+    buffers: Arc<BufferPool>,
+
+    /// Size of the reads from the backend, and so of the largest `Data` payload sent.
+    chunk_size: usize,
+
+    /// Version the `Data` messages are encoded with.
+    version: ProtocolVersion,
+
+    /// Notified once the backend closed the connection.
+    closed_tx: mpsc::UnboundedSender<Uuid>,
+
+    /// Metrics of the backend read from.
+    backend: Arc<BackendMetrics>,
+}
+
+impl BackendReader {
+    /// Forwards the bytes read from the backend to the client as [`MessageType::Data`] messages,
+    /// marked as replies to the last request forwarded to the backend.
+    /// Notifies `closed_tx` once the backend closes the connection; hands the read half back if
+    /// stopped before.
+    async fn relay(mut self, mut stop: oneshot::Receiver<()>) -> Option<OwnedReadHalf> {
+        let connection_id = self.connection_id;
+        // The payloads are copied out of this buffer, unless they are read into pooled ones.
+        let mut buf = vec![
+            0;
+            if self.buffers.is_enabled() {
+                0
+            } else {
+                self.chunk_size
+            }
+        ];
+
+        loop {
+            let read = tokio::select! {
+                read = read_chunk(&mut self.reader, &mut buf, &self.buffers) => read,
+                _ = &mut stop => return Some(self.reader),
+            };
+
+            let payload = match read {
+                Ok(payload) if payload.is_empty() => break,
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!(
+                        "[server] failed reading from backend: connection_id={connection_id} err={e:?}"
+                    );
+                    self.events.emit(ServerEvent::Error {
+                        id: connection_id,
+                        err: format!("failed reading from backend: {e}"),
+                    });
+                    break;
+                }
+            };
+            let n = payload.len();
+            self.registry.record_to_client(&connection_id, n);
+            self.backend.record_read(n);
+
+            let msg = Message::data(connection_id, payload).with_version(self.version);
+            let msg = match *self.last_request.borrow() {
+                Some(request_id) => msg.with_reply_to(request_id),
+                None => msg,
+            };
+
+            let (message_id, message_type) = (msg.message_id, msg.message_type);
+            if let Err(e) = self.send.send(msg).await {
+                error!(
+                    "[server] failed sending to client: connection_id={connection_id} err={e:?}"
+                );
+                break;
+            }
+
+            self.access_log.record(
+                Direction::BackendToClient,
+                connection_id,
+                message_id,
+                message_type,
+                n,
+            );
+        }
+
+        let _ = self.closed_tx.send(connection_id);
+        None
+    }
+}
+
+/// Authenticates the client, resolves the requested target and connects to it; returns the
+/// connection along with the target it was routed to and its metrics.
+async fn open_backend(
+    config: &Config,
+    pool: &BackendPool,
+    nonces: &NonceCache,
+    metrics: &Metrics,
+    msg: &Message,
+    payload: &InitializationMessage,
+) -> Result<(TcpStream, ProxyTarget, Arc<BackendMetrics>), CloseReason> {
+    let expected = config.auth_token.as_deref();
+    let authenticated = match config.auth_token_window {
+        Some(window) => auth::authenticate_fresh(expected, payload, window, nonces),
+        None => auth::authenticate(expected, payload),
+    };
+    if !authenticated {
+        warn!(
+            "[server] authentication failed: connection_id={}",
+            msg.connection_id
+        );
+        return Err(CloseReason::AuthFailed);
+    }
+
+    let target = config.backends.route(msg.connection_id, payload.target());
+    if let Some(stream) = pool.checkout(&target, &config.target_policy) {
+        debug!(
+            "[server] reusing pooled backend connection: connection_id={} target={:?}",
+            msg.connection_id, target
+        );
+        let backend = metrics.backend(&target);
+        return Ok((stream, target, backend));
+    }
+
+    let addrs = match backend::resolve(&target, config.resolve_timeout).await {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            warn!(
+                "[server] failed to resolve target: connection_id={} target={:?} err={e:?}",
+                msg.connection_id, target
+            );
+            return Err(CloseReason::BackendUnreachable);
+        }
+    };
+
+    let addrs = addrs
+        .into_iter()
+        .filter(|addr| config.target_policy.is_allowed(*addr))
+        .collect::<Vec<_>>();
+
+    if addrs.is_empty() {
+        warn!(
+            "[server] target not allowed: connection_id={} target={:?}",
+            msg.connection_id, target
+        );
+        return Err(CloseReason::TargetNotAllowed);
+    }
+
+    let started = Instant::now();
+    let mut stream = backend::connect(&addrs, config.connect_timeout, config.dscp)
+        .await
+        .map_err(|e| {
+            if e.kind() == io::ErrorKind::ConnectionRefused {
+                warn!(
+                    "[server] backend refused the connection: connection_id={} target={:?}",
+                    msg.connection_id, target
+                );
+                return CloseReason::BackendRefused;
+            }
+
+            warn!(
+                "[server] backend unreachable: connection_id={} target={:?} err={e:?}",
+                msg.connection_id, target
+            );
+            CloseReason::BackendUnreachable
+        })?;
+    let backend = metrics.backend(&target);
+    backend.record_connect(started.elapsed());
+
+    if config.proxy_protocol {
+        let source = SocketAddr::new(payload.client_ip.into(), payload.client_port);
+        if let Err(e) = proxy_protocol::write_header(&mut stream, source).await {
+            warn!(
+                "[server] failed to send the PROXY header: connection_id={} target={:?} err={e:?}",
+                msg.connection_id, target
+            );
+            return Err(CloseReason::BackendUnreachable);
+        }
+    }
+
+    Ok((stream, target, backend))
+}
+
+/// Reads the next chunk from the backend, into a buffer of the pool when it is enabled, or copied
+/// out of `buf` otherwise; empty once the backend closed its side.
+async fn read_chunk(
+    reader: &mut OwnedReadHalf,
+    buf: &mut [u8],
+    buffers: &BufferPool,
+) -> io::Result<Bytes> {
+    if !buffers.is_enabled() {
+        let n = reader.read(buf).await?;
+        return Ok(Bytes::copy_from_slice(&buf[..n]));
+    }
+
+    let mut chunk = buffers.checkout();
+    reader
+        .read_buf(&mut (&mut chunk).limit(buffers.buffer_size()))
+        .await?;
+    Ok(chunk.freeze())
+}
+
+fn ignore_closed(msg: &Message) {
+    debug!(
+        "[server] ignoring message for closed tunnel: connection_id={} type={}",
+        msg.connection_id, msg.message_type
+    );
+}
+
+/// Sends a [`MessageType::Close`] with the given reason and closes the QUIC connection with
+/// `code` once the peer received it (or a short grace period elapsed).
+async fn reject(
+    connection: &impl PeerConnection,
+    send: &Outbound,
+    connection_id: Uuid,
+    reason: CloseReason,
+    code: CloseCode,
+) {
+    let close = Message::close(connection_id, reason);
+
+    if let Err(e) = send.send(close).await {
+        error!("Failed to send close message: {:?}", e);
+    }
+    send.finish(Some(Duration::from_secs(1))).await;
+
+    connection.close(code, b"connection rejected");
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Bounds the number of connections served at once; the ones beyond wait in a bounded queue for
+/// a slot to free up, and are refused once the queue is full too. A limit of 0 disables it.
+#[derive(Debug)]
+pub struct ConnectionLimit {
+    slots: Option<Arc<Semaphore>>,
+    queue: Arc<Semaphore>,
+}
+
+/// Held while a connection is served; frees its slot when dropped.
+#[derive(Debug)]
+pub struct Slot {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Admission of a connection, waiting for a slot when queued.
+#[derive(Debug)]
+pub struct Admission(Pending);
+
+#[derive(Debug)]
+enum Pending {
+    Ready(Slot),
+
+    /// Holds a place in the queue until a slot is obtained.
+    Queued(Arc<Semaphore>, OwnedSemaphorePermit),
+}
+
+impl Admission {
+    pub fn is_queued(&self) -> bool {
+        matches!(self.0, Pending::Queued(..))
+    }
+
+    /// Waits for a slot; the place in the queue is given back once it is obtained.
+    pub async fn slot(self) -> Slot {
+        match self.0 {
+            Pending::Ready(slot) => slot,
+            Pending::Queued(slots, _place) => {
+                let permit = slots
+                    .acquire_owned()
+                    .await
+                    .expect("Semaphore is never closed");
+                Slot {
+                    _permit: Some(permit),
+                }
+            }
+        }
+    }
+}
+
+impl ConnectionLimit {
+    pub fn new(max_connections: usize, queue_depth: usize) -> ConnectionLimit {
+        ConnectionLimit {
+            slots: (max_connections > 0).then(|| Arc::new(Semaphore::new(max_connections))),
+            queue: Arc::new(Semaphore::new(queue_depth)),
+        }
+    }
+
+    /// Admits a new connection, right away if a slot is free, otherwise queued; `None` when the
+    /// queue is full.
+    pub fn admit(&self) -> Option<Admission> {
+        let Some(slots) = &self.slots else {
+            return Some(Admission(Pending::Ready(Slot { _permit: None })));
+        };
+
+        if let Ok(permit) = slots.clone().try_acquire_owned() {
+            return Some(Admission(Pending::Ready(Slot {
+                _permit: Some(permit),
+            })));
+        }
+
+        let place = self.queue.clone().try_acquire_owned().ok()?;
+        Some(Admission(Pending::Queued(slots.clone(), place)))
+    }
+}
@@ -0,0 +1,41 @@
+use std::collections::{HashSet, VecDeque};
+
+use uuid::Uuid;
+
+/// Bounded set of recently seen message IDs; the oldest ID is forgotten once the capacity is reached.
+#[derive(Debug)]
+pub struct RecentIds {
+    capacity: usize,
+    order: VecDeque<Uuid>,
+    ids: HashSet<Uuid>,
+}
+
+impl RecentIds {
+    pub fn new(capacity: usize) -> RecentIds {
+        RecentIds {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            ids: HashSet::with_capacity(capacity),
+        }
+    }
+
+    /// Records the ID; returns `false` if it was already seen.
+    pub fn insert(&mut self, id: Uuid) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+
+        if !self.ids.insert(id) {
+            return false;
+        }
+
+        self.order.push_back(id);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,38 @@
+use std::io::{self, ErrorKind};
+
+use socket2::SockRef;
+
+/// Largest DSCP codepoint; it takes the upper 6 bits of the traffic class.
+pub const MAX_DSCP: u8 = 63;
+
+/// Marks the packets sent by the socket with the DSCP codepoint: the upper 6 bits of the ToS
+/// byte of IPv4, or of the traffic class of IPv6.
+pub fn mark(socket: SockRef<'_>, ipv6: bool, dscp: u8) -> io::Result<()> {
+    if dscp > MAX_DSCP {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("DSCP {dscp} is out of range"),
+        ));
+    }
+
+    let tos = u32::from(dscp) << 2;
+    if !ipv6 {
+        return socket.set_tos_v4(tos);
+    }
+
+    #[cfg(any(
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    return socket.set_tclass_v6(tos);
+
+    #[allow(unreachable_code)]
+    Err(io::Error::new(
+        ErrorKind::Unsupported,
+        "DSCP marking of IPv6 sockets is not supported on this platform",
+    ))
+}
@@ -0,0 +1,61 @@
+use message::CloseReason;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Capacity of the [`ServerEvent`] channel; slow subscribers miss the oldest events.
+const EVENTS_CAPACITY: usize = 64;
+
+/// Lifecycle events of the tunnels, emitted by the connection tasks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerEvent {
+    /// Backend connection of the tunnel is established.
+    ConnectionOpened { id: Uuid },
+
+    /// Tunnel is closed, by either side, for the given reason.
+    ConnectionClosed { id: Uuid, reason: CloseReason },
+
+    /// Tunnel failed; followed by its [`ServerEvent::ConnectionClosed`].
+    Error { id: Uuid, err: String },
+}
+
+/// Broadcasts the [`ServerEvent`]s to the subscribers.
+///
+/// Create it before calling [`crate::serve`], passing it a clone, and call
+/// [`Events::subscribe`] to receive the events emitted from then on:
+///
+/// ```ignore
+/// let events = Events::new();
+/// let mut rx = events.subscribe();
+/// tokio::spawn(server::serve(endpoint, config, registry, events.clone()));
+///
+/// while let Ok(event) = rx.recv().await {
+///     println!("{event:?}");
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Events {
+    tx: broadcast::Sender<ServerEvent>,
+}
+
+impl Events {
+    pub fn new() -> Events {
+        let (tx, _) = broadcast::channel(EVENTS_CAPACITY);
+        Events { tx }
+    }
+
+    /// Subscribes to the [`ServerEvent`]s emitted from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.tx.subscribe()
+    }
+
+    pub(crate) fn emit(&self, event: ServerEvent) {
+        // Sending only fails when there are no subscribers.
+        let _ = self.tx.send(event);
+    }
+}
+
+impl Default for Events {
+    fn default() -> Events {
+        Events::new()
+    }
+}
@@ -0,0 +1,142 @@
+// HTTP health endpoint, for the load balancers to check the server is serving.
+//
+// `GET /health` answers `200 OK` while the QUIC endpoint is bound and, when a canary backend is
+// configured, it can be connected to; `503 Service Unavailable` otherwise. The result of the
+// canary check is cached for `health_canary_ttl`.
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{
+    Method, Request, Response, StatusCode, body::Incoming, server::conn::http1, service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use quinn::Endpoint;
+use spdlog::prelude::{debug, warn};
+use tokio::{net::TcpListener, sync::Mutex};
+
+use crate::{backend, config::Config};
+
+/// Path the health is reported on.
+pub const HEALTH_PATH: &str = "/health";
+
+/// Health of a running server.
+pub struct Health {
+    endpoint: Endpoint,
+    canary: Option<Canary>,
+}
+
+/// Backend whose reachability the health depends on.
+struct Canary {
+    addr: SocketAddr,
+    connect_timeout: Duration,
+    ttl: Duration,
+
+    /// Time and result of the last check. Locked while checking, so that concurrent health
+    /// checks wait for a single connection.
+    last: Mutex<Option<(Instant, bool)>>,
+}
+
+impl Health {
+    /// Health of the server serving `endpoint`, checking the canary of the config, if any.
+    pub fn new(endpoint: Endpoint, config: &Config) -> Health {
+        let canary = config.health_canary.map(|addr| Canary {
+            addr,
+            connect_timeout: config.connect_timeout,
+            ttl: config.health_canary_ttl,
+            last: Mutex::new(None),
+        });
+
+        Health { endpoint, canary }
+    }
+
+    /// Whether the endpoint is bound and the canary, if any, is reachable.
+    pub async fn check(&self) -> bool {
+        if let Err(e) = self.endpoint.local_addr() {
+            warn!("[server] health check failed, the endpoint isn't bound: {e}");
+            return false;
+        }
+
+        match &self.canary {
+            Some(canary) => canary.check().await,
+            None => true,
+        }
+    }
+}
+
+impl Canary {
+    async fn check(&self) -> bool {
+        let mut last = self.last.lock().await;
+        if let Some((checked_at, reachable)) = *last {
+            if checked_at.elapsed() < self.ttl {
+                return reachable;
+            }
+        }
+
+        let reachable = match backend::connect(&[self.addr], self.connect_timeout, None).await {
+            Ok(_) => true,
+            Err(e) => {
+                warn!(
+                    "[server] health canary unreachable: addr={} err={e}",
+                    self.addr
+                );
+                false
+            }
+        };
+        *last = Some((Instant::now(), reachable));
+        reachable
+    }
+}
+
+/// Serves the health endpoint on the connections accepted by `listener`.
+pub async fn serve(listener: TcpListener, health: Arc<Health>) {
+    loop {
+        let (socket, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("[server] failed to accept a health check connection: {e}");
+                continue;
+            }
+        };
+
+        let health = health.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let health = health.clone();
+                async move { Ok::<_, Infallible>(respond(&health, request).await) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(socket), service)
+                .await
+            {
+                debug!("[server] health check connection failed: addr={addr} err={e}");
+            }
+        });
+    }
+}
+
+async fn respond(health: &Health, request: Request<Incoming>) -> Response<Full<Bytes>> {
+    if request.uri().path() != HEALTH_PATH {
+        return response(StatusCode::NOT_FOUND, "not found\n");
+    }
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return response(StatusCode::METHOD_NOT_ALLOWED, "method not allowed\n");
+    }
+
+    if health.check().await {
+        response(StatusCode::OK, "ok\n")
+    } else {
+        response(StatusCode::SERVICE_UNAVAILABLE, "unavailable\n")
+    }
+}
+
+fn response(status: StatusCode, body: &'static str) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from_static(body.as_bytes())));
+    *response.status_mut() = status;
+    response
+}
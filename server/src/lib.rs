@@ -0,0 +1,275 @@
+// Server side of the tunnel.
+//
+// The server accepts QUIC connections from the clients and, for every tunnel opened on them by an
+// `Initial` message, connects to the requested backend and relays the `Data` messages between the
+// two sides. With the `tcp-fallback` feature, it also accepts the clients over TCP+TLS for the
+// networks blocking UDP.
+use std::{sync::Arc, time::Duration};
+
+use close_code::CloseCode;
+use quinn::{
+    Connection, ConnectionError, Endpoint, Incoming, RecvStream, SendStream, ZeroRttAccepted,
+};
+use spdlog::prelude::{debug, info, warn};
+use tokio::{sync::watch, time::timeout};
+
+pub mod access_log;
+pub mod allowlist;
+pub mod amplification;
+pub mod auth;
+pub mod backend;
+pub mod buffer_pool;
+pub mod close_code;
+pub mod config;
+pub mod connection;
+pub mod connection_limit;
+pub mod dedup;
+pub mod dscp;
+pub mod events;
+pub mod http;
+pub mod log_context;
+pub mod logging;
+pub mod metrics;
+pub mod outbound;
+pub mod pool;
+pub mod proxy_protocol;
+pub mod rate_limit;
+pub mod registry;
+pub mod reload;
+pub mod reorder;
+pub mod server;
+#[cfg(feature = "tcp-fallback")]
+pub mod tcp;
+pub mod transport;
+
+use access_log::AccessLog;
+use auth::NonceCache;
+use buffer_pool::BufferPool;
+use connection::Shared;
+use connection_limit::ConnectionLimit;
+use events::Events;
+use log_context::LogContext;
+use registry::Registry;
+use reload::Reloader;
+
+/// Accepts the incoming connections on the endpoint and serves their streams until the endpoint is
+/// closed. The lifecycle events of the tunnels are broadcast to the subscribers of `events`. The
+/// config may be reloaded through a clone of the [`Reloader`] passed instead of it.
+pub async fn serve(
+    endpoint: Endpoint,
+    config: impl Into<Reloader>,
+    registry: Arc<Registry>,
+    events: Events,
+) {
+    let reloader = config.into();
+    let (shared, limit) = shared(&reloader, registry, events);
+    serve_quic(endpoint, shared, limit, reloader).await;
+}
+
+/// Serves the endpoint like [`serve`], along with the clients falling back to TCP; the limits
+/// apply to the connections of both transports together. Returns once the endpoint is closed.
+#[cfg(feature = "tcp-fallback")]
+pub async fn serve_with_tcp_fallback(
+    endpoint: Endpoint,
+    fallback: tcp::TcpFallback,
+    config: impl Into<Reloader>,
+    registry: Arc<Registry>,
+    events: Events,
+) {
+    let reloader = config.into();
+    let (shared, limit) = shared(&reloader, registry, events);
+    let tcp = tokio::spawn(tcp::serve(
+        fallback,
+        shared.clone(),
+        limit.clone(),
+        reloader.clone(),
+    ));
+    serve_quic(endpoint, shared, limit, reloader).await;
+    tcp.abort();
+}
+
+/// State shared by the connections of the server, and the bound on their number.
+fn shared(
+    reloader: &Reloader,
+    registry: Arc<Registry>,
+    events: Events,
+) -> (Shared, Arc<ConnectionLimit>) {
+    let config = reloader.config();
+    let pool = reloader.pool();
+    let buffers = Arc::new(BufferPool::new(config.buffer_pool_size, config.chunk_size));
+    let access_log = AccessLog::new(config.access_log);
+    let rate_limiter = reloader.rate_limiter();
+    let limit = Arc::new(ConnectionLimit::new(
+        config.max_connections,
+        config.connection_queue,
+    ));
+
+    let shared = Shared {
+        config,
+        registry,
+        pool,
+        buffers,
+        access_log,
+        events,
+        rate_limiter,
+        nonces: Arc::new(NonceCache::new()),
+    };
+    (shared, limit)
+}
+
+/// Completes the handshake of a connection refused for lack of room only to close it with
+/// [`CloseCode::ServerFull`], so that the client can tell it from a network error.
+async fn close_full(incoming: Incoming, handshake_timeout: Duration) {
+    if let Ok(Ok(connection)) = timeout(handshake_timeout, incoming).await {
+        connection.close(CloseCode::ServerFull.into(), b"server full");
+    }
+}
+
+/// Completes the handshake of the incoming connection; with `zero_rtt`, returns the connection
+/// right away instead, along with the future resolving once its handshake completes.
+async fn accept(
+    incoming: Incoming,
+    zero_rtt: bool,
+) -> Result<(Connection, Option<ZeroRttAccepted>), ConnectionError> {
+    if !zero_rtt {
+        return Ok((incoming.await?, None));
+    }
+
+    match incoming.accept()?.into_0rtt() {
+        Ok((connection, handshake)) => Ok((connection, Some(handshake))),
+        Err(connecting) => Ok((connecting.await?, None)),
+    }
+}
+
+async fn serve_quic(
+    endpoint: Endpoint,
+    shared: Shared,
+    limit: Arc<ConnectionLimit>,
+    reloader: Reloader,
+) {
+    while let Some(incoming) = endpoint.accept().await {
+        // Served with the config current when it is accepted.
+        let shared = Shared {
+            config: reloader.config(),
+            ..shared.clone()
+        };
+        let addr = incoming.remote_address();
+        if !shared.rate_limiter.allow_connection(addr.ip()) {
+            warn!("[server] connection rate exceeded, refusing: addr={addr}");
+            incoming.refuse();
+            continue;
+        }
+
+        if shared.config.address_validation
+            && !incoming.remote_address_validated()
+            && incoming.may_retry()
+        {
+            debug!("[server] validating the address with a retry: addr={addr}");
+            if let Err(e) = incoming.retry() {
+                warn!("[server] failed to send a retry: addr={addr} err={e}");
+            }
+            continue;
+        }
+
+        let Some(admission) = limit.admit() else {
+            warn!("[server] too many connections, refusing: addr={addr}");
+            tokio::spawn(close_full(incoming, shared.config.handshake_timeout));
+            continue;
+        };
+        if admission.is_queued() {
+            info!("[server] too many connections, queuing: addr={addr}");
+        }
+
+        let context = LogContext::new(addr);
+
+        tokio::spawn(context.scope(async move {
+            // Held until the connection is closed.
+            let _slot = admission.slot().await;
+
+            let (connection, handshake) = match accept(incoming, shared.config.zero_rtt).await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("[server] handshake failed: {e}");
+                    return;
+                }
+            };
+
+            info!(
+                "[server] incoming connection: addr={}",
+                connection.remote_address()
+            );
+
+            // Resolved once the handshake completed, to whether it succeeded, with 0-RTT data;
+            // the streams accepted until then hold their early data.
+            let handshake = handshake.map(|handshake| {
+                let (completed_tx, completed_rx) = watch::channel(None);
+                let peer = connection.clone();
+                tokio::spawn(async move {
+                    // Resolves once the connection is lost as well.
+                    handshake.await;
+                    completed_tx.send_replace(Some(peer.close_reason().is_none()));
+                });
+                completed_rx
+            });
+
+            // The client has to open its stream within the handshake timeout.
+            let handshake_timeout = shared.config.handshake_timeout;
+            match timeout(handshake_timeout, connection.accept_bi()).await {
+                Ok(Ok((send, recv))) => {
+                    spawn_stream(&connection, send, recv, &shared, handshake.as_ref(), context);
+                }
+                Ok(Err(_)) => return,
+                Err(_) => {
+                    warn!(
+                        "[server] no stream opened within {handshake_timeout:?}, closing the connection: addr={}",
+                        connection.remote_address()
+                    );
+                    connection.close(CloseCode::HandshakeTimeout.into(), b"handshake timed out");
+                    return;
+                }
+            }
+
+            while let Ok((send, recv)) = connection.accept_bi().await {
+                spawn_stream(&connection, send, recv, &shared, handshake.as_ref(), context);
+            }
+        }));
+    }
+}
+
+/// Serves a stream of the connection; its messages that aren't replay safe wait for the
+/// `handshake` if it hasn't completed yet.
+fn spawn_stream(
+    connection: &Connection,
+    send: SendStream,
+    recv: RecvStream,
+    shared: &Shared,
+    handshake: Option<&watch::Receiver<Option<bool>>>,
+    context: LogContext,
+) {
+    match handshake.filter(|handshake| handshake.borrow().is_none()) {
+        Some(handshake) => {
+            let mut handshake = handshake.clone();
+            let completed = async move {
+                matches!(
+                    handshake.wait_for(Option::is_some).await.as_deref(),
+                    Ok(Some(true))
+                )
+            };
+            tokio::spawn(context.scope(connection::handle_early_stream(
+                connection.clone(),
+                send,
+                recv,
+                shared.clone(),
+                completed,
+            )));
+        }
+        None => {
+            tokio::spawn(context.scope(connection::handle_stream(
+                connection.clone(),
+                send,
+                recv,
+                shared.clone(),
+            )));
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::{fmt::Write, future::Future, net::SocketAddr};
+
+use spdlog::{
+    Record, StringBuf,
+    formatter::{Formatter, FormatterContext, FullFormatter},
+};
+use uuid::Uuid;
+
+tokio::task_local! {
+    static CONTEXT: LogContext;
+}
+
+/// Connection the log lines of a task are about, appended to them by [`ContextFormatter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogContext {
+    pub addr: SocketAddr,
+    pub connection_id: Option<Uuid>,
+}
+
+impl LogContext {
+    pub fn new(addr: SocketAddr) -> LogContext {
+        LogContext {
+            addr,
+            connection_id: None,
+        }
+    }
+
+    /// The same context, narrowed down to a tunnel.
+    pub fn with_connection(self, connection_id: Uuid) -> LogContext {
+        LogContext {
+            connection_id: Some(connection_id),
+            ..self
+        }
+    }
+
+    /// Context of the current task, if any.
+    pub fn current() -> Option<LogContext> {
+        CONTEXT.try_with(|context| *context).ok()
+    }
+
+    /// Runs `future` within the context. The tasks it spawns don't inherit it, so they have to be
+    /// scoped as well.
+    pub fn scope<F: Future>(self, future: F) -> impl Future<Output = F::Output> {
+        CONTEXT.scope(self, future)
+    }
+}
+
+/// Formats the records like [`FullFormatter`], followed by the [`LogContext`] of the task logging
+/// them.
+#[derive(Clone, Default)]
+pub struct ContextFormatter {
+    inner: FullFormatter,
+}
+
+impl ContextFormatter {
+    pub fn new() -> ContextFormatter {
+        ContextFormatter::default()
+    }
+}
+
+impl Formatter for ContextFormatter {
+    fn format(
+        &self,
+        record: &Record,
+        dest: &mut StringBuf,
+        ctx: &mut FormatterContext,
+    ) -> spdlog::Result<()> {
+        self.inner.format(record, dest, ctx)?;
+
+        let Some(context) = LogContext::current() else {
+            return Ok(());
+        };
+
+        let eol = dest.len() - dest.trim_end_matches(['\r', '\n']).len();
+        let eol = dest.split_off(dest.len() - eol);
+        write!(dest, " {{addr={}", context.addr).map_err(spdlog::Error::FormatRecord)?;
+        if let Some(connection_id) = context.connection_id {
+            write!(dest, " connection_id={connection_id}").map_err(spdlog::Error::FormatRecord)?;
+        }
+        dest.push('}');
+        dest.push_str(&eol);
+
+        Ok(())
+    }
+}
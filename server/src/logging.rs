@@ -0,0 +1,55 @@
+use std::{io, path::Path, sync::Arc};
+
+use spdlog::{
+    Level, LevelFilter,
+    sink::{RotatingFileSink, RotationPolicy},
+};
+
+use crate::{config::Config, log_context::ContextFormatter};
+
+/// Adds a size-rotated file sink to the default logger when `log_file` is configured; the console
+/// output is kept either way. The lines logged within a connection are tagged with its
+/// [`LogContext`](crate::log_context::LogContext).
+///
+/// Has to run before the server starts, as the loggers forked from the default one, like the
+/// access log, only get the sinks it has at that time.
+pub fn init(config: &Config) -> io::Result<()> {
+    if let Some(path) = &config.log_file {
+        add_file_sink(path, config)?;
+    }
+    set_level(config.log_level);
+
+    for sink in spdlog::default_logger().sinks() {
+        sink.set_formatter(Box::new(ContextFormatter::new()));
+    }
+
+    Ok(())
+}
+
+/// Logs the messages of the default logger down to `level`; the access log keeps its own level.
+pub fn set_level(level: Level) {
+    spdlog::default_logger().set_level_filter(LevelFilter::MoreSevereEqual(level));
+}
+
+fn add_file_sink(path: &Path, config: &Config) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let sink = RotatingFileSink::builder()
+        .base_path(path)
+        .rotation_policy(RotationPolicy::FileSize(config.log_max_size))
+        .max_files(config.log_max_files)
+        .build()
+        .map_err(io::Error::other)?;
+
+    let logger = spdlog::default_logger()
+        .fork_with(|logger| {
+            logger.sinks_mut().push(Arc::new(sink));
+            Ok(())
+        })
+        .map_err(io::Error::other)?;
+    spdlog::set_default_logger(logger);
+
+    Ok(())
+}
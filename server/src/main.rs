@@ -1,82 +1,121 @@
-use std::{error::Error, fs::File, io::Write, path::Path, time::Duration};
+use std::{env, error::Error, process::ExitCode, sync::Arc};
 
-use bytes::Bytes;
-use message::{InitializationMessage, MessageType};
-use pem::Pem;
-use spdlog::prelude::info;
-use tokio::time::sleep;
+use server::{config, events::Events, registry};
+use spdlog::prelude::{info, warn};
 
-mod config;
-mod server;
+/// Flag validating the config and exiting without serving.
+const CHECK_CONFIG_FLAG: &str = "--check-config";
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    let config = config::Config::new();
+async fn main() -> Result<ExitCode, Box<dyn Error + Send + Sync + 'static>> {
+    if env::args().skip(1).any(|arg| arg == CHECK_CONFIG_FLAG) {
+        return Ok(check_config());
+    }
+
+    let config = Arc::new(config::Config::from_env()?);
+    config.validate()?;
+    server::logging::init(&config)?;
 
-    let (endpoint, server_cert) = server::make_server_endpoint(config.host)?;
+    let identity = server::server::load_identity(&config)?;
+    let endpoint = server::server::make_endpoint(&config, &identity)?;
 
-    let pem = Pem::new("CERTIFICATE", server_cert.to_vec());
+    server::server::save_cert(&config.cert_path, &identity.cert)?;
+    info!("Saved server_cert to {}", config.cert_path.display());
 
-    let cert_path = Path::new("examples/server_cert.pem");
-    let mut file = File::create(&cert_path)?;
-    file.write_all(pem::encode(&pem).as_bytes())
-        .unwrap_or_else(|e| panic!("Error while writing pem file {e:?}"));
+    if config.auth_token.is_none() {
+        warn!("No auth token configured, every client will be accepted");
+    }
 
-    info!("Saved server_cert to {}", cert_path.display());
+    let registry = Arc::new(registry::Registry::new());
+    let reloader = server::reload::Reloader::new(config.clone());
+    #[cfg(unix)]
+    tokio::spawn(server::reload::reload_on_sighup(reloader.clone()));
 
     info!("Address: {:?}", config.host);
-    loop {
-        let connection = endpoint.accept().await.unwrap().await.unwrap();
-
-        tokio::spawn(async move {
-            info!(
-                "[server] incoming connection: addr={}",
-                connection.remote_address()
-            );
-
-            while let Ok((mut send, mut recv)) = connection.accept_bi().await {
-                tokio::spawn(async move {
-                    loop {
-                        match recv.read_chunk(500, false).await {
-                            Ok(Some(chunk)) => {
-                                let msg = message::Message::decode(&chunk.bytes).unwrap();
-                                info!("[server] received: {:?}", msg);
-
-                                match msg.message_type {
-                                    MessageType::Initial => {
-                                        info!("Message Type - Initial");
-                                        let payload = InitializationMessage::decode(&msg.payload);
-
-                                        info!("Message Payload -> {:?}", payload);
-                                    }
-                                    MessageType::Data => {}
-                                    MessageType::Close => {}
-                                    MessageType::Ping => {}
-                                    _ => panic!("Unreachable"),
-                                }
-                            }
-                            Ok(None) => {
-                                continue;
-                            }
-                            Err(e) => {
-                                info!("[server] error reading: {e:?}");
-                                break;
-                            }
-                        }
-                    }
-                });
-
-                send.write_chunk(Bytes::from_static(b"response"))
-                    .await
-                    .unwrap_or_else(|e| panic!("Err: {e:?}"));
-
-                sleep(Duration::from_secs(2)).await;
-                info!("here");
-
-                send.write_chunk(Bytes::from_static(b"response22"))
-                    .await
-                    .unwrap_or_else(|e| panic!("Err: {e:?}"));
-            }
-        });
+
+    if let Some(health_addr) = config.health_addr {
+        let listener = tokio::net::TcpListener::bind(health_addr).await?;
+        info!("Health address: {:?}", listener.local_addr()?);
+        let health = Arc::new(server::http::Health::new(endpoint.clone(), &config));
+        tokio::spawn(server::http::serve(listener, health));
+    }
+
+    #[cfg(feature = "tcp-fallback")]
+    if config.tcp_fallback {
+        let fallback = server::tcp::TcpFallback::bind(&config, &identity).await?;
+        info!("TCP fallback address: {:?}", fallback.local_addr()?);
+        server::serve_with_tcp_fallback(endpoint, fallback, reloader, registry, Events::new())
+            .await;
+        return Ok(ExitCode::SUCCESS);
     }
+
+    server::serve(endpoint, reloader, registry, Events::new()).await;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Loads and validates the config from the environment and prints a summary of it.
+fn check_config() -> ExitCode {
+    let config = match config::Config::from_env().and_then(|config| {
+        config.validate()?;
+        Ok(config)
+    }) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid config: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!("Config is valid");
+    println!("  bind address:  {}", config.host);
+    println!("  ALPN:          {}", String::from_utf8_lossy(&config.alpn));
+    println!("  cert path:     {}", config.cert_path.display());
+    match &config.key_path {
+        Some(key_path) => println!("  key path:      {}", key_path.display()),
+        None => println!("  key path:      none, the certificate is generated on every start"),
+    }
+    println!(
+        "  auth token:    {}",
+        if config.auth_token.is_some() {
+            "set"
+        } else {
+            "none"
+        }
+    );
+    if let Some(window) = config.auth_token_window {
+        println!("  token window:  {window:?}, signed tokens only");
+    }
+    match &config.client_ca {
+        Some(client_ca) => println!("  client CA:     {}", client_ca.display()),
+        None => println!("  client CA:     none"),
+    }
+    println!(
+        "  target policy: {:?} with {} rule(s)",
+        config.target_policy.mode,
+        config.target_policy.rules.len()
+    );
+    if !config.backends.instances.is_empty() {
+        println!(
+            "  backends:      {} instance(s), {:?} routing",
+            config.backends.instances.len(),
+            config.backends.routing
+        );
+    }
+    if config.proxy_protocol {
+        println!("  PROXY protocol: v2 header sent to the backends");
+    }
+    if let Some(health_addr) = config.health_addr {
+        match config.health_canary {
+            Some(canary) => println!("  health:        {health_addr}, canary {canary}"),
+            None => println!("  health:        {health_addr}"),
+        }
+    }
+    match &config.log_file {
+        Some(log_file) => println!("  log file:      {}", log_file.display()),
+        None => println!("  log file:      none"),
+    }
+    println!("  log level:     {}", config.log_level);
+
+    ExitCode::SUCCESS
 }
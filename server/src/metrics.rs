@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use message::ProxyTarget;
+
+/// Upper bounds of the buckets of a [`Histogram`], in microseconds; a last bucket counts the
+/// longer samples.
+pub const BUCKET_BOUNDS: [u64; 13] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000,
+];
+
+/// Number of backends whose metrics are recorded at most.
+pub const MAX_BACKENDS: usize = 1024;
+
+/// Distribution of durations over the fixed [`BUCKET_BOUNDS`].
+#[derive(Debug, Default)]
+pub struct Histogram {
+    counts: [AtomicU64; BUCKET_BOUNDS.len() + 1],
+    sum_micros: AtomicU64,
+}
+
+/// Counts of a [`Histogram`] at some point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    /// Number of samples of each bucket: up to the bound, or beyond the last one when `None`.
+    pub buckets: Vec<(Option<Duration>, u64)>,
+    pub count: u64,
+    pub sum: Duration,
+}
+
+impl Histogram {
+    pub fn record(&self, sample: Duration) {
+        let micros = u64::try_from(sample.as_micros()).unwrap_or(u64::MAX);
+        let bucket = BUCKET_BOUNDS.partition_point(|bound| *bound < micros);
+
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let bounds = BUCKET_BOUNDS
+            .iter()
+            .map(|bound| Some(Duration::from_micros(*bound)))
+            .chain([None]);
+        let buckets = bounds
+            .zip(&self.counts)
+            .map(|(bound, count)| (bound, count.load(Ordering::Relaxed)))
+            .collect::<Vec<_>>();
+
+        HistogramSnapshot {
+            count: buckets.iter().map(|(_, count)| count).sum(),
+            buckets,
+            sum: Duration::from_micros(self.sum_micros.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Measurements of the traffic relayed by the server.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// One-way transit time of the timestamped `Data` messages of the clients.
+    transit: Histogram,
+    /// Connections to each backend, by the target they are routed to.
+    backends: Mutex<HashMap<ProxyTarget, Arc<BackendMetrics>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    pub fn record_transit(&self, transit: Duration) {
+        self.transit.record(transit);
+    }
+
+    pub fn transit(&self) -> HistogramSnapshot {
+        self.transit.snapshot()
+    }
+
+    /// Metrics of the connections to `target`, recorded from then on if it's a new one.
+    ///
+    /// Once [`MAX_BACKENDS`] targets are recorded, the ones no tunnel relays to anymore are
+    /// evicted to make room; while all of them are in use, the new target isn't recorded.
+    pub fn backend(&self, target: &ProxyTarget) -> Arc<BackendMetrics> {
+        let mut backends = self.backends.lock().unwrap();
+        if let Some(metrics) = backends.get(target) {
+            return metrics.clone();
+        }
+
+        if backends.len() >= MAX_BACKENDS {
+            backends.retain(|_, metrics| Arc::strong_count(metrics) > 1);
+        }
+        if backends.len() >= MAX_BACKENDS {
+            return Arc::default();
+        }
+        backends.entry(target.clone()).or_default().clone()
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let backends = self.backends.lock().unwrap();
+        MetricsSnapshot {
+            transit: self.transit.snapshot(),
+            backends: backends
+                .iter()
+                .map(|(target, metrics)| (target.clone(), metrics.snapshot()))
+                .collect(),
+        }
+    }
+}
+
+/// Measurements of the connections to one backend.
+#[derive(Debug, Default)]
+pub struct BackendMetrics {
+    /// Time taken to connect, pooled connections aside.
+    connect: Histogram,
+    /// Time taken by each write of the payloads of the clients.
+    write: Histogram,
+    bytes_to_backend: AtomicU64,
+    bytes_from_backend: AtomicU64,
+    active: AtomicU64,
+}
+
+/// Counts of the [`BackendMetrics`] of a backend at some point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendSnapshot {
+    pub connect: HistogramSnapshot,
+    pub write: HistogramSnapshot,
+    pub bytes_to_backend: u64,
+    pub bytes_from_backend: u64,
+    /// Number of tunnels currently relaying to the backend.
+    pub active: u64,
+}
+
+impl BackendMetrics {
+    pub fn record_connect(&self, latency: Duration) {
+        self.connect.record(latency);
+    }
+
+    pub fn record_write(&self, latency: Duration, bytes: usize) {
+        self.write.record(latency);
+        self.bytes_to_backend
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_read(&self, bytes: usize) {
+        self.bytes_from_backend
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a tunnel relaying to the backend until the returned guard is dropped.
+    pub fn activate(self: &Arc<Self>) -> ActiveBackend {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        ActiveBackend(self.clone())
+    }
+
+    pub fn snapshot(&self) -> BackendSnapshot {
+        BackendSnapshot {
+            connect: self.connect.snapshot(),
+            write: self.write.snapshot(),
+            bytes_to_backend: self.bytes_to_backend.load(Ordering::Relaxed),
+            bytes_from_backend: self.bytes_from_backend.load(Ordering::Relaxed),
+            active: self.active.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A tunnel counted as active by the [`BackendMetrics`] of its backend.
+#[derive(Debug)]
+pub struct ActiveBackend(Arc<BackendMetrics>);
+
+impl ActiveBackend {
+    pub fn metrics(&self) -> &BackendMetrics {
+        &self.0
+    }
+}
+
+impl Drop for ActiveBackend {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts of the [`Metrics`] at some point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub transit: HistogramSnapshot,
+    pub backends: HashMap<ProxyTarget, BackendSnapshot>,
+}
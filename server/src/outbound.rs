@@ -0,0 +1,306 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{self, ErrorKind},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::BytesMut;
+use message::{Message, MessageType};
+use spdlog::prelude::{error, warn};
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    time::{Instant, timeout, timeout_at},
+};
+use uuid::Uuid;
+
+use crate::{
+    amplification::AmplificationLimit, buffer_pool::BufferPool, transport::AsyncFrameSink,
+};
+
+/// Number of frames of each queue before the senders wait for the stream writer.
+const QUEUE_CAPACITY: usize = 64;
+
+/// Sending half of a stream, shared by the tunnels multiplexed over it.
+///
+/// Frames are written by a dedicated task. A frame queued while the stream is idle is written
+/// right away; when frames keep coming, they are coalesced into a single write until the flush
+/// window elapses or the batch is full.
+///
+/// Control messages are queued apart and written ahead of the queued `Data`, so that pings and
+/// closes aren't held up by a backlog. The frames of a tunnel keep their order though: a control
+/// message of a tunnel with `Data` queued waits behind it.
+#[derive(Debug, Clone)]
+pub struct Outbound {
+    data_tx: mpsc::Sender<Command>,
+    control_tx: mpsc::Sender<Command>,
+
+    /// Number of `Data` frames queued per tunnel, not yet written.
+    queued: Arc<Mutex<HashMap<Uuid, usize>>>,
+
+    /// Longest frame sent, header included.
+    max_frame_size: usize,
+
+    /// Set once a write took longer than the write timeout.
+    stalled: watch::Receiver<bool>,
+
+    /// Bounds what is sent until the client is validated; unbounded when `None`.
+    amplification: Option<Arc<AmplificationLimit>>,
+}
+
+#[derive(Debug)]
+enum Command {
+    Frame(Message),
+
+    /// Writes the queued frames, finishes the stream and waits up to the grace period for the
+    /// peer to acknowledge them.
+    Finish {
+        grace: Option<Duration>,
+        done: oneshot::Sender<()>,
+    },
+}
+
+impl Outbound {
+    /// Spawns the writer of the stream. A zero `flush_window` writes every frame on its own. The
+    /// buffers of the `Data` payloads are returned to `buffers` once encoded. The writer gives up
+    /// on the stream once a write takes longer than `write_timeout`, see [`Outbound::stalled`].
+    pub fn spawn(
+        send: impl AsyncFrameSink,
+        flush_window: Duration,
+        max_batch: usize,
+        max_frame_size: usize,
+        buffers: Arc<BufferPool>,
+        write_timeout: Duration,
+    ) -> Outbound {
+        let (data_tx, data_rx) = mpsc::channel(QUEUE_CAPACITY);
+        let (control_tx, control_rx) = mpsc::channel(QUEUE_CAPACITY);
+        let queued = Arc::new(Mutex::new(HashMap::new()));
+
+        let queues = Queues {
+            data_rx,
+            control_rx,
+            queued: queued.clone(),
+            buffers,
+        };
+        let (stalled_tx, stalled) = watch::channel(false);
+        tokio::spawn(write_frames(
+            send,
+            queues,
+            flush_window,
+            max_batch,
+            write_timeout,
+            stalled_tx,
+        ));
+
+        Outbound {
+            data_tx,
+            control_tx,
+            queued,
+            max_frame_size,
+            stalled,
+            amplification: None,
+        }
+    }
+
+    /// Bounds the frames sent by the [`AmplificationLimit`] until it's lifted: the `Data` frames
+    /// wait for room, the others are dropped.
+    pub fn with_amplification_limit(mut self, limit: Arc<AmplificationLimit>) -> Outbound {
+        self.amplification = Some(limit);
+        self
+    }
+
+    /// Resolves once the writer ended: `true` if it gave up on the stream because a write timed
+    /// out, i.e. the peer isn't reading it anymore.
+    pub fn stalled(&self) -> impl Future<Output = bool> + Send + 'static {
+        let mut stalled = self.stalled.clone();
+        async move { stalled.wait_for(|stalled| *stalled).await.is_ok() }
+    }
+
+    /// Queues the message; fails if it is malformed, see [`Message::validate`], longer than the
+    /// maximum frame size, or once the stream is finished or broken.
+    pub async fn send(&self, msg: Message) -> io::Result<()> {
+        msg.validate()?;
+        if msg.encoded_len() > self.max_frame_size {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "Frame exceeds the maximum size",
+            ));
+        }
+
+        if let Some(limit) = &self.amplification {
+            let len = msg.encoded_len();
+            if msg.message_type.is_data() {
+                limit.send(len).await;
+            } else if !limit.try_send(len) {
+                warn!(
+                    "[server] amplification limit reached, dropping message: connection_id={} type={}",
+                    msg.connection_id, msg.message_type
+                );
+                return Ok(());
+            }
+        }
+
+        let tx = if self.prioritized(&msg) {
+            &self.control_tx
+        } else {
+            &self.data_tx
+        };
+        tx.send(Command::Frame(msg))
+            .await
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "Stream is closed"))
+    }
+
+    /// Writes the queued frames and finishes the stream, waiting up to `grace` for the peer to
+    /// receive them.
+    pub async fn finish(&self, grace: Option<Duration>) {
+        let (done, done_rx) = oneshot::channel();
+        if self
+            .data_tx
+            .send(Command::Finish { grace, done })
+            .await
+            .is_ok()
+        {
+            let _ = done_rx.await;
+        }
+    }
+
+    /// Whether the message skips the queued `Data`; counts the `Data` frames otherwise.
+    fn prioritized(&self, msg: &Message) -> bool {
+        let mut queued = self.queued.lock().unwrap();
+
+        if msg.message_type.is_data() {
+            *queued.entry(msg.connection_id).or_default() += 1;
+            return false;
+        }
+
+        !msg.message_type.requires_connection() || !queued.contains_key(&msg.connection_id)
+    }
+}
+
+/// Receiving ends of the queues, read by the stream writer.
+struct Queues {
+    data_rx: mpsc::Receiver<Command>,
+    control_rx: mpsc::Receiver<Command>,
+    queued: Arc<Mutex<HashMap<Uuid, usize>>>,
+    buffers: Arc<BufferPool>,
+}
+
+impl Queues {
+    /// Waits for the next command, control messages first; `None` once every sender is gone.
+    async fn recv(&mut self) -> Option<Command> {
+        tokio::select! {
+            biased;
+            Some(command) = self.control_rx.recv() => Some(command),
+            Some(command) = self.data_rx.recv() => Some(command),
+            else => None,
+        }
+    }
+
+    fn try_recv(&mut self) -> Option<Command> {
+        self.control_rx
+            .try_recv()
+            .or_else(|_| self.data_rx.try_recv())
+            .ok()
+    }
+
+    /// Encodes a frame into the batch; returns the finish request otherwise, once the control
+    /// messages queued before it are encoded as well.
+    fn queue(
+        &mut self,
+        command: Command,
+        buf: &mut BytesMut,
+    ) -> Option<(Option<Duration>, oneshot::Sender<()>)> {
+        match command {
+            Command::Frame(msg) => {
+                self.encode(msg, buf);
+                None
+            }
+            Command::Finish { grace, done } => {
+                while let Ok(Command::Frame(msg)) = self.control_rx.try_recv() {
+                    self.encode(msg, buf);
+                }
+                Some((grace, done))
+            }
+        }
+    }
+
+    fn encode(&self, msg: Message, buf: &mut BytesMut) {
+        msg.encode_into(buf);
+
+        if let MessageType::Data = msg.message_type {
+            let mut queued = self.queued.lock().unwrap();
+            if let Some(count) = queued.get_mut(&msg.connection_id) {
+                *count -= 1;
+                if *count == 0 {
+                    queued.remove(&msg.connection_id);
+                }
+            }
+            drop(queued);
+
+            self.buffers.checkin(msg.payload);
+        }
+    }
+}
+
+async fn write_frames(
+    mut send: impl AsyncFrameSink,
+    mut queues: Queues,
+    flush_window: Duration,
+    max_batch: usize,
+    write_timeout: Duration,
+    stalled: watch::Sender<bool>,
+) {
+    let mut buf = BytesMut::new();
+
+    while let Some(command) = queues.recv().await {
+        // Sized for a whole batch, so that the frames don't grow the buffer one by one.
+        buf.reserve(max_batch);
+        let mut finish = queues.queue(command, &mut buf);
+
+        if finish.is_none() && !flush_window.is_zero() {
+            // Frames queued behind the first one mean the stream is busy: keep batching.
+            let mut busy = false;
+            while finish.is_none() && buf.len() < max_batch {
+                match queues.try_recv() {
+                    Some(command) => {
+                        finish = queues.queue(command, &mut buf);
+                        busy = true;
+                    }
+                    None => break,
+                }
+            }
+
+            let deadline = Instant::now() + flush_window;
+            while busy && finish.is_none() && buf.len() < max_batch {
+                match timeout_at(deadline, queues.recv()).await {
+                    Ok(Some(command)) => finish = queues.queue(command, &mut buf),
+                    Ok(None) | Err(_) => break,
+                }
+            }
+        }
+
+        if !buf.is_empty() {
+            match timeout(write_timeout, send.send_chunk(buf.split().freeze())).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    error!("[server] failed writing to client: err={e:?}");
+                    return;
+                }
+                Err(_) => {
+                    warn!("[server] write to client timed out after {write_timeout:?}");
+                    stalled.send_replace(true);
+                    return;
+                }
+            }
+        }
+
+        if let Some((grace, done)) = finish {
+            send.finish(grace).await;
+            let _ = done.send(());
+            return;
+        }
+    }
+
+    send.finish(None).await;
+}
@@ -0,0 +1,88 @@
+use std::{collections::HashMap, io::ErrorKind, sync::Mutex};
+
+use message::ProxyTarget;
+
+use crate::allowlist::TargetPolicy;
+use spdlog::prelude::debug;
+use tokio::net::TcpStream;
+
+/// Idle backend connections kept open to be reused by the next tunnel to the same target.
+///
+/// Only meaningful for backends that keep serving requests on a connection (e.g. HTTP keep-alive);
+/// pooling is disabled when either limit is 0.
+pub struct BackendPool {
+    /// Maximum number of idle connections, all targets together.
+    max_idle: usize,
+
+    /// Maximum number of idle connections per target.
+    max_per_host: usize,
+
+    idle: Mutex<HashMap<ProxyTarget, Vec<TcpStream>>>,
+}
+
+impl BackendPool {
+    pub fn new(max_idle: usize, max_per_host: usize) -> BackendPool {
+        BackendPool {
+            max_idle,
+            max_per_host,
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_idle > 0 && self.max_per_host > 0
+    }
+
+    /// Takes an idle connection to the target, skipping the ones the backend closed meanwhile
+    /// and the ones to an address the policy doesn't allow.
+    pub fn checkout(&self, target: &ProxyTarget, policy: &TargetPolicy) -> Option<TcpStream> {
+        let mut idle = self.idle.lock().unwrap();
+        let streams = idle.get_mut(target)?;
+
+        while let Some(stream) = streams.pop() {
+            if !is_allowed(&stream, policy) {
+                debug!(
+                    "[server] dropping pooled connection not allowed anymore: target={target:?}"
+                );
+                continue;
+            }
+            // An idle connection has nothing to read; EOF or unexpected bytes make it unusable.
+            match stream.try_read(&mut [0; 1]) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Some(stream),
+                _ => debug!("[server] dropping stale pooled connection: target={target:?}"),
+            }
+        }
+
+        None
+    }
+
+    /// Closes the idle connections to the addresses the policy doesn't allow, once it changed.
+    pub fn retain_allowed(&self, policy: &TargetPolicy) {
+        let mut idle = self.idle.lock().unwrap();
+        for streams in idle.values_mut() {
+            streams.retain(|stream| is_allowed(stream, policy));
+        }
+        idle.retain(|_, streams| !streams.is_empty());
+    }
+
+    /// Keeps the connection for reuse; it is closed instead when the pool is full.
+    pub fn checkin(&self, target: ProxyTarget, stream: TcpStream) {
+        let mut idle = self.idle.lock().unwrap();
+
+        let total: usize = idle.values().map(Vec::len).sum();
+        let streams = idle.entry(target).or_default();
+
+        if total < self.max_idle && streams.len() < self.max_per_host {
+            streams.push(stream);
+        }
+    }
+
+    /// Number of idle connections, all targets together.
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().values().map(Vec::len).sum()
+    }
+}
+
+fn is_allowed(stream: &TcpStream, policy: &TargetPolicy) -> bool {
+    stream.peer_addr().is_ok_and(|addr| policy.is_allowed(addr))
+}
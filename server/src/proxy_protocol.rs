@@ -0,0 +1,57 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio::{io::AsyncWriteExt, net::TcpStream};
+
+/// Signature starting every PROXY protocol v2 header.
+pub const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Version 2, `PROXY` command: the connection is relayed on behalf of another host.
+const VERSION_COMMAND: u8 = 0x21;
+
+/// TCP over IPv4, or over IPv6.
+const TCP_V4: u8 = 0x11;
+const TCP_V6: u8 = 0x21;
+
+/// Builds the PROXY protocol v2 header telling the backend the connection is relayed from
+/// `source` to `destination`. An IPv4 address is mapped to IPv6 when the other one isn't IPv4.
+pub fn header(source: SocketAddr, destination: SocketAddr) -> Bytes {
+    let mut header = BytesMut::with_capacity(SIGNATURE.len() + 4 + 36);
+    header.put_slice(&SIGNATURE);
+    header.put_u8(VERSION_COMMAND);
+
+    match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source_ip), IpAddr::V4(destination_ip)) => {
+            header.put_u8(TCP_V4);
+            header.put_u16(12);
+            header.put_slice(&source_ip.octets());
+            header.put_slice(&destination_ip.octets());
+        }
+        (source_ip, destination_ip) => {
+            header.put_u8(TCP_V6);
+            header.put_u16(36);
+            header.put_slice(&to_ipv6(source_ip).octets());
+            header.put_slice(&to_ipv6(destination_ip).octets());
+        }
+    }
+    header.put_u16(source.port());
+    header.put_u16(destination.port());
+
+    header.freeze()
+}
+
+/// Starts the backend connection with the header of a connection relayed from `source`.
+pub async fn write_header(stream: &mut TcpStream, source: SocketAddr) -> io::Result<()> {
+    let header = header(source, stream.peer_addr()?);
+    stream.write_all(&header).await
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        Mutex,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+/// Number of tracked clients above which the idle ones are forgotten.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Token-bucket limits on the connections opened and the bytes sent by each client IP address.
+///
+/// A bucket holds up to one second worth of tokens, so a client may burst up to its rate before
+/// being limited; a limit of 0 disables it.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: AtomicU64,
+    connections_per_sec: AtomicU32,
+    clients: Mutex<HashMap<IpAddr, Buckets>>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    bytes: TokenBucket,
+    connections: TokenBucket,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+
+    /// Tokens available; negative once more was taken than available, to be paid back.
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, now: Instant) -> TokenBucket {
+        TokenBucket {
+            rate,
+            tokens: rate,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.updated = now;
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.rate
+    }
+
+    /// Takes a token if one is available.
+    fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// Takes `n` tokens, even if not available; returns the time until the debt is paid back.
+    fn take(&mut self, n: f64, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= n;
+        if self.tokens >= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-self.tokens / self.rate)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64, connections_per_sec: u32) -> RateLimiter {
+        RateLimiter {
+            bytes_per_sec: AtomicU64::new(bytes_per_sec),
+            connections_per_sec: AtomicU32::new(connections_per_sec),
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Changes the limits; the clients start over with full buckets of the new rates.
+    pub fn set_limits(&self, bytes_per_sec: u64, connections_per_sec: u32) {
+        let mut clients = self.clients.lock().unwrap();
+        self.bytes_per_sec.store(bytes_per_sec, Ordering::Relaxed);
+        self.connections_per_sec
+            .store(connections_per_sec, Ordering::Relaxed);
+        clients.clear();
+    }
+
+    /// Records a new connection from the address; `false` if it exceeds the connection rate.
+    pub fn allow_connection(&self, ip: IpAddr) -> bool {
+        if self.connections_per_sec.load(Ordering::Relaxed) == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        self.with_buckets(ip, now, |buckets| buckets.connections.try_take(now))
+    }
+
+    /// Records `bytes` read from the address; returns the time to wait before reading more to
+    /// stay within the byte rate.
+    pub fn throttle(&self, ip: IpAddr, bytes: usize) -> Duration {
+        if self.bytes_per_sec.load(Ordering::Relaxed) == 0 {
+            return Duration::ZERO;
+        }
+
+        let now = Instant::now();
+        self.with_buckets(ip, now, |buckets| buckets.bytes.take(bytes as f64, now))
+    }
+
+    fn with_buckets<T>(&self, ip: IpAddr, now: Instant, f: impl FnOnce(&mut Buckets) -> T) -> T {
+        let mut clients = self.clients.lock().unwrap();
+
+        // Clients whose buckets refilled completely are limited the same as new ones.
+        if clients.len() >= PRUNE_THRESHOLD {
+            clients.retain(|_, buckets| {
+                !(buckets.bytes.is_full(now) && buckets.connections.is_full(now))
+            });
+        }
+
+        let buckets = clients.entry(ip).or_insert_with(|| Buckets {
+            bytes: TokenBucket::new(self.bytes_per_sec.load(Ordering::Relaxed) as f64, now),
+            connections: TokenBucket::new(
+                self.connections_per_sec.load(Ordering::Relaxed) as f64,
+                now,
+            ),
+        });
+        f(buckets)
+    }
+}
@@ -0,0 +1,606 @@
+use std::{
+    collections::{HashMap, VecDeque, hash_map},
+    future, mem,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use tokio::{
+    io::AsyncWriteExt,
+    net::tcp::{OwnedReadHalf, OwnedWriteHalf},
+    sync::{mpsc, oneshot, watch},
+    task::JoinHandle,
+    time::timeout,
+};
+use uuid::Uuid;
+
+use message::{Message, ProtocolVersion, ProxyTarget};
+
+use crate::{
+    dedup::RecentIds,
+    metrics::Metrics,
+    pool::BackendPool,
+    reorder::{ReorderBuffer, WindowExceeded},
+};
+
+/// Number of closed connections remembered so that their late messages are ignored.
+const RECENTLY_CLOSED_CAPACITY: usize = 1024;
+
+/// Lifecycle of a tunnel identified by its `connection_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// `Initial` is received and the backend connection is being established.
+    AwaitingInit,
+
+    /// Backend is connected; `Data` is relayed in both directions.
+    Active,
+
+    /// Close is in progress; incoming messages are ignored.
+    Closing,
+
+    /// Tunnel is torn down; incoming messages are ignored.
+    Closed,
+}
+
+/// Directions of an active tunnel still carrying data; each is closed by a `ShutdownWrite` of
+/// its sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Directions {
+    /// Client still sends data to the backend.
+    pub to_backend: bool,
+
+    /// Backend still sends data to the client.
+    pub to_client: bool,
+}
+
+impl Directions {
+    /// Whether both sides finished sending.
+    pub fn is_closed(&self) -> bool {
+        !self.to_backend && !self.to_client
+    }
+}
+
+impl Default for Directions {
+    fn default() -> Directions {
+        Directions {
+            to_backend: true,
+            to_client: true,
+        }
+    }
+}
+
+/// Payload bytes relayed through a tunnel in each direction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traffic {
+    /// Bytes the client sent, forwarded to the backend.
+    pub to_backend: u64,
+
+    /// Bytes the backend sent, relayed to the client.
+    pub to_client: u64,
+}
+
+impl ConnectionState {
+    pub fn can_transition(self, to: ConnectionState) -> bool {
+        use ConnectionState::*;
+
+        matches!(
+            (self, to),
+            (AwaitingInit, Active)
+                | (AwaitingInit, Closing)
+                | (AwaitingInit, Closed)
+                | (Active, Closing)
+                | (Active, Closed)
+                | (Closing, Closed)
+        )
+    }
+}
+
+/// Backend side of an active tunnel.
+pub struct Tunnel {
+    /// Payloads waiting to be written to the backend; bounded, so a slow backend stops the
+    /// stream from being read and QUIC flow control slows the client down. `None` once the client
+    /// shut its write side down.
+    pub data_tx: Option<mpsc::Sender<Bytes>>,
+
+    /// Relays the backend data to the client; returns its half of the backend connection when
+    /// stopped before the backend closed it.
+    pub reader_task: JoinHandle<Option<OwnedReadHalf>>,
+
+    /// Writes the payloads to the backend; returns its half once the channel is closed.
+    pub writer_task: JoinHandle<Option<OwnedWriteHalf>>,
+
+    /// Stops the reader task.
+    pub stop_reader: oneshot::Sender<()>,
+
+    /// Target the backend connection is open to.
+    pub target: ProxyTarget,
+
+    /// ID of the last `Data` message forwarded to the backend; responses are correlated to it.
+    pub last_request: watch::Sender<Option<Uuid>>,
+
+    /// IDs of the `Data` messages already forwarded, used to drop retransmissions.
+    pub seen: RecentIds,
+
+    /// Holds sequenced `Data` payloads that arrived ahead of a gap.
+    pub reorder: ReorderBuffer,
+}
+
+impl Tunnel {
+    /// Stops reading from the backend. The writer task flushes the queued payloads
+    /// and the backend connection is shut down once the channel is dropped.
+    pub fn close(self) {
+        self.reader_task.abort();
+        tokio::spawn(shutdown(self.writer_task));
+    }
+
+    /// Shuts the backend write half down once the queued payloads are written; the backend data
+    /// keeps being relayed.
+    pub fn shutdown_write(&mut self) {
+        self.data_tx = None;
+
+        let writer_task = mem::replace(&mut self.writer_task, tokio::spawn(future::ready(None)));
+        self.writer_task = tokio::spawn(async move {
+            shutdown(writer_task).await;
+            None
+        });
+    }
+
+    /// Shuts the backend connection down once the queued payloads are written, and keeps relaying
+    /// the backend data to the client until the backend closes or the deadline elapses.
+    pub async fn drain(self, deadline: Duration) {
+        // Dropping `stop_reader` would stop the reader, so it's held until the backend is drained.
+        let Tunnel {
+            data_tx,
+            mut reader_task,
+            writer_task,
+            stop_reader,
+            ..
+        } = self;
+        drop(data_tx);
+
+        let drained = timeout(deadline, async {
+            shutdown(writer_task).await;
+            let _ = (&mut reader_task).await;
+        });
+
+        if drained.await.is_err() {
+            reader_task.abort();
+        }
+        drop(stop_reader);
+    }
+
+    /// Hands the backend connection over to the pool once the queued payloads are written;
+    /// it is shut down instead if the backend closed its side meanwhile.
+    pub async fn release(self, pool: &BackendPool) {
+        let Tunnel {
+            data_tx,
+            reader_task,
+            writer_task,
+            stop_reader,
+            target,
+            ..
+        } = self;
+        drop(data_tx);
+        let _ = stop_reader.send(());
+
+        match (reader_task.await, writer_task.await) {
+            (Ok(Some(reader)), Ok(Some(writer))) => {
+                if let Ok(stream) = reader.reunite(writer) {
+                    pool.checkin(target, stream);
+                }
+            }
+            (_, Ok(Some(mut writer))) => {
+                let _ = writer.shutdown().await;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Shuts the backend connection down once the writer task wrote the queued payloads.
+async fn shutdown(writer_task: JoinHandle<Option<OwnedWriteHalf>>) {
+    if let Ok(Some(mut writer)) = writer_task.await {
+        let _ = writer.shutdown().await;
+    }
+}
+
+/// Closes a tunnel when dropped, closing its backend connection, unless it is closed already; held
+/// by the task handling the tunnel so that it is cleaned up however the task ends, early returns
+/// and panics included.
+pub struct ConnectionGuard {
+    registry: Arc<Registry>,
+    connection_id: Uuid,
+}
+
+impl ConnectionGuard {
+    pub fn new(registry: Arc<Registry>, connection_id: Uuid) -> ConnectionGuard {
+        ConnectionGuard {
+            registry,
+            connection_id,
+        }
+    }
+
+    /// Whether the tunnel is closed, or unknown, so that the guard has nothing left to clean up.
+    pub fn is_released(&self) -> bool {
+        self.registry
+            .state(&self.connection_id)
+            .is_none_or(|state| state == ConnectionState::Closed)
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let _ = self
+            .registry
+            .transition(&self.connection_id, ConnectionState::Closed);
+    }
+}
+
+/// Outcome of routing a `Data` message to its tunnel.
+pub enum DataRoute {
+    /// Payloads have to be forwarded, in order, to the backend through the sender.
+    /// Empty when the message is held until the preceding ones arrive.
+    Forward(mpsc::Sender<Bytes>, Vec<Bytes>),
+
+    /// Message was already forwarded; it should be acknowledged, but not forwarded again.
+    Duplicate,
+
+    /// Too many messages are held waiting for a missing one.
+    WindowExceeded,
+
+    /// Tunnel has no backend, or the client shut its write side down.
+    Gone,
+}
+
+/// What a `Close` of the client leaves to tear down; see [`Registry::close_by_client`].
+pub enum CloseRequest {
+    /// Tunnel was open and is closing now; its backend is handed over, if it had one yet.
+    Teardown(Option<Box<Tunnel>>),
+
+    /// Server is closing the tunnel too; the `Close` of the client crossed its own.
+    Crossed,
+
+    /// Tunnel is closed already, or unknown (`None`): there's nothing left to tear down.
+    Closed(Option<ConnectionState>),
+}
+
+struct Entry {
+    state: ConnectionState,
+    tunnel: Option<Tunnel>,
+
+    /// Version the messages of the tunnel are encoded with.
+    version: ProtocolVersion,
+
+    /// Directions still open.
+    directions: Directions,
+
+    /// Bytes relayed so far.
+    traffic: Traffic,
+
+    /// Last time data was relayed through the tunnel, in either direction.
+    last_activity: Instant,
+
+    /// Time the tunnel was registered at.
+    opened_at: Instant,
+
+    /// Stream the tunnel was opened over, the only one its messages are accepted from.
+    stream: Uuid,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<Uuid, Entry>,
+    recently_closed: VecDeque<Uuid>,
+}
+
+/// Tunnels of all the connected clients, keyed by `connection_id`.
+#[derive(Default)]
+pub struct Registry {
+    inner: Mutex<Inner>,
+    metrics: Metrics,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// Measurements of the traffic of all the tunnels.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Current state of the tunnel; `None` if the `connection_id` is unknown.
+    pub fn state(&self, connection_id: &Uuid) -> Option<ConnectionState> {
+        let inner = self.inner.lock().unwrap();
+        inner.entries.get(connection_id).map(|entry| entry.state)
+    }
+
+    /// Number of tunnels that are not closed yet.
+    pub fn open_tunnels(&self) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .entries
+            .values()
+            .filter(|entry| entry.state != ConnectionState::Closed)
+            .count()
+    }
+
+    /// Version negotiated for the tunnel; [`ProtocolVersion::V1`] if the `connection_id` is
+    /// unknown.
+    pub fn version(&self, connection_id: &Uuid) -> ProtocolVersion {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .entries
+            .get(connection_id)
+            .map_or(ProtocolVersion::V1, |entry| entry.version)
+    }
+
+    /// Current state of the tunnel, if it was opened over `stream`; fails with its state if it
+    /// belongs to another stream.
+    pub fn owned_state(
+        &self,
+        connection_id: &Uuid,
+        stream: &Uuid,
+    ) -> Result<Option<ConnectionState>, ConnectionState> {
+        let inner = self.inner.lock().unwrap();
+        match inner.entries.get(connection_id) {
+            Some(entry) if entry.stream != *stream => Err(entry.state),
+            entry => Ok(entry.map(|entry| entry.state)),
+        }
+    }
+
+    /// Registers a new tunnel opened over `stream` in the [`ConnectionState::AwaitingInit`] state,
+    /// using the negotiated `version`. Fails with the current state if the `connection_id` is
+    /// already known.
+    pub fn insert(
+        &self,
+        connection_id: Uuid,
+        version: ProtocolVersion,
+        stream: Uuid,
+    ) -> Result<(), ConnectionState> {
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(entry) = inner.entries.get(&connection_id) {
+            return Err(entry.state);
+        }
+
+        inner.entries.insert(
+            connection_id,
+            Entry {
+                state: ConnectionState::AwaitingInit,
+                tunnel: None,
+                version,
+                directions: Directions::default(),
+                traffic: Traffic::default(),
+                last_activity: Instant::now(),
+                opened_at: Instant::now(),
+                stream,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Moves the tunnel to the given state; fails with the current state on an invalid transition.
+    /// Reaching [`ConnectionState::Closing`] or [`ConnectionState::Closed`] closes the backend.
+    pub fn transition(
+        &self,
+        connection_id: &Uuid,
+        to: ConnectionState,
+    ) -> Result<(), Option<ConnectionState>> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let entry = inner.entries.get_mut(connection_id).ok_or(None)?;
+        if !entry.state.can_transition(to) {
+            return Err(Some(entry.state));
+        }
+
+        entry.state = to;
+
+        match to {
+            ConnectionState::Closing => {
+                if let Some(tunnel) = entry.tunnel.take() {
+                    tunnel.close();
+                }
+            }
+            ConnectionState::Closed => {
+                if let Some(tunnel) = entry.tunnel.take() {
+                    tunnel.close();
+                }
+                entry.traffic = Traffic::default();
+
+                inner.recently_closed.push_back(*connection_id);
+                if inner.recently_closed.len() > RECENTLY_CLOSED_CAPACITY {
+                    let evicted = inner.recently_closed.pop_front().unwrap();
+                    inner.entries.remove(&evicted);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Moves the tunnel to [`ConnectionState::Closing`] like [`Registry::transition`], but hands
+    /// the backend over instead of closing it, so that it can be drained first.
+    pub fn drain(&self, connection_id: &Uuid) -> Result<Option<Tunnel>, Option<ConnectionState>> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let entry = inner.entries.get_mut(connection_id).ok_or(None)?;
+        if !entry.state.can_transition(ConnectionState::Closing) {
+            return Err(Some(entry.state));
+        }
+
+        entry.state = ConnectionState::Closing;
+
+        Ok(entry.tunnel.take())
+    }
+
+    /// Moves the tunnel closed by the client to [`ConnectionState::Closing`], handing its backend
+    /// over like [`Registry::drain`]. The state is checked and changed at once, so that a repeated
+    /// `Close`, or one racing the close of the server, doesn't tear the tunnel down twice.
+    pub fn close_by_client(&self, connection_id: &Uuid) -> CloseRequest {
+        let mut inner = self.inner.lock().unwrap();
+
+        let hash_map::Entry::Occupied(mut entry) = inner.entries.entry(*connection_id) else {
+            return CloseRequest::Closed(None);
+        };
+        let entry = entry.get_mut();
+
+        match entry.state {
+            ConnectionState::AwaitingInit | ConnectionState::Active => {
+                entry.state = ConnectionState::Closing;
+                CloseRequest::Teardown(entry.tunnel.take().map(Box::new))
+            }
+            ConnectionState::Closing => CloseRequest::Crossed,
+            ConnectionState::Closed => CloseRequest::Closed(Some(ConnectionState::Closed)),
+        }
+    }
+
+    /// Attaches the backend and moves the tunnel to [`ConnectionState::Active`].
+    pub fn activate(
+        &self,
+        connection_id: &Uuid,
+        tunnel: Tunnel,
+    ) -> Result<(), Option<ConnectionState>> {
+        self.transition(connection_id, ConnectionState::Active)?;
+
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(entry) = inner.entries.get_mut(connection_id) {
+            entry.tunnel = Some(tunnel);
+        }
+
+        Ok(())
+    }
+
+    /// Records that `bytes` of backend data were relayed to the client.
+    pub fn record_to_client(&self, connection_id: &Uuid, bytes: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(entry) = inner.entries.get_mut(connection_id) {
+            entry.last_activity = Instant::now();
+            entry.traffic.to_client += bytes as u64;
+        }
+    }
+
+    /// Bytes relayed through the tunnel so far; `None` if the `connection_id` is unknown or the
+    /// tunnel is closed.
+    pub fn traffic(&self, connection_id: &Uuid) -> Option<Traffic> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .entries
+            .get(connection_id)
+            .filter(|entry| entry.state != ConnectionState::Closed)
+            .map(|entry| entry.traffic)
+    }
+
+    /// Bytes relayed so far by each tunnel that is not closed yet.
+    pub fn traffic_snapshot(&self) -> HashMap<Uuid, Traffic> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.state != ConnectionState::Closed)
+            .map(|(connection_id, entry)| (*connection_id, entry.traffic))
+            .collect()
+    }
+
+    /// Whether the tunnel is active but relayed no data for longer than `threshold`.
+    pub fn is_idle(&self, connection_id: &Uuid, threshold: Duration) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.entries.get(connection_id).is_some_and(|entry| {
+            entry.state == ConnectionState::Active && entry.last_activity.elapsed() > threshold
+        })
+    }
+
+    /// Whether the tunnel is active and was opened longer than `max_lifetime` ago.
+    pub fn is_expired(&self, connection_id: &Uuid, max_lifetime: Duration) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.entries.get(connection_id).is_some_and(|entry| {
+            entry.state == ConnectionState::Active && entry.opened_at.elapsed() > max_lifetime
+        })
+    }
+
+    /// Routes a `Data` message of an active tunnel: returns the backend payload sender along with the
+    /// payloads ready to be forwarded, and records the request ID the backend responses are
+    /// correlated to. Messages without a sequence number are forwarded as they arrive, and the
+    /// payloads returned are counted as relayed to the backend.
+    pub fn route_data(&self, msg: Message) -> DataRoute {
+        let mut inner = self.inner.lock().unwrap();
+
+        let Some(entry) = inner.entries.get_mut(&msg.connection_id) else {
+            return DataRoute::Gone;
+        };
+        entry.last_activity = Instant::now();
+
+        let Some(tunnel) = entry.tunnel.as_mut() else {
+            return DataRoute::Gone;
+        };
+
+        let Some(data_tx) = tunnel.data_tx.clone() else {
+            return DataRoute::Gone;
+        };
+
+        if !tunnel.seen.insert(msg.message_id) {
+            return DataRoute::Duplicate;
+        }
+
+        let payloads = match msg.sequence {
+            Some(sequence) => match tunnel.reorder.push(sequence, msg.payload) {
+                Ok(payloads) => payloads,
+                Err(WindowExceeded) => return DataRoute::WindowExceeded,
+            },
+            None => vec![msg.payload],
+        };
+
+        tunnel.last_request.send_replace(Some(msg.message_id));
+        entry.traffic.to_backend += payloads
+            .iter()
+            .map(|payload| payload.len() as u64)
+            .sum::<u64>();
+
+        DataRoute::Forward(data_tx, payloads)
+    }
+
+    /// Directions of the tunnel still open; `None` if the `connection_id` is unknown.
+    pub fn directions(&self, connection_id: &Uuid) -> Option<Directions> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .entries
+            .get(connection_id)
+            .map(|entry| entry.directions)
+    }
+
+    /// Records that the client finished sending and shuts the backend write half down, keeping
+    /// the backend data flowing; returns the directions left open, `None` if the tunnel isn't
+    /// active.
+    pub fn shutdown_write(&self, connection_id: &Uuid) -> Option<Directions> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let entry = inner
+            .entries
+            .get_mut(connection_id)
+            .filter(|entry| entry.state == ConnectionState::Active)?;
+        if let Some(tunnel) = entry.tunnel.as_mut() {
+            tunnel.shutdown_write();
+        }
+        entry.directions.to_backend = false;
+
+        Some(entry.directions)
+    }
+
+    /// Records that the backend finished sending; returns the directions left open, `None` if
+    /// the tunnel isn't active.
+    pub fn backend_finished(&self, connection_id: &Uuid) -> Option<Directions> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let entry = inner
+            .entries
+            .get_mut(connection_id)
+            .filter(|entry| entry.state == ConnectionState::Active)?;
+        entry.directions.to_client = false;
+
+        Some(entry.directions)
+    }
+}
@@ -0,0 +1,212 @@
+// Reloading of the config while the server runs.
+//
+// Only the settings that don't need the endpoint to be rebuilt are applied: the log level, the
+// target policy, the rate limits and the timeouts. The log level and the rate limits apply at
+// once, as does the target policy to the pooled backend connections; the connections accepted
+// from then on use the other settings, while the ones already open keep the config they were
+// accepted with.
+use std::{fmt, io, sync::Arc};
+
+use spdlog::prelude::{info, warn};
+use tokio::sync::watch;
+
+use crate::{config::Config, logging, pool::BackendPool, rate_limit::RateLimiter};
+
+/// Config of a running server, see [`crate::serve`]; clones share it.
+#[derive(Clone)]
+pub struct Reloader {
+    config: Arc<watch::Sender<Arc<Config>>>,
+    rate_limiter: Arc<RateLimiter>,
+    pool: Arc<BackendPool>,
+}
+
+impl Reloader {
+    pub fn new(config: Arc<Config>) -> Reloader {
+        let rate_limiter = Arc::new(RateLimiter::new(
+            config.rate_limit_bytes,
+            config.rate_limit_connections,
+        ));
+
+        let pool = Arc::new(BackendPool::new(
+            config.backend_pool_max_idle,
+            config.backend_pool_max_per_host,
+        ));
+
+        Reloader {
+            config: Arc::new(watch::Sender::new(config)),
+            rate_limiter,
+            pool,
+        }
+    }
+
+    /// Config the connections accepted now are served with.
+    pub fn config(&self) -> Arc<Config> {
+        self.config.borrow().clone()
+    }
+
+    /// Rate limiter of all the connections, following the reloaded limits.
+    pub fn rate_limiter(&self) -> Arc<RateLimiter> {
+        self.rate_limiter.clone()
+    }
+
+    /// Pool of the idle backend connections, rid of the ones the reloaded target policy doesn't
+    /// allow.
+    pub fn pool(&self) -> Arc<BackendPool> {
+        self.pool.clone()
+    }
+
+    /// Reads the config again, see [`Config::from_env`], and applies it like [`Reloader::apply`];
+    /// the current config is kept if the new one is invalid.
+    pub fn reload(&self) -> io::Result<Vec<&'static str>> {
+        let reloaded = Config::from_env()?;
+        reloaded.validate()?;
+        Ok(self.apply(&reloaded))
+    }
+
+    /// Applies the settings of `reloaded` that can change at runtime and logs them; returns the
+    /// names of the ones that changed.
+    pub fn apply(&self, reloaded: &Config) -> Vec<&'static str> {
+        let current = self.config();
+        let mut config = (*current).clone();
+        let mut changed = Vec::new();
+
+        if reloaded.host != current.host {
+            warn!(
+                "[server] bind address changed to {}, it only applies after a restart",
+                reloaded.host
+            );
+        }
+
+        update(
+            &mut changed,
+            "log_level",
+            &mut config.log_level,
+            reloaded.log_level,
+        );
+        update(
+            &mut changed,
+            "target_policy",
+            &mut config.target_policy,
+            reloaded.target_policy.clone(),
+        );
+        update(
+            &mut changed,
+            "rate_limit_bytes",
+            &mut config.rate_limit_bytes,
+            reloaded.rate_limit_bytes,
+        );
+        update(
+            &mut changed,
+            "rate_limit_connections",
+            &mut config.rate_limit_connections,
+            reloaded.rate_limit_connections,
+        );
+        let timeouts = [
+            (
+                "resolve_timeout",
+                &mut config.resolve_timeout,
+                reloaded.resolve_timeout,
+            ),
+            (
+                "connect_timeout",
+                &mut config.connect_timeout,
+                reloaded.connect_timeout,
+            ),
+            (
+                "handshake_timeout",
+                &mut config.handshake_timeout,
+                reloaded.handshake_timeout,
+            ),
+            (
+                "idle_timeout",
+                &mut config.idle_timeout,
+                reloaded.idle_timeout,
+            ),
+            (
+                "drain_timeout",
+                &mut config.drain_timeout,
+                reloaded.drain_timeout,
+            ),
+            (
+                "close_timeout",
+                &mut config.close_timeout,
+                reloaded.close_timeout,
+            ),
+            (
+                "backend_write_timeout",
+                &mut config.backend_write_timeout,
+                reloaded.backend_write_timeout,
+            ),
+            (
+                "slow_backend_write",
+                &mut config.slow_backend_write,
+                reloaded.slow_backend_write,
+            ),
+            (
+                "client_write_timeout",
+                &mut config.client_write_timeout,
+                reloaded.client_write_timeout,
+            ),
+        ];
+        for (name, current, reloaded) in timeouts {
+            update(&mut changed, name, current, reloaded);
+        }
+
+        if changed.contains(&"log_level") {
+            logging::set_level(config.log_level);
+        }
+        if changed.iter().any(|name| name.starts_with("rate_limit_")) {
+            self.rate_limiter
+                .set_limits(config.rate_limit_bytes, config.rate_limit_connections);
+        }
+        if changed.contains(&"target_policy") {
+            self.pool.retain_allowed(&config.target_policy);
+        }
+
+        self.config.send_replace(Arc::new(config));
+        changed
+    }
+}
+
+impl From<Arc<Config>> for Reloader {
+    fn from(config: Arc<Config>) -> Reloader {
+        Reloader::new(config)
+    }
+}
+
+fn update<T: PartialEq + fmt::Debug>(
+    changed: &mut Vec<&'static str>,
+    name: &'static str,
+    current: &mut T,
+    reloaded: T,
+) {
+    if *current != reloaded {
+        info!("[server] reloaded {name}: {current:?} -> {reloaded:?}");
+        *current = reloaded;
+        changed.push(name);
+    }
+}
+
+/// Reloads the config on every `SIGHUP`, keeping the current one when the new one is invalid.
+#[cfg(unix)]
+pub async fn reload_on_sighup(reloader: Reloader) {
+    use spdlog::prelude::error;
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("[server] failed to listen for SIGHUP, the config can't be reloaded: {e}");
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        info!("[server] SIGHUP received, reloading the config");
+        match reloader.reload() {
+            Ok(changed) if changed.is_empty() => info!("[server] config reloaded, nothing changed"),
+            Ok(_) => {}
+            Err(e) => warn!("[server] invalid config, keeping the current one: {e}"),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+
+/// Returned when holding a message would exceed the reorder window, in messages or in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowExceeded;
+
+/// Restores the order of the `Data` payloads of a tunnel by their sequence number.
+/// Payloads arriving ahead of a gap are held until the gap is filled, up to `window` payloads
+/// and `max_bytes` bytes.
+#[derive(Debug)]
+pub struct ReorderBuffer {
+    next: u64,
+    pending: BTreeMap<u64, Bytes>,
+    pending_bytes: usize,
+    window: usize,
+    max_bytes: usize,
+}
+
+impl ReorderBuffer {
+    pub fn new(window: usize, max_bytes: usize) -> ReorderBuffer {
+        ReorderBuffer {
+            next: 0,
+            pending: BTreeMap::new(),
+            pending_bytes: 0,
+            window,
+            max_bytes,
+        }
+    }
+
+    /// Adds the payload and returns the contiguous run of payloads ready to be delivered.
+    /// Payloads with an already delivered sequence number are dropped.
+    pub fn push(&mut self, sequence: u64, payload: Bytes) -> Result<Vec<Bytes>, WindowExceeded> {
+        if sequence < self.next {
+            return Ok(Vec::new());
+        }
+
+        if sequence > self.next {
+            if self.pending.len() >= self.window
+                || self.pending_bytes + payload.len() > self.max_bytes
+            {
+                return Err(WindowExceeded);
+            }
+
+            self.pending_bytes += payload.len();
+            if let Some(replaced) = self.pending.insert(sequence, payload) {
+                self.pending_bytes -= replaced.len();
+            }
+            return Ok(Vec::new());
+        }
+
+        let mut ready = vec![payload];
+        self.next += 1;
+
+        while let Some(payload) = self.pending.remove(&self.next) {
+            self.pending_bytes -= payload.len();
+            ready.push(payload);
+            self.next += 1;
+        }
+
+        Ok(ready)
+    }
+}
@@ -1,28 +1,311 @@
-use quinn::{Endpoint, ServerConfig, VarInt};
-use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use quinn::{
+    Endpoint, EndpointConfig, ServerConfig, TokioRuntime, TransportConfig, VarInt,
+    congestion::{BbrConfig, CubicConfig, NewRenoConfig},
+    crypto::rustls::QuicServerConfig,
+};
+use rustls::{
+    RootCertStore,
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+    server::WebPkiClientVerifier,
+    sign::CertifiedKey,
+    version::TLS13,
+};
 
-use std::{error::Error, net::SocketAddr, sync::Arc};
+use std::{
+    error::Error,
+    fs,
+    io::{self, ErrorKind, Write},
+    net::SocketAddr,
+    path::Path,
+    sync::Arc,
+};
 
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use spdlog::prelude::info;
+
+use crate::{
+    config::{Config, CongestionController},
+    dscp,
+};
+
+/// Self-signed certificate of the server, issued for `localhost`, along with its private key.
+pub struct Identity {
+    pub cert: CertificateDer<'static>,
+    pub key: PrivatePkcs8KeyDer<'static>,
+}
+
+impl Identity {
+    /// Generates a new identity.
+    pub fn generate() -> Identity {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        Identity {
+            cert: CertificateDer::from(cert.cert),
+            key: PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()),
+        }
+    }
+
+    /// Loads the identity saved at the given paths; fails if either file is invalid or the key
+    /// isn't the one of the certificate.
+    pub fn load(cert_path: &Path, key_path: &Path) -> io::Result<Identity> {
+        let cert = load_certs(cert_path)?.remove(0);
+        let key = load_key(key_path)?;
+
+        let provider = ring::default_provider();
+        CertifiedKey::from_der(vec![cert.clone()], key.clone_key().into(), &provider).map_err(
+            |e| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Key {} doesn't match the certificate {}: {e}",
+                        key_path.display(),
+                        cert_path.display()
+                    ),
+                )
+            },
+        )?;
+
+        Ok(Identity { cert, key })
+    }
+
+    /// Loads the identity saved at the given paths; generates and saves a new one when either
+    /// file is missing, so that the certificate stays the same across restarts.
+    pub fn load_or_generate(cert_path: &Path, key_path: &Path) -> io::Result<Identity> {
+        if cert_path.exists() && key_path.exists() {
+            return Identity::load(cert_path, key_path);
+        }
+
+        let identity = Identity::generate();
+        save_cert(cert_path, &identity.cert)?;
+        save_key(key_path, &identity.key)?;
+        Ok(identity)
+    }
+}
+
+/// Creates the server endpoint bound to `config.host`; when `config.client_ca` is set, only the
+/// clients presenting a certificate signed by one of its CA certificates complete the handshake.
 pub fn make_server_endpoint(
-    bind_addr: SocketAddr,
+    config: &Config,
 ) -> Result<(Endpoint, CertificateDer<'static>), Box<dyn Error + Send + Sync + 'static>> {
-    let (server_config, server_cert) = configure_server()?;
-    let endpoint = Endpoint::server(server_config, bind_addr)?;
-    Ok((endpoint, server_cert))
+    let identity = load_identity(config)?;
+    let endpoint = make_endpoint(config, &identity)?;
+    Ok((endpoint, identity.cert))
 }
 
-fn configure_server()
--> Result<(ServerConfig, CertificateDer<'static>), Box<dyn Error + Send + Sync + 'static>> {
-    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
-    let cert_der = CertificateDer::from(cert.cert);
-    let priv_key = PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+/// Identity of the server: the one saved at `config.key_path` if set, a new one otherwise.
+pub fn load_identity(config: &Config) -> io::Result<Identity> {
+    match &config.key_path {
+        Some(key_path) => Identity::load_or_generate(&config.cert_path, key_path),
+        None => Ok(Identity::generate()),
+    }
+}
 
-    let mut server_config =
-        ServerConfig::with_single_cert(vec![cert_der.clone()], priv_key.into())?;
-    let transport_config = Arc::get_mut(&mut server_config.transport).unwrap();
+/// Creates the server endpoint bound to `config.host`, presenting the identity.
+pub fn make_endpoint(
+    config: &Config,
+    identity: &Identity,
+) -> Result<Endpoint, Box<dyn Error + Send + Sync + 'static>> {
+    let client_ca = config.client_ca.as_deref().map(load_certs).transpose()?;
+    let server_config = configure_server(
+        client_ca.as_deref(),
+        identity,
+        &config.alpn,
+        transport_config(config),
+        config.zero_rtt,
+    )?;
+    let socket = bind_socket(
+        config.host,
+        config.ipv6_only,
+        config.dscp,
+        config.socket_send_buffer,
+        config.socket_recv_buffer,
+    )?;
+    let endpoint = Endpoint::new(
+        EndpointConfig::default(),
+        Some(server_config),
+        socket,
+        Arc::new(TokioRuntime),
+    )?;
+    Ok(endpoint)
+}
+
+/// Binds the UDP socket of the endpoint; IPv6 sockets are dual-stack unless `ipv6_only` is set,
+/// regardless of the platform default. Its packets are marked with the `dscp` codepoint, if any,
+/// and its send and receive buffers get the sizes given, if any; the OS may clamp them.
+pub fn bind_socket(
+    addr: SocketAddr,
+    ipv6_only: bool,
+    dscp: Option<u8>,
+    send_buffer: Option<usize>,
+    recv_buffer: Option<usize>,
+) -> io::Result<std::net::UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(ipv6_only)?;
+    }
+    if let Some(dscp) = dscp {
+        dscp::mark(SockRef::from(&socket), addr.is_ipv6(), dscp)?;
+    }
+    if let Some(size) = send_buffer {
+        socket.set_send_buffer_size(size)?;
+        info!(
+            "[server] UDP send buffer: requested={size} effective={}",
+            socket.send_buffer_size()?
+        );
+    }
+    if let Some(size) = recv_buffer {
+        socket.set_recv_buffer_size(size)?;
+        info!(
+            "[server] UDP receive buffer: requested={size} effective={}",
+            socket.recv_buffer_size()?
+        );
+    }
+    socket.bind(&addr.into())?;
+
+    Ok(socket.into())
+}
+
+/// Reads every certificate of a PEM file.
+pub fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs: Vec<_> = pem::parse_many(fs::read(path)?)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+        .into_iter()
+        .filter(|pem| pem.tag() == "CERTIFICATE")
+        .map(|pem| CertificateDer::from(pem.into_contents()))
+        .collect();
+
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("No certificate found in {}", path.display()),
+        ));
+    }
+
+    Ok(certs)
+}
+
+/// Writes the certificate to a PEM file, creating the missing parent directories.
+pub fn save_cert(path: &Path, cert: &CertificateDer<'_>) -> io::Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    let pem = pem::Pem::new("CERTIFICATE", cert.to_vec());
+    fs::write(path, pem::encode(&pem))
+}
+
+/// Reads the PKCS #8 private key of a PEM file.
+pub fn load_key(path: &Path) -> io::Result<PrivatePkcs8KeyDer<'static>> {
+    pem::parse_many(fs::read(path)?)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+        .into_iter()
+        .find(|pem| pem.tag() == "PRIVATE KEY")
+        .map(|pem| PrivatePkcs8KeyDer::from(pem.into_contents()))
+        .ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("No private key found in {}", path.display()),
+            )
+        })
+}
+
+/// Writes the private key to a PEM file only readable by its owner, creating the missing parent
+/// directories.
+pub fn save_key(path: &Path, key: &PrivatePkcs8KeyDer<'_>) -> io::Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let pem = pem::Pem::new("PRIVATE KEY", key.secret_pkcs8_der().to_vec());
+    options.open(path)?.write_all(pem::encode(&pem).as_bytes())
+}
+
+/// Transport parameters of the client connections.
+pub fn transport_config(config: &Config) -> TransportConfig {
+    let mut transport_config = TransportConfig::default();
     transport_config.max_concurrent_uni_streams(0_u8.into());
+    transport_config.max_concurrent_bidi_streams(config.max_concurrent_bidi_streams.into());
+    if !config.datagrams {
+        transport_config.datagram_receive_buffer_size(None);
+    }
     // transport_config.keep_alive_interval(Duration::from_secs(30).into());
     transport_config.max_idle_timeout(Some(VarInt::from_u32(60_000).into()));
 
-    Ok((server_config, cert_der))
+    match config.congestion_controller {
+        CongestionController::Cubic => {
+            transport_config.congestion_controller_factory(Arc::new(CubicConfig::default()))
+        }
+        CongestionController::NewReno => {
+            transport_config.congestion_controller_factory(Arc::new(NewRenoConfig::default()))
+        }
+        CongestionController::Bbr => {
+            transport_config.congestion_controller_factory(Arc::new(BbrConfig::default()))
+        }
+    };
+
+    transport_config
+}
+
+/// Builds the QUIC server config presenting the identity and accepting the `alpn` protocol only.
+/// The clients resuming a session may send 0-RTT data only with `zero_rtt`.
+pub fn configure_server(
+    client_ca: Option<&[CertificateDer<'static>]>,
+    identity: &Identity,
+    alpn: &[u8],
+    transport_config: TransportConfig,
+    zero_rtt: bool,
+) -> Result<ServerConfig, Box<dyn Error + Send + Sync + 'static>> {
+    let mut tls_config = tls_server_config(client_ca, identity, alpn)?;
+    tls_config.max_early_data_size = if zero_rtt { u32::MAX } else { 0 };
+
+    let mut server_config =
+        ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls_config)?));
+    server_config.transport_config(Arc::new(transport_config));
+
+    Ok(server_config)
+}
+
+/// Builds the TLS 1.3 server config presenting the identity; when `client_ca` is set, only the
+/// clients presenting a certificate signed by one of these CA certificates are accepted. The
+/// handshake fails when the client offers ALPN protocols but not `alpn`.
+pub fn tls_server_config(
+    client_ca: Option<&[CertificateDer<'static>]>,
+    identity: &Identity,
+    alpn: &[u8],
+) -> Result<rustls::ServerConfig, Box<dyn Error + Send + Sync + 'static>> {
+    let provider = Arc::new(ring::default_provider());
+    let tls_config = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&TLS13])?;
+
+    let tls_config = match client_ca {
+        Some(ca_certs) => {
+            let mut roots = RootCertStore::empty();
+            for cert in ca_certs {
+                roots.add(cert.clone())?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+            tls_config.with_client_cert_verifier(verifier)
+        }
+        None => tls_config.with_no_client_auth(),
+    };
+
+    let mut tls_config = tls_config.with_single_cert(
+        vec![identity.cert.clone()],
+        PrivateKeyDer::from(identity.key.clone_key()),
+    )?;
+    tls_config.alpn_protocols = vec![alpn.to_vec()];
+
+    Ok(tls_config)
 }
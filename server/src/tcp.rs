@@ -0,0 +1,229 @@
+// TCP+TLS transport, for the clients whose network blocks UDP.
+//
+// A TCP connection carries a single stream of the same frames as the QUIC stream; frames are
+// delimited by the length of their header.
+use std::{
+    error::Error,
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use message::{CloseReason, Message};
+use spdlog::prelude::{info, warn};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    time::timeout,
+};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+use uuid::Uuid;
+
+use crate::{
+    close_code::CloseCode,
+    config::Config,
+    connection::{self, Shared},
+    connection_limit::ConnectionLimit,
+    log_context::LogContext,
+    reload::Reloader,
+    server::{Identity, load_certs, tls_server_config},
+    transport::{AsyncFrameSink, AsyncFrameSource, PeerConnection},
+};
+
+/// TCP listener accepting the clients that can't reach the QUIC endpoint.
+pub struct TcpFallback {
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+}
+
+impl TcpFallback {
+    /// Binds the TCP port of `config.host`, presenting the identity like the QUIC endpoint does.
+    pub async fn bind(
+        config: &Config,
+        identity: &Identity,
+    ) -> Result<TcpFallback, Box<dyn Error + Send + Sync + 'static>> {
+        let client_ca = config.client_ca.as_deref().map(load_certs).transpose()?;
+        let tls_config = tls_server_config(client_ca.as_deref(), identity, &config.alpn)?;
+        let listener = TcpListener::bind(config.host).await?;
+
+        Ok(TcpFallback {
+            listener,
+            acceptor: TlsAcceptor::from(Arc::new(tls_config)),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+/// Accepts the TCP connections and serves their stream.
+pub(crate) async fn serve(
+    fallback: TcpFallback,
+    shared: Shared,
+    limit: Arc<ConnectionLimit>,
+    reloader: Reloader,
+) {
+    loop {
+        let (socket, addr) = match fallback.listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("[server] failed to accept a TCP connection: {e}");
+                continue;
+            }
+        };
+        // Served with the config current when it is accepted.
+        let shared = Shared {
+            config: reloader.config(),
+            ..shared.clone()
+        };
+
+        if !shared.rate_limiter.allow_connection(addr.ip()) {
+            warn!("[server] connection rate exceeded, refusing: addr={addr}");
+            continue;
+        }
+
+        let Some(admission) = limit.admit() else {
+            warn!("[server] too many connections, refusing: addr={addr}");
+            let acceptor = fallback.acceptor.clone();
+            let handshake_timeout = shared.config.handshake_timeout;
+            tokio::spawn(close_full(acceptor, socket, handshake_timeout));
+            continue;
+        };
+        if admission.is_queued() {
+            info!("[server] too many connections, queuing: addr={addr}");
+        }
+
+        let acceptor = fallback.acceptor.clone();
+        let context = LogContext::new(addr);
+
+        tokio::spawn(context.scope(async move {
+            // Held until the connection is closed.
+            let _slot = admission.slot().await;
+
+            let handshake_timeout = shared.config.handshake_timeout;
+            let stream = match timeout(handshake_timeout, acceptor.accept(socket)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    warn!("[server] handshake failed: {e}");
+                    return;
+                }
+                Err(_) => {
+                    warn!("[server] no TLS handshake within {handshake_timeout:?}: addr={addr}");
+                    return;
+                }
+            };
+
+            // Unlike QUIC, TLS over TCP doesn't require the client to offer a protocol.
+            let alpn = stream.get_ref().1.alpn_protocol();
+            if alpn != Some(&shared.config.alpn[..]) {
+                warn!("[server] unexpected ALPN protocol {alpn:?}, closing the connection: addr={addr}");
+                return;
+            }
+
+            info!("[server] incoming TCP connection: addr={addr}");
+
+            let (peer, sink, source) = split(stream, addr);
+            connection::handle_stream(peer, sink, source, shared).await;
+        }));
+    }
+}
+
+/// Completes the handshake of a connection refused for lack of room only to send it a
+/// [`CloseReason::ServerFull`] `Close`, there being no room for a reason on a TCP connection.
+async fn close_full(acceptor: TlsAcceptor, socket: TcpStream, handshake_timeout: Duration) {
+    let Ok(Ok(mut stream)) = timeout(handshake_timeout, acceptor.accept(socket)).await else {
+        return;
+    };
+
+    let close = Message::close(Uuid::nil(), CloseReason::ServerFull);
+    let _ = timeout(handshake_timeout, async {
+        stream.write_all(&close.encode()).await?;
+        stream.shutdown().await
+    })
+    .await;
+}
+
+/// Splits the connection into the halves of its stream.
+fn split(stream: TlsStream<TcpStream>, addr: SocketAddr) -> (TcpPeer, TcpSink, TcpSource) {
+    let (closed, _) = watch::channel(false);
+    let peer = TcpPeer {
+        addr,
+        closed: Arc::new(closed),
+    };
+    let (read, write) = tokio::io::split(stream);
+
+    let sink = TcpSink {
+        write,
+        closed: peer.closed.subscribe(),
+    };
+    let source = TcpSource {
+        read,
+        closed: peer.closed.subscribe(),
+    };
+    (peer, sink, source)
+}
+
+/// TCP connection of a client; closing it aborts both halves of its stream.
+#[derive(Clone)]
+pub struct TcpPeer {
+    addr: SocketAddr,
+    closed: Arc<watch::Sender<bool>>,
+}
+
+impl PeerConnection for TcpPeer {
+    fn remote_address(&self) -> SocketAddr {
+        self.addr
+    }
+
+    // There's no room for the code on a TCP connection, the client only sees the stream end.
+    fn close(&self, _code: CloseCode, _details: &[u8]) {
+        self.closed.send_replace(true);
+    }
+}
+
+fn aborted() -> io::Error {
+    io::Error::new(ErrorKind::ConnectionAborted, "Connection was closed")
+}
+
+pub struct TcpSource {
+    read: ReadHalf<TlsStream<TcpStream>>,
+    closed: watch::Receiver<bool>,
+}
+
+impl AsyncFrameSource for TcpSource {
+    async fn recv_chunk(&mut self, max_length: usize) -> io::Result<Option<Bytes>> {
+        let mut chunk = BytesMut::zeroed(max_length);
+        let read = tokio::select! {
+            read = self.read.read(&mut chunk) => read?,
+            _ = self.closed.wait_for(|closed| *closed) => return Err(aborted()),
+        };
+
+        chunk.truncate(read);
+        Ok((read > 0).then(|| chunk.freeze()))
+    }
+}
+
+pub struct TcpSink {
+    write: WriteHalf<TlsStream<TcpStream>>,
+    closed: watch::Receiver<bool>,
+}
+
+impl AsyncFrameSink for TcpSink {
+    async fn send_chunk(&mut self, chunk: Bytes) -> io::Result<()> {
+        if *self.closed.borrow() {
+            return Err(aborted());
+        }
+
+        self.write.write_all(&chunk).await
+    }
+
+    // The TLS close_notify tells the client the stream is finished; TCP delivers what was sent
+    // before it on its own.
+    async fn finish(&mut self, _grace: Option<Duration>) {
+        let _ = self.write.shutdown().await;
+    }
+}
@@ -0,0 +1,63 @@
+use std::{future::Future, io, net::SocketAddr, time::Duration};
+
+use bytes::Bytes;
+use quinn::{Connection, RecvStream, SendStream};
+use tokio::time::timeout;
+
+use crate::close_code::CloseCode;
+
+/// Receiving half of a stream carrying frames; frames may be split across chunks.
+pub trait AsyncFrameSource: Send {
+    /// Reads the next chunk, of at most `max_length` bytes; `None` once the peer finished the
+    /// stream.
+    fn recv_chunk(
+        &mut self,
+        max_length: usize,
+    ) -> impl Future<Output = io::Result<Option<Bytes>>> + Send;
+}
+
+/// Sending half of a stream carrying frames.
+pub trait AsyncFrameSink: Send + 'static {
+    fn send_chunk(&mut self, chunk: Bytes) -> impl Future<Output = io::Result<()>> + Send;
+
+    /// Finishes the stream, then waits up to `grace` for the peer to receive what was sent.
+    fn finish(&mut self, grace: Option<Duration>) -> impl Future<Output = ()> + Send;
+}
+
+/// Connection the streams are opened on.
+pub trait PeerConnection: Clone + Send + Sync + 'static {
+    fn remote_address(&self) -> SocketAddr;
+
+    /// Closes the connection and all of its streams, telling the peer why with `code`.
+    fn close(&self, code: CloseCode, details: &[u8]);
+}
+
+impl AsyncFrameSource for RecvStream {
+    async fn recv_chunk(&mut self, max_length: usize) -> io::Result<Option<Bytes>> {
+        let chunk = self.read_chunk(max_length, true).await?;
+        Ok(chunk.map(|chunk| chunk.bytes))
+    }
+}
+
+impl AsyncFrameSink for SendStream {
+    async fn send_chunk(&mut self, chunk: Bytes) -> io::Result<()> {
+        self.write_chunk(chunk).await.map_err(io::Error::from)
+    }
+
+    async fn finish(&mut self, grace: Option<Duration>) {
+        let _ = SendStream::finish(self);
+        if let Some(grace) = grace {
+            let _ = timeout(grace, self.stopped()).await;
+        }
+    }
+}
+
+impl PeerConnection for Connection {
+    fn remote_address(&self) -> SocketAddr {
+        Connection::remote_address(self)
+    }
+
+    fn close(&self, code: CloseCode, details: &[u8]) {
+        Connection::close(self, code.into(), details);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use message::{Decoder, InitializationMessage, Message, MessageType};
+use server::config::Config;
+use spdlog::{Logger, sink::WriteSink};
+use tokio::time::timeout;
+use uuid::Uuid;
+
+mod common;
+
+#[tokio::test]
+async fn logs_forwarded_data_in_both_directions() {
+    // The access logger is forked from the default one, so it writes to this sink as well.
+    let sink = Arc::new(WriteSink::builder().target(Vec::new()).build().unwrap());
+    let logger = Logger::builder().sink(sink.clone()).build().unwrap();
+    spdlog::set_default_logger(Arc::new(logger));
+
+    let backend_addr = common::spawn_echo_backend().await;
+
+    let mut config = Config::new();
+    config.access_log = true;
+    let connection = common::connect(config).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    send.write_all(&Message::new(MessageType::Initial, connection_id, init.encode()).encode())
+        .await
+        .unwrap();
+
+    let data = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from_static(b"logged"),
+    )
+    .with_sequence(0);
+    send.write_all(&data.encode()).await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let response = timeout(Duration::from_secs(5), async {
+        loop {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            if msg.message_type.is_data() {
+                return msg;
+            }
+        }
+    })
+    .await
+    .expect("no response from the backend");
+
+    // The line of the response is emitted once it's sent, possibly after it was received.
+    let logged = |message_id: Uuid, direction: &str| {
+        let output = String::from_utf8(sink.clone_target()).unwrap();
+        output.lines().any(|line| {
+            line.contains(&format!("connection_id={connection_id}"))
+                && line.contains(&format!("message_id={message_id}"))
+                && line.contains(&format!("direction={direction}"))
+                && line.contains("bytes=6")
+                && line.contains("type=data")
+        })
+    };
+
+    assert!(logged(data.message_id, "client->backend"));
+
+    timeout(Duration::from_secs(5), async {
+        while !logged(response.message_id, "backend->client") {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("response was not logged");
+}
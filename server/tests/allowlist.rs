@@ -0,0 +1,77 @@
+use message::{CloseReason, Decoder, InitializationMessage, MessageType};
+use server::{
+    allowlist::{Cidr, PolicyMode, TargetPolicy},
+    config::Config,
+};
+
+mod common;
+
+fn policy(mode: PolicyMode, rules: &[&str]) -> TargetPolicy {
+    TargetPolicy {
+        mode,
+        rules: rules.iter().map(|rule| rule.parse().unwrap()).collect(),
+    }
+}
+
+#[test]
+fn allow_policy_only_allows_the_targets_of_its_rules() {
+    let policy = policy(PolicyMode::Allow, &["10.0.0.0/8:80-443", "[::1/128]:8080"]);
+
+    assert!(policy.is_allowed("10.1.2.3:80".parse().unwrap()));
+    assert!(policy.is_allowed("10.1.2.3:443".parse().unwrap()));
+    assert!(policy.is_allowed("[::1]:8080".parse().unwrap()));
+
+    // Blocked IP.
+    assert!(!policy.is_allowed("192.168.0.1:80".parse().unwrap()));
+    // Blocked ports.
+    assert!(!policy.is_allowed("10.1.2.3:22".parse().unwrap()));
+    assert!(!policy.is_allowed("[::1]:8081".parse().unwrap()));
+}
+
+#[test]
+fn deny_policy_blocks_the_targets_of_its_rules() {
+    let policy = policy(PolicyMode::Deny, &["127.0.0.0/8", "0.0.0.0/0:25"]);
+
+    assert!(policy.is_allowed("10.0.0.1:80".parse().unwrap()));
+    assert!(!policy.is_allowed("127.0.0.1:80".parse().unwrap()));
+    assert!(!policy.is_allowed("10.0.0.1:25".parse().unwrap()));
+}
+
+#[test]
+fn ipv4_mapped_addresses_match_the_ipv4_rules() {
+    let policy = policy(PolicyMode::Deny, &["127.0.0.0/8"]);
+    assert!(!policy.is_allowed("[::ffff:127.0.0.1]:80".parse().unwrap()));
+    assert!(policy.is_allowed("[::ffff:10.0.0.1]:80".parse().unwrap()));
+
+    // And the other way around.
+    let mapped: Cidr = "::ffff:10.0.0.0/104".parse().unwrap();
+    assert!(mapped.contains("10.1.2.3".parse().unwrap()));
+    assert!(!mapped.contains("11.1.2.3".parse().unwrap()));
+}
+
+#[tokio::test]
+async fn target_outside_the_policy_is_refused() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let mut config = Config::new();
+    config.target_policy = policy(
+        PolicyMode::Allow,
+        &[&format!("127.0.0.1/32:{}", backend_addr.port())],
+    );
+    let server = common::TestServer::start(config);
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    // Same IP, port outside the rule.
+    let target = format!("127.0.0.1:{}", backend_addr.port().wrapping_add(1));
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), target.parse().unwrap())
+        .unwrap();
+    let (_, reply) = common::request_tunnel(&mut send, &mut recv, &mut decoder, &init).await;
+    assert!(matches!(reply.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&reply.payload).unwrap(),
+        CloseReason::TargetNotAllowed
+    );
+}
@@ -0,0 +1,75 @@
+use std::{slice, time::Duration};
+
+use quinn::{Connection, ConnectionError, Endpoint};
+use server::config::Config;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::timeout,
+};
+
+mod common;
+
+/// Connects to the server offering the given ALPN protocols.
+async fn connect_with(
+    server: &common::TestServer,
+    alpn: &[&[u8]],
+) -> Result<Connection, ConnectionError> {
+    let mut tls_config = client::tls::tls_config(slice::from_ref(&server.cert), None).unwrap();
+    tls_config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+
+    let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(client::tls::quic_config(tls_config).unwrap());
+    endpoint.connect(server.addr, "localhost").unwrap().await
+}
+
+#[tokio::test]
+async fn matching_alpn_is_accepted() {
+    let server = common::TestServer::start(Config::new());
+
+    let connection = connect_with(&server, &[b"other/1", message::DEFAULT_ALPN])
+        .await
+        .unwrap();
+    let protocol = connection
+        .handshake_data()
+        .unwrap()
+        .downcast::<quinn::crypto::rustls::HandshakeData>()
+        .unwrap()
+        .protocol;
+    assert_eq!(protocol.as_deref(), Some(message::DEFAULT_ALPN));
+}
+
+#[tokio::test]
+async fn mismatched_alpn_is_rejected() {
+    let server = common::TestServer::start(Config::new());
+
+    assert!(connect_with(&server, &[b"other/1"]).await.is_err());
+    assert!(connect_with(&server, &[]).await.is_err());
+}
+
+#[tokio::test]
+async fn clients_offer_the_configured_alpn() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let mut config = Config::new();
+    config.alpn = b"custom/2".to_vec();
+    let server = common::TestServer::start(config);
+
+    let mut config = common::client_config(&server, backend_addr);
+    config.alpn = b"custom/2".to_vec();
+    let (_client, local_addr) = common::spawn_client(config).await;
+
+    let mut socket = common::connect_local(local_addr).await;
+    socket.write_all(b"hello").await.unwrap();
+    let mut echoed = [0; 5];
+    timeout(Duration::from_secs(5), socket.read_exact(&mut echoed))
+        .await
+        .expect("timed out waiting for the echo")
+        .unwrap();
+    assert_eq!(&echoed, b"hello");
+
+    // Clients offering the default protocol are refused.
+    assert!(
+        connect_with(&server, &[message::DEFAULT_ALPN])
+            .await
+            .is_err()
+    );
+}
@@ -0,0 +1,171 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bytes::Bytes;
+use message::{Decoder, InitializationMessage, Message, MessageType};
+use server::{
+    amplification::{AMPLIFICATION_FACTOR, AmplificationLimit},
+    config::Config,
+    connection,
+    transport::{AsyncFrameSink, AsyncFrameSource},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::TcpListener,
+    sync::oneshot,
+    time::{sleep, timeout},
+};
+use uuid::Uuid;
+
+mod common;
+
+use common::memory::{self, MemoryConnection, MemorySource, shared};
+
+/// Sent by the backend as soon as it's connected, far more than the client sends.
+const BANNER: usize = 256 * 1024;
+
+/// Accepts a backend connection and writes `BANNER` bytes to it right away.
+async fn spawn_banner_backend() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        socket.write_all(&vec![0x42; BANNER]).await.unwrap();
+        // Keeps the connection open until the server closes it.
+        sleep(Duration::from_secs(30)).await;
+    });
+
+    addr
+}
+
+/// Reads everything the stream delivers until it stays idle for `idle`; returns the bytes read.
+async fn read_until_idle(recv: &mut MemorySource, decoder: &mut Decoder, idle: Duration) -> usize {
+    let mut read = 0;
+    while let Ok(chunk) = timeout(idle, recv.recv_chunk(usize::MAX)).await {
+        let Some(chunk) = chunk.unwrap() else {
+            break;
+        };
+        read += chunk.len();
+        decoder.extend(&chunk);
+    }
+    read
+}
+
+#[test]
+fn limit_allows_a_multiple_of_the_bytes_received() {
+    let limit = AmplificationLimit::new();
+    assert!(!limit.try_send(1));
+
+    limit.record_received(100);
+    assert!(limit.try_send(100 * AMPLIFICATION_FACTOR as usize));
+    assert!(!limit.try_send(1));
+
+    limit.record_received(10);
+    assert!(!limit.try_send(31));
+    assert!(limit.try_send(30));
+}
+
+#[test]
+fn validated_limit_allows_anything() {
+    let limit = AmplificationLimit::new();
+    limit.record_received(10);
+    assert!(!limit.try_send(1000));
+
+    limit.validate();
+    assert!(limit.is_validated());
+    assert!(limit.try_send(1000));
+    assert!(limit.try_send(usize::MAX));
+}
+
+#[tokio::test]
+async fn send_waits_for_room() {
+    let limit = std::sync::Arc::new(AmplificationLimit::new());
+    let sending = tokio::spawn({
+        let limit = limit.clone();
+        async move { limit.send(300).await }
+    });
+
+    limit.record_received(50);
+    sleep(Duration::from_millis(50)).await;
+    assert!(!sending.is_finished());
+
+    limit.record_received(50);
+    timeout(Duration::from_secs(5), sending)
+        .await
+        .expect("send still waiting")
+        .unwrap();
+    assert!(!limit.try_send(1));
+}
+
+#[tokio::test]
+async fn response_to_an_unvalidated_client_stays_within_the_limit() {
+    let backend_addr = spawn_banner_backend().await;
+    let connection = MemoryConnection::new("127.0.0.1:4000".parse().unwrap());
+    let (mut client_send, server_recv) = memory::stream();
+    let (server_send, mut client_recv) = memory::stream();
+    let (handshake_tx, handshake_rx) = oneshot::channel();
+    tokio::spawn(connection::handle_early_stream(
+        connection,
+        server_send,
+        server_recv,
+        shared(Config::new()),
+        async { handshake_rx.await.unwrap_or(false) },
+    ));
+    let mut decoder = Decoder::new();
+
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    let initial = Message::new(MessageType::Initial, Uuid::new_v4(), init.encode());
+    let ping = Message::new(MessageType::Ping, Uuid::nil(), Bytes::from_static(b"ping"));
+    let sent = initial.encoded_len() + ping.encoded_len();
+    client_send.send_chunk(initial.encode()).await.unwrap();
+    client_send.send_chunk(ping.encode()).await.unwrap();
+
+    // Until the handshake completes, only the ping is answered, within the limit.
+    let read = read_until_idle(&mut client_recv, &mut decoder, Duration::from_millis(300)).await;
+    assert!(read > 0, "the Ping wasn't answered");
+    assert!(
+        read as u64 <= sent as u64 * AMPLIFICATION_FACTOR,
+        "sent {read} bytes in response to {sent}"
+    );
+    let pong = decoder.next_message().unwrap().unwrap();
+    assert!(matches!(pong.message_type, MessageType::Pong));
+    assert!(decoder.next_message().unwrap().is_none());
+
+    handshake_tx.send(true).unwrap();
+    let ack = memory::next_message(&mut client_recv, &mut decoder).await;
+    assert!(matches!(ack.message_type, MessageType::Ack));
+    let mut relayed = 0;
+    timeout(Duration::from_secs(5), async {
+        while relayed < BANNER {
+            let msg = memory::next_message(&mut client_recv, &mut decoder).await;
+            assert!(msg.message_type.is_data());
+            relayed += msg.payload.len();
+        }
+    })
+    .await
+    .expect("banner not relayed once the client was validated");
+    assert_eq!(relayed, BANNER);
+}
+
+#[tokio::test]
+async fn validated_addresses_can_tunnel() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let mut config = Config::new();
+    config.address_validation = true;
+    let server = common::TestServer::start(config);
+
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    let data = Message::data(connection_id, "hello".into()).with_sequence(0);
+    send.write_all(&data.encode()).await.unwrap();
+    let echo = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("no echo received");
+    assert_eq!(&echo.payload[..], b"hello");
+}
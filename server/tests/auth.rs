@@ -0,0 +1,72 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, InitializationMessage, Message, MessageType};
+use server::config::Config;
+use tokio::time::timeout;
+
+mod common;
+
+const TOKEN: &[u8] = b"secret";
+
+/// Initial for a tunnel to `backend_addr`, presenting `token` if any.
+fn init(backend_addr: SocketAddr, token: Option<&'static [u8]>) -> InitializationMessage {
+    let init = InitializationMessage::new("127.0.0.1:4000".parse().unwrap(), backend_addr).unwrap();
+    match token {
+        Some(token) => init.with_token(Bytes::from_static(token)).unwrap(),
+        None => init,
+    }
+}
+
+fn protected_server() -> common::TestServer {
+    let mut config = Config::new();
+    config.auth_token = Some(Bytes::from_static(TOKEN));
+    common::TestServer::start(config)
+}
+
+#[tokio::test]
+async fn correct_token_opens_the_tunnel() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = protected_server();
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    let init = init(backend_addr, Some(TOKEN));
+    let (connection_id, reply) =
+        common::request_tunnel(&mut send, &mut recv, &mut decoder, &init).await;
+    assert!(matches!(reply.message_type, MessageType::Ack));
+
+    let data = Message::data(connection_id, "hello".into());
+    send.write_all(&data.encode()).await.unwrap();
+    let echo = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("no echo received");
+    assert_eq!(&echo.payload[..], b"hello");
+}
+
+#[tokio::test]
+async fn wrong_or_missing_token_fails_authentication() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = protected_server();
+
+    // Failing the authentication closes the connection, each attempt opens one of its own.
+    for token in [Some(&b"wrong"[..]), Some(&b"secre"[..]), None] {
+        let connection = server.connect().await;
+        let (mut send, mut recv) = connection.open_bi().await.unwrap();
+        let mut decoder = Decoder::new();
+        let init = init(backend_addr, token);
+        let (_, reply) = common::request_tunnel(&mut send, &mut recv, &mut decoder, &init).await;
+        assert!(
+            matches!(reply.message_type, MessageType::Close),
+            "{token:?} was not rejected"
+        );
+        assert_eq!(
+            CloseReason::decode(&reply.payload).unwrap(),
+            CloseReason::AuthFailed
+        );
+    }
+}
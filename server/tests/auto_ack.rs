@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use client::ClientEvent;
+use message::{Decoder, Message, MessageType};
+use server::config::Config;
+use tokio::{io::AsyncWriteExt, time::timeout};
+
+mod common;
+
+fn server_config() -> Config {
+    let mut config = Config::new();
+    config.auto_ack = true;
+    config
+}
+
+#[tokio::test]
+async fn data_is_acknowledged_with_auto_ack() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = common::connect(server_config()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    let data = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from_static(b"hello"),
+    );
+    send.write_all(&data.encode()).await.unwrap();
+
+    // The echo may arrive before or after the Ack.
+    let ack = timeout(Duration::from_secs(5), async {
+        loop {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            if matches!(msg.message_type, MessageType::Ack) {
+                return msg;
+            }
+        }
+    })
+    .await
+    .expect("Data was not acknowledged");
+    assert_eq!(ack.in_reply_to, Some(data.message_id));
+}
+
+#[tokio::test]
+async fn client_emits_the_acks_it_receives() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(server_config());
+    let (client, local_addr) =
+        common::start_client(&server, backend_addr, Duration::from_secs(5)).await;
+    let mut events = client.subscribe();
+
+    let mut socket = common::connect_local(local_addr).await;
+    socket.write_all(b"hello").await.unwrap();
+
+    // The Initial is acknowledged, then the Data.
+    let acked = timeout(Duration::from_secs(5), async {
+        let mut acked = Vec::new();
+        while acked.len() < 2 {
+            if let ClientEvent::Acked {
+                connection_id,
+                message_id,
+            } = events.recv().await.unwrap()
+            {
+                acked.push((connection_id, message_id));
+            }
+        }
+        acked
+    })
+    .await
+    .expect("Acks were not emitted");
+    assert_eq!(acked[0].0, acked[1].0);
+    assert_ne!(acked[0].1, acked[1].1);
+}
@@ -0,0 +1,84 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use client::ClientEvent;
+use message::{CloseReason, Decoder, InitializationMessage, Message, MessageType};
+use server::config::Config;
+use tokio::{io::AsyncReadExt, net::TcpListener, time::timeout};
+use uuid::Uuid;
+
+mod common;
+
+/// Opens a tunnel to the backend and returns the reason of the `Close` the server answers with,
+/// along with the time it took.
+async fn rejected_tunnel(config: Config, backend_addr: SocketAddr) -> (CloseReason, Duration) {
+    let connection = common::connect(config).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    let started = Instant::now();
+    send.write_all(&Message::new(MessageType::Initial, connection_id, init.encode()).encode())
+        .await
+        .unwrap();
+
+    let reply = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("tunnel was not closed");
+    assert!(matches!(reply.message_type, MessageType::Close));
+
+    (
+        CloseReason::decode(&reply.payload).unwrap(),
+        started.elapsed(),
+    )
+}
+
+#[tokio::test]
+async fn closed_port_is_reported_as_refused() {
+    let backend_addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let (reason, elapsed) = rejected_tunnel(Config::new(), backend_addr).await;
+    assert_eq!(reason, CloseReason::BackendRefused);
+    assert!(elapsed < Duration::from_secs(1), "took {elapsed:?}");
+}
+
+#[tokio::test]
+async fn refusal_is_delivered_to_the_client() {
+    let backend_addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server = common::TestServer::start(Config::new());
+    let (client, local_addr) =
+        common::start_client(&server, backend_addr, Duration::from_secs(5)).await;
+    let mut events = client.subscribe();
+
+    let mut socket = common::connect_local(local_addr).await;
+    let mut buf = Vec::new();
+    timeout(Duration::from_secs(5), socket.read_to_end(&mut buf))
+        .await
+        .expect("local connection was not closed")
+        .ok();
+
+    let rejected = timeout(Duration::from_secs(5), async {
+        loop {
+            if let ClientEvent::TunnelRejected { reason, .. } = events.recv().await.unwrap() {
+                return reason;
+            }
+        }
+    })
+    .await
+    .expect("rejection was not reported");
+    assert_eq!(rejected, CloseReason::BackendRefused);
+}
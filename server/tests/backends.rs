@@ -0,0 +1,99 @@
+use std::{collections::HashSet, net::SocketAddr, time::Duration};
+
+use message::ProxyTarget;
+use server::{
+    backend::{Backends, Routing},
+    config::Config,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::timeout,
+};
+use uuid::Uuid;
+
+mod common;
+
+fn instances() -> Vec<SocketAddr> {
+    (1..=4)
+        .map(|i| format!("10.0.0.{i}:8080").parse().unwrap())
+        .collect()
+}
+
+#[test]
+fn connection_ids_stick_to_their_instance() {
+    let backends = Backends::new(instances(), Routing::Override);
+    let ids = (0..64).map(Uuid::from_u128).collect::<Vec<_>>();
+
+    let selected = ids
+        .iter()
+        .map(|id| backends.select(*id).unwrap())
+        .collect::<Vec<_>>();
+
+    // The same ids map to the same instances, whatever the order of the pool.
+    let mut reversed = instances();
+    reversed.reverse();
+    let reversed = Backends::new(reversed, Routing::Override);
+    for (id, instance) in ids.iter().zip(&selected) {
+        assert_eq!(backends.select(*id), Some(*instance));
+        assert_eq!(reversed.select(*id), Some(*instance));
+    }
+
+    // Different ids are spread across the instances.
+    let used = selected.iter().collect::<HashSet<_>>();
+    assert_eq!(used.len(), instances().len());
+}
+
+#[test]
+fn removing_an_instance_only_moves_its_tunnels() {
+    let backends = Backends::new(instances(), Routing::Override);
+    let removed = instances()[0];
+    let remaining = Backends::new(instances()[1..].to_vec(), Routing::Override);
+
+    for id in (0..64).map(Uuid::from_u128) {
+        let before = backends.select(id).unwrap();
+        if before != removed {
+            assert_eq!(remaining.select(id), Some(before));
+        }
+    }
+}
+
+#[test]
+fn unspecified_routing_keeps_the_targets_of_the_client() {
+    let backends = Backends::new(instances(), Routing::Unspecified);
+    let id = Uuid::from_u128(7);
+    let instance = ProxyTarget::Addr(backends.select(id).unwrap());
+
+    let requested = ProxyTarget::Addr("192.0.2.1:80".parse().unwrap());
+    assert_eq!(backends.route(id, requested.clone()), requested);
+    let host = ProxyTarget::Host("example.com".into(), 80);
+    assert_eq!(backends.route(id, host.clone()), host);
+
+    let unspecified = ProxyTarget::Addr("0.0.0.0:0".parse().unwrap());
+    assert_eq!(backends.route(id, unspecified), instance);
+
+    let overriding = Backends::new(instances(), Routing::Override);
+    assert_eq!(overriding.route(id, requested), instance);
+    assert_eq!(Backends::default().route(id, host.clone()), host);
+}
+
+#[tokio::test]
+async fn tunnels_are_routed_to_the_pool() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let mut config = Config::new();
+    config.backends = Backends::new(vec![backend_addr], Routing::Override);
+    let server = common::TestServer::start(config);
+
+    // Nothing listens on the target of the client, the pool overrides it.
+    let unreachable = "127.0.0.1:1".parse().unwrap();
+    let (_client, local_addr) =
+        common::start_client(&server, unreachable, Duration::from_secs(5)).await;
+
+    let mut socket = common::connect_local(local_addr).await;
+    socket.write_all(b"hello").await.unwrap();
+    let mut echoed = [0; 5];
+    timeout(Duration::from_secs(5), socket.read_exact(&mut echoed))
+        .await
+        .expect("timed out waiting for the echo")
+        .unwrap();
+    assert_eq!(&echoed, b"hello");
+}
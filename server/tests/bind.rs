@@ -0,0 +1,48 @@
+use std::{net::SocketAddr, time::Duration};
+
+use quinn::Endpoint;
+use server::{config::Config, server::make_server_endpoint};
+use tokio::time::timeout;
+
+/// Starts a server bound to `bind_addr`, connects to it from `client_addr` and returns the
+/// address the server is bound to.
+async fn connect(bind_addr: &str, client_addr: &str, ipv6_only: bool) -> SocketAddr {
+    let mut config = Config::new();
+    config.host = bind_addr.parse().unwrap();
+    config.ipv6_only = ipv6_only;
+
+    let (server, server_cert) = make_server_endpoint(&config).unwrap();
+    let local_addr = server.local_addr().unwrap();
+
+    let client_addr: SocketAddr = client_addr.parse().unwrap();
+    let mut endpoint = Endpoint::client(SocketAddr::new(client_addr.ip(), 0)).unwrap();
+    endpoint
+        .set_default_client_config(client::tls::configure_client(&[server_cert], None).unwrap());
+
+    let server_addr = SocketAddr::new(client_addr.ip(), local_addr.port());
+    let connecting = endpoint.connect(server_addr, "localhost").unwrap();
+    let accepted = async { server.accept().await.unwrap().await };
+
+    let (connected, accepted) = timeout(Duration::from_secs(5), async {
+        tokio::join!(connecting, accepted)
+    })
+    .await
+    .expect("handshake timed out");
+
+    connected.unwrap();
+    accepted.unwrap();
+
+    local_addr
+}
+
+#[tokio::test]
+async fn binds_to_ipv6_loopback() {
+    let addr = connect("[::1]:0", "[::1]:0", true).await;
+    assert!(addr.is_ipv6());
+}
+
+#[tokio::test]
+async fn dual_stack_accepts_ipv4_clients() {
+    let addr = connect("[::]:0", "127.0.0.1:0", false).await;
+    assert!(addr.is_ipv6());
+}
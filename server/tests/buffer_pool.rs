@@ -0,0 +1,120 @@
+use std::{sync::Arc, time::Duration};
+
+use bytes::{BufMut, Bytes};
+use futures_util::StreamExt;
+use message::{Message, MessageStream, MessageType};
+use server::{buffer_pool::BufferPool, config::Config, outbound::Outbound};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::{sleep, timeout},
+};
+use uuid::Uuid;
+
+mod common;
+
+#[test]
+fn returned_buffers_are_reused() {
+    let pool = BufferPool::new(2, 1024);
+
+    let mut buf = pool.checkout();
+    assert!(buf.capacity() >= 1024);
+    buf.put_slice(b"payload");
+    let payload = buf.freeze();
+    let ptr = payload.as_ptr();
+
+    pool.checkin(payload);
+    assert_eq!(pool.free_buffers(), 1);
+
+    let buf = pool.checkout();
+    assert_eq!(buf.as_ptr(), ptr);
+    assert!(buf.is_empty());
+    assert!(buf.capacity() >= 1024);
+    assert_eq!(pool.free_buffers(), 0);
+}
+
+#[test]
+fn shared_small_or_surplus_buffers_are_dropped() {
+    let pool = BufferPool::new(1, 1024);
+
+    // Still referenced by the clone.
+    let mut buf = pool.checkout();
+    buf.put_slice(b"payload");
+    let payload = buf.freeze();
+    let clone = payload.clone();
+    pool.checkin(payload);
+    assert_eq!(pool.free_buffers(), 0);
+    drop(clone);
+
+    pool.checkin(Bytes::from(vec![0; 16]));
+    assert_eq!(pool.free_buffers(), 0);
+
+    pool.checkin(pool.checkout().freeze());
+    pool.checkin(pool.checkout().freeze());
+    pool.checkin(Bytes::from(vec![0; 1024]));
+    assert_eq!(pool.free_buffers(), 1);
+
+    let disabled = BufferPool::new(0, 1024);
+    disabled.checkin(disabled.checkout().freeze());
+    assert_eq!(disabled.free_buffers(), 0);
+}
+
+#[tokio::test]
+async fn written_payloads_are_returned_to_the_pool() {
+    let config = Config::new();
+    let (send, mut peer) = common::raw_stream(&config).await;
+    let pool = Arc::new(BufferPool::new(4, 1024));
+    let outbound = Outbound::spawn(
+        send,
+        config.flush_window,
+        config.coalesce_max_bytes,
+        config.max_frame_size,
+        pool.clone(),
+        config.client_write_timeout,
+    );
+
+    let mut buf = pool.checkout();
+    buf.put_slice(b"pooled payload");
+    let data = Message::new(MessageType::Data, Uuid::new_v4(), buf.freeze());
+    outbound.send(data).await.unwrap();
+
+    let mut messages = MessageStream::new(peer.accept().await);
+    let msg = timeout(Duration::from_secs(5), messages.next())
+        .await
+        .expect("frame was not received")
+        .unwrap()
+        .unwrap();
+    assert_eq!(&msg.payload[..], b"pooled payload");
+
+    timeout(Duration::from_secs(5), async {
+        while pool.free_buffers() == 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("buffer was not returned to the pool");
+}
+
+#[tokio::test]
+async fn tunnel_relays_through_pooled_buffers() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let mut config = Config::new();
+    config.buffer_pool_size = 8;
+    config.chunk_size = 1024;
+    let server = common::TestServer::start(config);
+    let (_client, local_addr) =
+        common::start_client(&server, backend_addr, Duration::from_secs(5)).await;
+
+    let mut socket = common::connect_local(local_addr).await;
+    let sent = (0..64 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+    let (mut reader, mut writer) = socket.split();
+
+    let mut received = vec![0; sent.len()];
+    let (written, read) = timeout(Duration::from_secs(10), async {
+        tokio::join!(writer.write_all(&sent), reader.read_exact(&mut received))
+    })
+    .await
+    .expect("timed out relaying through the tunnel");
+    written.unwrap();
+    read.unwrap();
+    assert_eq!(received, sent);
+}
@@ -0,0 +1,46 @@
+use std::{fs, sync::Arc, time::Duration};
+
+use client::{CertSource, Client, ClientConfig, ClientEvent};
+use server::{
+    config::{self, Config},
+    server::{load_certs, save_cert},
+};
+use tokio::time::timeout;
+
+mod common;
+
+#[tokio::test]
+async fn client_trusts_the_certificate_written_by_the_server() {
+    let server = common::TestServer::start(Config::new());
+
+    let dir = std::env::temp_dir().join(format!("reverprox-cert-{}", uuid::Uuid::new_v4()));
+    let cert_path = dir.join("nested").join("server_cert.pem");
+    save_cert(&cert_path, &server.cert).unwrap();
+
+    assert_eq!(load_certs(&cert_path).unwrap(), vec![server.cert.clone()]);
+
+    let mut config = ClientConfig::new(server.addr, "127.0.0.1:3000".parse().unwrap());
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.server_cert_sources = vec![CertSource::File(cert_path)];
+    let client = Arc::new(Client::new(config).unwrap());
+    let mut events = client.subscribe();
+
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    let event = timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("client did not connect")
+        .unwrap();
+    assert_eq!(event, ClientEvent::Connected);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn server_and_client_default_to_the_same_path() {
+    assert_eq!(
+        config::default_cert_path(),
+        client::tls::default_server_cert_path()
+    );
+}
@@ -0,0 +1,109 @@
+use std::{io::ErrorKind, process::Command, time::Duration};
+
+use server::{
+    config::{self, Config},
+    server::{Identity, save_cert, save_key},
+};
+use uuid::Uuid;
+
+fn check_config(envs: &[(&str, &str)]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("--check-config")
+        .env_remove(config::CLIENT_CA_ENV)
+        .env_remove(config::BIND_ENV)
+        .envs(envs.iter().copied())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn default_config_is_valid() {
+    Config::new().validate().unwrap();
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+    let mut config = Config::new();
+    config.client_ca = Some("/nonexistent/ca.pem".into());
+    assert_eq!(config.validate().unwrap_err().kind(), ErrorKind::NotFound);
+
+    let mut config = Config::new();
+    config.idle_sweep_interval = Duration::ZERO;
+    assert_eq!(
+        config.validate().unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    let mut config = Config::new();
+    config.chunk_size = config.max_frame_size;
+    assert_eq!(
+        config.validate().unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn check_config_flag_reports_a_good_config() {
+    let output = check_config(&[(config::BIND_ENV, "127.0.0.1:9100")]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Config is valid"));
+    assert!(stdout.contains("127.0.0.1:9100"));
+}
+
+#[test]
+fn check_config_flag_fails_on_a_bad_config() {
+    let output = check_config(&[(config::CLIENT_CA_ENV, "/nonexistent/ca.pem")]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Invalid config"), "{stderr}");
+
+    let output = check_config(&[(config::BIND_ENV, "not an address")]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn saved_identity_has_to_load() {
+    let dir = std::env::temp_dir().join(format!("reverprox-identity-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    let identity = Identity::generate();
+    save_cert(&cert_path, &identity.cert).unwrap();
+    save_key(&key_path, &identity.key).unwrap();
+
+    let mut config = Config::new();
+    config.cert_path = cert_path.clone();
+    config.key_path = Some(key_path.clone());
+    config.validate().unwrap();
+
+    // The key of another identity.
+    save_key(&key_path, &Identity::generate().key).unwrap();
+    assert_eq!(
+        config.validate().unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn check_config_flag_fails_on_a_garbage_key() {
+    let dir = std::env::temp_dir().join(format!("reverprox-identity-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    save_cert(&cert_path, &Identity::generate().cert).unwrap();
+    std::fs::write(&key_path, "not a key").unwrap();
+
+    let output = check_config(&[
+        (config::CERT_PATH_ENV, cert_path.to_str().unwrap()),
+        (config::KEY_PATH_ENV, key_path.to_str().unwrap()),
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Invalid server identity"), "{stderr}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
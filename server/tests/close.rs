@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, InitializationMessage, Message, MessageType};
+use server::{config::Config, events::ServerEvent};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    time::{sleep, timeout},
+};
+use uuid::Uuid;
+
+mod common;
+
+/// Long enough to tell an acknowledged close from a timed out one.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Accepts backend connections, greets them and closes them right away.
+async fn spawn_closing_backend() -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let _ = socket.write_all(b"bye").await;
+            let _ = socket.shutdown().await;
+        }
+    });
+
+    addr
+}
+
+/// Waits until neither side has a tunnel left.
+async fn wait_for_cleanup(server: &common::TestServer, client: &client::Client) {
+    timeout(Duration::from_secs(5), async {
+        while server.registry.open_tunnels() > 0 || client.open_tunnels() > 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("tunnel state was not cleaned up");
+}
+
+fn server_config() -> Config {
+    let mut config = Config::new();
+    config.close_timeout = CLOSE_TIMEOUT;
+    config
+}
+
+#[tokio::test]
+async fn close_of_the_client_is_acknowledged() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(server_config());
+    let (client, local_addr) = common::start_client(&server, backend_addr, CLOSE_TIMEOUT).await;
+
+    let mut socket = common::connect_local(local_addr).await;
+    socket.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    socket.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+    assert_eq!(server.registry.open_tunnels(), 1);
+
+    // Resetting the local connection makes the client close the tunnel.
+    socket.set_zero_linger().unwrap();
+    drop(socket);
+    wait_for_cleanup(&server, &client).await;
+}
+
+#[tokio::test]
+async fn close_of_the_server_is_acknowledged() {
+    let backend_addr = spawn_closing_backend().await;
+    let server = common::TestServer::start(server_config());
+    let (client, local_addr) = common::start_client(&server, backend_addr, CLOSE_TIMEOUT).await;
+
+    let mut socket = common::connect_local(local_addr).await;
+    let mut received = Vec::new();
+    socket.read_to_end(&mut received).await.unwrap();
+    assert_eq!(received, b"bye");
+
+    // Both sides finished sending: the server closes the tunnel.
+    drop(socket);
+    wait_for_cleanup(&server, &client).await;
+}
+
+#[tokio::test]
+async fn unacknowledged_close_is_removed_after_the_timeout() {
+    let backend_addr = spawn_closing_backend().await;
+    let mut config = Config::new();
+    config.close_timeout = Duration::from_millis(500);
+    let server = common::TestServer::start(config);
+
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    send.write_all(&Message::new(MessageType::Initial, connection_id, init.encode()).encode())
+        .await
+        .unwrap();
+
+    let mut decoder = Decoder::new();
+    let close = timeout(Duration::from_secs(5), async {
+        loop {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            match msg.message_type {
+                // The backend finished sending, finishing as well makes the server close.
+                MessageType::ShutdownWrite => {
+                    let shutdown =
+                        Message::new(MessageType::ShutdownWrite, connection_id, Bytes::new());
+                    send.write_all(&shutdown.encode()).await.unwrap();
+                }
+                MessageType::Close => return msg,
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for the close");
+    assert_eq!(
+        CloseReason::decode(&close.payload).unwrap(),
+        CloseReason::Normal
+    );
+
+    // The close isn't acknowledged: the tunnel is kept until the timeout elapses.
+    assert_eq!(server.registry.open_tunnels(), 1);
+
+    timeout(Duration::from_secs(5), async {
+        while server.registry.open_tunnels() > 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("tunnel was not removed after the close timeout");
+}
+
+#[tokio::test]
+async fn repeated_close_is_acknowledged_without_a_second_teardown() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(server_config());
+    let mut events = server.events.subscribe();
+
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    // Both frames are sent before the server handles the first one.
+    let closes = [0, 1].map(|_| {
+        Message::new(
+            MessageType::Close,
+            connection_id,
+            CloseReason::Normal.encode(),
+        )
+    });
+    let mut frames = closes[0].encode().to_vec();
+    frames.extend_from_slice(&closes[1].encode());
+    send.write_all(&frames).await.unwrap();
+
+    for close in &closes {
+        let ack = timeout(
+            Duration::from_secs(5),
+            common::next_message(&mut recv, &mut decoder, connection_id),
+        )
+        .await
+        .expect("close was not acknowledged");
+        assert!(matches!(ack.message_type, MessageType::Ack));
+        assert_eq!(ack.in_reply_to, Some(close.message_id));
+    }
+    assert_eq!(server.registry.open_tunnels(), 0);
+
+    // The connection is still usable.
+    common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    let mut closed = 0;
+    while let Ok(event) = events.try_recv() {
+        match event {
+            ServerEvent::ConnectionClosed { id, .. } if id == connection_id => closed += 1,
+            ServerEvent::Error { err, .. } => panic!("unexpected error: {err}"),
+            _ => {}
+        }
+    }
+    assert_eq!(closed, 1);
+}
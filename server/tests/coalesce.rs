@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use message::{Decoder, Message, MessageType};
+use server::config::Config;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+mod common;
+
+/// Sends a burst of pings in a single write and checks every pong comes back in order.
+async fn answers_a_burst(flush_window: Duration) {
+    let mut config = Config::new();
+    config.flush_window = flush_window;
+    let connection = common::connect(config).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let pings = (0..500_u32)
+        .map(|i| {
+            Message::new(
+                MessageType::Ping,
+                connection_id,
+                Bytes::copy_from_slice(&i.to_be_bytes()),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let burst = pings
+        .iter()
+        .flat_map(|ping| ping.encode())
+        .collect::<Vec<_>>();
+    send.write_all(&burst).await.unwrap();
+
+    let mut decoder = Decoder::new();
+    timeout(Duration::from_secs(5), async {
+        for ping in &pings {
+            let pong = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            assert!(matches!(pong.message_type, MessageType::Pong));
+            assert_eq!(pong.in_reply_to, Some(ping.message_id));
+            assert_eq!(pong.payload, ping.payload);
+        }
+    })
+    .await
+    .expect("timed out waiting for the pongs");
+}
+
+#[tokio::test]
+async fn coalesced_frames_arrive_intact_and_in_order() {
+    answers_a_burst(Duration::from_millis(5)).await;
+}
+
+#[tokio::test]
+async fn frames_are_written_one_by_one_without_a_flush_window() {
+    answers_a_burst(Duration::ZERO).await;
+}
+
+#[tokio::test]
+async fn frame_sent_on_an_idle_stream_is_not_delayed() {
+    let mut config = Config::new();
+    config.flush_window = Duration::from_secs(2);
+    let connection = common::connect(config).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let ping = Message::new(MessageType::Ping, connection_id, Bytes::new());
+
+    let started = Instant::now();
+    send.write_all(&ping.encode()).await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let pong = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("timed out waiting for the pong");
+
+    assert_eq!(pong.in_reply_to, Some(ping.message_id));
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
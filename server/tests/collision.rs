@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, InitializationMessage, Message, MessageType};
+use server::{config::Config, registry::ConnectionState};
+use tokio::time::{sleep, timeout};
+
+mod common;
+
+#[tokio::test]
+async fn initial_reusing_an_active_connection_id_is_rejected() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+
+    let first = server.connect().await;
+    let (mut first_send, mut first_recv) = first.open_bi().await.unwrap();
+    let mut first_decoder = Decoder::new();
+    let connection_id = common::open_tunnel(
+        &mut first_send,
+        &mut first_recv,
+        &mut first_decoder,
+        backend_addr,
+    )
+    .await;
+
+    // Another client presents the same connection_id.
+    let second = server.connect().await;
+    let (mut second_send, mut second_recv) = second.open_bi().await.unwrap();
+    let mut second_decoder = Decoder::new();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    let initial = Message::new(MessageType::Initial, connection_id, init.encode());
+    second_send.write_all(&initial.encode()).await.unwrap();
+
+    let reply = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut second_recv, &mut second_decoder, connection_id),
+    )
+    .await
+    .expect("second Initial was not rejected");
+    assert!(matches!(reply.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&reply.payload).unwrap(),
+        CloseReason::ProtocolError
+    );
+
+    // The first tunnel is left untouched.
+    assert_eq!(
+        server.registry.state(&connection_id),
+        Some(ConnectionState::Active)
+    );
+    let data = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from_static(b"still mine"),
+    );
+    first_send.write_all(&data.encode()).await.unwrap();
+    let echoed = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut first_recv, &mut first_decoder, connection_id),
+    )
+    .await
+    .expect("first tunnel stopped relaying");
+    assert!(matches!(echoed.message_type, MessageType::Data));
+    assert_eq!(&echoed.payload[..], b"still mine");
+}
+
+#[tokio::test]
+async fn messages_for_the_tunnel_of_another_client_are_ignored() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+
+    let first = server.connect().await;
+    let (mut first_send, mut first_recv) = first.open_bi().await.unwrap();
+    let mut first_decoder = Decoder::new();
+    let connection_id = common::open_tunnel(
+        &mut first_send,
+        &mut first_recv,
+        &mut first_decoder,
+        backend_addr,
+    )
+    .await;
+
+    // Another client targets the tunnel by its ID.
+    let second = server.connect().await;
+    let (mut second_send, _second_recv) = second.open_bi().await.unwrap();
+    let injected = [
+        Message::new(
+            MessageType::Data,
+            connection_id,
+            Bytes::from_static(b"injected"),
+        ),
+        Message::new(MessageType::ShutdownWrite, connection_id, Bytes::new()),
+        Message::close(connection_id, CloseReason::Normal),
+    ];
+    for msg in &injected {
+        second_send.write_all(&msg.encode()).await.unwrap();
+    }
+    sleep(Duration::from_millis(200)).await;
+
+    assert_eq!(
+        server.registry.state(&connection_id),
+        Some(ConnectionState::Active)
+    );
+    let data = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from_static(b"still mine"),
+    );
+    first_send.write_all(&data.encode()).await.unwrap();
+    let echoed = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut first_recv, &mut first_decoder, connection_id),
+    )
+    .await
+    .expect("first tunnel stopped relaying");
+    // Nothing injected reached the backend.
+    assert_eq!(&echoed.payload[..], b"still mine");
+}
@@ -0,0 +1,135 @@
+// In-memory stream halves and connection, for running the stream handler without QUIC.
+
+use std::{
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use message::{Decoder, Message};
+use server::{
+    access_log::AccessLog,
+    auth::NonceCache,
+    buffer_pool::BufferPool,
+    close_code::CloseCode,
+    config::Config,
+    connection::Shared,
+    events::Events,
+    pool::BackendPool,
+    rate_limit::RateLimiter,
+    registry::Registry,
+    transport::{AsyncFrameSink, AsyncFrameSource, PeerConnection},
+};
+use tokio::{sync::mpsc, time::timeout};
+
+/// State of a stream handler serving the config, with the pools and limits disabled.
+pub fn shared(config: Config) -> Shared {
+    Shared {
+        config: Arc::new(config),
+        registry: Arc::new(Registry::new()),
+        pool: Arc::new(BackendPool::new(0, 0)),
+        buffers: Arc::new(BufferPool::new(0, 0)),
+        access_log: AccessLog::new(false),
+        events: Events::new(),
+        rate_limiter: Arc::new(RateLimiter::new(0, 0)),
+        nonces: Arc::new(NonceCache::new()),
+    }
+}
+
+/// Reads from the stream until the next message is decoded.
+pub async fn next_message(recv: &mut MemorySource, decoder: &mut Decoder) -> Message {
+    timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(msg) = decoder.next_message().unwrap() {
+                return msg;
+            }
+            let chunk = recv.recv_chunk(usize::MAX).await.unwrap();
+            decoder.extend(&chunk.expect("stream finished before the expected message"));
+        }
+    })
+    .await
+    .expect("no message received")
+}
+
+/// Returns the two halves of a one-way in-memory stream.
+pub fn stream() -> (MemorySink, MemorySource) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (
+        MemorySink { tx: Some(tx) },
+        MemorySource {
+            rx,
+            pending: Bytes::new(),
+        },
+    )
+}
+
+pub struct MemorySink {
+    /// Dropped once the stream is finished.
+    tx: Option<mpsc::UnboundedSender<Bytes>>,
+}
+
+impl AsyncFrameSink for MemorySink {
+    async fn send_chunk(&mut self, chunk: Bytes) -> io::Result<()> {
+        self.tx
+            .as_ref()
+            .and_then(|tx| tx.send(chunk).ok())
+            .ok_or_else(|| io::Error::new(ErrorKind::BrokenPipe, "Stream is closed"))
+    }
+
+    async fn finish(&mut self, _grace: Option<Duration>) {
+        self.tx = None;
+    }
+}
+
+pub struct MemorySource {
+    rx: mpsc::UnboundedReceiver<Bytes>,
+
+    /// Rest of the last chunk sent, beyond the length read.
+    pending: Bytes,
+}
+
+impl AsyncFrameSource for MemorySource {
+    async fn recv_chunk(&mut self, max_length: usize) -> io::Result<Option<Bytes>> {
+        while self.pending.is_empty() {
+            match self.rx.recv().await {
+                Some(chunk) => self.pending = chunk,
+                None => return Ok(None),
+            }
+        }
+
+        let length = max_length.min(self.pending.len());
+        Ok(Some(self.pending.split_to(length)))
+    }
+}
+
+/// Connection recording the code it was closed with.
+#[derive(Clone)]
+pub struct MemoryConnection {
+    addr: SocketAddr,
+    close_code: Arc<Mutex<Option<CloseCode>>>,
+}
+
+impl MemoryConnection {
+    pub fn new(addr: SocketAddr) -> MemoryConnection {
+        MemoryConnection {
+            addr,
+            close_code: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn close_code(&self) -> Option<CloseCode> {
+        *self.close_code.lock().unwrap()
+    }
+}
+
+impl PeerConnection for MemoryConnection {
+    fn remote_address(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn close(&self, code: CloseCode, _details: &[u8]) {
+        *self.close_code.lock().unwrap() = Some(code);
+    }
+}
@@ -0,0 +1,272 @@
+#![allow(dead_code)]
+
+pub mod memory;
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use message::{Decoder, InitializationMessage, Message, MessageType};
+use quinn::{Connection, ConnectionError, Endpoint, RecvStream, SendStream};
+use rustls::pki_types::CertificateDer;
+use server::{
+    config::Config,
+    events::Events,
+    registry::Registry,
+    reload::Reloader,
+    server::{Identity, configure_server, make_server_endpoint, transport_config},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+use uuid::Uuid;
+
+/// Accepts backend connections and writes back everything they receive.
+pub async fn spawn_echo_backend() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = socket.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                let _ = writer.shutdown().await;
+            });
+        }
+    });
+
+    addr
+}
+
+/// Running server the tests connect to.
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub cert: CertificateDer<'static>,
+    pub registry: Arc<Registry>,
+    pub events: Events,
+}
+
+impl TestServer {
+    /// Serves the config, bound to an ephemeral loopback port.
+    pub fn start(config: Config) -> TestServer {
+        TestServer::start_reloadable(config).0
+    }
+
+    /// Serves the config like [`TestServer::start`], along with the handle reloading it.
+    pub fn start_reloadable(mut config: Config) -> (TestServer, Reloader) {
+        config.host = "127.0.0.1:0".parse().unwrap();
+
+        let (endpoint, cert) = make_server_endpoint(&config).unwrap();
+        let addr = endpoint.local_addr().unwrap();
+        let registry = Arc::new(Registry::new());
+        let events = Events::new();
+        let reloader = Reloader::new(Arc::new(config));
+        tokio::spawn(server::serve(
+            endpoint,
+            reloader.clone(),
+            registry.clone(),
+            events.clone(),
+        ));
+
+        let server = TestServer {
+            addr,
+            cert,
+            registry,
+            events,
+        };
+        (server, reloader)
+    }
+
+    /// Opens a new QUIC connection to the server.
+    pub async fn connect(&self) -> Connection {
+        self.try_connect().await.unwrap()
+    }
+
+    /// Opens a new QUIC connection to the server, returning the error if it is refused.
+    pub async fn try_connect(&self) -> Result<Connection, ConnectionError> {
+        let mut client = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.set_default_client_config(
+            client::tls::configure_client(std::slice::from_ref(&self.cert), None).unwrap(),
+        );
+        client.connect(self.addr, "localhost").unwrap().await
+    }
+}
+
+/// Server side of a bare QUIC connection, to an endpoint configured like the server but not
+/// serving it.
+pub struct RawPeer {
+    client: Connection,
+    accepted: Option<JoinHandle<(Connection, RecvStream)>>,
+    server: Option<Connection>,
+}
+
+impl RawPeer {
+    /// Accepts the stream; it is only announced once written to.
+    pub async fn accept(&mut self) -> RecvStream {
+        let (connection, recv) = self.accepted.take().unwrap().await.unwrap();
+        self.server = Some(connection);
+        recv
+    }
+}
+
+/// Opens a stream to a bare endpoint using the transport of `config`.
+pub async fn raw_stream(config: &Config) -> (SendStream, RawPeer) {
+    let identity = Identity::generate();
+    let server_config = configure_server(
+        None,
+        &identity,
+        &config.alpn,
+        transport_config(config),
+        false,
+    )
+    .unwrap();
+    let server = Endpoint::server(server_config, "127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let accepted = tokio::spawn(async move {
+        let connection = server.accept().await.unwrap().await.unwrap();
+        let (_send, recv) = connection.accept_bi().await.unwrap();
+        (connection, recv)
+    });
+
+    let mut client = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    client.set_default_client_config(
+        client::tls::configure_client(std::slice::from_ref(&identity.cert), None).unwrap(),
+    );
+    let connection = client.connect(addr, "localhost").unwrap().await.unwrap();
+    let (send, _recv) = connection.open_bi().await.unwrap();
+
+    let peer = RawPeer {
+        client: connection,
+        accepted: Some(accepted),
+        server: None,
+    };
+    (send, peer)
+}
+
+/// Serves the config and returns a QUIC connection to it.
+pub async fn connect(config: Config) -> Connection {
+    TestServer::start(config).connect().await
+}
+
+/// Reads from the stream until the next message belonging to the tunnel is decoded.
+pub async fn next_message(
+    recv: &mut RecvStream,
+    decoder: &mut Decoder,
+    connection_id: Uuid,
+) -> Message {
+    loop {
+        while let Some(msg) = decoder.next_message().unwrap() {
+            if msg.connection_id == connection_id {
+                return msg;
+            }
+        }
+
+        let chunk = recv
+            .read_chunk(usize::MAX, true)
+            .await
+            .unwrap()
+            .expect("stream finished before the expected message");
+        decoder.extend(&chunk.bytes);
+    }
+}
+
+/// Sends an `Initial` with the payload and returns the `connection_id` of the tunnel along with
+/// the reply of the server.
+pub async fn request_tunnel(
+    send: &mut SendStream,
+    recv: &mut RecvStream,
+    decoder: &mut Decoder,
+    init: &InitializationMessage,
+) -> (Uuid, Message) {
+    let connection_id = Uuid::new_v4();
+    send.write_all(&Message::initial(connection_id, init).encode())
+        .await
+        .unwrap();
+
+    let reply = timeout(
+        Duration::from_secs(5),
+        next_message(recv, decoder, connection_id),
+    )
+    .await
+    .expect("Initial was not replied to");
+    (connection_id, reply)
+}
+
+/// Opens a tunnel to the backend and waits for the server to acknowledge its `Initial`; returns
+/// the `connection_id` of the tunnel.
+pub async fn open_tunnel(
+    send: &mut SendStream,
+    recv: &mut RecvStream,
+    decoder: &mut Decoder,
+    backend_addr: SocketAddr,
+) -> Uuid {
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    let initial = Message::new(MessageType::Initial, connection_id, init.encode());
+    send.write_all(&initial.encode()).await.unwrap();
+
+    let reply = timeout(
+        Duration::from_secs(5),
+        next_message(recv, decoder, connection_id),
+    )
+    .await
+    .expect("Initial was not acknowledged");
+    assert!(matches!(reply.message_type, MessageType::Ack));
+    assert_eq!(reply.in_reply_to, Some(initial.message_id));
+
+    connection_id
+}
+
+/// Config of a client trusting the server and tunneling to `backend_addr`.
+pub fn client_config(server: &TestServer, backend_addr: SocketAddr) -> client::ClientConfig {
+    let mut config = client::ClientConfig::new(server.addr, backend_addr);
+    config.bind_addr = "127.0.0.1:0".parse().unwrap();
+    config.server_certs = vec![server.cert.clone()];
+    config
+}
+
+/// Starts a client tunneling to `backend_addr` and returns it with its local address.
+pub async fn start_client(
+    server: &TestServer,
+    backend_addr: SocketAddr,
+    close_timeout: Duration,
+) -> (Arc<client::Client>, SocketAddr) {
+    let mut config = client_config(server, backend_addr);
+    config.close_timeout = close_timeout;
+    spawn_client(config).await
+}
+
+/// Starts a client with the config and returns it with its local address.
+pub async fn spawn_client(config: client::ClientConfig) -> (Arc<client::Client>, SocketAddr) {
+    let client = Arc::new(client::Client::new(config).unwrap());
+
+    let local_addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+    let listener = client.clone();
+    tokio::spawn(async move { listener.listen_local(local_addr).await });
+
+    (client, local_addr)
+}
+
+/// Connects to the local listener of a client, waiting for it to listen.
+pub async fn connect_local(local_addr: SocketAddr) -> TcpStream {
+    timeout(Duration::from_secs(5), async {
+        loop {
+            match TcpStream::connect(local_addr).await {
+                Ok(socket) => return socket,
+                Err(_) => sleep(Duration::from_millis(10)).await,
+            }
+        }
+    })
+    .await
+    .expect("client is not listening")
+}
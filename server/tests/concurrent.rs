@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{Decoder, Message, MessageType};
+use server::config::Config;
+use tokio::time::timeout;
+
+mod common;
+
+/// Far more than the stream and queue buffers hold, so that neither side gets ahead for long.
+const TOTAL: usize = 4 * 1024 * 1024;
+
+#[tokio::test]
+async fn sending_while_receiving_does_not_deadlock() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    let sender = tokio::spawn(async move {
+        let chunk = Bytes::from(vec![0x42; 16 * 1024]);
+        for sequence in 0..(TOTAL / chunk.len()) as u64 {
+            let data = Message::new(MessageType::Data, connection_id, chunk.clone())
+                .with_sequence(sequence);
+            send.write_all(&data.encode()).await.unwrap();
+        }
+        send
+    });
+
+    let mut received = 0;
+    timeout(Duration::from_secs(20), async {
+        while received < TOTAL {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            if matches!(msg.message_type, MessageType::Data) {
+                received += msg.payload.len();
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("stalled after {received} of {TOTAL} bytes"));
+    assert_eq!(received, TOTAL);
+
+    timeout(Duration::from_secs(5), sender)
+        .await
+        .expect("sender stalled")
+        .unwrap();
+}
@@ -0,0 +1,69 @@
+use std::{sync::Arc, time::Duration};
+
+use server::{
+    config::{Config, CongestionController},
+    server::transport_config,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    time::timeout,
+};
+
+mod common;
+
+const CONTROLLERS: [CongestionController; 3] = [
+    CongestionController::Cubic,
+    CongestionController::NewReno,
+    CongestionController::Bbr,
+];
+
+#[test]
+fn controllers_are_parsed_by_name() {
+    for controller in CONTROLLERS {
+        assert_eq!(
+            controller.as_str().parse::<CongestionController>().unwrap(),
+            controller
+        );
+        let mut config = Config::new();
+        config.congestion_controller = controller;
+        transport_config(&config);
+    }
+
+    let err = "reno".parse::<CongestionController>().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn tunnels_work_with_every_controller() {
+    let backend_addr = common::spawn_echo_backend().await;
+
+    for controller in CONTROLLERS {
+        let mut config = Config::new();
+        config.congestion_controller = controller;
+        let server = common::TestServer::start(config);
+
+        let mut config = common::client_config(&server, backend_addr);
+        config.congestion_controller = controller.as_str().parse().unwrap();
+        let client = Arc::new(client::Client::new(config).unwrap());
+
+        let local_addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let runner = client.clone();
+        tokio::spawn(async move { runner.run().await });
+        let listener = client.clone();
+        tokio::spawn(async move { listener.listen_local(local_addr).await });
+
+        let mut socket = common::connect_local(local_addr).await;
+        socket.write_all(b"congested").await.unwrap();
+        let mut echoed = [0; 9];
+        timeout(Duration::from_secs(5), socket.read_exact(&mut echoed))
+            .await
+            .unwrap_or_else(|_| panic!("no echo with {}", controller.as_str()))
+            .unwrap();
+        assert_eq!(&echoed, b"congested");
+    }
+}
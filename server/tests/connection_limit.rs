@@ -0,0 +1,64 @@
+use std::{sync::Arc, time::Duration};
+
+use quinn::{Connection, ConnectionError, VarInt};
+use server::{close_code::CloseCode, config::Config};
+use tokio::time::{sleep, timeout};
+
+mod common;
+
+/// Asserts the server closed the connection with [`CloseCode::ServerFull`], during the
+/// handshake or right after it.
+async fn assert_server_full(result: Result<Connection, ConnectionError>) {
+    let err = match result {
+        Ok(connection) => timeout(Duration::from_secs(5), connection.closed())
+            .await
+            .expect("connection was not closed"),
+        Err(err) => err,
+    };
+
+    match err {
+        ConnectionError::ApplicationClosed(close) => {
+            assert_eq!(close.error_code, VarInt::from(CloseCode::ServerFull))
+        }
+        err => panic!("expected the server to be full, got {err:?}"),
+    }
+}
+
+#[tokio::test]
+async fn queued_connection_proceeds_once_a_slot_frees() {
+    let mut config = Config::new();
+    config.max_connections = 1;
+    config.connection_queue = 1;
+    let server = Arc::new(common::TestServer::start(config));
+
+    let first = server.connect().await;
+
+    let queued = {
+        let server = server.clone();
+        tokio::spawn(async move { server.try_connect().await })
+    };
+    // Gives the queued connection time to reach the server before the next one.
+    sleep(Duration::from_millis(200)).await;
+    assert!(!queued.is_finished(), "connection was not queued");
+
+    assert_server_full(server.try_connect().await).await;
+
+    first.close(VarInt::from_u32(0), b"done");
+    let queued = timeout(Duration::from_secs(5), queued)
+        .await
+        .expect("queued connection did not proceed")
+        .unwrap();
+    assert!(queued.is_ok(), "{queued:?}");
+}
+
+#[tokio::test]
+async fn connections_are_refused_beyond_the_limit_without_a_queue() {
+    let mut config = Config::new();
+    config.max_connections = 2;
+    config.connection_queue = 0;
+    let server = common::TestServer::start(config);
+
+    let _first = server.connect().await;
+    let _second = server.connect().await;
+    assert_server_full(server.try_connect().await).await;
+}
@@ -0,0 +1,104 @@
+use std::{fs, time::Duration};
+
+use bytes::Bytes;
+use message::{Decoder, Message, MessageType};
+use quinn::{Connection, ConnectionError, VarInt};
+use server::{close_code::CloseCode, config::Config, logging};
+use tokio::time::timeout;
+use uuid::Uuid;
+
+mod common;
+
+/// Writes `bytes` on a new stream of a new connection, finishing it when `finish` is set, and
+/// waits for the server to close the connection with `code`.
+async fn send_invalid(server: &common::TestServer, bytes: &[u8], finish: bool, code: CloseCode) {
+    let connection = server.connect().await;
+    let (mut send, _recv) = connection.open_bi().await.unwrap();
+    send.write_all(bytes).await.unwrap();
+    if finish {
+        send.finish().unwrap();
+    }
+
+    let err = timeout(Duration::from_secs(5), connection.closed())
+        .await
+        .expect("connection was not closed");
+    match err {
+        ConnectionError::ApplicationClosed(close) => {
+            assert_eq!(close.error_code, VarInt::from(code))
+        }
+        err => panic!("unexpected connection error: {err:?}"),
+    }
+}
+
+async fn assert_pong(connection: &Connection) {
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let ping = Message::new(MessageType::Ping, Uuid::nil(), Bytes::new());
+    send.write_all(&ping.encode()).await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let pong = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, Uuid::nil()),
+    )
+    .await
+    .expect("no pong received");
+    assert!(matches!(pong.message_type, MessageType::Pong));
+}
+
+#[tokio::test]
+async fn invalid_frames_only_drop_their_connection() {
+    let dir = std::env::temp_dir().join(format!("reverprox-log-{}", Uuid::new_v4()));
+    let path = dir.join("server.log");
+    let mut config = Config::new();
+    config.log_file = Some(path.clone());
+    logging::init(&config).unwrap();
+
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+    let bystander = server.connect().await;
+
+    let frame = Message::new(MessageType::Ping, Uuid::new_v4(), Bytes::new())
+        .encode()
+        .to_vec();
+    let mut bad_magic = frame.clone();
+    bad_magic[0] = 0x00;
+    let mut unknown_type = frame.clone();
+    unknown_type[2] = 0xEE;
+
+    send_invalid(&server, &[0x42], false, CloseCode::InvalidFrame).await;
+    send_invalid(&server, &bad_magic, false, CloseCode::InvalidFrame).await;
+    send_invalid(&server, &unknown_type, false, CloseCode::InvalidFrame).await;
+    send_invalid(&server, &frame[..10], true, CloseCode::TruncatedFrame).await;
+
+    spdlog::default_logger().flush();
+    let logs = fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        logs.matches("failed to decode message").count(),
+        3,
+        "{logs}"
+    );
+    assert_eq!(logs.matches("stream finished within a frame").count(), 1);
+
+    // Neither the connections opened before nor the ones opened after are affected.
+    assert_pong(&bystander).await;
+
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+    let data = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from_static(b"hello"),
+    );
+    send.write_all(&data.encode()).await.unwrap();
+    let echo = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("no echo received");
+    assert_eq!(&echo.payload[..], b"hello");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{Decoder, Message, MessageType};
+use server::config::Config;
+use tokio::time::timeout;
+
+mod common;
+
+#[tokio::test]
+async fn retransmitted_data_is_written_once_and_acknowledged() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    let data = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from_static(b"hello"),
+    )
+    .with_sequence(0);
+    let last =
+        Message::new(MessageType::Data, connection_id, Bytes::from_static(b"!")).with_sequence(1);
+    for msg in [&data, &data, &last] {
+        send.write_all(&msg.encode()).await.unwrap();
+    }
+
+    // The echo shows what the backend received.
+    let mut echoed = Vec::new();
+    let mut acked = false;
+    timeout(Duration::from_secs(5), async {
+        while !echoed.ends_with(b"!") || !acked {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            match msg.message_type {
+                MessageType::Data => echoed.extend_from_slice(&msg.payload),
+                MessageType::Ack => {
+                    assert_eq!(msg.in_reply_to, Some(data.message_id));
+                    assert!(!acked, "acknowledged more than once");
+                    acked = true;
+                }
+                _ => panic!("unexpected {msg:?}"),
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for the echo and the ack");
+    assert_eq!(echoed, b"hello!");
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{CHUNK_SIZE, CloseReason, Decoder, InitializationMessage, Message, MessageType};
+use server::config::Config;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+mod common;
+
+#[tokio::test]
+async fn delivers_pending_data_before_close_when_the_stream_finishes() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    send.write_all(&Message::new(MessageType::Initial, connection_id, init.encode()).encode())
+        .await
+        .unwrap();
+
+    let payload: Vec<u8> = (0..64 * 1024).map(|i| i as u8).collect();
+    for (sequence, chunk) in payload.chunks(CHUNK_SIZE).enumerate() {
+        let msg = Message::new(
+            MessageType::Data,
+            connection_id,
+            Bytes::copy_from_slice(chunk),
+        )
+        .with_sequence(sequence as u64);
+        send.write_all(&msg.encode()).await.unwrap();
+    }
+
+    // Finishing the stream right away makes the server drain the tunnel.
+    send.finish().unwrap();
+
+    let mut decoder = Decoder::new();
+    let mut echoed = Vec::new();
+    let close = timeout(Duration::from_secs(5), async {
+        loop {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            match msg.message_type {
+                MessageType::Data => echoed.extend_from_slice(&msg.payload),
+                MessageType::Close => return msg,
+                // Reply to the Initial, carrying the negotiated version.
+                MessageType::Ack => {}
+                other => panic!("unexpected message: {other:?}"),
+            }
+        }
+    })
+    .await
+    .expect("tunnel was not closed");
+
+    assert_eq!(echoed, payload);
+    assert_eq!(
+        CloseReason::decode(&close.payload).unwrap(),
+        CloseReason::Normal
+    );
+}
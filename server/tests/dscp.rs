@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use server::{backend, config::Config, server::bind_socket};
+use socket2::SockRef;
+use tokio::net::TcpListener;
+
+/// Expedited Forwarding.
+const EF: u8 = 46;
+
+#[test]
+fn quic_socket_is_marked() {
+    let socket = bind_socket("127.0.0.1:0".parse().unwrap(), false, Some(EF), None, None).unwrap();
+    assert_eq!(SockRef::from(&socket).tos_v4().unwrap(), u32::from(EF) << 2);
+
+    let socket = bind_socket("127.0.0.1:0".parse().unwrap(), false, None, None, None).unwrap();
+    assert_eq!(SockRef::from(&socket).tos_v4().unwrap(), 0);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn ipv6_quic_socket_is_marked() {
+    let Ok(socket) = bind_socket("[::1]:0".parse().unwrap(), true, Some(EF), None, None) else {
+        // IPv6 is not available.
+        return;
+    };
+    assert_eq!(
+        SockRef::from(&socket).tclass_v6().unwrap(),
+        u32::from(EF) << 2
+    );
+}
+
+#[tokio::test]
+async fn backend_connections_are_marked() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let stream = backend::connect(&[addr], Duration::from_secs(5), Some(EF))
+        .await
+        .unwrap();
+    assert_eq!(SockRef::from(&stream).tos_v4().unwrap(), u32::from(EF) << 2);
+}
+
+#[test]
+fn dscp_out_of_range_is_rejected() {
+    let mut config = Config::new();
+    config.dscp = Some(63);
+    config.validate().unwrap();
+
+    config.dscp = Some(64);
+    let e = config.validate().unwrap_err();
+    assert!(e.to_string().contains("dscp 64"), "{e}");
+    assert!(bind_socket("127.0.0.1:0".parse().unwrap(), false, Some(64), None, None).is_err());
+}
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{Decoder, Message, MessageType};
+use server::config::Config;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    time::timeout,
+};
+
+mod common;
+
+#[tokio::test]
+async fn proxies_data_to_the_backend_and_back() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+    let payload = Bytes::from_static(b"hello through the tunnel");
+
+    send.write_all(
+        &Message::new(MessageType::Data, connection_id, payload.clone())
+            .with_sequence(0)
+            .encode(),
+    )
+    .await
+    .unwrap();
+
+    let mut echoed = Vec::new();
+    timeout(Duration::from_secs(5), async {
+        while echoed.len() < payload.len() {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            assert!(matches!(msg.message_type, MessageType::Data));
+            echoed.extend_from_slice(&msg.payload);
+        }
+    })
+    .await
+    .expect("timed out waiting for the echoed payload");
+
+    assert_eq!(echoed, payload);
+}
+
+#[tokio::test]
+async fn client_tunnels_its_local_connections_to_the_backend() {
+    // Answers each request with a response of its own, rather than echoing it.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut request = [0; 4];
+                socket.read_exact(&mut request).await.unwrap();
+                assert_eq!(&request, b"ping");
+                socket.write_all(b"pong").await.unwrap();
+            });
+        }
+    });
+    let server = common::TestServer::start(Config::new());
+    let (_client, local_addr) =
+        common::spawn_client(common::client_config(&server, backend_addr)).await;
+
+    for _ in 0..2 {
+        let mut socket = common::connect_local(local_addr).await;
+        socket.write_all(b"ping").await.unwrap();
+
+        let mut response = [0; 4];
+        timeout(Duration::from_secs(5), socket.read_exact(&mut response))
+            .await
+            .expect("timed out waiting for the response")
+            .unwrap();
+        assert_eq!(&response, b"pong");
+    }
+}
+
+#[tokio::test]
+async fn responses_reply_to_the_request_they_answer() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    for (sequence, payload) in [&b"first"[..], b"second"].into_iter().enumerate() {
+        let request = Message::new(MessageType::Data, connection_id, Bytes::from(payload))
+            .with_sequence(sequence as u64);
+        send.write_all(&request.encode()).await.unwrap();
+
+        let mut echoed = Vec::new();
+        timeout(Duration::from_secs(5), async {
+            while echoed.len() < payload.len() {
+                let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+                if !msg.message_type.is_data() {
+                    continue;
+                }
+                assert_eq!(msg.in_reply_to, Some(request.message_id));
+                echoed.extend_from_slice(&msg.payload);
+            }
+        })
+        .await
+        .expect("timed out waiting for the response");
+        assert_eq!(echoed, payload);
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{Decoder, Message, MessageType};
+use server::config::Config;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+mod common;
+
+#[tokio::test]
+async fn finished_stream_ends_the_handler() {
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let ping = Message::new(MessageType::Ping, Uuid::nil(), Bytes::new());
+    send.write_all(&ping.encode()).await.unwrap();
+    send.finish().unwrap();
+
+    // The server finishes its side once it read the end of the client's, rather than spinning
+    // on it.
+    let replies = timeout(Duration::from_secs(5), recv.read_to_end(usize::MAX))
+        .await
+        .expect("server did not finish the stream")
+        .unwrap();
+    let mut decoder = Decoder::new();
+    decoder.extend(&replies);
+    let pong = decoder
+        .next_message()
+        .unwrap()
+        .expect("ping was not answered");
+    assert!(matches!(pong.message_type, MessageType::Pong));
+    assert_eq!(pong.in_reply_to, Some(ping.message_id));
+    assert!(decoder.next_message().unwrap().is_none());
+}
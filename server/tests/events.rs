@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use message::{CloseReason, InitializationMessage, Message, MessageType};
+use server::{config::Config, events::ServerEvent};
+use tokio::{net::TcpListener, sync::broadcast, time::timeout};
+use uuid::Uuid;
+
+mod common;
+
+async fn next_event(rx: &mut broadcast::Receiver<ServerEvent>) -> ServerEvent {
+    timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("timed out waiting for an event")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn opening_and_closing_a_tunnel_emits_events() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+    let mut events = server.events.subscribe();
+
+    let connection = server.connect().await;
+    let (mut send, _recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    send.write_all(&Message::new(MessageType::Initial, connection_id, init.encode()).encode())
+        .await
+        .unwrap();
+    assert_eq!(
+        next_event(&mut events).await,
+        ServerEvent::ConnectionOpened { id: connection_id }
+    );
+
+    let close = Message::new(
+        MessageType::Close,
+        connection_id,
+        CloseReason::Normal.encode(),
+    );
+    send.write_all(&close.encode()).await.unwrap();
+    assert_eq!(
+        next_event(&mut events).await,
+        ServerEvent::ConnectionClosed {
+            id: connection_id,
+            reason: CloseReason::Normal,
+        }
+    );
+}
+
+#[tokio::test]
+async fn refused_backend_emits_an_error() {
+    let backend_addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server = common::TestServer::start(Config::new());
+    let mut events = server.events.subscribe();
+
+    let connection = server.connect().await;
+    let (mut send, _recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    send.write_all(&Message::new(MessageType::Initial, connection_id, init.encode()).encode())
+        .await
+        .unwrap();
+
+    match next_event(&mut events).await {
+        ServerEvent::Error { id, .. } => assert_eq!(id, connection_id),
+        event => panic!("expected an error, got {event:?}"),
+    }
+    assert_eq!(
+        next_event(&mut events).await,
+        ServerEvent::ConnectionClosed {
+            id: connection_id,
+            reason: CloseReason::BackendRefused,
+        }
+    );
+}
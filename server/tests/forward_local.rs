@@ -0,0 +1,50 @@
+use std::{sync::Arc, time::Duration};
+
+use server::config::Config;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    time::{sleep, timeout},
+};
+
+mod common;
+
+#[tokio::test]
+async fn forwards_a_local_port_to_the_backend() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+    let client =
+        Arc::new(client::Client::new(common::client_config(&server, backend_addr)).unwrap());
+
+    let local_port = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+    let forwarder = client.clone();
+    tokio::spawn(async move { forwarder.forward_local(local_port).await });
+
+    let mut socket = common::connect_local(([127, 0, 0, 1], local_port).into()).await;
+    socket.write_all(b"ping over the tunnel").await.unwrap();
+
+    let mut echoed = [0; 20];
+    timeout(Duration::from_secs(5), socket.read_exact(&mut echoed))
+        .await
+        .expect("timed out waiting for the echo")
+        .unwrap();
+    assert_eq!(&echoed, b"ping over the tunnel");
+
+    // Closing the local connection closes its tunnel.
+    drop(socket);
+    timeout(Duration::from_secs(5), async {
+        while client.open_tunnels() > 0 || server.registry.open_tunnels() > 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("tunnel was not closed");
+}
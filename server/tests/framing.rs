@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use message::{Decoder, InitializationMessage, Message, MessageType};
+use server::config::Config;
+use tokio::time::{sleep, timeout};
+use uuid::Uuid;
+
+mod common;
+
+#[tokio::test]
+async fn frame_read_in_pieces_is_decoded_once_complete() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    let initial = Message::new(MessageType::Initial, connection_id, init.encode());
+    let frame = initial.encode();
+
+    // Pieces shorter than the header are accumulated until the frame is complete.
+    let (head, last) = frame.split_at(frame.len() - 1);
+    for piece in head.chunks(10) {
+        send.write_all(piece).await.unwrap();
+        sleep(Duration::from_millis(5)).await;
+    }
+
+    let early = timeout(
+        Duration::from_millis(200),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await;
+    assert!(early.is_err(), "replied before the frame was complete");
+    assert!(connection.close_reason().is_none());
+
+    send.write_all(last).await.unwrap();
+    let reply = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("Initial was not decoded");
+    assert!(matches!(reply.message_type, MessageType::Ack));
+    assert_eq!(reply.in_reply_to, Some(initial.message_id));
+}
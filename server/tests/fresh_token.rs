@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use client::token;
+use message::InitializationMessage;
+use server::{
+    auth::{self, NonceCache},
+    config::Config,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::timeout,
+};
+
+mod common;
+
+const SECRET: &[u8] = b"secret";
+const WINDOW: Duration = Duration::from_secs(30);
+const NOW: u64 = 1_700_000_000_000_000;
+
+#[test]
+fn fresh_token_is_accepted() {
+    let nonces = NonceCache::new();
+    let token = token::sign_at(SECRET, NOW - 1_000_000, [1; 16]);
+
+    assert!(auth::verify_fresh(SECRET, &token, NOW, WINDOW, &nonces));
+    assert_eq!(nonces.len(), 1);
+}
+
+#[test]
+fn expired_token_is_rejected() {
+    let nonces = NonceCache::new();
+    let expired = token::sign_at(SECRET, NOW - 31_000_000, [1; 16]);
+    let early = token::sign_at(SECRET, NOW + 31_000_000, [2; 16]);
+
+    assert!(!auth::verify_fresh(SECRET, &expired, NOW, WINDOW, &nonces));
+    assert!(!auth::verify_fresh(SECRET, &early, NOW, WINDOW, &nonces));
+    assert!(nonces.is_empty());
+}
+
+#[test]
+fn replayed_nonce_is_rejected() {
+    let nonces = NonceCache::new();
+    let token = token::sign_at(SECRET, NOW, [1; 16]);
+
+    assert!(auth::verify_fresh(SECRET, &token, NOW, WINDOW, &nonces));
+    assert!(!auth::verify_fresh(
+        SECRET,
+        &token,
+        NOW + 1_000_000,
+        WINDOW,
+        &nonces
+    ));
+
+    // Nonces are forgotten once their token expired, and couldn't be replayed anyway.
+    let later = NOW + 31_000_000;
+    assert!(!auth::verify_fresh(SECRET, &token, later, WINDOW, &nonces));
+    assert!(auth::verify_fresh(
+        SECRET,
+        &token::sign_at(SECRET, later, [2; 16]),
+        later,
+        WINDOW,
+        &nonces
+    ));
+    assert_eq!(nonces.len(), 1);
+}
+
+#[test]
+fn token_signed_with_another_secret_is_rejected() {
+    let nonces = NonceCache::new();
+    let token = token::sign_at(b"other", NOW, [1; 16]);
+
+    assert!(!auth::verify_fresh(SECRET, &token, NOW, WINDOW, &nonces));
+    assert!(!auth::verify_fresh(SECRET, SECRET, NOW, WINDOW, &nonces));
+}
+
+#[test]
+fn static_token_is_rejected_when_fresh_ones_are_required() {
+    let nonces = NonceCache::new();
+    let init = InitializationMessage::new(
+        "127.0.0.1:4000".parse().unwrap(),
+        "127.0.0.1:3000".parse().unwrap(),
+    )
+    .unwrap()
+    .with_token(Bytes::from_static(SECRET))
+    .unwrap();
+
+    assert!(!auth::authenticate_fresh(
+        Some(SECRET),
+        &init,
+        WINDOW,
+        &nonces
+    ));
+}
+
+#[tokio::test]
+async fn client_signing_its_token_is_tunneled() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let mut config = Config::new();
+    config.auth_token = Some(Bytes::from_static(SECRET));
+    config.auth_token_window = Some(WINDOW);
+    let server = common::TestServer::start(config);
+
+    let mut client_config = common::client_config(&server, backend_addr);
+    client_config.token = Some(Bytes::from_static(SECRET));
+    client_config.sign_token = true;
+    let (_client, local_addr) = common::spawn_client(client_config).await;
+
+    // Each tunnel presents a token of its own.
+    for _ in 0..2 {
+        let mut socket = common::connect_local(local_addr).await;
+        socket.write_all(b"hello").await.unwrap();
+
+        let mut echoed = [0; 5];
+        timeout(Duration::from_secs(5), socket.read_exact(&mut echoed))
+            .await
+            .expect("timed out waiting for the echo")
+            .unwrap();
+        assert_eq!(&echoed, b"hello");
+    }
+}
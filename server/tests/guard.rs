@@ -0,0 +1,66 @@
+use std::{io, sync::Arc};
+
+use message::ProtocolVersion;
+use server::registry::{ConnectionGuard, ConnectionState, Registry};
+use uuid::Uuid;
+
+/// Registers a tunnel awaiting its backend.
+fn open_tunnel(registry: &Registry) -> Uuid {
+    let connection_id = Uuid::new_v4();
+    registry
+        .insert(connection_id, ProtocolVersion::V1, Uuid::new_v4())
+        .unwrap();
+    connection_id
+}
+
+#[tokio::test]
+async fn tunnel_is_closed_when_its_task_returns_early() {
+    let registry = Arc::new(Registry::new());
+    let connection_id = open_tunnel(&registry);
+
+    let guard = ConnectionGuard::new(registry.clone(), connection_id);
+    let task = tokio::spawn(async move {
+        let _guard = guard;
+        Err::<(), _>(io::Error::other("backend failed"))?;
+        Ok::<_, io::Error>(())
+    });
+
+    assert!(task.await.unwrap().is_err());
+    assert_eq!(
+        registry.state(&connection_id),
+        Some(ConnectionState::Closed)
+    );
+    assert_eq!(registry.open_tunnels(), 0);
+}
+
+#[tokio::test]
+async fn tunnel_is_closed_when_its_task_panics() {
+    let registry = Arc::new(Registry::new());
+    let connection_id = open_tunnel(&registry);
+
+    let guard = ConnectionGuard::new(registry.clone(), connection_id);
+    let task = tokio::spawn(async move {
+        let _guard = guard;
+        panic!("handler bug");
+    });
+
+    assert!(task.await.unwrap_err().is_panic());
+    assert_eq!(
+        registry.state(&connection_id),
+        Some(ConnectionState::Closed)
+    );
+    assert_eq!(registry.open_tunnels(), 0);
+}
+
+#[test]
+fn guard_of_a_closed_tunnel_is_released() {
+    let registry = Arc::new(Registry::new());
+    let connection_id = open_tunnel(&registry);
+    let guard = ConnectionGuard::new(registry.clone(), connection_id);
+    assert!(!guard.is_released());
+
+    registry
+        .transition(&connection_id, ConnectionState::Closed)
+        .unwrap();
+    assert!(guard.is_released());
+}
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{Decoder, Message, MessageType};
+use server::{
+    config::Config,
+    registry::{ConnectionState, Directions},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::oneshot,
+    time::timeout,
+};
+
+mod common;
+
+#[tokio::test]
+async fn backend_answers_once_the_client_finished_sending() {
+    // Answers with the length of the request once it is complete, like a one-shot RPC.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        socket.read_to_end(&mut request).await.unwrap();
+        let response = format!("received {} bytes", request.len());
+        socket.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    let server = common::TestServer::start(Config::new());
+    let (_client, local_addr) =
+        common::start_client(&server, backend_addr, Duration::from_secs(5)).await;
+
+    let mut socket = common::connect_local(local_addr).await;
+    socket.write_all(b"hello").await.unwrap();
+    socket.shutdown().await.unwrap();
+
+    let mut response = String::new();
+    timeout(Duration::from_secs(5), socket.read_to_string(&mut response))
+        .await
+        .expect("timed out waiting for the response")
+        .unwrap();
+    assert_eq!(response, "received 5 bytes");
+}
+
+#[tokio::test]
+async fn client_keeps_sending_once_the_backend_finished() {
+    // Finishes sending right away and collects what the client sends afterwards.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = listener.local_addr().unwrap();
+    let (received_tx, received_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        socket.write_all(b"greetings").await.unwrap();
+        socket.shutdown().await.unwrap();
+
+        let mut received = Vec::new();
+        socket.read_to_end(&mut received).await.unwrap();
+        let _ = received_tx.send(received);
+    });
+
+    let server = common::TestServer::start(Config::new());
+    let (client, local_addr) =
+        common::start_client(&server, backend_addr, Duration::from_secs(5)).await;
+
+    let mut socket = common::connect_local(local_addr).await;
+    let mut greeting = Vec::new();
+    timeout(Duration::from_secs(5), socket.read_to_end(&mut greeting))
+        .await
+        .expect("backend shutdown was not propagated")
+        .unwrap();
+    assert_eq!(greeting, b"greetings");
+
+    socket.write_all(b"still here").await.unwrap();
+    socket.shutdown().await.unwrap();
+
+    let received = timeout(Duration::from_secs(5), received_rx)
+        .await
+        .expect("timed out waiting for the backend")
+        .unwrap();
+    assert_eq!(received, b"still here");
+
+    // Both sides finished sending: the tunnel is closed.
+    timeout(Duration::from_secs(5), async {
+        while server.registry.open_tunnels() > 0 || client.open_tunnels() > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("tunnel was not closed");
+}
+
+#[tokio::test]
+async fn write_only_close_keeps_the_reverse_direction_open() {
+    // Answers once the request is complete, then keeps the connection open.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = listener.local_addr().unwrap();
+    let (done_tx, done_rx) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        socket.read_to_end(&mut request).await.unwrap();
+        socket.write_all(&request).await.unwrap();
+        let _ = done_rx.await;
+    });
+
+    let server = common::TestServer::start(Config::new());
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    let data = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from_static(b"request"),
+    )
+    .with_sequence(0);
+    send.write_all(&data.encode()).await.unwrap();
+    let shutdown = Message::new(MessageType::ShutdownWrite, connection_id, Bytes::new());
+    send.write_all(&shutdown.encode()).await.unwrap();
+
+    let response = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("no response from the backend");
+    assert!(matches!(response.message_type, MessageType::Data));
+    assert_eq!(response.payload, "request");
+
+    assert_eq!(
+        server.registry.directions(&connection_id),
+        Some(Directions {
+            to_backend: false,
+            to_client: true,
+        })
+    );
+    assert_eq!(
+        server.registry.state(&connection_id),
+        Some(ConnectionState::Active)
+    );
+    let _ = done_tx.send(());
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, Message, MessageType};
+use quinn::{ConnectionError, VarInt};
+use server::{close_code::CloseCode, config::Config};
+use tokio::time::{sleep, timeout};
+use uuid::Uuid;
+
+mod common;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(300);
+
+fn server_config() -> Config {
+    let mut config = Config::new();
+    config.handshake_timeout = HANDSHAKE_TIMEOUT;
+    config
+}
+
+fn assert_timed_out(err: ConnectionError) {
+    match err {
+        ConnectionError::ApplicationClosed(close) => {
+            assert_eq!(close.error_code, VarInt::from(CloseCode::HandshakeTimeout));
+        }
+        err => panic!("unexpected connection error: {err:?}"),
+    }
+}
+
+#[tokio::test]
+async fn silent_connection_is_closed_after_the_handshake_timeout() {
+    let connection = common::connect(server_config()).await;
+
+    let err = timeout(Duration::from_secs(5), connection.closed())
+        .await
+        .expect("connection was not closed");
+    assert_timed_out(err);
+}
+
+#[tokio::test]
+async fn stream_without_initial_is_closed_after_the_handshake_timeout() {
+    let connection = common::connect(server_config()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    // A pong opens the stream without completing the handshake.
+    let pong = Message::new(MessageType::Pong, Uuid::nil(), Bytes::new());
+    send.write_all(&pong.encode()).await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let close = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, Uuid::nil()),
+    )
+    .await
+    .expect("no Close received");
+    assert!(matches!(close.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&close.payload).unwrap(),
+        CloseReason::Timeout
+    );
+
+    let err = timeout(Duration::from_secs(5), connection.closed())
+        .await
+        .expect("connection was not closed");
+    assert_timed_out(err);
+}
+
+#[tokio::test]
+async fn ping_completes_the_handshake() {
+    let connection = common::connect(server_config()).await;
+    let (mut send, _recv) = connection.open_bi().await.unwrap();
+
+    let ping = Message::new(MessageType::Ping, Uuid::nil(), Bytes::new());
+    send.write_all(&ping.encode()).await.unwrap();
+
+    sleep(HANDSHAKE_TIMEOUT * 3).await;
+    assert!(connection.close_reason().is_none());
+}
+
+#[tokio::test]
+async fn invalid_initial_payload_closes_the_connection() {
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let initial = Message::new(
+        MessageType::Initial,
+        connection_id,
+        Bytes::from_static(&[1, 2, 3, 4, 5]),
+    );
+    send.write_all(&initial.encode()).await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let close = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("no Close received");
+    assert!(matches!(close.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&close.payload).unwrap(),
+        CloseReason::ProtocolError
+    );
+
+    let err = timeout(Duration::from_secs(5), connection.closed())
+        .await
+        .expect("connection was not closed");
+    match err {
+        ConnectionError::ApplicationClosed(close) => {
+            assert_eq!(close.error_code, VarInt::from(CloseCode::InvalidInitial))
+        }
+        err => panic!("unexpected connection error: {err:?}"),
+    }
+}
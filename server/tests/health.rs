@@ -0,0 +1,85 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use server::{
+    config::Config,
+    http::{self, Health},
+    server::make_server_endpoint,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::sleep,
+};
+
+/// Serves the health endpoint of a server bound to an ephemeral port; returns its address.
+async fn spawn_health(mut config: Config) -> SocketAddr {
+    config.host = "127.0.0.1:0".parse().unwrap();
+    let (endpoint, _) = make_server_endpoint(&config).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(http::serve(
+        listener,
+        Arc::new(Health::new(endpoint, &config)),
+    ));
+    addr
+}
+
+/// Requests `path` and returns the status code of the response.
+async fn get(addr: SocketAddr, path: &str) -> u16 {
+    let mut socket = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    socket.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    socket.read_to_string(&mut response).await.unwrap();
+    response
+        .strip_prefix("HTTP/1.1 ")
+        .and_then(|rest| rest.get(..3))
+        .and_then(|status| status.parse().ok())
+        .unwrap_or_else(|| panic!("invalid response: {response}"))
+}
+
+/// Address nothing listens on.
+async fn unreachable_addr() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap()
+}
+
+#[tokio::test]
+async fn bound_server_is_healthy() {
+    let addr = spawn_health(Config::new()).await;
+
+    assert_eq!(get(addr, "/health").await, 200);
+    assert_eq!(get(addr, "/other").await, 404);
+}
+
+#[tokio::test]
+async fn health_follows_the_canary() {
+    let canary = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = Config::new();
+    config.health_canary = Some(canary.local_addr().unwrap());
+    assert_eq!(get(spawn_health(config).await, "/health").await, 200);
+
+    let mut config = Config::new();
+    config.health_canary = Some(unreachable_addr().await);
+    assert_eq!(get(spawn_health(config).await, "/health").await, 503);
+}
+
+#[tokio::test]
+async fn canary_check_is_cached() {
+    let ttl = Duration::from_millis(300);
+    let canary = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = Config::new();
+    config.health_canary = Some(canary.local_addr().unwrap());
+    config.health_canary_ttl = ttl;
+    let addr = spawn_health(config).await;
+
+    assert_eq!(get(addr, "/health").await, 200);
+
+    // The canary going down only shows once the last check expired.
+    drop(canary);
+    assert_eq!(get(addr, "/health").await, 200);
+    sleep(ttl).await;
+    assert_eq!(get(addr, "/health").await, 503);
+}
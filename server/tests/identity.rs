@@ -0,0 +1,51 @@
+use std::fs;
+
+use server::{
+    config::Config,
+    server::{Identity, load_certs, make_server_endpoint},
+};
+
+#[test]
+fn identity_is_generated_once_then_reused() {
+    let dir = std::env::temp_dir().join(format!("reverprox-identity-{}", uuid::Uuid::new_v4()));
+    let cert_path = dir.join("server_cert.pem");
+    let key_path = dir.join("keys").join("server_key.pem");
+
+    let first = Identity::load_or_generate(&cert_path, &key_path).unwrap();
+    assert_eq!(load_certs(&cert_path).unwrap(), vec![first.cert.clone()]);
+    assert!(key_path.is_file());
+
+    let second = Identity::load_or_generate(&cert_path, &key_path).unwrap();
+    assert_eq!(second.cert, first.cert);
+    assert_eq!(second.key.secret_pkcs8_der(), first.key.secret_pkcs8_der());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&key_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_presents_the_same_certificate_across_restarts() {
+    let dir = std::env::temp_dir().join(format!("reverprox-restart-{}", uuid::Uuid::new_v4()));
+    let mut config = Config::new();
+    config.host = "127.0.0.1:0".parse().unwrap();
+    config.cert_path = dir.join("server_cert.pem");
+    config.key_path = Some(dir.join("server_key.pem"));
+
+    let (first, first_cert) = make_server_endpoint(&config).unwrap();
+    drop(first);
+    let (_second, second_cert) = make_server_endpoint(&config).unwrap();
+    assert_eq!(second_cert, first_cert);
+
+    // Without a key path, every start generates a new certificate.
+    config.key_path = None;
+    let (_third, third_cert) = make_server_endpoint(&config).unwrap();
+    assert_ne!(third_cert, first_cert);
+
+    fs::remove_dir_all(dir).unwrap();
+}
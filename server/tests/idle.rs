@@ -0,0 +1,37 @@
+use std::time::{Duration, Instant};
+
+use message::{CloseReason, Decoder, MessageType};
+use server::config::Config;
+use tokio::time::timeout;
+
+mod common;
+
+#[tokio::test]
+async fn closes_idle_tunnels_after_the_threshold() {
+    let backend_addr = common::spawn_echo_backend().await;
+
+    let mut config = Config::new();
+    config.idle_timeout = Duration::from_millis(300);
+    config.idle_sweep_interval = Duration::from_millis(50);
+
+    let connection = common::connect(config).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let opened = Instant::now();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    let msg = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("idle tunnel was not closed");
+
+    assert!(matches!(msg.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&msg.payload).unwrap(),
+        CloseReason::Timeout
+    );
+    assert!(opened.elapsed() >= Duration::from_millis(300));
+}
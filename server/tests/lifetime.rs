@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, Message, MessageType};
+use server::config::Config;
+use tokio::time::{interval, timeout};
+
+mod common;
+
+const MAX_LIFETIME: Duration = Duration::from_millis(400);
+
+#[tokio::test]
+async fn active_tunnel_is_closed_past_its_maximum_lifetime() {
+    let backend_addr = common::spawn_echo_backend().await;
+
+    let mut config = Config::new();
+    config.max_connection_lifetime = Some(MAX_LIFETIME);
+    config.idle_timeout = Duration::from_millis(200);
+    config.idle_sweep_interval = Duration::from_millis(50);
+
+    let connection = common::connect(config).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let opened = Instant::now();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    // Data keeps flowing both ways, more often than the tunnel could be found idle.
+    let close = timeout(Duration::from_secs(5), async {
+        let mut ticks = interval(Duration::from_millis(20));
+        loop {
+            ticks.tick().await;
+            let data = Message::new(MessageType::Data, connection_id, Bytes::from_static(b"hi"));
+            send.write_all(&data.encode()).await.unwrap();
+
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            if !matches!(msg.message_type, MessageType::Data) {
+                return msg;
+            }
+        }
+    })
+    .await
+    .expect("tunnel was not closed");
+
+    assert!(matches!(close.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&close.payload).unwrap(),
+        CloseReason::Normal
+    );
+    assert!(opened.elapsed() >= MAX_LIFETIME);
+}
+
+#[test]
+fn zero_max_lifetime_is_invalid() {
+    let mut config = Config::new();
+    config.max_connection_lifetime = Some(Duration::ZERO);
+    assert!(config.validate().is_err());
+}
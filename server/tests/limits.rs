@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, InitializationMessage, Message, MessageType};
+use server::config::Config;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+mod common;
+
+const MAX_FRAME_SIZE: usize = 1024;
+
+#[tokio::test]
+async fn frame_at_the_maximum_size_is_accepted() {
+    let backend_addr = common::spawn_echo_backend().await;
+
+    let mut config = Config::new();
+    config.max_frame_size = MAX_FRAME_SIZE;
+    let server = common::TestServer::start(config);
+
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    let header = Message::new(MessageType::Data, connection_id, Bytes::new())
+        .with_sequence(0)
+        .encoded_len();
+    let payload = Bytes::from(vec![7; MAX_FRAME_SIZE - header]);
+    let data = Message::new(MessageType::Data, connection_id, payload.clone()).with_sequence(0);
+    assert_eq!(data.encoded_len(), MAX_FRAME_SIZE);
+    send.write_all(&data.encode()).await.unwrap();
+
+    let mut echoed = Vec::new();
+    timeout(Duration::from_secs(5), async {
+        while echoed.len() < payload.len() {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            assert!(matches!(msg.message_type, MessageType::Data), "{msg:?}");
+            echoed.extend_from_slice(&msg.payload);
+        }
+    })
+    .await
+    .expect("timed out waiting for the echo");
+    assert_eq!(echoed, payload);
+}
+
+#[tokio::test]
+async fn closes_connection_sending_an_oversized_frame() {
+    let backend_addr = common::spawn_echo_backend().await;
+
+    let mut config = Config::new();
+    config.max_frame_size = MAX_FRAME_SIZE;
+    let server = common::TestServer::start(config);
+
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    // Only the header is sent: the server has to reject the frame before the payload arrives.
+    let oversized = Message::new(MessageType::Data, connection_id, Bytes::from(vec![0; 4096]));
+    send.write_all(&oversized.encode()[..message::HEADER_LENGTH])
+        .await
+        .unwrap();
+
+    let close = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("no Close received");
+
+    assert!(matches!(close.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&close.payload).unwrap(),
+        CloseReason::ProtocolError
+    );
+
+    timeout(Duration::from_secs(5), connection.closed())
+        .await
+        .expect("connection was not closed");
+
+    // The server keeps accepting other clients.
+    let connection = server.connect().await;
+    connection.open_bi().await.unwrap();
+}
+
+#[tokio::test]
+async fn backend_data_is_split_into_chunks() {
+    let backend_addr = common::spawn_echo_backend().await;
+
+    let mut config = Config::new();
+    config.chunk_size = 16;
+    let server = common::TestServer::start(config);
+
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    send.write_all(&Message::new(MessageType::Initial, connection_id, init.encode()).encode())
+        .await
+        .unwrap();
+
+    let payload = Bytes::from(vec![7; 100]);
+    let data = Message::new(MessageType::Data, connection_id, payload.clone()).with_sequence(0);
+    send.write_all(&data.encode()).await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let mut echoed = Vec::new();
+    timeout(Duration::from_secs(5), async {
+        while echoed.len() < payload.len() {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            if matches!(msg.message_type, MessageType::Data) {
+                assert!(msg.payload.len() <= 16);
+                echoed.extend_from_slice(&msg.payload);
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for the echo");
+    assert_eq!(echoed, payload);
+}
@@ -0,0 +1,57 @@
+use std::{fs, time::Duration};
+
+use server::{config::Config, logging};
+use spdlog::prelude::info;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::timeout,
+};
+
+mod common;
+
+#[tokio::test]
+async fn log_lines_are_tagged_with_their_connection() {
+    let dir = std::env::temp_dir().join(format!("reverprox-log-{}", uuid::Uuid::new_v4()));
+    let path = dir.join("server.log");
+    let mut config = Config::new();
+    config.log_file = Some(path.clone());
+    logging::init(&config).unwrap();
+
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+    let (_client, local_addr) =
+        common::start_client(&server, backend_addr, Duration::from_secs(5)).await;
+
+    let mut socket = common::connect_local(local_addr).await;
+    socket.write_all(b"hello").await.unwrap();
+    let mut echoed = [0; 5];
+    timeout(Duration::from_secs(5), socket.read_exact(&mut echoed))
+        .await
+        .expect("timed out waiting for the echo")
+        .unwrap();
+
+    let snapshot = server.registry.traffic_snapshot();
+    let connection_id = *snapshot.keys().next().unwrap();
+
+    info!("[server] outside of any connection");
+    spdlog::default_logger().flush();
+
+    let logs = fs::read_to_string(&path).unwrap();
+    let initial = logs
+        .lines()
+        .find(|line| line.contains("[server] Initial:"))
+        .expect("Initial was not logged");
+    assert!(initial.contains(" {addr="), "{initial}");
+    assert!(
+        initial.ends_with(&format!(" connection_id={connection_id}}}")),
+        "{initial}"
+    );
+
+    let outside = logs
+        .lines()
+        .find(|line| line.contains("outside of any connection"))
+        .unwrap();
+    assert!(!outside.contains("{addr="), "{outside}");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
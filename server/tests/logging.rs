@@ -0,0 +1,25 @@
+use std::fs;
+
+use server::{config::Config, logging};
+use spdlog::prelude::info;
+
+#[test]
+fn log_file_is_rotated_once_full() {
+    let dir = std::env::temp_dir().join(format!("reverprox-log-{}", uuid::Uuid::new_v4()));
+    let mut config = Config::new();
+    config.log_file = Some(dir.join("server.log"));
+    config.log_max_size = 1024;
+    config.log_max_files = 3;
+    logging::init(&config).unwrap();
+
+    for i in 0..100 {
+        info!("[server] filling the log file: line={i}");
+    }
+    spdlog::default_logger().flush();
+
+    let files = fs::read_dir(&dir).unwrap().count();
+    assert!(files >= 2, "log file was not rotated: {files} file(s)");
+    assert!(files <= 3, "too many log files kept: {files}");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
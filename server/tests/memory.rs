@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, InitializationMessage, Message, MessageType};
+use server::{
+    config::Config,
+    connection,
+    transport::{AsyncFrameSink, AsyncFrameSource},
+};
+use tokio::time::timeout;
+use uuid::Uuid;
+
+mod common;
+
+use common::memory::{self, MemoryConnection, next_message, shared};
+
+#[tokio::test]
+async fn handler_relays_a_tunnel_over_an_in_memory_stream() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = MemoryConnection::new("127.0.0.1:4000".parse().unwrap());
+    let (mut client_send, server_recv) = memory::stream();
+    let (server_send, mut client_recv) = memory::stream();
+    let shared = shared(Config::new());
+    let registry = shared.registry.clone();
+    let handler = tokio::spawn(connection::handle_stream(
+        connection.clone(),
+        server_send,
+        server_recv,
+        shared,
+    ));
+    let mut decoder = Decoder::new();
+
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    let initial = Message::new(MessageType::Initial, connection_id, init.encode());
+    client_send.send_chunk(initial.encode()).await.unwrap();
+    let ack = next_message(&mut client_recv, &mut decoder).await;
+    assert!(matches!(ack.message_type, MessageType::Ack));
+    assert_eq!(ack.in_reply_to, Some(initial.message_id));
+
+    let data = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from_static(b"hello"),
+    )
+    .with_sequence(0);
+    // Split mid-frame, like a stream read would.
+    let frame = data.encode();
+    client_send.send_chunk(frame.slice(..7)).await.unwrap();
+    client_send.send_chunk(frame.slice(7..)).await.unwrap();
+    let echo = next_message(&mut client_recv, &mut decoder).await;
+    assert!(matches!(echo.message_type, MessageType::Data));
+    assert_eq!(&echo.payload[..], b"hello");
+    assert_eq!(echo.in_reply_to, Some(data.message_id));
+
+    let close = Message::new(
+        MessageType::Close,
+        connection_id,
+        CloseReason::Normal.encode(),
+    );
+    client_send.send_chunk(close.encode()).await.unwrap();
+    let ack = next_message(&mut client_recv, &mut decoder).await;
+    assert!(matches!(ack.message_type, MessageType::Ack));
+    assert_eq!(ack.in_reply_to, Some(close.message_id));
+    assert!(registry.traffic(&connection_id).is_none());
+
+    // Finishing the stream ends the handler, which finishes its side in turn.
+    client_send.finish(None).await;
+    timeout(Duration::from_secs(5), handler)
+        .await
+        .expect("handler did not end")
+        .unwrap();
+    assert!(client_recv.recv_chunk(usize::MAX).await.unwrap().is_none());
+    assert_eq!(connection.close_code(), None);
+}
@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, InitializationMessage, Message, MessageType, ProxyTarget};
+use server::{
+    config::Config,
+    metrics::{Histogram, MAX_BACKENDS, Metrics},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    time::{sleep, timeout},
+};
+
+mod common;
+
+#[test]
+fn samples_fall_in_the_bucket_of_their_bound() {
+    let histogram = Histogram::default();
+    histogram.record(Duration::ZERO);
+    histogram.record(Duration::from_micros(100));
+    histogram.record(Duration::from_micros(101));
+    histogram.record(Duration::from_secs(5));
+
+    let snapshot = histogram.snapshot();
+    assert_eq!(snapshot.count, 4);
+    assert_eq!(snapshot.sum, Duration::from_micros(5_000_201));
+    assert_eq!(snapshot.buckets[0], (Some(Duration::from_micros(100)), 2));
+    assert_eq!(snapshot.buckets[1], (Some(Duration::from_micros(250)), 1));
+    assert_eq!(snapshot.buckets.last(), Some(&(None, 1)));
+}
+
+#[test]
+fn backends_no_tunnel_relays_to_are_evicted_once_full() {
+    let metrics = Metrics::new();
+    let target = |port| ProxyTarget::Host("backend".into(), port);
+
+    let active = metrics.backend(&target(0)).activate();
+    for port in 1..MAX_BACKENDS as u16 {
+        metrics.backend(&target(port));
+    }
+    assert_eq!(metrics.snapshot().backends.len(), MAX_BACKENDS);
+
+    // Only the backend still relayed to is kept, next to the new one.
+    metrics.backend(&target(u16::MAX));
+    let backends = metrics.snapshot().backends;
+    assert_eq!(backends.len(), 2);
+    assert_eq!(backends[&target(0)].active, 1);
+    assert!(backends.contains_key(&target(u16::MAX)));
+    drop(active);
+}
+
+#[test]
+fn backends_in_use_are_never_evicted() {
+    let metrics = Metrics::new();
+    let target = |port| ProxyTarget::Host("backend".into(), port);
+
+    let in_use = (0..MAX_BACKENDS as u16)
+        .map(|port| metrics.backend(&target(port)))
+        .collect::<Vec<_>>();
+
+    // Not recorded while all the others are in use.
+    metrics.backend(&target(u16::MAX)).record_read(1);
+    let backends = metrics.snapshot().backends;
+    assert_eq!(backends.len(), MAX_BACKENDS);
+    assert!(!backends.contains_key(&target(u16::MAX)));
+    drop(in_use);
+}
+
+#[tokio::test]
+async fn backends_not_connected_to_are_not_recorded() {
+    // Nothing listens on the port of a dropped listener.
+    let unreachable = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server = common::TestServer::start(Config::new());
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), unreachable).unwrap();
+    let (_, reply) = common::request_tunnel(&mut send, &mut recv, &mut decoder, &init).await;
+    assert!(matches!(reply.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&reply.payload).unwrap(),
+        CloseReason::BackendRefused
+    );
+
+    assert!(server.registry.metrics().snapshot().backends.is_empty());
+}
+
+#[tokio::test]
+async fn transit_of_timestamped_data_is_recorded() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    let now = message::now_micros();
+    let frames = [
+        // Sent by a client whose clock is 2 ms behind, or 2 ms ago.
+        Message::new(MessageType::Data, connection_id, Bytes::from_static(b"a"))
+            .with_timestamp(now - 2_000),
+        // Sent by a client whose clock is ahead: counted as no time at all.
+        Message::new(MessageType::Data, connection_id, Bytes::from_static(b"b"))
+            .with_timestamp(now + 60_000_000),
+        // Without a timestamp: not measured.
+        Message::new(MessageType::Data, connection_id, Bytes::from_static(b"c")),
+    ];
+    for frame in &frames {
+        send.write_all(&frame.encode()).await.unwrap();
+    }
+
+    let mut echoed = Vec::new();
+    timeout(Duration::from_secs(5), async {
+        while echoed.len() < frames.len() {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            echoed.extend_from_slice(&msg.payload);
+        }
+    })
+    .await
+    .expect("timed out waiting for the echo");
+    assert_eq!(echoed, b"abc");
+
+    let transit = server.registry.metrics().transit();
+    assert_eq!(transit.count, 2);
+    assert!(transit.sum >= Duration::from_millis(2), "{transit:?}");
+    assert_eq!(transit.buckets[0].1, 1, "{transit:?}");
+}
+
+#[tokio::test]
+async fn clients_can_timestamp_their_data() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+    let mut config = common::client_config(&server, backend_addr);
+    config.timestamps = true;
+    let (_client, local_addr) = common::spawn_client(config).await;
+
+    let mut socket = common::connect_local(local_addr).await;
+    socket.write_all(b"hello").await.unwrap();
+    let mut echoed = [0; 5];
+    timeout(Duration::from_secs(5), socket.read_exact(&mut echoed))
+        .await
+        .expect("timed out waiting for the echo")
+        .unwrap();
+
+    timeout(Duration::from_secs(5), async {
+        while server.registry.metrics().transit().count == 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("transit was not recorded");
+}
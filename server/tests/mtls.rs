@@ -0,0 +1,96 @@
+use std::{fs, net::SocketAddr, path::PathBuf, time::Duration};
+
+use client::{ClientIdentity, tls::configure_client};
+use quinn::Endpoint;
+use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use server::{config::Config, server::make_server_endpoint};
+use tokio::time::timeout;
+use uuid::Uuid;
+
+struct Ca {
+    cert: rcgen::Certificate,
+    key: KeyPair,
+}
+
+impl Ca {
+    fn new() -> Ca {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let cert = params.self_signed(&key).unwrap();
+
+        Ca { cert, key }
+    }
+
+    /// Writes the CA certificate to a PEM file the server can load.
+    fn write_pem(&self) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("reverprox-ca-{}.pem", Uuid::new_v4()));
+        fs::write(&path, self.cert.pem()).unwrap();
+        path
+    }
+
+    fn issue(&self) -> ClientIdentity {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["client".into()])
+            .unwrap()
+            .signed_by(&key, &self.cert, &self.key)
+            .unwrap();
+
+        ClientIdentity {
+            certs: vec![cert.der().clone()],
+            key: PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+        }
+    }
+}
+
+/// Starts a server requiring client certificates signed by `ca`.
+fn start_server(ca: &Ca) -> (Endpoint, SocketAddr, CertificateDer<'static>) {
+    let mut config = Config::new();
+    config.host = "127.0.0.1:0".parse().unwrap();
+    config.client_ca = Some(ca.write_pem());
+
+    let (endpoint, server_cert) = make_server_endpoint(&config).unwrap();
+    fs::remove_file(config.client_ca.unwrap()).unwrap();
+
+    let addr = endpoint.local_addr().unwrap();
+    (endpoint, addr, server_cert)
+}
+
+/// Connects with the given identity and reports whether the server accepted the handshake.
+async fn handshake(identity: Option<ClientIdentity>, ca: &Ca) -> bool {
+    let (server, server_addr, server_cert) = start_server(ca);
+
+    let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint
+        .set_default_client_config(configure_client(&[server_cert], identity.as_ref()).unwrap());
+
+    let connecting = endpoint.connect(server_addr, "localhost").unwrap();
+    let accepted = async { server.accept().await.unwrap().await };
+
+    let (_, accepted) = timeout(Duration::from_secs(5), async {
+        tokio::join!(connecting, accepted)
+    })
+    .await
+    .expect("handshake timed out");
+
+    accepted.is_ok()
+}
+
+#[tokio::test]
+async fn accepts_client_certificate_signed_by_the_ca() {
+    let ca = Ca::new();
+    assert!(handshake(Some(ca.issue()), &ca).await);
+}
+
+#[tokio::test]
+async fn rejects_client_certificate_signed_by_another_ca() {
+    let ca = Ca::new();
+    assert!(!handshake(Some(Ca::new().issue()), &ca).await);
+}
+
+#[tokio::test]
+async fn rejects_client_without_certificate() {
+    let ca = Ca::new();
+    assert!(!handshake(None, &ca).await);
+}
@@ -0,0 +1,108 @@
+use std::{collections::HashMap, time::Duration};
+
+use bytes::Bytes;
+use message::{Decoder, Message, MessageType};
+use server::config::Config;
+use tokio::{
+    net::{TcpSocket, TcpStream},
+    time::timeout,
+};
+use uuid::Uuid;
+
+mod common;
+
+const ROUNDS: usize = 50;
+
+/// Listener whose accept queue is full, so that connecting to it never completes; returns its
+/// address along with the listener and the connections filling it, to keep open.
+async fn unanswering_backend() -> (std::net::SocketAddr, impl Sized) {
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let listener = socket.listen(0).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut queued = Vec::new();
+    while let Ok(connected) = timeout(Duration::from_millis(200), TcpStream::connect(addr)).await {
+        queued.push(connected.unwrap());
+    }
+    (addr, (listener, queued))
+}
+
+#[tokio::test]
+async fn simultaneous_tunnels_keep_their_data_apart() {
+    let first_backend = common::spawn_echo_backend().await;
+    let second_backend = common::spawn_echo_backend().await;
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    let first = common::open_tunnel(&mut send, &mut recv, &mut decoder, first_backend).await;
+    let second = common::open_tunnel(&mut send, &mut recv, &mut decoder, second_backend).await;
+
+    // Interleaves the frames of both tunnels on the stream.
+    for round in 0..ROUNDS as u64 {
+        for (connection_id, byte) in [(first, b'a'), (second, b'b')] {
+            let data = Message::new(
+                MessageType::Data,
+                connection_id,
+                Bytes::from(vec![byte; 100]),
+            )
+            .with_sequence(round);
+            send.write_all(&data.encode()).await.unwrap();
+        }
+    }
+
+    let mut echoed = HashMap::<Uuid, Vec<u8>>::new();
+    let mut received = 0;
+    timeout(Duration::from_secs(10), async {
+        while received < 2 * ROUNDS * 100 {
+            let chunk = recv.read_chunk(usize::MAX, true).await.unwrap().unwrap();
+            decoder.extend(&chunk.bytes);
+
+            while let Some(msg) = decoder.next_message().unwrap() {
+                if msg.message_type.is_data() {
+                    received += msg.payload.len();
+                    echoed
+                        .entry(msg.connection_id)
+                        .or_default()
+                        .extend_from_slice(&msg.payload);
+                }
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for the echoes");
+
+    assert_eq!(echoed.len(), 2);
+    assert_eq!(echoed[&first], vec![b'a'; ROUNDS * 100]);
+    assert_eq!(echoed[&second], vec![b'b'; ROUNDS * 100]);
+}
+
+#[tokio::test]
+async fn backend_slow_to_connect_does_not_hold_the_other_tunnels_up() {
+    let (unanswering, _backend) = unanswering_backend().await;
+    let echo_backend = common::spawn_echo_backend().await;
+    let mut config = Config::new();
+    config.connect_timeout = Duration::from_secs(10);
+    let connection = common::connect(config).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    let init =
+        message::InitializationMessage::new("127.0.0.1:0".parse().unwrap(), unanswering).unwrap();
+    send.write_all(&Message::initial(Uuid::new_v4(), &init).encode())
+        .await
+        .unwrap();
+
+    timeout(Duration::from_secs(2), async {
+        let connection_id =
+            common::open_tunnel(&mut send, &mut recv, &mut decoder, echo_backend).await;
+        let data = Message::data(connection_id, "hello".into());
+        send.write_all(&data.encode()).await.unwrap();
+
+        let echo = common::next_message(&mut recv, &mut decoder, connection_id).await;
+        assert_eq!(&echo.payload[..], b"hello");
+    })
+    .await
+    .expect("tunnel held up by the backend of another one");
+}
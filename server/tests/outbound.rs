@@ -0,0 +1,107 @@
+use std::{io::ErrorKind, sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use futures_util::StreamExt;
+use message::{CloseReason, Message, MessageStream, MessageType};
+use server::{buffer_pool::BufferPool, config::Config, outbound::Outbound};
+use tokio::time::{sleep, timeout};
+use uuid::Uuid;
+
+mod common;
+
+/// Number of `Data` frames queued, more than the stream accepts before the peer reads.
+const BACKLOG: usize = 200;
+
+/// Queues a backlog of `Data` for the tunnel, then `control` once the writer is held up; returns
+/// the message types in the order they are received.
+async fn received_order(data_tunnel: Uuid, control: Message) -> Vec<MessageType> {
+    let config = Config::new();
+    let (send, mut peer) = common::raw_stream(&config).await;
+    let outbound = Outbound::spawn(
+        send,
+        config.flush_window,
+        config.coalesce_max_bytes,
+        config.max_frame_size,
+        Arc::new(BufferPool::new(0, 0)),
+        config.client_write_timeout,
+    );
+
+    let sender = outbound.clone();
+    let backlog = tokio::spawn(async move {
+        for _ in 0..BACKLOG {
+            let data = Message::new(
+                MessageType::Data,
+                data_tunnel,
+                Bytes::from(vec![0x5A; 16 * 1024]),
+            );
+            sender.send(data).await.unwrap();
+        }
+    });
+    // Lets the flow control window fill up.
+    sleep(Duration::from_millis(200)).await;
+
+    let sender = outbound.clone();
+    let control = tokio::spawn(async move { sender.send(control).await.unwrap() });
+
+    let mut messages = MessageStream::new(peer.accept().await);
+    let mut order = Vec::new();
+    timeout(Duration::from_secs(10), async {
+        while order.len() < BACKLOG + 1 {
+            let msg = messages.next().await.unwrap().unwrap();
+            order.push(msg.message_type);
+        }
+    })
+    .await
+    .expect("frames were not received");
+
+    backlog.await.unwrap();
+    control.await.unwrap();
+    order
+}
+
+#[tokio::test]
+async fn control_messages_skip_the_queued_data() {
+    let close = Message::new(
+        MessageType::Close,
+        Uuid::new_v4(),
+        CloseReason::Normal.encode(),
+    );
+    let order = received_order(Uuid::new_v4(), close).await;
+
+    let position = order
+        .iter()
+        .position(|message_type| matches!(message_type, MessageType::Close))
+        .unwrap();
+    assert!(position < BACKLOG, "Close was sent after all the Data");
+}
+
+#[tokio::test]
+async fn close_of_a_tunnel_waits_for_its_data() {
+    let tunnel = Uuid::new_v4();
+    let close = Message::new(MessageType::Close, tunnel, CloseReason::Normal.encode());
+    let order = received_order(tunnel, close).await;
+
+    assert!(matches!(order.last(), Some(MessageType::Close)));
+}
+
+#[tokio::test]
+async fn frames_longer_than_the_maximum_size_are_not_sent() {
+    let config = Config::new();
+    let (send, _peer) = common::raw_stream(&config).await;
+    let outbound = Outbound::spawn(
+        send,
+        config.flush_window,
+        config.coalesce_max_bytes,
+        1024,
+        Arc::new(BufferPool::new(0, 0)),
+        config.client_write_timeout,
+    );
+
+    let data = |len| Message::new(MessageType::Data, Uuid::new_v4(), Bytes::from(vec![0; len]));
+    let at_limit = data(1024 - message::HEADER_LENGTH);
+    assert_eq!(at_limit.encoded_len(), 1024);
+    outbound.send(at_limit).await.unwrap();
+
+    let err = outbound.send(data(1024)).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
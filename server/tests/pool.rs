@@ -0,0 +1,165 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, InitializationMessage, Message, MessageType, ProxyTarget};
+use quinn::{RecvStream, SendStream};
+use server::{
+    allowlist::{PolicyMode, TargetPolicy},
+    config::Config,
+    pool::BackendPool,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+mod common;
+
+/// Echo backend counting the connections it accepted.
+async fn spawn_counting_backend() -> (SocketAddr, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+
+    let counter = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = socket.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                let _ = writer.shutdown().await;
+            });
+        }
+    });
+
+    (addr, accepted)
+}
+
+/// Opens a tunnel, waits for the echo of a single request and closes the tunnel.
+async fn request(
+    send: &mut SendStream,
+    recv: &mut RecvStream,
+    decoder: &mut Decoder,
+    backend_addr: SocketAddr,
+    body: &'static [u8],
+) {
+    let connection_id = common::open_tunnel(send, recv, decoder, backend_addr).await;
+    send.write_all(
+        &Message::new(MessageType::Data, connection_id, Bytes::from_static(body))
+            .with_sequence(0)
+            .encode(),
+    )
+    .await
+    .unwrap();
+
+    let msg = timeout(
+        Duration::from_secs(5),
+        common::next_message(recv, decoder, connection_id),
+    )
+    .await
+    .expect("no response from the backend");
+    assert_eq!(msg.payload, body);
+
+    let close = Message::new(
+        MessageType::Close,
+        connection_id,
+        CloseReason::Normal.encode(),
+    );
+    send.write_all(&close.encode()).await.unwrap();
+
+    // Leaves the server the time to return the connection to the pool.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+}
+
+/// Proxies two sequential requests and returns the number of backend connections opened.
+async fn backend_connections(config: Config) -> usize {
+    let (backend_addr, accepted) = spawn_counting_backend().await;
+    let connection = common::connect(config).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    request(&mut send, &mut recv, &mut decoder, backend_addr, b"first").await;
+    request(&mut send, &mut recv, &mut decoder, backend_addr, b"second").await;
+
+    accepted.load(Ordering::SeqCst)
+}
+
+#[tokio::test]
+async fn reuses_pooled_backend_connection() {
+    let mut config = Config::new();
+    config.backend_pool_max_idle = 8;
+
+    assert_eq!(backend_connections(config).await, 1);
+}
+
+#[tokio::test]
+async fn opens_a_connection_per_tunnel_without_pool() {
+    assert_eq!(backend_connections(Config::new()).await, 2);
+}
+
+#[tokio::test]
+async fn pooled_connections_follow_the_reloaded_policy() {
+    let (backend_addr, accepted) = spawn_counting_backend().await;
+    let mut config = Config::new();
+    config.backend_pool_max_idle = 8;
+    let (server, reloader) = common::TestServer::start_reloadable(config.clone());
+
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    request(&mut send, &mut recv, &mut decoder, backend_addr, b"first").await;
+    assert_eq!(reloader.pool().idle(), 1);
+
+    config.target_policy = TargetPolicy {
+        mode: PolicyMode::Deny,
+        rules: vec!["127.0.0.0/8".parse().unwrap()],
+    };
+    assert_eq!(reloader.apply(&config), ["target_policy"]);
+    assert_eq!(reloader.pool().idle(), 0);
+
+    // The target isn't served anymore, pooled connection or not.
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    let (_, reply) = common::request_tunnel(&mut send, &mut recv, &mut decoder, &init).await;
+    assert!(matches!(reply.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&reply.payload).unwrap(),
+        CloseReason::TargetNotAllowed
+    );
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn checkout_skips_the_connections_the_policy_denies() {
+    let (backend_addr, _) = spawn_counting_backend().await;
+    let target = ProxyTarget::Addr(backend_addr);
+    let pool = BackendPool::new(8, 4);
+    pool.checkin(
+        target.clone(),
+        TcpStream::connect(backend_addr).await.unwrap(),
+    );
+
+    let denied = TargetPolicy {
+        mode: PolicyMode::Allow,
+        rules: Vec::new(),
+    };
+    assert!(pool.checkout(&target, &denied).is_none());
+    assert_eq!(pool.idle(), 0);
+
+    pool.checkin(
+        target.clone(),
+        TcpStream::connect(backend_addr).await.unwrap(),
+    );
+    assert!(pool.checkout(&target, &TargetPolicy::default()).is_some());
+}
@@ -0,0 +1,91 @@
+use std::{net::Ipv6Addr, time::Duration};
+
+use bytes::Bytes;
+use message::{InitializationMessage, Message, MessageType};
+use server::{config::Config, proxy_protocol};
+use tokio::{io::AsyncReadExt, net::TcpListener, time::timeout};
+use uuid::Uuid;
+
+mod common;
+
+#[test]
+fn ipv4_header_carries_both_addresses() {
+    let header = proxy_protocol::header(
+        "198.51.100.7:4242".parse().unwrap(),
+        "10.0.0.1:8080".parse().unwrap(),
+    );
+
+    let mut expected = proxy_protocol::SIGNATURE.to_vec();
+    expected.extend_from_slice(&[0x21, 0x11, 0, 12]);
+    expected.extend_from_slice(&[198, 51, 100, 7, 10, 0, 0, 1]);
+    expected.extend_from_slice(&4242u16.to_be_bytes());
+    expected.extend_from_slice(&8080u16.to_be_bytes());
+    assert_eq!(&header[..], &expected[..]);
+}
+
+#[test]
+fn mixed_families_are_sent_as_ipv6() {
+    let header = proxy_protocol::header(
+        "198.51.100.7:4242".parse().unwrap(),
+        "[::1]:8080".parse().unwrap(),
+    );
+
+    assert_eq!(&header[12..16], &[0x21, 0x21, 0, 36]);
+    assert_eq!(
+        &header[16..32],
+        &"::ffff:198.51.100.7".parse::<Ipv6Addr>().unwrap().octets()
+    );
+    assert_eq!(header.len(), 16 + 36);
+}
+
+#[test]
+fn pool_is_rejected_with_the_proxy_protocol() {
+    let mut config = Config::new();
+    config.proxy_protocol = true;
+    config.validate().unwrap();
+
+    config.backend_pool_max_idle = 4;
+    let e = config.validate().unwrap_err();
+    assert!(e.to_string().contains("proxy_protocol"), "{e}");
+}
+
+#[tokio::test]
+async fn header_is_written_before_the_payload() {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap();
+
+    let mut config = Config::new();
+    config.proxy_protocol = true;
+    let connection = common::connect(config).await;
+    let (mut send, _recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let init =
+        InitializationMessage::new("198.51.100.7:4242".parse().unwrap(), backend_addr).unwrap();
+    send.write_all(&Message::new(MessageType::Initial, connection_id, init.encode()).encode())
+        .await
+        .unwrap();
+    let data = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from_static(b"payload"),
+    );
+    send.write_all(&data.encode()).await.unwrap();
+
+    let (mut stream, _) = timeout(Duration::from_secs(5), backend.accept())
+        .await
+        .expect("backend connection was not opened")
+        .unwrap();
+    let expected = proxy_protocol::header(
+        "198.51.100.7:4242".parse().unwrap(),
+        stream.local_addr().unwrap(),
+    );
+
+    let mut received = vec![0; expected.len() + b"payload".len()];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut received))
+        .await
+        .expect("timed out reading the backend connection")
+        .unwrap();
+    assert_eq!(&received[..expected.len()], &expected[..]);
+    assert_eq!(&received[expected.len()..], b"payload");
+}
@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+
+use quinn::ConnectionError;
+use server::config::Config;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::timeout,
+};
+
+mod common;
+
+#[tokio::test]
+async fn connections_beyond_the_rate_are_refused() {
+    let mut config = Config::new();
+    config.rate_limit_connections = 2;
+    let server = common::TestServer::start(config);
+
+    let mut refused = 0;
+    let mut connections = Vec::new();
+    for _ in 0..5 {
+        match server.try_connect().await {
+            Ok(connection) => connections.push(connection),
+            Err(ConnectionError::ConnectionClosed(_)) => refused += 1,
+            Err(e) => panic!("unexpected connection error: {e:?}"),
+        }
+    }
+
+    assert_eq!(connections.len(), 2);
+    assert_eq!(refused, 3);
+}
+
+#[tokio::test]
+async fn reads_beyond_the_byte_rate_are_throttled() {
+    const RATE: u64 = 100_000;
+    const TRANSFER: usize = 300_000;
+
+    let backend_addr = common::spawn_echo_backend().await;
+    let mut config = Config::new();
+    config.rate_limit_bytes = RATE;
+    let server = common::TestServer::start(config);
+    let (_client, local_addr) =
+        common::start_client(&server, backend_addr, Duration::from_secs(5)).await;
+
+    let socket = common::connect_local(local_addr).await;
+    let (mut reader, mut writer) = socket.into_split();
+
+    let started = Instant::now();
+    tokio::spawn(async move {
+        writer.write_all(&vec![1; TRANSFER]).await.unwrap();
+    });
+
+    let mut echoed = vec![0; TRANSFER];
+    timeout(Duration::from_secs(10), reader.read_exact(&mut echoed))
+        .await
+        .expect("timed out waiting for the echo")
+        .unwrap();
+
+    // The first second worth of bytes is let through right away, the rest at the rate.
+    let expected = Duration::from_secs_f64((TRANSFER as u64 - RATE) as f64 / RATE as f64);
+    assert!(
+        started.elapsed() >= expected.mul_f64(0.75),
+        "transfer took {:?}",
+        started.elapsed()
+    );
+}
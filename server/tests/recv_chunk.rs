@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{CHUNK_SIZE, Message, MessageType};
+use server::config::Config;
+use tokio::time::{sleep, timeout};
+use uuid::Uuid;
+
+mod common;
+
+#[tokio::test]
+async fn full_size_frame_is_read_at_once() {
+    let config = Config::new();
+    let (mut send, mut peer) = common::raw_stream(&config).await;
+
+    // The largest frame the clients send by default.
+    let frame = Message::new(
+        MessageType::Data,
+        Uuid::new_v4(),
+        Bytes::from(vec![0x5A; CHUNK_SIZE]),
+    )
+    .with_reply_to(Uuid::new_v4())
+    .with_sequence(0)
+    .encode();
+    send.write_all(&frame).await.unwrap();
+    send.finish().unwrap();
+
+    let mut recv = peer.accept().await;
+    // Lets the whole frame arrive before reading.
+    sleep(Duration::from_millis(100)).await;
+
+    let chunk = timeout(
+        Duration::from_secs(5),
+        recv.read_chunk(config.recv_chunk_size, true),
+    )
+    .await
+    .expect("frame was not received")
+    .unwrap()
+    .unwrap();
+    assert_eq!(chunk.bytes, frame);
+}
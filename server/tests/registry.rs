@@ -0,0 +1,80 @@
+use message::ProtocolVersion;
+use server::registry::{ConnectionState, Registry};
+use uuid::Uuid;
+
+use ConnectionState::*;
+
+const STATES: [ConnectionState; 4] = [AwaitingInit, Active, Closing, Closed];
+
+/// Every transition a tunnel is allowed to make; all the others are forbidden.
+const ALLOWED: [(ConnectionState, ConnectionState); 6] = [
+    (AwaitingInit, Active),
+    (AwaitingInit, Closing),
+    (AwaitingInit, Closed),
+    (Active, Closing),
+    (Active, Closed),
+    (Closing, Closed),
+];
+
+/// Registers a tunnel and moves it to `state` through the allowed transitions.
+fn tunnel_in(registry: &Registry, state: ConnectionState) -> Uuid {
+    let connection_id = Uuid::new_v4();
+    registry
+        .insert(connection_id, ProtocolVersion::V1, Uuid::new_v4())
+        .unwrap();
+
+    let path: &[ConnectionState] = match state {
+        AwaitingInit => &[],
+        Active => &[Active],
+        Closing => &[Active, Closing],
+        Closed => &[Active, Closing, Closed],
+    };
+    for to in path {
+        registry.transition(&connection_id, *to).unwrap();
+    }
+    assert_eq!(registry.state(&connection_id), Some(state));
+
+    connection_id
+}
+
+#[test]
+fn only_the_allowed_transitions_can_be_made() {
+    for from in STATES {
+        for to in STATES {
+            assert_eq!(
+                from.can_transition(to),
+                ALLOWED.contains(&(from, to)),
+                "{from:?} -> {to:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn forbidden_transitions_leave_the_tunnel_as_it_is() {
+    let registry = Registry::new();
+
+    for from in STATES {
+        for to in STATES {
+            let connection_id = tunnel_in(&registry, from);
+            let result = registry.transition(&connection_id, to);
+
+            if ALLOWED.contains(&(from, to)) {
+                assert_eq!(result, Ok(()), "{from:?} -> {to:?}");
+                assert_eq!(registry.state(&connection_id), Some(to));
+            } else {
+                assert_eq!(result, Err(Some(from)), "{from:?} -> {to:?}");
+                assert_eq!(registry.state(&connection_id), Some(from));
+            }
+        }
+    }
+}
+
+#[test]
+fn unknown_tunnels_cannot_transition() {
+    let registry = Registry::new();
+
+    for to in STATES {
+        assert_eq!(registry.transition(&Uuid::new_v4(), to), Err(None));
+    }
+}
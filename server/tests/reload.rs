@@ -0,0 +1,95 @@
+use std::{env, fs, time::Duration};
+
+use message::{CloseReason, Decoder, InitializationMessage, Message, MessageType};
+use server::{
+    allowlist::{PolicyMode, TargetPolicy},
+    config::{CONFIG_FILE_ENV, Config},
+    logging,
+};
+use spdlog::Level;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+mod common;
+
+// The only test of this binary reading the environment.
+#[test]
+fn reload_applies_the_log_level_of_the_config_file() {
+    let path = env::temp_dir().join(format!("reverprox-config-{}", Uuid::new_v4()));
+    fs::write(&path, "# Quiet by default\nREVERPROX_LOG_LEVEL=warn\n").unwrap();
+    env::set_var(CONFIG_FILE_ENV, &path);
+
+    let config = Config::from_env().unwrap();
+    assert_eq!(config.log_level, Level::Warn);
+    logging::init(&config).unwrap();
+    let logger = spdlog::default_logger();
+    assert!(!logger.should_log(Level::Info));
+
+    let reloader = server::reload::Reloader::new(config.into());
+    fs::write(
+        &path,
+        "REVERPROX_LOG_LEVEL=debug\nREVERPROX_RATE_LIMIT_CONNECTIONS=5\nREVERPROX_BIND=127.0.0.1:1\n",
+    )
+    .unwrap();
+    let changed = reloader.reload().unwrap();
+    assert_eq!(changed, ["log_level", "rate_limit_connections"]);
+    assert!(logger.should_log(Level::Debug));
+    assert!(!logger.should_log(Level::Trace));
+
+    let config = reloader.config();
+    assert_eq!(config.rate_limit_connections, 5);
+    // Only applies after a restart.
+    assert_eq!(config.host, Config::new().host);
+
+    // An invalid config is rejected as a whole.
+    fs::write(
+        &path,
+        "REVERPROX_LOG_LEVEL=chatty\nREVERPROX_RATE_LIMIT_CONNECTIONS=9\n",
+    )
+    .unwrap();
+    reloader.reload().unwrap_err();
+    assert!(logger.should_log(Level::Debug));
+    assert_eq!(reloader.config().rate_limit_connections, 5);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn reloaded_target_policy_applies_to_new_connections() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let (server, reloader) = common::TestServer::start_reloadable(Config::new());
+
+    let before = server.connect().await;
+    let (mut send, mut recv) = before.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    // Allow mode without rules: no target is allowed anymore.
+    let mut config = Config::new();
+    config.target_policy = TargetPolicy {
+        mode: PolicyMode::Allow,
+        rules: Vec::new(),
+    };
+    assert_eq!(reloader.apply(&config), ["target_policy"]);
+
+    let after = server.connect().await;
+    let (mut send, mut recv) = after.open_bi().await.unwrap();
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    send.write_all(&Message::initial(connection_id, &init).encode())
+        .await
+        .unwrap();
+
+    let mut decoder = Decoder::new();
+    let close = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("no Close received");
+    assert!(matches!(close.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&close.payload).unwrap(),
+        CloseReason::TargetNotAllowed
+    );
+}
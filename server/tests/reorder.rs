@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, Message, MessageType};
+use server::config::Config;
+use tokio::time::timeout;
+
+mod common;
+
+/// Sends `count` payloads of `size` bytes, all after a missing first one, and returns the first
+/// reply of the server.
+async fn send_after_gap(config: Config, count: u64, size: usize) -> Message {
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = common::connect(config).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    for sequence in 1..=count {
+        let data = Message::new(MessageType::Data, connection_id, Bytes::from(vec![0; size]))
+            .with_sequence(sequence);
+        send.write_all(&data.encode()).await.unwrap();
+    }
+
+    timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("tunnel was not closed")
+}
+
+fn assert_protocol_error(msg: Message) {
+    assert!(matches!(msg.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&msg.payload).unwrap(),
+        CloseReason::ProtocolError
+    );
+}
+
+#[tokio::test]
+async fn too_many_held_messages_close_the_tunnel() {
+    let mut config = Config::new();
+    config.reorder_window = 4;
+
+    assert_protocol_error(send_after_gap(config, 5, 1).await);
+}
+
+#[tokio::test]
+async fn too_many_held_bytes_close_the_tunnel() {
+    let mut config = Config::new();
+    config.reorder_max_bytes = 1000;
+
+    assert_protocol_error(send_after_gap(config, 3, 400).await);
+}
+
+#[tokio::test]
+async fn shuffled_frames_are_written_in_order() {
+    const FRAMES: u64 = 16;
+
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    // Every sequence once, in a fixed order far from the sorted one: 0, 5, 10, 15, 4, 9, ...
+    let shuffled = (0..FRAMES).map(|i| i * 5 % FRAMES).collect::<Vec<_>>();
+    assert_ne!(shuffled, (0..FRAMES).collect::<Vec<_>>());
+    for sequence in shuffled {
+        let payload = Bytes::from(vec![b'a' + sequence as u8; 3]);
+        let data = Message::new(MessageType::Data, connection_id, payload).with_sequence(sequence);
+        send.write_all(&data.encode()).await.unwrap();
+    }
+
+    let expected = (0..FRAMES)
+        .flat_map(|sequence| vec![b'a' + sequence as u8; 3])
+        .collect::<Vec<_>>();
+    let mut echoed = Vec::new();
+    timeout(Duration::from_secs(5), async {
+        while echoed.len() < expected.len() {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            assert!(msg.message_type.is_data(), "unexpected {msg:?}");
+            echoed.extend_from_slice(&msg.payload);
+        }
+    })
+    .await
+    .expect("timed out waiting for the echo");
+    assert_eq!(echoed, expected);
+}
@@ -0,0 +1,61 @@
+use std::{io::ErrorKind, time::Duration};
+
+use bytes::Bytes;
+use message::InitializationMessage;
+use server::config::Config;
+use tokio::{net::TcpListener, time::timeout};
+
+mod common;
+
+fn init(backend_addr: std::net::SocketAddr) -> InitializationMessage {
+    InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap()
+}
+
+#[tokio::test]
+async fn request_returns_the_echoed_payload() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+    let mut config = common::client_config(&server, backend_addr);
+    // Sent and echoed in several chunks.
+    config.chunk_size = 1024;
+    let (client, _) = common::spawn_client(config).await;
+
+    let payload = Bytes::from((0..10_000).map(|i| i as u8).collect::<Vec<_>>());
+    let response = timeout(
+        Duration::from_secs(5),
+        client.request(init(backend_addr), payload.clone()),
+    )
+    .await
+    .expect("no response")
+    .unwrap();
+
+    assert_eq!(response, payload);
+    assert_eq!(client.open_tunnels(), 0);
+}
+
+#[tokio::test]
+async fn request_times_out_when_the_backend_never_answers() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = listener.local_addr().unwrap();
+    // Accepts the connection, then neither answers nor closes it.
+    tokio::spawn(async move {
+        let _socket = listener.accept().await.unwrap();
+        std::future::pending::<()>().await;
+    });
+
+    let server = common::TestServer::start(Config::new());
+    let mut config = common::client_config(&server, backend_addr);
+    config.request_timeout = Duration::from_millis(200);
+    config.close_timeout = Duration::from_millis(200);
+    let (client, _) = common::spawn_client(config).await;
+
+    let result = timeout(
+        Duration::from_secs(5),
+        client.request(init(backend_addr), Bytes::from_static(b"hello")),
+    )
+    .await
+    .expect("request did not time out");
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::TimedOut);
+    assert_eq!(client.open_tunnels(), 0);
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use message::{CloseReason, Decoder, InitializationMessage, Message, MessageType};
+use server::config::Config;
+use tokio::time::timeout;
+
+mod common;
+
+#[tokio::test]
+async fn hostname_target_is_resolved_by_the_server() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    let init = InitializationMessage::with_hostname(
+        "127.0.0.1:4000".parse().unwrap(),
+        "localhost",
+        backend_addr.port(),
+    )
+    .unwrap();
+    let (connection_id, reply) =
+        common::request_tunnel(&mut send, &mut recv, &mut decoder, &init).await;
+    assert!(matches!(reply.message_type, MessageType::Ack));
+
+    let data = Message::data(connection_id, "hello".into());
+    send.write_all(&data.encode()).await.unwrap();
+    let echo = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("no echo received");
+    assert_eq!(&echo.payload[..], b"hello");
+}
+
+#[tokio::test]
+async fn unresolvable_hostname_is_unreachable() {
+    let mut config = Config::new();
+    config.resolve_timeout = Duration::from_secs(2);
+    let server = common::TestServer::start(config);
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    // `.invalid` names never resolve.
+    let init = InitializationMessage::with_hostname(
+        "127.0.0.1:4000".parse().unwrap(),
+        "backend.invalid",
+        80,
+    )
+    .unwrap();
+    let (_, reply) = common::request_tunnel(&mut send, &mut recv, &mut decoder, &init).await;
+    assert!(matches!(reply.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&reply.payload).unwrap(),
+        CloseReason::BackendUnreachable
+    );
+}
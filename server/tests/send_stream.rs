@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, MAGIC_BYTE, Message, MessageType};
+use server::config::Config;
+use tokio::time::timeout;
+
+mod common;
+
+#[tokio::test]
+async fn send_stream_only_carries_message_frames() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+
+    let ping = Message::new(
+        MessageType::Ping,
+        uuid::Uuid::nil(),
+        Bytes::from_static(b"t"),
+    );
+    send.write_all(&ping.encode()).await.unwrap();
+
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+    let data = Message::new(
+        MessageType::Data,
+        connection_id,
+        Bytes::from(vec![0x42; 10_000]),
+    );
+    send.write_all(&data.encode()).await.unwrap();
+    let close = Message::new(
+        MessageType::Close,
+        connection_id,
+        CloseReason::Normal.encode(),
+    );
+    send.write_all(&close.encode()).await.unwrap();
+
+    // Lets the echo and the Ack of the Close arrive before finishing the stream.
+    timeout(Duration::from_secs(5), async {
+        loop {
+            let msg = common::next_message(&mut recv, &mut decoder, connection_id).await;
+            if msg.in_reply_to == Some(close.message_id) {
+                break;
+            }
+        }
+    })
+    .await
+    .expect("Close was not acknowledged");
+    send.finish().unwrap();
+
+    let rest = timeout(Duration::from_secs(5), recv.read_to_end(usize::MAX))
+        .await
+        .expect("server did not finish its stream")
+        .unwrap();
+    decoder.extend(&rest);
+
+    // Everything left decodes as whole frames, up to the end of the stream.
+    while decoder.buffered() > 0 {
+        let msg = decoder
+            .next_message()
+            .expect("bytes that are not a Message frame")
+            .expect("stream ended within a frame");
+        assert_eq!(msg.magic, MAGIC_BYTE);
+        msg.validate().unwrap();
+    }
+}
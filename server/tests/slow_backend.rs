@@ -0,0 +1,188 @@
+use std::{
+    fs,
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use message::{Decoder, Message, MessageType, ProxyTarget};
+use server::{config::Config, logging};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::oneshot,
+    time::{sleep, timeout},
+};
+use uuid::Uuid;
+
+mod common;
+
+const SLOW_WRITE: Duration = Duration::from_millis(100);
+
+/// Far more than the socket buffers hold.
+const SENT: usize = 160 * 60 * 1024;
+
+/// Accepts a backend connection, leaves it unread for a while, then drains `SENT` bytes and
+/// replies `ok`; sends the number of bytes read once done.
+async fn spawn_slow_backend() -> (SocketAddr, oneshot::Receiver<usize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (read_tx, read_rx) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        sleep(Duration::from_millis(500)).await;
+
+        let mut buf = vec![0; 64 * 1024];
+        let mut read = 0;
+        while read < SENT {
+            match socket.read(&mut buf).await.unwrap() {
+                0 => break,
+                n => read += n,
+            }
+        }
+        socket.write_all(b"ok").await.unwrap();
+        let _ = read_tx.send(read);
+
+        // Keeps the connection open until the server closes it.
+        let _ = socket.read(&mut buf).await;
+    });
+
+    (addr, read_rx)
+}
+
+#[tokio::test]
+async fn slow_backend_is_warned_about_and_measured() {
+    let dir = std::env::temp_dir().join(format!("reverprox-log-{}", Uuid::new_v4()));
+    let path = dir.join("server.log");
+    let mut config = Config::new();
+    config.log_file = Some(path.clone());
+    logging::init(&config).unwrap();
+
+    let (backend_addr, read_rx) = spawn_slow_backend().await;
+    let mut config = Config::new();
+    config.slow_backend_write = SLOW_WRITE;
+    let server = common::TestServer::start(config);
+
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    let payload = Bytes::from(vec![0x5A; 60 * 1024]);
+    for _ in 0..SENT / payload.len() {
+        let data = Message::new(MessageType::Data, connection_id, payload.clone());
+        send.write_all(&data.encode()).await.unwrap();
+    }
+
+    let read = timeout(Duration::from_secs(10), read_rx)
+        .await
+        .expect("backend didn't drain the data")
+        .unwrap();
+    assert_eq!(read, SENT);
+    let reply = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("no reply from the backend");
+    assert_eq!(&reply.payload[..], b"ok");
+
+    spdlog::default_logger().flush();
+    let logs = fs::read_to_string(&path).unwrap();
+    assert!(logs.contains("slow backend"), "{logs}");
+
+    let target = ProxyTarget::Addr(backend_addr);
+    let backend = server.registry.metrics().snapshot().backends[&target].clone();
+    assert_eq!(backend.connect.count, 1);
+    // Some write waited for the backend to start reading.
+    assert!(backend.write.count > 0);
+    assert!(backend.write.sum > SLOW_WRITE, "{:?}", backend.write);
+    assert_eq!(backend.bytes_to_backend, SENT as u64);
+    assert_eq!(backend.bytes_from_backend, 2);
+    assert_eq!(backend.active, 1);
+
+    // The tunnel stops counting once closed.
+    drop((send, recv));
+    connection.close(0u32.into(), b"done");
+    timeout(Duration::from_secs(5), async {
+        while server.registry.metrics().snapshot().backends[&target].active > 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("tunnel still counted as active");
+}
+
+#[tokio::test]
+async fn backend_not_reading_stops_the_client_sending() {
+    const TOTAL: usize = 64 * 1024 * 1024;
+
+    // Reads nothing until told to, then drains everything.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = listener.local_addr().unwrap();
+    let (drain_tx, drain_rx) = oneshot::channel::<()>();
+    let backend = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        drain_rx.await.unwrap();
+
+        let mut buf = vec![0; 64 * 1024];
+        let mut read = 0;
+        while read < TOTAL {
+            match socket.read(&mut buf).await.unwrap() {
+                0 => break,
+                n => read += n,
+            }
+        }
+        read
+    });
+
+    let mut config = Config::new();
+    config.backend_channel_capacity = 4;
+    let server = common::TestServer::start(config);
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+
+    let sent = Arc::new(AtomicUsize::new(0));
+    let sender = tokio::spawn({
+        let sent = sent.clone();
+        async move {
+            let payload = Bytes::from(vec![0x5A; 16 * 1024]);
+            for _ in 0..TOTAL / payload.len() {
+                let data = Message::new(MessageType::Data, connection_id, payload.clone());
+                send.write_all(&data.encode()).await.unwrap();
+                sent.fetch_add(payload.len(), Ordering::Relaxed);
+            }
+            send
+        }
+    });
+
+    // The client is held back once the buffers along the way are full, rather than the server
+    // queueing whatever it sends.
+    sleep(Duration::from_secs(1)).await;
+    let stalled_at = sent.load(Ordering::Relaxed);
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(
+        sent.load(Ordering::Relaxed),
+        stalled_at,
+        "client kept sending"
+    );
+    assert!(stalled_at < TOTAL / 4, "{stalled_at} bytes sent");
+
+    drain_tx.send(()).unwrap();
+    let read = timeout(Duration::from_secs(20), backend)
+        .await
+        .expect("backend didn't drain the data")
+        .unwrap();
+    assert_eq!(read, TOTAL);
+    timeout(Duration::from_secs(5), sender)
+        .await
+        .expect("sender stalled")
+        .unwrap();
+}
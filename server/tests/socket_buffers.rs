@@ -0,0 +1,42 @@
+use server::{config::Config, server::bind_socket};
+use socket2::SockRef;
+
+mod common;
+
+const BUFFER: usize = 128 * 1024;
+
+#[test]
+fn socket_buffers_get_the_sizes_requested() {
+    let socket = bind_socket(
+        "127.0.0.1:0".parse().unwrap(),
+        false,
+        None,
+        Some(BUFFER),
+        Some(BUFFER),
+    )
+    .unwrap();
+
+    // The OS may round the sizes up, Linux doubles them for its bookkeeping.
+    let socket = SockRef::from(&socket);
+    assert!(socket.send_buffer_size().unwrap() >= BUFFER);
+    assert!(socket.recv_buffer_size().unwrap() >= BUFFER);
+}
+
+#[tokio::test]
+async fn endpoint_with_custom_buffers_serves() {
+    let mut config = Config::new();
+    config.socket_send_buffer = Some(4 * 1024 * 1024);
+    config.socket_recv_buffer = Some(4 * 1024 * 1024);
+    assert!(config.validate().is_ok());
+    let server = common::TestServer::start(config);
+
+    let connection = server.connect().await;
+    connection.close(0u32.into(), b"done");
+}
+
+#[test]
+fn zero_buffer_size_is_invalid() {
+    let mut config = Config::new();
+    config.socket_recv_buffer = Some(0);
+    assert!(config.validate().is_err());
+}
@@ -0,0 +1,113 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use client::socks5;
+use server::config::Config;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+mod common;
+
+/// Starts a client running a SOCKS5 proxy and returns it with the address of the proxy.
+async fn spawn_socks5_client(server: &common::TestServer) -> (Arc<client::Client>, SocketAddr) {
+    // The configured target is never used: each request names its own.
+    let config = common::client_config(server, "127.0.0.1:9".parse().unwrap());
+    let client = Arc::new(client::Client::new(config).unwrap());
+
+    let proxy_addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let runner = client.clone();
+    tokio::spawn(async move { runner.run().await });
+    let proxy = client.clone();
+    tokio::spawn(async move { proxy.listen_socks5(proxy_addr).await });
+
+    (client, proxy_addr)
+}
+
+/// Negotiates the method and sends the `CONNECT` request with the address type and address bytes
+/// given, then returns the reply status.
+async fn socks_connect(
+    proxy_addr: SocketAddr,
+    address_type: u8,
+    address: &[u8],
+) -> (TcpStream, u8) {
+    let mut socket = common::connect_local(proxy_addr).await;
+    socket
+        .write_all(&[socks5::VERSION, 1, socks5::NO_AUTHENTICATION])
+        .await
+        .unwrap();
+    let mut method = [0; 2];
+    socket.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [socks5::VERSION, socks5::NO_AUTHENTICATION]);
+
+    let mut request = vec![socks5::VERSION, socks5::CMD_CONNECT, 0, address_type];
+    request.extend_from_slice(address);
+    socket.write_all(&request).await.unwrap();
+
+    let mut reply = [0; 10];
+    timeout(Duration::from_secs(5), socket.read_exact(&mut reply))
+        .await
+        .expect("no SOCKS reply")
+        .unwrap();
+    assert_eq!(reply[0], socks5::VERSION);
+
+    (socket, reply[1])
+}
+
+async fn assert_echoed(socket: &mut TcpStream) {
+    socket.write_all(b"hello").await.unwrap();
+
+    let mut echoed = [0; 5];
+    timeout(Duration::from_secs(5), socket.read_exact(&mut echoed))
+        .await
+        .expect("timed out waiting for the echo")
+        .unwrap();
+    assert_eq!(&echoed, b"hello");
+}
+
+#[tokio::test]
+async fn connect_to_ipv4_target_is_tunneled() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+    let (_client, proxy_addr) = spawn_socks5_client(&server).await;
+
+    let SocketAddr::V4(backend_v4) = backend_addr else {
+        panic!("backend listens on IPv4");
+    };
+    let mut address = backend_v4.ip().octets().to_vec();
+    address.extend_from_slice(&backend_v4.port().to_be_bytes());
+
+    let (mut socket, status) = socks_connect(proxy_addr, socks5::ATYP_IPV4, &address).await;
+    assert_eq!(status, socks5::REPLY_SUCCEEDED);
+    assert_echoed(&mut socket).await;
+}
+
+#[tokio::test]
+async fn connect_to_domain_target_is_resolved_by_the_server() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+    let (_client, proxy_addr) = spawn_socks5_client(&server).await;
+
+    let mut address = vec![b"localhost".len() as u8];
+    address.extend_from_slice(b"localhost");
+    address.extend_from_slice(&backend_addr.port().to_be_bytes());
+
+    let (mut socket, status) = socks_connect(proxy_addr, socks5::ATYP_DOMAIN, &address).await;
+    assert_eq!(status, socks5::REPLY_SUCCEEDED);
+    assert_echoed(&mut socket).await;
+}
+
+#[tokio::test]
+async fn ipv6_target_is_refused() {
+    let server = common::TestServer::start(Config::new());
+    let (_client, proxy_addr) = spawn_socks5_client(&server).await;
+
+    let (_socket, status) = socks_connect(proxy_addr, socks5::ATYP_IPV6, &[0; 18]).await;
+    assert_eq!(status, socks5::REPLY_ADDRESS_TYPE_NOT_SUPPORTED);
+}
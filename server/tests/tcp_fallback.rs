@@ -0,0 +1,119 @@
+#![cfg(feature = "tcp-fallback")]
+
+use std::{sync::Arc, time::Duration};
+
+use client::transport::MessageReader;
+use message::{CloseReason, DEFAULT_ALPN, MessageType};
+use server::{
+    config::Config,
+    events::Events,
+    registry::Registry,
+    server::{load_identity, make_endpoint},
+    tcp::TcpFallback,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::timeout,
+};
+use uuid::Uuid;
+
+mod common;
+
+#[tokio::test]
+async fn requests_are_proxied_over_tcp_when_quic_is_unavailable() {
+    let backend_addr = common::spawn_echo_backend().await;
+
+    let mut config = Config::new();
+    config.host = "127.0.0.1:0".parse().unwrap();
+    let identity = load_identity(&config).unwrap();
+    let endpoint = make_endpoint(&config, &identity).unwrap();
+    // Refuses every QUIC connection, as if UDP was blocked.
+    endpoint.set_server_config(None);
+
+    config.host = endpoint.local_addr().unwrap();
+    let fallback = TcpFallback::bind(&config, &identity).await.unwrap();
+    assert_eq!(fallback.local_addr().unwrap(), config.host);
+
+    let server = common::TestServer {
+        addr: config.host,
+        cert: identity.cert.clone(),
+        registry: Arc::new(Registry::new()),
+        events: Events::new(),
+    };
+    tokio::spawn(server::serve_with_tcp_fallback(
+        endpoint,
+        fallback,
+        Arc::new(config),
+        server.registry.clone(),
+        server.events.clone(),
+    ));
+
+    let mut client_config = common::client_config(&server, backend_addr);
+    client_config.tcp_fallback = Some(Duration::from_secs(1));
+    let (client, local_addr) = common::spawn_client(client_config).await;
+
+    let payload = vec![0x5A; 100_000];
+    let mut socket = common::connect_local(local_addr).await;
+    socket.write_all(&payload).await.unwrap();
+
+    let mut echoed = vec![0; payload.len()];
+    timeout(Duration::from_secs(5), socket.read_exact(&mut echoed))
+        .await
+        .expect("timed out waiting for the echo")
+        .unwrap();
+    assert_eq!(echoed, payload);
+    assert!(client.is_healthy());
+
+    let snapshot = server.registry.traffic_snapshot();
+    let traffic = snapshot.values().next().expect("tunnel is not registered");
+    assert_eq!(traffic.to_backend, payload.len() as u64);
+    assert_eq!(traffic.to_client, payload.len() as u64);
+}
+
+#[tokio::test]
+async fn connection_beyond_the_limit_receives_a_server_full_close() {
+    let mut config = Config::new();
+    config.host = "127.0.0.1:0".parse().unwrap();
+    config.max_connections = 1;
+    config.connection_queue = 0;
+    let identity = load_identity(&config).unwrap();
+    let endpoint = make_endpoint(&config, &identity).unwrap();
+    config.host = endpoint.local_addr().unwrap();
+    let fallback = TcpFallback::bind(&config, &identity).await.unwrap();
+
+    let server = common::TestServer {
+        addr: config.host,
+        cert: identity.cert.clone(),
+        registry: Arc::new(Registry::new()),
+        events: Events::new(),
+    };
+    tokio::spawn(server::serve_with_tcp_fallback(
+        endpoint,
+        fallback,
+        Arc::new(config),
+        server.registry.clone(),
+        server.events.clone(),
+    ));
+    // Takes the only slot, shared with the TCP connections.
+    let _first = server.connect().await;
+
+    let mut tls_config = client::tls::tls_config(std::slice::from_ref(&server.cert), None).unwrap();
+    tls_config.alpn_protocols = vec![DEFAULT_ALPN.to_vec()];
+    let (_link, _sink, source) =
+        client::tcp::connect(server.addr, "localhost", Arc::new(tls_config))
+            .await
+            .unwrap();
+
+    let mut reader = MessageReader::new(source, 64 * 1024);
+    let close = timeout(Duration::from_secs(5), reader.next_message())
+        .await
+        .expect("no Close received")
+        .unwrap()
+        .expect("stream finished without a Close");
+    assert!(matches!(close.message_type, MessageType::Close));
+    assert_eq!(close.connection_id, Uuid::nil());
+    assert_eq!(
+        CloseReason::decode(&close.payload).unwrap(),
+        CloseReason::ServerFull
+    );
+}
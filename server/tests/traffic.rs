@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use server::{config::Config, registry::Traffic};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::{sleep, timeout},
+};
+
+mod common;
+
+#[tokio::test]
+async fn relayed_bytes_are_counted_per_tunnel() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let server = common::TestServer::start(Config::new());
+    let (_client, local_addr) =
+        common::start_client(&server, backend_addr, Duration::from_secs(5)).await;
+
+    let payload = vec![0x5A; 100_000];
+    let mut socket = common::connect_local(local_addr).await;
+    socket.write_all(&payload).await.unwrap();
+
+    let mut echoed = vec![0; payload.len()];
+    timeout(Duration::from_secs(5), socket.read_exact(&mut echoed))
+        .await
+        .expect("timed out waiting for the echo")
+        .unwrap();
+    assert_eq!(echoed, payload);
+
+    let snapshot = server.registry.traffic_snapshot();
+    assert_eq!(snapshot.len(), 1);
+    let (connection_id, traffic) = snapshot.into_iter().next().unwrap();
+    let expected = Traffic {
+        to_backend: payload.len() as u64,
+        to_client: payload.len() as u64,
+    };
+    assert_eq!(traffic, expected);
+    assert_eq!(server.registry.traffic(&connection_id), Some(expected));
+
+    // Counters are dropped along with the tunnel.
+    drop(socket);
+    timeout(Duration::from_secs(5), async {
+        while server.registry.traffic(&connection_id).is_some() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("tunnel was not closed");
+    assert!(server.registry.traffic_snapshot().is_empty());
+}
@@ -0,0 +1,69 @@
+use server::{
+    config::Config,
+    server::{Identity, configure_server, transport_config},
+};
+
+mod common;
+
+#[test]
+fn stream_and_datagram_limits_are_applied() {
+    let mut config = Config::new();
+    config.max_concurrent_bidi_streams = 8;
+    config.datagrams = true;
+
+    let server_config = configure_server(
+        None,
+        &Identity::generate(),
+        &config.alpn,
+        transport_config(&config),
+        false,
+    )
+    .unwrap();
+    let transport = format!("{:?}", server_config.transport);
+    assert!(
+        transport.contains("max_concurrent_bidi_streams: 8,"),
+        "{transport}"
+    );
+    assert!(
+        !transport.contains("datagram_receive_buffer_size: None"),
+        "{transport}"
+    );
+
+    config.datagrams = false;
+    let server_config = configure_server(
+        None,
+        &Identity::generate(),
+        &config.alpn,
+        transport_config(&config),
+        false,
+    )
+    .unwrap();
+    let transport = format!("{:?}", server_config.transport);
+    assert!(
+        transport.contains("datagram_receive_buffer_size: None"),
+        "{transport}"
+    );
+}
+
+#[tokio::test]
+async fn datagrams_are_announced_to_the_client_when_enabled() {
+    let connection = common::connect(Config::new()).await;
+    assert_eq!(connection.max_datagram_size(), None);
+
+    let mut config = Config::new();
+    config.datagrams = true;
+    let connection = common::connect(config).await;
+    assert!(connection.max_datagram_size().is_some());
+}
+
+#[test]
+fn zero_bidi_streams_are_rejected() {
+    let mut config = Config::new();
+    config.max_concurrent_bidi_streams = 0;
+
+    let err = config.validate().unwrap_err();
+    assert!(
+        err.to_string().contains("max_concurrent_bidi_streams"),
+        "{err}"
+    );
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use message::{CloseReason, Decoder, InitializationMessage, Message, MessageType, ProtocolVersion};
+use server::config::Config;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+mod common;
+
+/// Opens a tunnel advertising `max_version` and returns the first reply of the server.
+async fn first_reply(max_version: u8) -> (Message, Message) {
+    let backend_addr = common::spawn_echo_backend().await;
+    let connection = common::connect(Config::new()).await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr)
+        .unwrap()
+        .with_max_version(max_version);
+    let initial = Message::new(MessageType::Initial, connection_id, init.encode());
+    send.write_all(&initial.encode()).await.unwrap();
+
+    let mut decoder = Decoder::new();
+    let reply = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("no reply to the Initial");
+
+    (initial, reply)
+}
+
+#[tokio::test]
+async fn matching_version_is_chosen() {
+    let (initial, reply) = first_reply(ProtocolVersion::V1 as u8).await;
+
+    assert!(matches!(reply.message_type, MessageType::Ack));
+    assert_eq!(reply.in_reply_to, Some(initial.message_id));
+    assert_eq!(
+        ProtocolVersion::decode(&reply.payload).unwrap(),
+        ProtocolVersion::V1
+    );
+    assert_eq!(reply.version, ProtocolVersion::V1);
+}
+
+#[tokio::test]
+async fn higher_client_version_is_downgraded() {
+    let (initial, reply) = first_reply(ProtocolVersion::LATEST as u8 + 1).await;
+
+    assert!(matches!(reply.message_type, MessageType::Ack));
+    assert_eq!(reply.in_reply_to, Some(initial.message_id));
+    assert_eq!(
+        ProtocolVersion::decode(&reply.payload).unwrap(),
+        ProtocolVersion::LATEST
+    );
+}
+
+#[tokio::test]
+async fn client_without_a_common_version_is_refused() {
+    let (_, reply) = first_reply(0).await;
+
+    assert!(matches!(reply.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&reply.payload).unwrap(),
+        CloseReason::ProtocolError
+    );
+}
@@ -0,0 +1,122 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bytes::Bytes;
+use message::{CloseReason, Decoder, Message, MessageType};
+use quinn::{ConnectionError, SendStream, VarInt};
+use server::{close_code::CloseCode, config::Config, registry::ConnectionState};
+use tokio::{
+    net::TcpListener,
+    time::{sleep, timeout},
+};
+use uuid::Uuid;
+
+mod common;
+
+const WRITE_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Accepts backend connections and keeps them open without ever reading them.
+async fn spawn_stuck_backend() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let mut sockets = Vec::new();
+        while let Ok((socket, _)) = listener.accept().await {
+            sockets.push(socket);
+        }
+    });
+
+    addr
+}
+
+/// Sends `Data` for the tunnel until the stream is closed, far more than the socket buffers hold.
+fn flood(mut send: SendStream, connection_id: Uuid) {
+    tokio::spawn(async move {
+        let payload = Bytes::from(vec![0x5A; 60 * 1024]);
+        for _ in 0..1024 {
+            let data = Message::new(MessageType::Data, connection_id, payload.clone());
+            if send.write_all(&data.encode()).await.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+#[tokio::test]
+async fn tunnel_of_a_backend_not_draining_is_closed() {
+    let backend_addr = spawn_stuck_backend().await;
+    let mut config = Config::new();
+    config.backend_write_timeout = WRITE_TIMEOUT;
+    let server = common::TestServer::start(config);
+
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+    flood(send, connection_id);
+
+    let close = timeout(
+        Duration::from_secs(10),
+        common::next_message(&mut recv, &mut decoder, connection_id),
+    )
+    .await
+    .expect("no Close received");
+    assert!(matches!(close.message_type, MessageType::Close));
+    assert_eq!(
+        CloseReason::decode(&close.payload).unwrap(),
+        CloseReason::Timeout
+    );
+    assert_eq!(
+        server.registry.state(&connection_id),
+        Some(ConnectionState::Closing)
+    );
+
+    // The connection itself is still served.
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let ping = Message::new(MessageType::Ping, Uuid::nil(), Bytes::new());
+    send.write_all(&ping.encode()).await.unwrap();
+    let mut decoder = Decoder::new();
+    let pong = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, Uuid::nil()),
+    )
+    .await
+    .expect("no pong received");
+    assert!(matches!(pong.message_type, MessageType::Pong));
+}
+
+#[tokio::test]
+async fn client_not_reading_its_stream_is_closed() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let mut config = Config::new();
+    config.client_write_timeout = WRITE_TIMEOUT;
+    // The echo backend stops reading once its replies can't be relayed either.
+    config.backend_write_timeout = WRITE_TIMEOUT;
+    let server = common::TestServer::start(config);
+
+    let connection = server.connect().await;
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    let connection_id = common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+    // The echo is never read from `recv`.
+    flood(send, connection_id);
+
+    let err = timeout(Duration::from_secs(10), connection.closed())
+        .await
+        .expect("connection was not closed");
+    match err {
+        ConnectionError::ApplicationClosed(close) => {
+            assert_eq!(close.error_code, VarInt::from(CloseCode::WriteTimeout))
+        }
+        err => panic!("unexpected connection error: {err:?}"),
+    }
+
+    // The tunnel is torn down along with the connection.
+    timeout(Duration::from_secs(5), async {
+        while server.registry.state(&connection_id) != Some(ConnectionState::Closed) {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("tunnel was not closed");
+}
@@ -0,0 +1,318 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bytes::Bytes;
+use message::{Decoder, InitializationMessage, Message, MessageType};
+use quinn::Endpoint;
+use server::{config::Config, connection, transport::AsyncFrameSink};
+use tokio::{
+    io::AsyncReadExt,
+    net::{TcpListener, UdpSocket},
+    sync::{mpsc, oneshot},
+    time::timeout,
+};
+use uuid::Uuid;
+
+mod common;
+
+use common::memory::{self, MemoryConnection, next_message, shared};
+
+/// Accepts backend connections and reports everything they receive.
+async fn spawn_recording_backend() -> (SocketAddr, mpsc::UnboundedReceiver<Bytes>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (received_tx, received_rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let received_tx = received_tx.clone();
+            tokio::spawn(async move {
+                let mut buf = vec![0; 1024];
+                while let Ok(n) = socket.read(&mut buf).await {
+                    if n == 0 {
+                        break;
+                    }
+                    let _ = received_tx.send(Bytes::copy_from_slice(&buf[..n]));
+                }
+            });
+        }
+    });
+
+    (addr, received_rx)
+}
+
+/// `Initial` of a tunnel to `backend_addr`, followed by a `Data` message of it, as a single chunk.
+fn initial_and_data(backend_addr: SocketAddr) -> (Message, Bytes) {
+    let connection_id = Uuid::new_v4();
+    let init = InitializationMessage::new("127.0.0.1:0".parse().unwrap(), backend_addr).unwrap();
+    let initial = Message::new(MessageType::Initial, connection_id, init.encode());
+    let data = Message::data(connection_id, Bytes::from_static(b"hello")).with_sequence(0);
+
+    let mut chunk = initial.encode().to_vec();
+    chunk.extend_from_slice(&data.encode());
+    (initial, Bytes::from(chunk))
+}
+
+/// Relays the datagrams between a client and the server, holding those of the client that would
+/// complete the handshake: once the server replied, only its 0-RTT packets go through until
+/// `release` is sent. Returns the address the client connects to.
+async fn spawn_handshake_holding_relay(
+    server_addr: SocketAddr,
+) -> (SocketAddr, oneshot::Sender<()>) {
+    let client_side = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let relay_addr = client_side.local_addr().unwrap();
+    let server_side = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    server_side.connect(server_addr).await.unwrap();
+    let (release_tx, mut release_rx) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let mut client_addr = None;
+        let mut server_replied = false;
+        let mut held = Some(Vec::new());
+        let mut from_client = vec![0; 65536];
+        let mut from_server = vec![0; 65536];
+
+        loop {
+            tokio::select! {
+                received = client_side.recv_from(&mut from_client) => {
+                    let (n, addr) = received.unwrap();
+                    client_addr = Some(addr);
+                    let datagram = &from_client[..n];
+                    // Long header packets of type 1 are the 0-RTT ones.
+                    let zero_rtt = datagram[0] & 0x80 != 0 && (datagram[0] >> 4) & 0x3 == 1;
+                    match &mut held {
+                        Some(held) if server_replied && !zero_rtt => held.push(datagram.to_vec()),
+                        _ => {
+                            let _ = server_side.send(datagram).await;
+                        }
+                    }
+                }
+                received = server_side.recv(&mut from_server) => {
+                    let n = received.unwrap();
+                    server_replied = true;
+                    if let Some(addr) = client_addr {
+                        let _ = client_side.send_to(&from_server[..n], addr).await;
+                    }
+                }
+                _ = &mut release_rx, if held.is_some() => {
+                    for datagram in held.take().unwrap() {
+                        let _ = server_side.send(&datagram).await;
+                    }
+                }
+            }
+        }
+    });
+
+    (relay_addr, release_tx)
+}
+
+#[tokio::test]
+async fn early_data_is_forwarded_once_the_handshake_completes() {
+    let (backend_addr, mut received) = spawn_recording_backend().await;
+    let connection = MemoryConnection::new("127.0.0.1:4000".parse().unwrap());
+    let (mut client_send, server_recv) = memory::stream();
+    let (server_send, mut client_recv) = memory::stream();
+    let (handshake_tx, handshake_rx) = oneshot::channel();
+    tokio::spawn(connection::handle_early_stream(
+        connection,
+        server_send,
+        server_recv,
+        shared(Config::new()),
+        async { handshake_rx.await.unwrap_or(false) },
+    ));
+    let mut decoder = Decoder::new();
+
+    let (initial, chunk) = initial_and_data(backend_addr);
+    let ping = Message::new(MessageType::Ping, Uuid::nil(), Bytes::from_static(b"ping"));
+    client_send.send_chunk(chunk).await.unwrap();
+    client_send.send_chunk(ping.encode()).await.unwrap();
+
+    // Only the ping is answered before the handshake: the tunnel isn't even opened.
+    let pong = next_message(&mut client_recv, &mut decoder).await;
+    assert!(matches!(pong.message_type, MessageType::Pong));
+    assert!(
+        timeout(Duration::from_millis(200), received.recv())
+            .await
+            .is_err()
+    );
+
+    handshake_tx.send(true).unwrap();
+    let ack = next_message(&mut client_recv, &mut decoder).await;
+    assert!(matches!(ack.message_type, MessageType::Ack));
+    assert_eq!(ack.in_reply_to, Some(initial.message_id));
+    let forwarded = timeout(Duration::from_secs(5), received.recv())
+        .await
+        .expect("data not forwarded once the handshake completed")
+        .unwrap();
+    assert_eq!(&forwarded[..], b"hello");
+}
+
+#[tokio::test]
+async fn early_data_is_dropped_when_the_handshake_fails() {
+    let (backend_addr, mut received) = spawn_recording_backend().await;
+    let connection = MemoryConnection::new("127.0.0.1:4000".parse().unwrap());
+    let (mut client_send, server_recv) = memory::stream();
+    let (server_send, mut client_recv) = memory::stream();
+    let (handshake_tx, handshake_rx) = oneshot::channel();
+    let handler = tokio::spawn(connection::handle_early_stream(
+        connection,
+        server_send,
+        server_recv,
+        shared(Config::new()),
+        async { handshake_rx.await.unwrap_or(false) },
+    ));
+    let mut decoder = Decoder::new();
+
+    let (_, chunk) = initial_and_data(backend_addr);
+    client_send.send_chunk(chunk).await.unwrap();
+    assert!(
+        timeout(
+            Duration::from_millis(200),
+            next_message(&mut client_recv, &mut decoder)
+        )
+        .await
+        .is_err()
+    );
+
+    handshake_tx.send(false).unwrap();
+    timeout(Duration::from_secs(5), handler)
+        .await
+        .expect("handler did not end")
+        .unwrap();
+    assert!(
+        timeout(Duration::from_millis(200), received.recv())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn resumed_connection_tunnels_its_0rtt_data() {
+    let backend_addr = common::spawn_echo_backend().await;
+    let mut config = Config::new();
+    config.zero_rtt = true;
+    let server = common::TestServer::start(config);
+
+    let mut tls_config = client::tls::tls_config(std::slice::from_ref(&server.cert), None).unwrap();
+    tls_config.enable_early_data = true;
+    let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(client::tls::quic_config(tls_config).unwrap());
+
+    // A first connection gets the session ticket to resume.
+    let connection = endpoint
+        .connect(server.addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+    connection.close(0u32.into(), b"done");
+
+    let (connection, accepted) = endpoint
+        .connect(server.addr, "localhost")
+        .unwrap()
+        .into_0rtt()
+        .unwrap_or_else(|_| panic!("session was not resumed"));
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let (initial, chunk) = initial_and_data(backend_addr);
+    send.write_all(&chunk).await.unwrap();
+    assert!(accepted.await, "0-RTT data was rejected");
+
+    let mut decoder = Decoder::new();
+    let ack = common::next_message(&mut recv, &mut decoder, initial.connection_id).await;
+    assert!(matches!(ack.message_type, MessageType::Ack));
+    let echo = timeout(
+        Duration::from_secs(5),
+        common::next_message(&mut recv, &mut decoder, initial.connection_id),
+    )
+    .await
+    .expect("no echo received");
+    assert_eq!(&echo.payload[..], b"hello");
+}
+
+#[tokio::test]
+async fn every_stream_opened_in_0rtt_waits_for_the_handshake() {
+    let (backend_addr, mut received) = spawn_recording_backend().await;
+    let mut config = Config::new();
+    config.zero_rtt = true;
+    let server = common::TestServer::start(config);
+
+    let mut tls_config = client::tls::tls_config(std::slice::from_ref(&server.cert), None).unwrap();
+    tls_config.enable_early_data = true;
+    let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(client::tls::quic_config(tls_config).unwrap());
+
+    // A first connection gets the session ticket to resume.
+    let connection = endpoint
+        .connect(server.addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut decoder = Decoder::new();
+    common::open_tunnel(&mut send, &mut recv, &mut decoder, backend_addr).await;
+    connection.close(0u32.into(), b"done");
+    while timeout(Duration::from_millis(200), received.recv())
+        .await
+        .is_ok()
+    {}
+
+    let (relay_addr, release) = spawn_handshake_holding_relay(server.addr).await;
+    let (connection, _accepted) = endpoint
+        .connect(relay_addr, "localhost")
+        .unwrap()
+        .into_0rtt()
+        .unwrap_or_else(|_| panic!("session was not resumed"));
+    let mut streams = Vec::new();
+    for _ in 0..2 {
+        let (mut send, recv) = connection.open_bi().await.unwrap();
+        let (initial, chunk) = initial_and_data(backend_addr);
+        send.write_all(&chunk).await.unwrap();
+        streams.push((send, recv, initial));
+    }
+
+    // Neither stream reaches the backend while the handshake is held back.
+    assert!(
+        timeout(Duration::from_millis(500), received.recv())
+            .await
+            .is_err(),
+        "early data forwarded before the handshake completed"
+    );
+
+    release.send(()).unwrap();
+    for (_send, mut recv, initial) in streams {
+        let mut decoder = Decoder::new();
+        let ack = timeout(
+            Duration::from_secs(5),
+            common::next_message(&mut recv, &mut decoder, initial.connection_id),
+        )
+        .await
+        .expect("tunnel not opened once the handshake completed");
+        assert!(matches!(ack.message_type, MessageType::Ack));
+    }
+    let mut forwarded = Vec::new();
+    timeout(Duration::from_secs(5), async {
+        while forwarded.len() < 2 * b"hello".len() {
+            forwarded.extend_from_slice(&received.recv().await.unwrap());
+        }
+    })
+    .await
+    .expect("data not forwarded once the handshake completed");
+    assert_eq!(forwarded, b"hellohello");
+}
+
+#[test]
+fn zero_rtt_along_with_client_certificates_is_invalid() {
+    let dir = std::env::temp_dir().join(format!("reverprox-ca-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let ca_path = dir.join("ca.pem");
+    let identity = server::server::Identity::generate();
+    server::server::save_cert(&ca_path, &identity.cert).unwrap();
+
+    let mut config = Config::new();
+    config.client_ca = Some(ca_path);
+    assert!(config.validate().is_ok());
+
+    config.zero_rtt = true;
+    assert!(config.validate().is_err());
+}